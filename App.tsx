@@ -217,6 +217,9 @@ function App() {
       if (vaultPath && activeNotebook === notebookPath) {
         console.log(`[QuickCapture] Refreshing notes for notebook: ${notebookPath}`)
         await loadNotes(vaultPath, activeNotebook)
+      } else if (vaultPath) {
+        // Date-based filing may have created the notebook it went into
+        await loadNotebooks(vaultPath)
       }
       
       // Also refresh tags in case the note has new tags
@@ -228,7 +231,7 @@ function App() {
     return () => {
       unlisten.then((fn) => fn())
     }
-  }, [vaultPath, activeNotebook, loadNotes, loadTags])
+  }, [vaultPath, activeNotebook, loadNotebooks, loadNotes, loadTags])
 
   // Auto-reconnect vault sync when session is restored and vault is available
   useEffect(() => {
@@ -342,7 +345,7 @@ function App() {
     } else if (contextMenu.type === 'message') {
       const note = contextMenu.data as Note
       if (action === 'edit') {
-        setEditing(note.filename)
+        if (!note.placeholder) setEditing(note.filename)
       } else if (action === 'delete') {
         handleDeleteMessage(note.filename)
       } else if (action === 'copy') {
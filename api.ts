@@ -10,7 +10,8 @@ import {
   OgMetadata,
   KanbanBoard,
   KanbanColumn,
-  BoardMetadata
+  BoardMetadata,
+  VaultValidation
 } from './types'
 import { parseKanbanMarkdown, serializeKanbanMarkdown } from './utils/kanban'
 import { Reminder, ReminderFilter } from './types/reminders'
@@ -279,8 +280,20 @@ function mapNotebook(raw: RawNotebook, pinnedSet: Set<string>): Notebook {
   }
 }
 
+interface NotebookScanWarning {
+  relative_path: string
+  error: string
+}
+
 export async function listNotebooks(vaultPath: string): Promise<Notebook[]> {
-  const notebooks = await invoke<RawNotebook[]>('list_notebooks', { vaultPath })
+  const { notebooks, warnings } = await invoke<{
+    notebooks: RawNotebook[]
+    warnings: NotebookScanWarning[]
+    read_only: boolean
+  }>('list_notebooks', { vaultPath })
+  for (const warning of warnings) {
+    console.warn(`Skipped notebook folder ${warning.relative_path}: ${warning.error}`)
+  }
   const database = await getDb()
   const pinnedResult = await database.select<{ name: string }[]>(
     'SELECT name FROM pinned_notebooks'
@@ -421,11 +434,33 @@ export async function updateNotebookOrder(
   }
 }
 
+export interface ListNotesOptions {
+  /** Fill in `title` from the first heading or frontmatter `title:` */
+  withTitle?: boolean
+  withPreview?: boolean
+}
+
 export async function listNotes(
   vaultPath: string,
-  notebookPath: string
+  notebookPath: string,
+  options: ListNotesOptions = {}
 ): Promise<NoteMetadata[]> {
-  return invoke<NoteMetadata[]>('list_notes', { vaultPath, notebookPath })
+  return invoke<NoteMetadata[]>('list_notes', {
+    vaultPath,
+    notebookPath,
+    withTitle: options.withTitle,
+    withPreview: options.withPreview
+  })
+}
+
+/** Download a placeholder note's content from the sync server */
+export async function materializeNote(
+  vaultPath: string,
+  notebookPath: string,
+  filename: string
+): Promise<void> {
+  const relativePath = notebookPath ? `${notebookPath}/${filename}` : filename
+  await invoke('sync_materialize_file', { vaultPath, relativePath })
 }
 
 export async function readNote(
@@ -466,10 +501,13 @@ export async function createNote(
     filename: string
     content: string
     created_at: number
+    notebook_path?: string
   }>('create_note', {
     vaultPath,
     notebookPath,
-    content
+    content,
+    // Date-based filing picks the YYYY/MM folder by local date
+    utcOffsetMinutes: -new Date().getTimezoneOffset()
   })
 
   const tags = extractTags(result.content)
@@ -482,10 +520,23 @@ export async function createNote(
     tags,
     hasLink: urls.length > 0,
     urls,
-    notebookName: notebookPath
+    notebookName: result.notebook_path ?? notebookPath
   }
 }
 
+/** Check a vault folder before working in it */
+export async function validateVault(vaultPath: string): Promise<VaultValidation> {
+  return invoke<VaultValidation>('validate_vault', { vaultPath })
+}
+
+export async function getDateFiling(vaultPath: string): Promise<boolean> {
+  return invoke<boolean>('get_date_filing', { vaultPath })
+}
+
+export async function setDateFiling(vaultPath: string, enabled: boolean): Promise<void> {
+  await invoke('set_date_filing', { vaultPath, enabled })
+}
+
 export async function updateNote(
   vaultPath: string,
   notebookPath: string,
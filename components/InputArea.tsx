@@ -17,7 +17,7 @@ import { CommandContext } from '../types/chatCommands'
 
 export const InputArea: React.FC = () => {
   const { vaultPath } = useVaultStore()
-  const { activeNotebook, currentNotebook } = useNotebookStore()
+  const { activeNotebook, currentNotebook, loadNotebooks } = useNotebookStore()
   const { createNote } = useNotesStore()
   const { syncNoteTags } = useTagsStore()
   const { executeCommand } = useCommandStore()
@@ -108,7 +108,10 @@ export const InputArea: React.FC = () => {
       }
 
       if (result.createNote && result.noteContent) {
-        await createNote(vaultPath!, activeNotebook!, result.noteContent)
+        const newNote = await createNote(vaultPath!, activeNotebook!, result.noteContent)
+        if (newNote.notebookName !== activeNotebook) {
+          await loadNotebooks(vaultPath!)
+        }
       }
 
       if (result.clearInput) {
@@ -194,6 +197,10 @@ export const InputArea: React.FC = () => {
 
     const newNote = await createNote(vaultPath, activeNotebook, content)
     await syncNoteTags(newNote)
+    // Date-based filing may have created the dated subfolder it went into
+    if (newNote.notebookName !== activeNotebook) {
+      await loadNotebooks(vaultPath)
+    }
     setContent('')
     setIsPreviewMode(false) // Reset preview mode after sending
   }
@@ -114,6 +114,11 @@ export const MessageItem = React.memo(
                 />
               ) : (
                 <>
+                  {note.placeholder && (
+                    <div className="text-textMuted/60 text-[12px] italic">
+                      Not downloaded yet. It will be once the sync server is reachable.
+                    </div>
+                  )}
                   <div className="text-textMain/90 text-[14px] leading-relaxed markdown-content">
                     <ReactMarkdown
                       remarkPlugins={remarkPlugins}
@@ -416,7 +416,7 @@ export const MessageList: React.FC = React.memo(function MessageList() {
                     deleteConfirmId={deleteConfirmId}
                     vaultPath={vaultPath}
                     onContextMenu={(e) => handleContextMenu(e, note)}
-                    onEdit={() => setEditing(note.filename)}
+                    onEdit={() => !note.placeholder && setEditing(note.filename)}
                     onEditSubmit={(content) =>
                       handleEditSubmit(note.filename, content)
                     }
@@ -158,7 +158,7 @@ export const QuickCapture: React.FC = () => {
       // Emit event to notify main window about the new note
       await emit('quick-capture-note-created', {
         vaultPath,
-        notebookPath: selectedNotebook,
+        notebookPath: note.notebookName,
         filename: note.filename
       })
 
@@ -0,0 +1,822 @@
+//! Content index of the vault's attachments
+//!
+//! `attachments/.index.json` maps every attachment to the BLAKE3 hash of its
+//! content, so saving an image that is already in the vault reuses the
+//! existing file instead of writing a copy. The index is local to each
+//! device (dotfiles aren't synced) and is only a cache: files added,
+//! replaced or removed by hand make it drift, so it is checked against the
+//! folder cheaply by size and modification time and rebuilt when they differ.
+//!
+//! Since saves reuse files, one attachment can back several notes (and a
+//! duplicated note shares its attachments with the original), so orphan
+//! cleanup counts the references of every attachment and only deletes those
+//! with none.
+//!
+//! Optionally attachments are named by content (the BLAKE3 hash plus the
+//! extension) instead of by timestamp. Identical images then get the same
+//! path in every note and on every device, so syncing them is idempotent;
+//! `migrate_attachments_to_content_addressed` renames existing files and
+//! rewrites the references to them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, State};
+
+use crate::autosave::PendingSaves;
+use crate::local_crypto::LocalEncryption;
+use crate::sync::scanner;
+use crate::{trash, vault};
+
+/// Index file name inside the attachments folder
+pub const ATTACHMENT_INDEX_FILE: &str = ".index.json";
+
+/// What the index records about one attachment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttachmentEntry {
+    pub hash: String,
+    pub size_bytes: u64,
+    /// Modification time (ms since epoch) when the file was hashed
+    pub modified_at: u64,
+}
+
+/// Attachments keyed by their path relative to the attachments folder
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AttachmentIndex {
+    pub files: BTreeMap<String, AttachmentEntry>,
+}
+
+/// Attachments with identical content
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentDuplicate {
+    pub hash: String,
+    /// Paths relative to the vault (`attachments/...`)
+    pub files: Vec<String>,
+    /// Bytes that would be freed by keeping only one copy
+    pub wasted_bytes: u64,
+}
+
+/// Result of `rebuild_attachment_index`
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentIndexRebuild {
+    pub file_count: usize,
+    pub duplicates: Vec<AttachmentDuplicate>,
+}
+
+/// Notes referencing one attachment
+#[derive(Debug, Clone, Serialize)]
+pub struct AttachmentReferences {
+    /// Path relative to the vault (`attachments/...`)
+    pub path: String,
+    pub count: usize,
+    /// Notes relative to the vault; notes in the trash count too, since
+    /// they can be restored
+    pub notes: Vec<String>,
+}
+
+/// Result of `migrate_attachments_to_content_addressed`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AttachmentMigration {
+    /// Old path -> new path, both relative to the vault
+    pub renamed: BTreeMap<String, String>,
+    /// Old copies removed because a file with the same content already
+    /// had the new name
+    pub deduplicated: usize,
+    pub notes_updated: usize,
+}
+
+/// Result of `cleanup_orphan_attachments`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct OrphanCleanup {
+    /// Attachments deleted (or that would be, for a dry run)
+    pub deleted: Vec<String>,
+    pub freed_bytes: u64,
+}
+
+/// Where attachment references point: vault-relative paths under this
+const ATTACHMENTS_PREFIX: &str = "attachments/";
+
+/// An attachment link target found in a note
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttachmentRef {
+    /// Bytes of the note holding the target's path as written, without
+    /// the `|alias`, `#fragment` or `?query` that may follow it
+    pub range: Range<usize>,
+    /// The path relative to the vault (`attachments/...`), URL-decoded
+    pub path: String,
+}
+
+/// Find the attachment targets of a note's links: markdown links and
+/// images (`![](attachments/a.png)`, or `<...>`-wrapped to allow spaces),
+/// wiki links and embeds (`![[attachments/a b.png|300]]`), autolinks and
+/// HTML `src`/`href` attributes. Only targets relative to the vault count;
+/// an external URL that happens to contain `attachments/` doesn't.
+pub fn parse_attachment_refs(content: &str) -> Vec<AttachmentRef> {
+    let mut refs = Vec::new();
+    let mut i = 0;
+
+    while let Some(rest) = content.get(i..).filter(|rest| !rest.is_empty()) {
+        // Start and end of the link target, and whether it's a wiki link
+        let target = if let Some(inner) = rest.strip_prefix("[[") {
+            inner.find("]]").map(|end| (i + 2, i + 2 + end, true))
+        } else if let Some(inner) = rest.strip_prefix("](<") {
+            inner.find('>').map(|end| (i + 3, i + 3 + end, false))
+        } else if let Some(inner) = rest.strip_prefix("](") {
+            let end = inner.find(|c: char| c.is_whitespace() || c == ')').unwrap_or(inner.len());
+            Some((i + 2, i + 2 + end, false))
+        } else if rest.starts_with("<attachments/") {
+            rest[1..].find('>').map(|end| (i + 1, i + 1 + end, false))
+        } else {
+            ["src=", "href="].iter().find_map(|attribute| {
+                let value = rest.strip_prefix(attribute)?;
+                let quote = value.chars().next().filter(|c| matches!(c, '"' | '\''))?;
+                let start = i + attribute.len() + 1;
+                value[1..].find(quote).map(|end| (start, start + end, false))
+            })
+        };
+
+        // Link targets never span lines
+        let target = target.filter(|(start, end, _)| !content[*start..*end].contains('\n'));
+        let Some((start, end, wiki)) = target else {
+            i += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+        let written = &content[start..end];
+        let cut = if wiki { &['|', '#'][..] } else { &['#', '?'][..] };
+        let written = &written[..written.find(cut).unwrap_or(written.len())];
+        if written.len() > ATTACHMENTS_PREFIX.len() && written.starts_with(ATTACHMENTS_PREFIX) {
+            let path = if wiki {
+                written.to_string()
+            } else {
+                urlencoding::decode(written).map(|p| p.into_owned()).unwrap_or_else(|_| written.to_string())
+            };
+            refs.push(AttachmentRef {
+                range: start..start + written.len(),
+                path,
+            });
+        }
+        i = end;
+    }
+
+    refs
+}
+
+/// Replace the attachment targets for which `replace` returns a new path,
+/// keeping aliases, fragments and the rest of the note as written. A target
+/// that was URL-encoded gets its replacement's spaces encoded too. Returns
+/// `None` if nothing was replaced.
+pub fn rewrite_refs_with(content: &str, mut replace: impl FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut rewritten = String::with_capacity(content.len());
+    let mut copied_up_to = 0;
+    for reference in parse_attachment_refs(content) {
+        let Some(new) = replace(&reference.path) else { continue };
+        rewritten.push_str(&content[copied_up_to..reference.range.start]);
+        if content[reference.range.clone()] == reference.path {
+            rewritten.push_str(&new);
+        } else {
+            rewritten.push_str(&new.replace(' ', "%20"));
+        }
+        copied_up_to = reference.range.end;
+    }
+    if copied_up_to == 0 {
+        return None;
+    }
+    rewritten.push_str(&content[copied_up_to..]);
+    Some(rewritten)
+}
+
+fn attachments_dir(vault_path: &Path) -> PathBuf {
+    vault_path.join("attachments")
+}
+
+fn index_path(vault_path: &Path) -> PathBuf {
+    attachments_dir(vault_path).join(ATTACHMENT_INDEX_FILE)
+}
+
+/// Size and modification time of every attachment, keyed like the index
+fn stat_attachments(vault_path: &Path) -> Result<BTreeMap<String, (PathBuf, u64, u64)>, String> {
+    let dir = attachments_dir(vault_path);
+    let mut files = BTreeMap::new();
+    for path in vault::collect_attachment_paths(vault_path)? {
+        let Ok(metadata) = fs::metadata(&path) else { continue };
+        let modified_at = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let relative = path.strip_prefix(&dir).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+        files.insert(relative, (path, metadata.len(), modified_at));
+    }
+    Ok(files)
+}
+
+/// Load the index; `None` if it is missing or unreadable
+pub fn load_index(vault_path: &Path) -> Option<AttachmentIndex> {
+    let json = fs::read_to_string(index_path(vault_path)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+fn save_index(vault_path: &Path, index: &AttachmentIndex) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    fs::write(index_path(vault_path), json).map_err(|e| e.to_string())
+}
+
+/// Whether the index lists exactly the attachments on disk, with the sizes
+/// and modification times they had when hashed. Nothing is hashed here.
+pub fn index_is_consistent(vault_path: &Path) -> Result<bool, String> {
+    let on_disk = stat_attachments(vault_path)?;
+    let Some(index) = load_index(vault_path) else {
+        return Ok(on_disk.is_empty());
+    };
+
+    Ok(index.files.len() == on_disk.len()
+        && on_disk.iter().all(|(relative, (_, size, modified_at))| {
+            index
+                .files
+                .get(relative)
+                .is_some_and(|entry| entry.size_bytes == *size && entry.modified_at == *modified_at)
+        }))
+}
+
+/// Rehash every attachment and rewrite the index, reporting duplicates
+pub fn rebuild_index(vault_path: &Path) -> Result<AttachmentIndexRebuild, String> {
+    let mut index = AttachmentIndex::default();
+    for (relative, (path, size_bytes, modified_at)) in stat_attachments(vault_path)? {
+        let Ok(bytes) = fs::read(&path) else { continue };
+        let hash = scanner::compute_hash(&bytes);
+        index.files.insert(relative, AttachmentEntry { hash, size_bytes, modified_at });
+    }
+    if !index.files.is_empty() || index_path(vault_path).exists() {
+        save_index(vault_path, &index)?;
+    }
+
+    let mut by_hash: HashMap<&str, Vec<(&String, u64)>> = HashMap::new();
+    for (relative, entry) in &index.files {
+        by_hash.entry(&entry.hash).or_default().push((relative, entry.size_bytes));
+    }
+    let mut duplicates: Vec<AttachmentDuplicate> = by_hash
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(hash, files)| AttachmentDuplicate {
+            hash: hash.to_string(),
+            wasted_bytes: files.iter().skip(1).map(|(_, size)| size).sum(),
+            files: files.iter().map(|(relative, _)| format!("attachments/{}", relative)).collect(),
+        })
+        .collect();
+    duplicates.sort_by(|a, b| b.wasted_bytes.cmp(&a.wasted_bytes).then_with(|| a.files.cmp(&b.files)));
+
+    println!(
+        "[Attachments] Indexed {} attachments in {} ({} duplicate groups)",
+        index.files.len(),
+        vault_path.display(),
+        duplicates.len()
+    );
+    Ok(AttachmentIndexRebuild {
+        file_count: index.files.len(),
+        duplicates,
+    })
+}
+
+/// Rebuild the index if it no longer matches the attachments folder
+pub fn ensure_index_consistent(vault_path: &Path) -> Result<(), String> {
+    if !index_is_consistent(vault_path)? {
+        println!("[Attachments] Index of {} is out of date, rebuilding", vault_path.display());
+        rebuild_index(vault_path)?;
+    }
+    Ok(())
+}
+
+/// Attachment with the same content as `bytes`, as `attachments/...`, if
+/// the index knows one that still matches on disk
+pub fn find_existing(vault_path: &Path, bytes: &[u8]) -> Option<String> {
+    let index = load_index(vault_path)?;
+    let hash = scanner::compute_hash(bytes);
+    index
+        .files
+        .iter()
+        .filter(|(_, entry)| entry.hash == hash && entry.size_bytes == bytes.len() as u64)
+        .find(|(relative, _)| attachments_dir(vault_path).join(relative).is_file())
+        .map(|(relative, _)| format!("attachments/{}", relative))
+}
+
+/// Add a newly written attachment to the index, building the index first
+/// if the vault has none yet
+pub fn record(vault_path: &Path, filename: &str, bytes: &[u8]) -> Result<(), String> {
+    let Some(mut index) = load_index(vault_path) else {
+        return rebuild_index(vault_path).map(|_| ());
+    };
+    let modified_at = fs::metadata(attachments_dir(vault_path).join(filename))
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    index.files.insert(
+        filename.to_string(),
+        AttachmentEntry {
+            hash: scanner::compute_hash(bytes),
+            size_bytes: bytes.len() as u64,
+            modified_at,
+        },
+    );
+    save_index(vault_path, &index)
+}
+
+/// Count the notes referencing each attachment, including attachments no
+/// note references. Fails if a note can't be read, e.g. because the vault
+/// is locked, since a missed reference could get a used file deleted.
+pub fn reference_counts(vault_path: &Path, encryption: &LocalEncryption) -> Result<Vec<AttachmentReferences>, String> {
+    let mut references: BTreeMap<String, Vec<String>> = vault::collect_attachment_paths(vault_path)?
+        .into_iter()
+        .map(|path| (relative_to(vault_path, &path), Vec::new()))
+        .collect();
+
+    let mut notes = vault::collect_note_paths(vault_path)?;
+    let trash = vault_path.join(trash::TRASH_DIR);
+    if trash.is_dir() {
+        notes.extend(vault::collect_note_paths(&trash)?);
+    }
+
+    let vault_key = vault_path.to_string_lossy();
+    for note in notes {
+        let bytes = fs::read(&note).map_err(|e| format!("Cannot read {}: {}", note.display(), e))?;
+        let content = encryption
+            .decode(&vault_key, String::from_utf8_lossy(&bytes).into_owned())
+            .map_err(|e| format!("Cannot read {}: {}", note.display(), e))?;
+        let note = relative_to(vault_path, &note);
+        for reference in parse_attachment_refs(&content) {
+            if let Some(notes) = references.get_mut(&reference.path) {
+                if !notes.contains(&note) {
+                    notes.push(note.clone());
+                }
+            }
+        }
+    }
+
+    Ok(references
+        .into_iter()
+        .map(|(path, mut notes)| {
+            notes.sort();
+            AttachmentReferences {
+                path,
+                count: notes.len(),
+                notes,
+            }
+        })
+        .collect())
+}
+
+/// Delete the attachments no note references, keeping the index in step
+pub fn cleanup_orphans(vault_path: &Path, encryption: &LocalEncryption, dry_run: bool) -> Result<OrphanCleanup, String> {
+    let mut cleanup = OrphanCleanup::default();
+    for attachment in reference_counts(vault_path, encryption)? {
+        if attachment.count > 0 {
+            continue;
+        }
+        let path = vault_path.join(&attachment.path);
+        let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if !dry_run {
+            fs::remove_file(&path).map_err(|e| format!("Cannot delete {}: {}", attachment.path, e))?;
+        }
+        cleanup.freed_bytes += size;
+        cleanup.deleted.push(attachment.path);
+    }
+
+    if !dry_run && !cleanup.deleted.is_empty() {
+        if let Some(mut index) = load_index(vault_path) {
+            for deleted in &cleanup.deleted {
+                index.files.remove(deleted.trim_start_matches("attachments/"));
+            }
+            save_index(vault_path, &index)?;
+        }
+        println!(
+            "[Attachments] Deleted {} unreferenced attachments from {}",
+            cleanup.deleted.len(),
+            vault_path.display()
+        );
+    }
+    Ok(cleanup)
+}
+
+/// Content-addressed file name for an attachment: the hash of its bytes,
+/// followed by the (lowercased) extension if there is one
+pub fn content_addressed_name(bytes: &[u8], extension: &str) -> String {
+    let hash = scanner::compute_hash(bytes);
+    let extension = extension.trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        hash
+    } else {
+        format!("{}.{}", hash, extension)
+    }
+}
+
+/// `relative` (inside the attachments folder) or, if that is taken by
+/// another file, the same name with the first free `-<n>` suffix
+fn free_name(dir: &Path, relative: &str) -> String {
+    let (stem, extension) = match relative.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() && !extension.contains('/') => (stem, Some(extension)),
+        _ => (relative, None),
+    };
+    let mut name = relative.to_string();
+    let mut suffix = 0;
+    while dir.join(&name).exists() {
+        suffix += 1;
+        name = match extension {
+            Some(extension) => format!("{}-{}.{}", stem, suffix, extension),
+            None => format!("{}-{}", stem, suffix),
+        };
+    }
+    name
+}
+
+/// Copy an attachment of another vault (a decoded `attachments/...`
+/// reference) into this vault and return the reference to use here. A file
+/// with the same content already in `vault_path` is reused; otherwise the
+/// copy keeps its name (content-addressed if `content_addressed`), with a
+/// suffix if the name is taken.
+pub fn copy_from(source_vault: &Path, reference: &str, vault_path: &Path, content_addressed: bool) -> Result<String, String> {
+    let source = vault::resolve_in_vault(source_vault, reference)?;
+    let bytes = fs::read(&source).map_err(|e| format!("{}: {}", reference, e))?;
+    if let Some(existing) = find_existing(vault_path, &bytes) {
+        return Ok(existing);
+    }
+
+    let relative = reference.strip_prefix("attachments/").unwrap_or(reference);
+    let dir = attachments_dir(vault_path);
+    let filename = if content_addressed {
+        let extension = Path::new(relative).extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        content_addressed_name(&bytes, &extension)
+    } else {
+        free_name(&dir, relative)
+    };
+    let target = vault::long_path(dir.join(&filename));
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    fs::write(&target, &bytes).map_err(|e| e.to_string())?;
+    if let Err(e) = record(vault_path, &filename, &bytes) {
+        eprintln!("[Attachments] Failed to update the index: {}", e);
+    }
+    Ok(format!("attachments/{}", filename))
+}
+
+/// Replace the attachment targets found in `map` (old -> new, both
+/// relative to the vault). Returns `None` if nothing was replaced.
+pub fn rewrite_refs(content: &str, map: &BTreeMap<String, String>) -> Option<String> {
+    rewrite_refs_with(content, |path| map.get(path).cloned())
+}
+
+/// Rename every attachment to its content-addressed name and rewrite the
+/// notes referencing it. New files are written and notes rewritten before
+/// any old file is removed, so an interruption never leaves a reference
+/// without its file; running it again finishes the job. Fails before
+/// changing anything if a note can't be read (e.g. the vault is locked).
+pub fn migrate_to_content_addressed(vault_path: &Path, encryption: &LocalEncryption) -> Result<AttachmentMigration, String> {
+    let mut migration = AttachmentMigration::default();
+    let dir = attachments_dir(vault_path);
+    let mut old_files = Vec::new();
+    let mut case_renames = Vec::new();
+    for path in vault::collect_attachment_paths(vault_path)? {
+        let bytes = fs::read(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let extension = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        let name = content_addressed_name(&bytes, &extension);
+        let target = dir.join(&name);
+        if target == path {
+            continue;
+        }
+        // Only the case of the extension differs: on a case-insensitive
+        // filesystem the target is this very file, so it must be renamed,
+        // never removed as a duplicate of itself
+        if target.to_string_lossy().eq_ignore_ascii_case(&path.to_string_lossy()) {
+            case_renames.push((path.clone(), target));
+        } else {
+            if !target.is_file() {
+                fs::write(&target, &bytes).map_err(|e| format!("Cannot write {}: {}", name, e))?;
+            } else {
+                migration.deduplicated += 1;
+            }
+            old_files.push(path.clone());
+        }
+        migration.renamed.insert(relative_to(vault_path, &path), format!("attachments/{}", name));
+    }
+    if migration.renamed.is_empty() {
+        return Ok(migration);
+    }
+
+    let mut notes = vault::collect_note_paths(vault_path)?;
+    let trash = vault_path.join(trash::TRASH_DIR);
+    if trash.is_dir() {
+        notes.extend(vault::collect_note_paths(&trash)?);
+    }
+    let vault_key = vault_path.to_string_lossy();
+    let mut rewrites = Vec::new();
+    for note in notes {
+        let stored = fs::read_to_string(&note).map_err(|e| format!("Cannot read {}: {}", note.display(), e))?;
+        let content = encryption
+            .decode(&vault_key, stored)
+            .map_err(|e| format!("Cannot read {}: {}", note.display(), e))?;
+        if let Some(rewritten) = rewrite_refs(&content, &migration.renamed) {
+            rewrites.push((note, encryption.encode(&vault_key, &rewritten)?));
+        }
+    }
+    for (note, content) in rewrites {
+        fs::write(&note, content).map_err(|e| format!("Cannot write {}: {}", note.display(), e))?;
+        migration.notes_updated += 1;
+    }
+
+    for path in old_files {
+        if let Err(e) = fs::remove_file(&path) {
+            eprintln!("[Attachments] Failed to remove {}: {}", path.display(), e);
+        }
+    }
+    for (path, target) in case_renames {
+        // Through a temp name, since some filesystems ignore a rename that
+        // only changes case
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        let staged = path.with_file_name(format!(".{}.echopad-rename", name));
+        if let Err(e) = fs::rename(&path, &staged).and_then(|()| fs::rename(&staged, &target)) {
+            eprintln!("[Attachments] Failed to rename {}: {}", path.display(), e);
+        }
+    }
+    rebuild_index(vault_path)?;
+    println!(
+        "[Attachments] Renamed {} attachments by content in {} ({} notes updated)",
+        migration.renamed.len(),
+        vault_path.display(),
+        migration.notes_updated
+    );
+    Ok(migration)
+}
+
+fn relative_to(vault_path: &Path, path: &Path) -> String {
+    path.strip_prefix(vault_path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// List every attachment with the notes referencing it
+#[tauri::command]
+pub fn reference_count_attachments(
+    app: AppHandle,
+    encryption: State<'_, LocalEncryption>,
+    pending: State<'_, PendingSaves>,
+    vault_path: String,
+) -> Result<Vec<AttachmentReferences>, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault does not exist".to_string());
+    }
+    // Held-back saves may add or drop references
+    let (_, errors) = pending.flush_all(&app);
+    if !errors.is_empty() {
+        return Err(format!("Failed to save: {}", errors.join("; ")));
+    }
+    reference_counts(&vault, &encryption)
+}
+
+/// Delete attachments that no note (including notes in the trash)
+/// references. Unless `dry_run` is `false`, only report what would be
+/// deleted.
+#[tauri::command]
+pub fn cleanup_orphan_attachments(
+    app: AppHandle,
+    encryption: State<'_, LocalEncryption>,
+    pending: State<'_, PendingSaves>,
+    vault_path: String,
+    dry_run: Option<bool>,
+) -> Result<OrphanCleanup, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault does not exist".to_string());
+    }
+    let dry_run = dry_run.unwrap_or(true);
+    if !dry_run {
+        vault::ensure_writable(&vault)?;
+    }
+    // A note waiting to be saved may already reference a new attachment
+    let (_, errors) = pending.flush_all(&app);
+    if !errors.is_empty() {
+        return Err(format!("Failed to save: {}", errors.join("; ")));
+    }
+    cleanup_orphans(&vault, &encryption, dry_run)
+}
+
+/// Rename a vault's attachments to content-addressed names, rewriting the
+/// references in its notes
+#[tauri::command]
+pub fn migrate_attachments_to_content_addressed(
+    app: AppHandle,
+    encryption: State<'_, LocalEncryption>,
+    pending: State<'_, PendingSaves>,
+    vault_path: String,
+) -> Result<AttachmentMigration, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault does not exist".to_string());
+    }
+    vault::ensure_writable(&vault)?;
+    // Notes are rewritten on disk, so held-back saves must land first
+    let (_, errors) = pending.flush_all(&app);
+    if !errors.is_empty() {
+        return Err(format!("Failed to save: {}", errors.join("; ")));
+    }
+    migrate_to_content_addressed(&vault, &encryption)
+}
+
+/// Rehash every attachment in a vault and rewrite `attachments/.index.json`
+#[tauri::command]
+pub fn rebuild_attachment_index(vault_path: String) -> Result<AttachmentIndexRebuild, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault does not exist".to_string());
+    }
+    rebuild_index(&vault)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebuild_finds_duplicates_and_detects_drift() {
+        let vault = std::env::temp_dir().join(format!("echopad-attachments-{}", uuid::Uuid::new_v4()));
+        let dir = attachments_dir(&vault);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.png"), b"same").unwrap();
+        fs::write(dir.join("b.png"), b"same").unwrap();
+        fs::write(dir.join("c.png"), b"other").unwrap();
+
+        assert!(!index_is_consistent(&vault).unwrap());
+        let rebuild = rebuild_index(&vault).unwrap();
+        assert_eq!(rebuild.file_count, 3);
+        assert_eq!(rebuild.duplicates.len(), 1);
+        assert_eq!(rebuild.duplicates[0].files, vec!["attachments/a.png", "attachments/b.png"]);
+        assert!(index_is_consistent(&vault).unwrap());
+        assert_eq!(find_existing(&vault, b"other").as_deref(), Some("attachments/c.png"));
+
+        fs::remove_file(dir.join("c.png")).unwrap();
+        assert!(!index_is_consistent(&vault).unwrap());
+        assert_eq!(find_existing(&vault, b"other"), None);
+
+        let _ = fs::remove_dir_all(vault);
+    }
+
+    #[test]
+    fn test_cleanup_keeps_attachment_shared_by_two_notes() {
+        let vault = std::env::temp_dir().join(format!("echopad-attachments-{}", uuid::Uuid::new_v4()));
+        let dir = attachments_dir(&vault);
+        fs::create_dir_all(&dir).unwrap();
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        fs::write(dir.join("shared.png"), b"shared").unwrap();
+        fs::write(dir.join("orphan.png"), b"orphan").unwrap();
+        fs::write(vault.join("Notes/original.md"), "![](attachments/shared.png)").unwrap();
+        fs::write(vault.join("Notes/copy.md"), "![](attachments/shared.png)").unwrap();
+        let encryption = LocalEncryption::new();
+
+        let counts = reference_counts(&vault, &encryption).unwrap();
+        let shared = counts.iter().find(|a| a.path == "attachments/shared.png").unwrap();
+        assert_eq!(shared.count, 2);
+        assert_eq!(shared.notes, vec!["Notes/copy.md", "Notes/original.md"]);
+
+        // Deleting one of the notes leaves the attachment referenced once
+        fs::remove_file(vault.join("Notes/original.md")).unwrap();
+        let cleanup = cleanup_orphans(&vault, &encryption, false).unwrap();
+        assert_eq!(cleanup.deleted, vec!["attachments/orphan.png"]);
+        assert!(dir.join("shared.png").is_file());
+        assert!(!dir.join("orphan.png").exists());
+
+        let _ = fs::remove_dir_all(vault);
+    }
+
+    #[test]
+    fn test_parse_attachment_refs() {
+        let content = "![](attachments/a.png) [doc](<attachments/my doc.pdf> \"title\")\n\
+            ![[attachments/b c.png|300]] [[attachments/d.pdf#page=2]] ![](attachments/e%20f.png?v=1)\n\
+            <img src=\"attachments/g.png\"> <attachments/h.png>\n\
+            ![](https://example.com/attachments/x.png) [[Notes/attachments/y.png]] attachments/z.png";
+        let paths: Vec<String> = parse_attachment_refs(content).into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "attachments/a.png",
+                "attachments/my doc.pdf",
+                "attachments/b c.png",
+                "attachments/d.pdf",
+                "attachments/e f.png",
+                "attachments/g.png",
+                "attachments/h.png",
+            ]
+        );
+
+        let map = BTreeMap::from([
+            ("attachments/a.png".to_string(), "attachments/1.png".to_string()),
+            ("attachments/b c.png".to_string(), "attachments/2.png".to_string()),
+            ("attachments/e f.png".to_string(), "attachments/3 4.png".to_string()),
+        ]);
+        let content = "![](attachments/a.png) ![](attachments/a.png.bak) ![[attachments/b c.png|300]] \
+            ![](attachments/e%20f.png?v=1) ![](https://example.com/attachments/a.png)";
+        assert_eq!(
+            rewrite_refs(content, &map).unwrap(),
+            "![](attachments/1.png) ![](attachments/a.png.bak) ![[attachments/2.png|300]] \
+            ![](attachments/3%204.png?v=1) ![](https://example.com/attachments/a.png)"
+        );
+        assert_eq!(rewrite_refs("no links", &map), None);
+    }
+
+    #[test]
+    fn test_references_from_every_note_type_keep_attachments() {
+        let vault = std::env::temp_dir().join(format!("echopad-attachments-{}", uuid::Uuid::new_v4()));
+        let dir = attachments_dir(&vault);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("in txt.png"), b"one").unwrap();
+        fs::write(dir.join("doc.pdf"), b"two").unwrap();
+        fs::write(vault.join("plain.txt"), "![[attachments/in txt.png|200]]").unwrap();
+        fs::write(vault.join("note.markdown"), "[doc](attachments/doc.pdf#page=3)").unwrap();
+
+        let cleanup = cleanup_orphans(&vault, &LocalEncryption::new(), false).unwrap();
+        assert!(cleanup.deleted.is_empty());
+
+        let _ = fs::remove_dir_all(vault);
+    }
+
+    #[test]
+    fn test_copy_from_reuses_same_content_and_avoids_name_clashes() {
+        let root = std::env::temp_dir().join(format!("echopad-attachments-{}", uuid::Uuid::new_v4()));
+        let (source, target) = (root.join("source"), root.join("target"));
+        fs::create_dir_all(attachments_dir(&source)).unwrap();
+        fs::create_dir_all(attachments_dir(&target)).unwrap();
+        fs::write(attachments_dir(&source).join("a.png"), b"image").unwrap();
+        fs::write(attachments_dir(&source).join("b.png"), b"other").unwrap();
+        fs::write(attachments_dir(&target).join("a.png"), b"different").unwrap();
+        fs::write(attachments_dir(&target).join("existing.png"), b"other").unwrap();
+
+        assert_eq!(copy_from(&source, "attachments/a.png", &target, false).unwrap(), "attachments/a-1.png");
+        assert_eq!(fs::read(attachments_dir(&target).join("a-1.png")).unwrap(), b"image");
+        assert_eq!(copy_from(&source, "attachments/b.png", &target, false).unwrap(), "attachments/existing.png");
+        // Copying again reuses the first copy
+        assert_eq!(copy_from(&source, "attachments/a.png", &target, false).unwrap(), "attachments/a-1.png");
+        assert!(copy_from(&source, "attachments/../../outside.png", &target, false).is_err());
+
+        let _ = fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_migration_renames_by_content_and_rewrites_notes() {
+        let vault = std::env::temp_dir().join(format!("echopad-attachments-{}", uuid::Uuid::new_v4()));
+        let dir = attachments_dir(&vault);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("1700000000000.PNG"), b"same").unwrap();
+        fs::write(dir.join("1700000000001.png"), b"same").unwrap();
+        fs::write(
+            vault.join("note.md"),
+            "![](attachments/1700000000000.PNG) ![](attachments/1700000000001.png) \
+            ![](https://example.com/attachments/1700000000000.PNG)",
+        )
+        .unwrap();
+        let encryption = LocalEncryption::new();
+
+        let migration = migrate_to_content_addressed(&vault, &encryption).unwrap();
+        let name = content_addressed_name(b"same", "png");
+        assert_eq!(migration.renamed.len(), 2);
+        assert_eq!(migration.deduplicated, 1);
+        assert_eq!(migration.notes_updated, 1);
+        assert_eq!(
+            fs::read_to_string(vault.join("note.md")).unwrap(),
+            format!(
+                "![](attachments/{0}) ![](attachments/{0}) ![](https://example.com/attachments/1700000000000.PNG)",
+                name
+            )
+        );
+        assert_eq!(vault::collect_attachment_paths(&vault).unwrap(), vec![dir.join(&name)]);
+
+        // Already migrated
+        assert!(migrate_to_content_addressed(&vault, &encryption).unwrap().renamed.is_empty());
+
+        let _ = fs::remove_dir_all(vault);
+    }
+
+    #[test]
+    fn test_migration_renames_name_differing_only_in_case() {
+        let vault = std::env::temp_dir().join(format!("echopad-attachments-{}", uuid::Uuid::new_v4()));
+        let dir = attachments_dir(&vault);
+        fs::create_dir_all(&dir).unwrap();
+        let name = content_addressed_name(b"image", "png");
+        let upper = name.replace(".png", ".PNG");
+        fs::write(dir.join(&upper), b"image").unwrap();
+        fs::write(vault.join("note.md"), format!("![](attachments/{})", upper)).unwrap();
+
+        let migration = migrate_to_content_addressed(&vault, &LocalEncryption::new()).unwrap();
+        assert_eq!(migration.deduplicated, 0);
+        assert_eq!(fs::read(dir.join(&name)).unwrap(), b"image");
+        assert_eq!(vault::collect_attachment_paths(&vault).unwrap(), vec![dir.join(&name)]);
+        assert_eq!(fs::read_to_string(vault.join("note.md")).unwrap(), format!("![](attachments/{})", name));
+
+        let _ = fs::remove_dir_all(vault);
+    }
+}
@@ -0,0 +1,243 @@
+//! Coalesced note saves for auto-save
+//!
+//! The editor saves on a timer while typing. Writing every one of those to
+//! disk churns the file and, with sync watching the vault, starts a sync per
+//! burst of keystrokes. `save_note_debounced` instead keeps only the latest
+//! content of each note in memory and writes it once the note has been quiet
+//! for the debounce period, or when `flush_pending_saves` is called.
+//!
+//! Until then the held-back content is the note's current content:
+//! `read_note` serves it, and commands that move, rename or delete notes
+//! carry it along or drop it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::{local_crypto, note_cache, note_watch, vault};
+
+/// Quiet period used when the caller doesn't give one
+const DEFAULT_DEBOUNCE_MS: u64 = 1000;
+
+/// Longest a save may be held back
+const MAX_DEBOUNCE_MS: u64 = 60_000;
+
+struct PendingSave {
+    vault_path: String,
+    content: String,
+    /// Bumped by every save of the note; only the timer of the latest one
+    /// writes
+    generation: u64,
+}
+
+/// Note contents waiting to be written, keyed by note path
+#[derive(Default)]
+pub struct PendingSaves {
+    saves: Mutex<HashMap<PathBuf, PendingSave>>,
+    next_generation: AtomicU64,
+}
+
+/// Emitted when a held-back save fails to reach the disk
+#[derive(Debug, Clone, Serialize)]
+pub struct SaveFailed {
+    pub path: String,
+    pub error: String,
+}
+
+impl PendingSaves {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn queue(&self, path: PathBuf, vault_path: String, content: String) -> u64 {
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        self.saves.lock().insert(
+            path,
+            PendingSave {
+                vault_path,
+                content,
+                generation,
+            },
+        );
+        generation
+    }
+
+    /// The save queued as `generation`, wherever its note has moved since,
+    /// unless a later save of the note replaced it
+    fn take_if_latest(&self, generation: u64) -> Option<(PathBuf, PendingSave)> {
+        let mut saves = self.saves.lock();
+        let path = saves
+            .iter()
+            .find(|(_, save)| save.generation == generation)
+            .map(|(path, _)| path.clone())?;
+        saves.remove_entry(&path)
+    }
+
+    /// Content of a note that is waiting to be written
+    pub fn get(&self, path: &Path) -> Option<String> {
+        self.saves.lock().get(path).map(|save| save.content.clone())
+    }
+
+    /// Drop the held-back saves of a note, or of every note in a folder,
+    /// e.g. because it was just saved directly or deleted
+    pub fn discard(&self, path: &Path) {
+        self.saves.lock().retain(|pending, _| !pending.starts_with(path));
+    }
+
+    /// Follow a note, or a folder of notes, to where it was moved, so
+    /// held-back saves are written there instead of failing
+    pub fn rename(&self, from: &Path, to: &Path) {
+        let mut saves = self.saves.lock();
+        let moved: Vec<PathBuf> = saves.keys().filter(|pending| pending.starts_with(from)).cloned().collect();
+        for old in moved {
+            if let (Some(save), Ok(rest)) = (saves.remove(&old), old.strip_prefix(from)) {
+                let new = if rest.as_os_str().is_empty() { to.to_path_buf() } else { to.join(rest) };
+                saves.insert(new, save);
+            }
+        }
+    }
+
+    /// Remove the held-back save of a note and return its content, for a
+    /// command that rewrites the note to build on instead of the older
+    /// content on disk
+    pub fn take(&self, path: &Path) -> Option<String> {
+        self.saves.lock().remove(path).map(|save| save.content)
+    }
+
+    /// Write every held-back save now. Returns how many were written and
+    /// the errors of those that failed.
+    pub fn flush_all<R: Runtime>(&self, app: &AppHandle<R>) -> (usize, Vec<String>) {
+        self.flush_in(app, Path::new(""))
+    }
+
+    /// Write the held-back saves of the notes in `dir` (everything for an
+    /// empty path) now, e.g. before the folder is moved to the trash
+    pub fn flush_in<R: Runtime>(&self, app: &AppHandle<R>, dir: &Path) -> (usize, Vec<String>) {
+        let saves: Vec<(PathBuf, PendingSave)> = {
+            let mut saves = self.saves.lock();
+            let flushed: Vec<PathBuf> = saves.keys().filter(|path| path.starts_with(dir)).cloned().collect();
+            flushed.into_iter().filter_map(|path| saves.remove_entry(&path)).collect()
+        };
+        let mut written = 0;
+        let mut errors = Vec::new();
+        for (path, save) in saves {
+            match write_save(app, &path, &save) {
+                Ok(()) => written += 1,
+                Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        (written, errors)
+    }
+}
+
+/// Write a held-back save the way `update_note` writes a note
+fn write_save<R: Runtime>(app: &AppHandle<R>, path: &Path, save: &PendingSave) -> Result<(), String> {
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+    let encryption = app.state::<local_crypto::LocalEncryption>();
+    let encoded = encryption.encode(&save.vault_path, &save.content)?;
+    app.state::<note_watch::NoteWatches>().record_write(path, encoded.as_bytes());
+    fs::write(path, &encoded).map_err(|e| e.to_string())?;
+    app.state::<note_cache::NoteCache>().invalidate(path);
+    Ok(())
+}
+
+/// Save a note after `debounce_ms` (default 1s) without another save of
+/// it. Returns immediately; a failed write is reported through a
+/// `note-save-failed` event.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn save_note_debounced<R: Runtime>(
+    app: AppHandle<R>,
+    pending: State<'_, PendingSaves>,
+    sync_state: State<'_, crate::sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    content: String,
+    debounce_ms: Option<u64>,
+) -> Result<(), String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+    crate::ensure_materialized(&sync_state, &vault_path, &notebook_path, &filename)?;
+
+    let delay = Duration::from_millis(debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS).min(MAX_DEBOUNCE_MS));
+    let generation = pending.queue(path.clone(), vault_path, content);
+
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        let Some((path, save)) = app.state::<PendingSaves>().take_if_latest(generation) else {
+            return;
+        };
+        if let Err(error) = write_save(&app, &path, &save) {
+            eprintln!("[Notes] Failed to save {}: {}", path.display(), error);
+            let _ = app.emit(
+                "note-save-failed",
+                SaveFailed {
+                    path: vault::display_path(&path),
+                    error,
+                },
+            );
+        }
+    });
+
+    Ok(())
+}
+
+/// Write every held-back save now, e.g. before switching notes or closing
+/// the window. Returns how many notes were written.
+#[tauri::command]
+pub fn flush_pending_saves<R: Runtime>(app: AppHandle<R>, pending: State<'_, PendingSaves>) -> Result<usize, String> {
+    let (written, errors) = pending.flush_all(&app);
+    if errors.is_empty() {
+        Ok(written)
+    } else {
+        Err(format!("Failed to save: {}", errors.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rename_moves_saves_of_a_folder() {
+        let pending = PendingSaves::new();
+        pending.queue(PathBuf::from("/vault/Old/a.md"), "/vault".into(), "a".into());
+        pending.queue(PathBuf::from("/vault/Old/Sub/b.md"), "/vault".into(), "b".into());
+        pending.queue(PathBuf::from("/vault/Older/c.md"), "/vault".into(), "c".into());
+
+        pending.rename(Path::new("/vault/Old"), Path::new("/vault/New"));
+
+        assert_eq!(pending.get(Path::new("/vault/New/a.md")).as_deref(), Some("a"));
+        assert_eq!(pending.get(Path::new("/vault/New/Sub/b.md")).as_deref(), Some("b"));
+        assert_eq!(pending.get(Path::new("/vault/Older/c.md")).as_deref(), Some("c"));
+        assert!(pending.get(Path::new("/vault/Old/a.md")).is_none());
+    }
+
+    #[test]
+    fn test_timer_writes_renamed_save_unless_replaced() {
+        let pending = PendingSaves::new();
+        let first = pending.queue(PathBuf::from("/vault/a.md"), "/vault".into(), "one".into());
+        pending.rename(Path::new("/vault/a.md"), Path::new("/vault/b.md"));
+
+        let (path, save) = pending.take_if_latest(first).unwrap();
+        assert_eq!(path, PathBuf::from("/vault/b.md"));
+        assert_eq!(save.content, "one");
+
+        let second = pending.queue(PathBuf::from("/vault/a.md"), "/vault".into(), "two".into());
+        pending.queue(PathBuf::from("/vault/a.md"), "/vault".into(), "three".into());
+        assert!(pending.take_if_latest(second).is_none());
+        assert_eq!(pending.get(Path::new("/vault/a.md")).as_deref(), Some("three"));
+    }
+}
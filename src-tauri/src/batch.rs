@@ -0,0 +1,157 @@
+//! Batched note operations
+//!
+//! Bulk frontend workflows such as imports or reorganizing notebooks would
+//! otherwise make one IPC round-trip per step. `batch_execute` runs a list of
+//! operations in order through the same functions the individual commands
+//! use, so each operation behaves exactly like its command.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Runtime, State};
+
+use crate::{autosave, local_crypto, note_cache, note_watch, sync, DateFilingConfig, NoteOptions};
+
+/// One operation of a batch, tagged by `op`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOperation {
+    CreateNotebook {
+        name: String,
+        parent_path: Option<String>,
+    },
+    RenameNotebook {
+        old_relative_path: String,
+        new_name: String,
+    },
+    DeleteNotebook {
+        relative_path: String,
+    },
+    CreateNote {
+        notebook_path: String,
+        content: String,
+        #[serde(default)]
+        utc_offset_minutes: Option<i32>,
+    },
+    UpdateNote {
+        notebook_path: String,
+        filename: String,
+        content: String,
+    },
+    TouchNote {
+        notebook_path: String,
+        filename: String,
+    },
+    DeleteNote {
+        notebook_path: String,
+        filename: String,
+    },
+    MoveNote {
+        from: String,
+        to: String,
+        filename: String,
+    },
+}
+
+/// Outcome of one operation, in the order the operations were given
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub index: usize,
+    pub success: bool,
+    /// What the matching single command returns, when it returns anything
+    pub value: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+fn to_value<T: Serialize>(result: Result<T, String>) -> Result<Option<serde_json::Value>, String> {
+    result.and_then(|value| serde_json::to_value(value).map(Some).map_err(|e| e.to_string()))
+}
+
+/// Run a list of note operations in order. Unless `continue_on_error` is
+/// set, the batch stops at the first failure, and operations after it are
+/// left out of the results.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn batch_execute<R: Runtime>(
+    app: AppHandle<R>,
+    encryption: State<'_, local_crypto::LocalEncryption>,
+    cache: State<'_, note_cache::NoteCache>,
+    pending: State<'_, autosave::PendingSaves>,
+    watches: State<'_, note_watch::NoteWatches>,
+    options: State<'_, NoteOptions>,
+    date_filing: State<'_, DateFilingConfig>,
+    sync_state: State<'_, sync::SyncState>,
+    vault_path: String,
+    operations: Vec<BatchOperation>,
+    continue_on_error: Option<bool>,
+) -> Vec<BatchResult> {
+    let continue_on_error = continue_on_error.unwrap_or(false);
+    let vault = PathBuf::from(&vault_path);
+    let mut results = Vec::with_capacity(operations.len());
+
+    for (index, operation) in operations.into_iter().enumerate() {
+        let outcome = match operation {
+            BatchOperation::CreateNotebook { name, parent_path } => {
+                to_value(crate::create_notebook(vault_path.clone(), name, parent_path))
+            }
+            BatchOperation::RenameNotebook { old_relative_path, new_name } => to_value(crate::rename_notebook(
+                sync_state.clone(),
+                pending.clone(),
+                vault_path.clone(),
+                old_relative_path,
+                new_name,
+            )),
+            BatchOperation::DeleteNotebook { relative_path } => {
+                to_value(crate::delete_notebook(
+                    app.clone(),
+                    sync_state.clone(),
+                    pending.clone(),
+                    vault_path.clone(),
+                    relative_path,
+                ))
+            }
+            BatchOperation::CreateNote { notebook_path, content, utc_offset_minutes } => {
+                to_value(crate::create_note(
+                    encryption.clone(),
+                    options.clone(),
+                    date_filing.clone(),
+                    vault_path.clone(),
+                    notebook_path,
+                    content,
+                    utc_offset_minutes,
+                ))
+            }
+            BatchOperation::UpdateNote { notebook_path, filename, content } => to_value(crate::update_note(
+                encryption.clone(),
+                cache.clone(),
+                pending.clone(),
+                watches.clone(),
+                sync_state.clone(),
+                vault_path.clone(),
+                notebook_path,
+                filename,
+                content,
+            )),
+            BatchOperation::TouchNote { notebook_path, filename } => {
+                to_value(crate::touch_note(vault_path.clone(), notebook_path, filename))
+            }
+            BatchOperation::DeleteNote { notebook_path, filename } => {
+                crate::delete_note(cache.clone(), pending.clone(), vault_path.clone(), notebook_path, filename).map(|()| None)
+            }
+            BatchOperation::MoveNote { from, to, filename } => {
+                crate::move_note_file(&sync_state, &pending, &vault, &from, &to, &filename).map(|()| None)
+            }
+        };
+
+        let failed = outcome.is_err();
+        results.push(match outcome {
+            Ok(value) => BatchResult { index, success: true, value, error: None },
+            Err(e) => BatchResult { index, success: false, value: None, error: Some(e) },
+        });
+        if failed && !continue_on_error {
+            break;
+        }
+    }
+
+    results
+}
@@ -0,0 +1,141 @@
+//! Line-level diffs between two versions of a note
+
+use serde::Serialize;
+use similar::{DiffTag, TextDiff};
+
+/// Kind of change a hunk represents
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HunkKind {
+    Unchanged,
+    Added,
+    Removed,
+}
+
+/// A run of consecutive lines with the same kind of change
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffHunk {
+    pub kind: HunkKind,
+    /// First line in `content_a` (1-based); `None` for added lines
+    pub old_start: Option<usize>,
+    /// First line in `content_b` (1-based); `None` for removed lines
+    pub new_start: Option<usize>,
+    /// Lines without their trailing newline
+    pub lines: Vec<String>,
+}
+
+fn hunk_lines(lines: &[&str]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.trim_end_matches(['\n', '\r']).to_string())
+        .collect()
+}
+
+/// Diff two texts line by line. A replaced block is reported as a removed
+/// hunk followed by an added hunk.
+pub fn diff_lines(content_a: &str, content_b: &str) -> Vec<DiffHunk> {
+    let diff = TextDiff::from_lines(content_a, content_b);
+    let old_lines = diff.old_slices();
+    let new_lines = diff.new_slices();
+
+    let mut hunks = Vec::new();
+    for op in diff.ops() {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+
+        if matches!(tag, DiffTag::Equal) {
+            hunks.push(DiffHunk {
+                kind: HunkKind::Unchanged,
+                old_start: Some(old_range.start + 1),
+                new_start: Some(new_range.start + 1),
+                lines: hunk_lines(&old_lines[old_range]),
+            });
+            continue;
+        }
+
+        if !old_range.is_empty() {
+            hunks.push(DiffHunk {
+                kind: HunkKind::Removed,
+                old_start: Some(old_range.start + 1),
+                new_start: None,
+                lines: hunk_lines(&old_lines[old_range]),
+            });
+        }
+        if !new_range.is_empty() {
+            hunks.push(DiffHunk {
+                kind: HunkKind::Added,
+                old_start: None,
+                new_start: Some(new_range.start + 1),
+                lines: hunk_lines(&new_lines[new_range]),
+            });
+        }
+    }
+
+    hunks
+}
+
+/// Compare two note contents and return line-level hunks
+#[tauri::command]
+pub fn diff_notes(content_a: String, content_b: String) -> Vec<DiffHunk> {
+    diff_lines(&content_a, &content_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kind, start lines and lines of a hunk
+    type Summary<'a> = (HunkKind, Option<usize>, Option<usize>, Vec<&'a str>);
+
+    fn summary(hunks: &[DiffHunk]) -> Vec<Summary<'_>> {
+        hunks
+            .iter()
+            .map(|h| (h.kind, h.old_start, h.new_start, h.lines.iter().map(String::as_str).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_input_is_one_unchanged_hunk() {
+        let hunks = diff_lines("a\nb\n", "a\nb\n");
+        assert_eq!(summary(&hunks), vec![(HunkKind::Unchanged, Some(1), Some(1), vec!["a", "b"])]);
+        assert!(diff_lines("", "").is_empty());
+    }
+
+    #[test]
+    fn test_inserted_lines() {
+        let hunks = diff_lines("a\nc\n", "a\nb\nc\n");
+        assert_eq!(
+            summary(&hunks),
+            vec![
+                (HunkKind::Unchanged, Some(1), Some(1), vec!["a"]),
+                (HunkKind::Added, None, Some(2), vec!["b"]),
+                (HunkKind::Unchanged, Some(2), Some(3), vec!["c"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deleted_lines() {
+        let hunks = diff_lines("a\nb\nc\n", "a\n");
+        assert_eq!(
+            summary(&hunks),
+            vec![
+                (HunkKind::Unchanged, Some(1), Some(1), vec!["a"]),
+                (HunkKind::Removed, Some(2), None, vec!["b", "c"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replaced_lines_are_removed_then_added() {
+        let hunks = diff_lines("a\r\nb\r\nc\r\n", "a\r\nB\r\nc\r\n");
+        assert_eq!(
+            summary(&hunks),
+            vec![
+                (HunkKind::Unchanged, Some(1), Some(1), vec!["a"]),
+                (HunkKind::Removed, Some(2), None, vec!["b"]),
+                (HunkKind::Added, None, Some(2), vec!["B"]),
+                (HunkKind::Unchanged, Some(3), Some(3), vec!["c"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Duplicate note detection for the "clean up duplicates" tool
+//!
+//! Notes are grouped by the same BLAKE3 content hashes the sync scanner
+//! computes, so copy-pasted or double-imported notes show up as one group.
+//! Optionally only the body after the frontmatter is compared, which catches
+//! copies whose `created`/`id` fields were rewritten on import.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::local_crypto::LocalEncryption;
+use crate::sync::scanner;
+use crate::vault;
+
+/// One copy of a duplicated note
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateNote {
+    /// Note path relative to the vault
+    pub relative_path: String,
+    /// Notebook containing the note, relative to the vault ("" for the root)
+    pub notebook: String,
+    pub size_bytes: u64,
+}
+
+/// Notes sharing the same content
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub content_hash: String,
+    pub notes: Vec<DuplicateNote>,
+    /// Bytes that would be freed by keeping only one copy
+    pub wasted_bytes: u64,
+}
+
+/// Whether a scanned file is a note the app shows
+fn is_note(relative_path: &str) -> bool {
+    let mut parts: Vec<&str> = relative_path.split('/').collect();
+    let Some(name) = parts.pop() else { return false };
+    vault::has_extension_in(Path::new(name), vault::NOTE_EXTENSIONS) && !parts.iter().any(|dir| vault::is_skipped_dir(dir))
+}
+
+/// Group the vault's notes by content. With `body_only`, frontmatter is
+/// ignored when comparing; encrypted notes are compared by their decrypted
+/// body, and skipped while the vault is locked. Groups are sorted by wasted
+/// space, largest first.
+pub fn find_duplicates(
+    encryption: &LocalEncryption,
+    vault_path: &Path,
+    body_only: bool,
+) -> Result<Vec<DuplicateGroup>, String> {
+    let vault_key = vault_path.to_string_lossy();
+    let scan = scanner::scan_vault(vault_path).map_err(|e| e.to_string())?;
+
+    let mut by_hash: HashMap<String, Vec<DuplicateNote>> = HashMap::new();
+    for info in scan.files.values() {
+        if !is_note(&info.relative_path) {
+            continue;
+        }
+
+        let content_hash = if body_only {
+            let path: PathBuf = vault_path.join(&info.relative_path);
+            let content = fs::read_to_string(vault::long_path(&path))
+                .map_err(|e| e.to_string())
+                .and_then(|content| encryption.decode(&vault_key, content));
+            let Ok(content) = content else {
+                continue;
+            };
+            scanner::compute_hash(vault::strip_frontmatter(&content).as_bytes())
+        } else {
+            info.content_hash.clone()
+        };
+
+        let notebook = info
+            .relative_path
+            .rsplit_once('/')
+            .map(|(dir, _)| dir.to_string())
+            .unwrap_or_default();
+        by_hash.entry(content_hash).or_default().push(DuplicateNote {
+            relative_path: info.relative_path.clone(),
+            notebook,
+            size_bytes: info.size_bytes,
+        });
+    }
+
+    let mut groups: Vec<DuplicateGroup> = by_hash
+        .into_iter()
+        .filter(|(_, notes)| notes.len() > 1)
+        .map(|(content_hash, mut notes)| {
+            notes.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+            let total: u64 = notes.iter().map(|n| n.size_bytes).sum();
+            let largest = notes.iter().map(|n| n.size_bytes).max().unwrap_or(0);
+            DuplicateGroup {
+                content_hash,
+                notes,
+                wasted_bytes: total - largest,
+            }
+        })
+        .collect();
+
+    groups.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then_with(|| a.notes[0].relative_path.cmp(&b.notes[0].relative_path))
+    });
+    Ok(groups)
+}
+
+/// Find notes with identical content, optionally ignoring frontmatter
+#[tauri::command]
+pub fn find_duplicate_notes(
+    encryption: State<'_, LocalEncryption>,
+    vault_path: String,
+    body_only: Option<bool>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    let path = PathBuf::from(&vault_path);
+    if !path.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+
+    find_duplicates(&encryption, &path, body_only.unwrap_or(false))
+}
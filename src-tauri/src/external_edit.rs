@@ -0,0 +1,277 @@
+//! "Edit in external editor" support
+//!
+//! A note is copied to a temp folder of this process and opened with the
+//! system's default app. Optionally the temp copy is watched and each save
+//! is written back into the vault once the editor has stopped writing for a
+//! moment. Sessions end with `finish_external_edit`, which removes the temp
+//! copy; folders left by a crash are removed on a later start once stale.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_opener::OpenerExt;
+
+use crate::sync::scanner::compute_hash;
+use crate::{autosave, local_crypto, note_cache, note_watch, vault};
+
+/// Prefix of the folders under the system temp dir holding external edit
+/// copies; each process uses its own, suffixed with its ID
+const TEMP_DIR_PREFIX: &str = "echopad-edit-";
+
+/// Age after which a temp folder of another process counts as left behind
+const STALE_TEMP_DIR_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Quiet time after the last change to the temp copy before it is written
+/// back, so editors that save in several steps aren't copied half-written
+const WRITE_BACK_DELAY: Duration = Duration::from_millis(500);
+
+/// Payload of the `external-note-changed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct ExternalNoteChanged {
+    pub vault_path: String,
+    pub notebook_path: String,
+    pub filename: String,
+}
+
+/// One note opened in an external editor
+struct EditSession {
+    /// Per-session folder containing the temp copy
+    dir: PathBuf,
+    vault_path: String,
+    /// Note in the vault the copy is written back to
+    note_path: PathBuf,
+    /// Hash of the content last copied between the vault and the temp copy
+    synced_hash: Arc<Mutex<String>>,
+    /// Watches the temp copy while write-back is enabled
+    watcher: Option<RecommendedWatcher>,
+}
+
+/// Open external edit sessions, keyed by temp file path
+pub struct ExternalEdits {
+    sessions: Mutex<HashMap<String, EditSession>>,
+}
+
+impl ExternalEdits {
+    /// Create the registry, clearing this process's temp folder and stale
+    /// ones left by earlier runs. Folders of other running instances (or
+    /// other users) are left alone.
+    pub fn new() -> Self {
+        let own = temp_root();
+        if own.exists() {
+            if let Err(e) = fs::remove_dir_all(&own) {
+                eprintln!("[ExternalEdit] Failed to clear {:?}: {}", own, e);
+            }
+        }
+        remove_stale_temp_dirs();
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+/// Temp folder of this process
+fn temp_root() -> PathBuf {
+    std::env::temp_dir().join(format!("{}{}", TEMP_DIR_PREFIX, std::process::id()))
+}
+
+fn remove_stale_temp_dirs() {
+    let Ok(entries) = fs::read_dir(std::env::temp_dir()) else { return };
+    for entry in entries.flatten() {
+        let is_edit_dir = entry.file_name().to_string_lossy().starts_with(TEMP_DIR_PREFIX);
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > STALE_TEMP_DIR_AGE);
+        if is_edit_dir && stale {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// Copy the temp file back into the vault unless it still holds the content
+/// last copied either way (`synced_hash`). Written like a save from the
+/// editor, so it is encrypted in an encrypted vault and replaces a held-back
+/// auto-save.
+fn write_back(
+    app: &AppHandle,
+    vault_path: &str,
+    temp_path: &Path,
+    note_path: &Path,
+    synced_hash: &Mutex<String>,
+) -> Result<bool, String> {
+    let edited = fs::read(temp_path).map_err(|e| e.to_string())?;
+    let hash = compute_hash(&edited);
+    let mut synced_hash = synced_hash.lock();
+    if *synced_hash == hash {
+        return Ok(false);
+    }
+    let content = String::from_utf8(edited).map_err(|_| "Edited note is not valid UTF-8".to_string())?;
+    vault::ensure_writable(Path::new(vault_path))?;
+    crate::write_note_content(
+        &app.state::<local_crypto::LocalEncryption>(),
+        &app.state::<note_cache::NoteCache>(),
+        &app.state::<autosave::PendingSaves>(),
+        &app.state::<note_watch::NoteWatches>(),
+        vault_path,
+        &vault::long_path(note_path),
+        &content,
+    )?;
+    *synced_hash = hash;
+    Ok(true)
+}
+
+/// Watch the temp copy and write saves back into the vault once it has been
+/// quiet for [`WRITE_BACK_DELAY`]. The folder is watched rather than the
+/// file because many editors save by replacing it.
+fn watch_temp_copy(
+    app: AppHandle,
+    vault_path: String,
+    temp_path: PathBuf,
+    note_path: PathBuf,
+    synced_hash: Arc<Mutex<String>>,
+    changed: ExternalNoteChanged,
+) -> Result<RecommendedWatcher, String> {
+    let dir = temp_path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let watched = temp_path.clone();
+    let (tx, rx) = mpsc::channel::<()>();
+
+    // Ends when the watcher, and with it the sender, is dropped
+    std::thread::spawn(move || {
+        while rx.recv().is_ok() {
+            loop {
+                match rx.recv_timeout(WRITE_BACK_DELAY) {
+                    Ok(()) => continue,
+                    Err(mpsc::RecvTimeoutError::Timeout) => break,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+            }
+            match write_back(&app, &vault_path, &temp_path, &note_path, &synced_hash) {
+                Ok(true) => {
+                    println!("[ExternalEdit] Saved changes to {:?}", note_path);
+                    let _ = app.emit("external-note-changed", changed.clone());
+                }
+                Ok(false) => {}
+                // The editor may be in the middle of replacing the file; the
+                // next change retries
+                Err(e) => eprintln!("[ExternalEdit] Failed to write back {:?}: {}", note_path, e),
+            }
+        }
+    });
+
+    let mut watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
+        let Ok(event) = res else { return };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            return;
+        }
+        if event.paths.iter().any(|p| p == &watched) {
+            let _ = tx.send(());
+        }
+    })
+    .map_err(|e| e.to_string())?;
+
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| e.to_string())?;
+    Ok(watcher)
+}
+
+/// Copy a note to a temp file and open it in the default editor. With
+/// `watch`, saves in the editor are written back into the vault and an
+/// `external-note-changed` event is emitted. Returns the temp file path,
+/// which identifies the session for `finish_external_edit`.
+#[tauri::command]
+pub fn export_note_to_temp(
+    app: AppHandle,
+    edits: State<'_, ExternalEdits>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    watch: Option<bool>,
+) -> Result<String, String> {
+    if !vault::is_plain_filename(&filename) {
+        return Err(format!("Invalid note filename: {}", filename));
+    }
+    let note_path = vault::resolve_in_vault(Path::new(&vault_path), &notebook_path)?.join(&filename);
+    if !note_path.is_file() {
+        return Err("Note does not exist".to_string());
+    }
+
+    let content = fs::read(&note_path).map_err(|e| e.to_string())?;
+    if content.starts_with(local_crypto::ENCRYPTED_NOTE_HEADER.as_bytes()) {
+        return Err("Encrypted notes can't be opened in an external editor".to_string());
+    }
+
+    let dir = temp_root().join(uuid::Uuid::new_v4().to_string());
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let temp_path = dir.join(&filename);
+    let synced_hash = Arc::new(Mutex::new(compute_hash(&content)));
+    fs::write(&temp_path, content).map_err(|e| e.to_string())?;
+
+    let watcher = if watch.unwrap_or(false) {
+        let changed = ExternalNoteChanged {
+            vault_path: vault_path.clone(),
+            notebook_path: notebook_path.clone(),
+            filename: filename.clone(),
+        };
+        Some(watch_temp_copy(
+            app.clone(),
+            vault_path.clone(),
+            temp_path.clone(),
+            note_path.clone(),
+            Arc::clone(&synced_hash),
+            changed,
+        )?)
+    } else {
+        None
+    };
+
+    let temp_display = vault::display_path(&temp_path);
+    if let Err(e) = app.opener().open_path(temp_display.clone(), None::<&str>) {
+        let _ = fs::remove_dir_all(&dir);
+        return Err(format!("Failed to open editor: {}", e));
+    }
+
+    edits.sessions.lock().insert(
+        temp_display.clone(),
+        EditSession {
+            dir,
+            vault_path,
+            note_path,
+            synced_hash,
+            watcher,
+        },
+    );
+    Ok(temp_display)
+}
+
+/// End an external edit session: write back any last save (when watching)
+/// and delete the temp copy
+#[tauri::command]
+pub fn finish_external_edit(app: AppHandle, edits: State<'_, ExternalEdits>, temp_path: String) -> Result<(), String> {
+    let session = edits
+        .sessions
+        .lock()
+        .remove(&temp_path)
+        .ok_or("No external edit in progress for that file")?;
+
+    if session.watcher.is_some() {
+        drop(session.watcher);
+        write_back(
+            &app,
+            &session.vault_path,
+            Path::new(&temp_path),
+            &session.note_path,
+            &session.synced_hash,
+        )?;
+    }
+    fs::remove_dir_all(&session.dir).map_err(|e| e.to_string())
+}
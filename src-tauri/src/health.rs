@@ -0,0 +1,193 @@
+//! Vault hygiene report for the diagnostics screen
+//!
+//! One pass over the vault collecting the problems that otherwise show up
+//! piecemeal: undecodable notes, inconsistent line endings (a common source
+//! of sync churn between platforms), notes without frontmatter, orphaned
+//! attachments and links that point nowhere. Encrypted notes are checked
+//! in their decrypted form.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::local_crypto::LocalEncryption;
+use crate::vault;
+
+/// A link in a note whose target doesn't exist
+#[derive(Debug, Clone, Serialize)]
+pub struct BrokenLink {
+    /// Note containing the link, relative to the vault
+    pub note: String,
+    pub target: String,
+}
+
+/// Result of `vault_health_check`. File paths are relative to the vault.
+#[derive(Debug, Default, Serialize)]
+pub struct VaultHealthReport {
+    pub note_count: u32,
+    pub attachment_count: u32,
+    /// Notes that could not be read, or are encrypted and the vault is
+    /// locked; they are left out of every other check
+    pub unreadable_files: Vec<String>,
+    /// Notes that are not valid UTF-8
+    pub non_utf8_files: Vec<String>,
+    /// Number of notes using only `\n` line endings
+    pub lf_count: u32,
+    /// Number of notes using only `\r\n` line endings
+    pub crlf_count: u32,
+    /// Notes mixing both line ending styles
+    pub mixed_line_endings: Vec<String>,
+    pub missing_frontmatter: Vec<String>,
+    /// Files in `attachments/` that no note references. Left empty when
+    /// some notes were unreadable, since their references are unknown.
+    pub unreferenced_attachments: Vec<String>,
+    pub broken_links: Vec<BrokenLink>,
+}
+
+/// Line ending style of a text
+enum LineEndings {
+    None,
+    Lf,
+    Crlf,
+    Mixed,
+}
+
+fn line_endings(content: &str) -> LineEndings {
+    let total = content.matches('\n').count();
+    let crlf = content.matches("\r\n").count();
+    match (total, crlf) {
+        (0, _) => LineEndings::None,
+        (_, 0) => LineEndings::Lf,
+        (t, c) if t == c => LineEndings::Crlf,
+        _ => LineEndings::Mixed,
+    }
+}
+
+/// Targets of markdown links `[text](target)` that point at local files.
+/// URLs, anchors and mail links are left out, as are `attachments/`
+/// references, which are checked separately.
+fn local_link_targets(content: &str) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut rest = content;
+
+    while let Some(idx) = rest.find("](") {
+        let after = &rest[idx + 2..];
+        let Some(end) = after.find(')') else { break };
+        let target = after[..end].split_whitespace().next().unwrap_or("");
+        rest = &after[end..];
+
+        let is_external = target.contains("://")
+            || target.starts_with('#')
+            || target.starts_with("mailto:")
+            || target.starts_with("data:");
+        if !target.is_empty() && !is_external && !target.starts_with("attachments/") {
+            let target = target.split('#').next().unwrap_or(target);
+            targets.push(
+                urlencoding::decode(target)
+                    .map(|t| t.into_owned())
+                    .unwrap_or_else(|_| target.to_string()),
+            );
+        }
+    }
+
+    targets
+}
+
+fn relative_to(vault_path: &Path, path: &Path) -> String {
+    path.strip_prefix(vault_path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Check every note and attachment in the vault
+pub fn check_vault(encryption: &LocalEncryption, vault_path: &Path) -> Result<VaultHealthReport, String> {
+    let vault_key = vault_path.to_string_lossy();
+    let mut report = VaultHealthReport::default();
+    let mut referenced: HashSet<String> = HashSet::new();
+
+    for note_path in vault::collect_note_paths(vault_path)? {
+        report.note_count += 1;
+        let relative = relative_to(vault_path, &note_path);
+
+        let Ok(bytes) = fs::read(&note_path) else {
+            report.unreadable_files.push(relative);
+            continue;
+        };
+        let content = match String::from_utf8(bytes) {
+            Ok(content) => content,
+            Err(_) => {
+                report.non_utf8_files.push(relative);
+                continue;
+            }
+        };
+        let Ok(content) = encryption.decode(&vault_key, content) else {
+            report.unreadable_files.push(relative);
+            continue;
+        };
+
+        match line_endings(&content) {
+            LineEndings::Lf => report.lf_count += 1,
+            LineEndings::Crlf => report.crlf_count += 1,
+            LineEndings::Mixed => report.mixed_line_endings.push(relative.clone()),
+            LineEndings::None => {}
+        }
+
+        if !vault::has_frontmatter(&content) {
+            report.missing_frontmatter.push(relative.clone());
+        }
+
+        for reference in crate::extract_attachment_refs(&content) {
+            if !vault_path.join(&reference).is_file() {
+                report.broken_links.push(BrokenLink {
+                    note: relative.clone(),
+                    target: reference.clone(),
+                });
+            }
+            referenced.insert(reference);
+        }
+
+        let note_dir = note_path.parent().unwrap_or(vault_path);
+        for target in local_link_targets(&content) {
+            let candidates: [PathBuf; 2] = [
+                note_dir.join(&target),
+                vault_path.join(target.trim_start_matches('/')),
+            ];
+            if !candidates.iter().any(|p| p.exists()) {
+                report.broken_links.push(BrokenLink {
+                    note: relative.clone(),
+                    target,
+                });
+            }
+        }
+    }
+
+    for attachment in vault::collect_attachment_paths(vault_path)? {
+        report.attachment_count += 1;
+        let relative = relative_to(vault_path, &attachment);
+        if report.unreadable_files.is_empty() && !referenced.contains(&relative) {
+            report.unreferenced_attachments.push(relative);
+        }
+    }
+
+    report.unreadable_files.sort();
+    report.non_utf8_files.sort();
+    report.mixed_line_endings.sort();
+    report.missing_frontmatter.sort();
+    report.unreferenced_attachments.sort();
+    Ok(report)
+}
+
+/// Report vault hygiene problems in one pass
+#[tauri::command]
+pub fn vault_health_check(encryption: State<'_, LocalEncryption>, vault_path: String) -> Result<VaultHealthReport, String> {
+    let path = PathBuf::from(&vault_path);
+    if !path.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+
+    check_vault(&encryption, &path)
+}
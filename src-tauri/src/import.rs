@@ -0,0 +1,317 @@
+//! Import a folder of markdown files from another tool
+//!
+//! Subfolders become notebooks and each `.md` file becomes a note named by
+//! its modification time, the way the app names notes it creates. The
+//! original file name survives as a heading when the note doesn't start with
+//! one. Local images and PDFs a note links to are copied into `attachments/`
+//! and the links rewritten to point there; links leading out of the source
+//! folder are left alone. Notes are written encrypted in vaults with local
+//! encryption.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::State;
+
+use crate::{local_crypto, vault};
+
+/// Result of `import_markdown_folder`
+#[derive(Debug, Default, Serialize)]
+pub struct ImportSummary {
+    /// Notebooks created (existing ones that received notes aren't counted)
+    pub notebooks: u32,
+    pub notes: u32,
+    pub attachments: u32,
+    /// Source files left out, relative to the source folder, with the reason
+    pub skipped: Vec<String>,
+}
+
+/// Source attachments already copied, mapped to their vault reference, so a
+/// file linked from several notes is copied once
+type CopiedAttachments = HashMap<PathBuf, String>;
+
+fn modified_ms(path: &Path) -> u64 {
+    match vault::modified_at_ms(path) {
+        0 => std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+        ms => ms,
+    }
+}
+
+/// Copy a linked file into `attachments/`, keeping its name unless taken
+fn copy_attachment(vault_path: &Path, source: &Path, copied: &mut CopiedAttachments) -> Result<String, String> {
+    if let Some(reference) = copied.get(source) {
+        return Ok(reference.clone());
+    }
+
+    let attachments = vault_path.join("attachments");
+    fs::create_dir_all(&attachments).map_err(|e| e.to_string())?;
+
+    let stem = source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let ext = source.extension().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+    let mut filename = format!("{}.{}", stem, ext);
+    let mut counter = 1;
+    while attachments.join(&filename).exists() {
+        filename = format!("{}-{}.{}", stem, counter, ext);
+        counter += 1;
+    }
+
+    fs::copy(source, attachments.join(&filename)).map_err(|e| e.to_string())?;
+    let reference = format!("attachments/{}", filename);
+    copied.insert(source.to_path_buf(), reference.clone());
+    Ok(reference)
+}
+
+/// Rewrite markdown links `](target)` that point at local images or PDFs
+/// next to the note, copying each file into the vault's attachments. Only
+/// files inside `source_root` (canonical) are imported.
+fn import_links(
+    content: &str,
+    note_dir: &Path,
+    source_root: &Path,
+    vault_path: &Path,
+    copied: &mut CopiedAttachments,
+) -> Result<String, String> {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(idx) = rest.find("](") {
+        let after = &rest[idx + 2..];
+        let Some(end) = after.find(')') else { break };
+        let raw = after[..end].trim();
+        let target = raw
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        output.push_str(&rest[..idx + 2]);
+        rest = &after[end..];
+
+        let decoded = urlencoding::decode(target)
+            .map(|t| t.into_owned())
+            .unwrap_or_else(|_| target.to_string());
+        let source = fs::canonicalize(note_dir.join(&decoded)).ok().filter(|source| source.starts_with(source_root));
+        let is_attachment = !target.contains("://")
+            && source.as_ref().is_some_and(|source| {
+                crate::mime_type_for_extension(
+                    &source.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default(),
+                ) != "application/octet-stream"
+            });
+
+        if let Some(source) = source.filter(|source| is_attachment && source.is_file()) {
+            let reference = copy_attachment(vault_path, &source, copied)?;
+            output.push_str(&after[..end].replacen(target, &reference, 1));
+        } else {
+            output.push_str(&after[..end]);
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Give a note without a leading heading its original file name as title
+/// and record `created_at` in frontmatter
+fn with_title_and_created_at(content: &str, title: &str, created_at: u64) -> String {
+    if vault::has_frontmatter(content) {
+        return content.to_string();
+    }
+
+    let starts_with_heading = content
+        .trim_start_matches('\u{feff}')
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .is_some_and(|line| line.trim_start().starts_with('#'));
+    let body = if starts_with_heading {
+        content.to_string()
+    } else {
+        format!("# {}\n\n{}", title, content.trim_start_matches('\u{feff}'))
+    };
+    vault::with_created_at_frontmatter(&body, created_at)
+}
+
+/// Import `source_dir` into `target_dir`. `source_root` is the canonical
+/// folder the import started from.
+fn import_dir(
+    encryption: &local_crypto::LocalEncryption,
+    source_root: &Path,
+    source_dir: &Path,
+    target_dir: &Path,
+    vault_path: &Path,
+    copied: &mut CopiedAttachments,
+    summary: &mut ImportSummary,
+) -> Result<(), String> {
+    if !target_dir.exists() {
+        fs::create_dir_all(vault::long_path(target_dir)).map_err(|e| e.to_string())?;
+        summary.notebooks += 1;
+    }
+
+    let mut entries: Vec<PathBuf> = fs::read_dir(source_dir)
+        .map_err(|e| e.to_string())?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        let name = path.file_name().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let relative = path.strip_prefix(source_root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+
+        if path.is_dir() {
+            if !vault::is_skipped_dir(&name) {
+                import_dir(encryption, source_root, &path, &target_dir.join(&name), vault_path, copied, summary)?;
+            }
+            continue;
+        }
+        if !vault::has_extension_in(&path, vault::NOTE_EXTENSIONS) {
+            continue;
+        }
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                summary.skipped.push(format!("{}: {}", relative, e));
+                continue;
+            }
+        };
+
+        let before = copied.len();
+        let content = import_links(&content, source_dir, source_root, vault_path, copied)?;
+        summary.attachments += (copied.len() - before) as u32;
+
+        let created_at = modified_ms(&path);
+        let title = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let note = with_title_and_created_at(&content, &title, created_at);
+        let note = encryption.encode(&vault_path.to_string_lossy(), &note)?;
+        vault::create_note_file(&vault::long_path(target_dir), created_at, note.as_bytes())
+            .map_err(|e| e.to_string())?;
+        summary.notes += 1;
+    }
+
+    Ok(())
+}
+
+/// Import every markdown file under `source_path` into the vault, below
+/// `target_notebook` (the vault root when `None`)
+#[tauri::command]
+pub fn import_markdown_folder(
+    encryption: State<'_, local_crypto::LocalEncryption>,
+    vault_path: String,
+    source_path: String,
+    target_notebook: Option<String>,
+) -> Result<ImportSummary, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+    vault::ensure_writable(&vault)?;
+    let source = PathBuf::from(&source_path);
+    if !source.is_dir() {
+        return Err("Source folder does not exist".to_string());
+    }
+
+    let canonical_vault = fs::canonicalize(&vault).map_err(|e| e.to_string())?;
+    let canonical_source = fs::canonicalize(&source).map_err(|e| e.to_string())?;
+    if canonical_source.starts_with(&canonical_vault) || canonical_vault.starts_with(&canonical_source) {
+        return Err("Source folder overlaps the vault".to_string());
+    }
+
+    let target = vault::resolve_in_vault(&vault, target_notebook.as_deref().unwrap_or(""))?;
+    let mut summary = ImportSummary::default();
+    let mut copied = CopiedAttachments::new();
+    import_dir(&encryption, &canonical_source, &source, &target, &vault, &mut copied, &mut summary)?;
+
+    println!(
+        "[Import] Imported {} notes, {} notebooks and {} attachments from {}",
+        summary.notes, summary.notebooks, summary.attachments, source_path
+    );
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Folders {
+        root: PathBuf,
+        source: PathBuf,
+        vault: PathBuf,
+    }
+
+    impl Folders {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("echopad-import-{}", uuid::Uuid::new_v4()));
+            let (source, vault) = (root.join("source"), root.join("vault"));
+            fs::create_dir_all(source.join("Projects")).unwrap();
+            fs::create_dir_all(&vault).unwrap();
+            Self { root, source, vault }
+        }
+
+        fn import(&self, encryption: &local_crypto::LocalEncryption) -> Result<ImportSummary, String> {
+            let mut summary = ImportSummary::default();
+            let source_root = fs::canonicalize(&self.source).unwrap();
+            import_dir(
+                encryption,
+                &source_root,
+                &self.source,
+                &self.vault,
+                &self.vault,
+                &mut CopiedAttachments::new(),
+                &mut summary,
+            )?;
+            Ok(summary)
+        }
+
+        /// Contents of the notes imported into a notebook
+        fn notes(&self, notebook: &str) -> Vec<String> {
+            vault::collect_note_paths(&self.vault.join(notebook))
+                .unwrap()
+                .into_iter()
+                .map(|path| fs::read_to_string(path).unwrap())
+                .collect()
+        }
+    }
+
+    impl Drop for Folders {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn test_links_outside_the_source_folder_are_not_imported() {
+        let folders = Folders::new();
+        fs::write(folders.source.join("shared.png"), b"shared").unwrap();
+        fs::write(folders.root.join("private.png"), b"private").unwrap();
+        fs::write(
+            folders.source.join("Projects/plan.md"),
+            "# Plan\n![](../shared.png) ![](../../private.png)\n",
+        )
+        .unwrap();
+
+        let summary = folders.import(&local_crypto::LocalEncryption::new()).unwrap();
+
+        assert_eq!((summary.notes, summary.attachments), (1, 1));
+        assert!(folders.vault.join("attachments/shared.png").is_file());
+        assert!(!folders.vault.join("attachments/private.png").exists());
+        let note = &folders.notes("Projects")[0];
+        assert!(note.contains("![](attachments/shared.png) ![](../../private.png)"));
+    }
+
+    #[test]
+    fn test_locked_encrypted_vault_gets_no_plaintext_notes() {
+        let folders = Folders::new();
+        fs::write(folders.source.join("idea.md"), "secret idea").unwrap();
+        fs::write(folders.vault.join(local_crypto::ENCRYPTION_CONFIG_FILENAME), "{}").unwrap();
+
+        assert_eq!(
+            folders.import(&local_crypto::LocalEncryption::new()).unwrap_err(),
+            "Vault is locked"
+        );
+        assert!(folders.notes("").is_empty());
+    }
+}
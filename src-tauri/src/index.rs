@@ -0,0 +1,514 @@
+//! Local SQLite index of notes
+//!
+//! Backs fast search, tag and backlink queries without re-walking the vault
+//! on every request. The index lives in the same `echopad.db` the frontend
+//! opens through the SQL plugin, but its tables are owned by the Rust side
+//! and versioned through the `note_index_schema` table. When sync data is
+//! moved out of the app data directory, the index goes with it into an
+//! `echopad.db` of its own there.
+
+use std::fs;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serde::Serialize;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use sqlx::Row;
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::OnceCell;
+
+use crate::local_crypto;
+use crate::sync::scanner::compute_hash;
+use crate::sync::watcher::{FileChange, VaultWatcher};
+use crate::vault;
+
+/// A single schema migration
+struct Migration {
+    version: i64,
+    description: &'static str,
+    sql: &'static str,
+}
+
+/// Schema migrations, applied in order. Never edit a released migration;
+/// append a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create notes index",
+        sql: "
+            CREATE TABLE IF NOT EXISTS notes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                vault_path TEXT NOT NULL,
+                notebook_path TEXT NOT NULL,
+                filename TEXT NOT NULL,
+                title TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                content_hash TEXT NOT NULL,
+                UNIQUE(vault_path, notebook_path, filename)
+            );
+            CREATE INDEX IF NOT EXISTS idx_notes_notebook ON notes(vault_path, notebook_path);
+            CREATE INDEX IF NOT EXISTS idx_notes_updated_at ON notes(vault_path, updated_at);
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "mark encrypted notes",
+        // Rows indexed before this took the encryption header as the title
+        sql: "
+            ALTER TABLE notes ADD COLUMN encrypted INTEGER NOT NULL DEFAULT 0;
+            UPDATE notes SET encrypted = 1, title = NULL WHERE title = 'echopad-encrypted:v1';
+        ",
+    },
+];
+
+/// Result of reindexing a vault
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexResult {
+    pub indexed: u32,
+    pub removed: u32,
+    /// Notes skipped because they haven't changed since they were indexed
+    pub unchanged: u32,
+    /// Stopped early by `cancel_reindex`; rows of deleted notes are only
+    /// removed by a reindex that runs to completion
+    pub cancelled: bool,
+}
+
+/// Payload of `reindex-progress` events
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexProgress {
+    pub vault_path: String,
+    pub processed: u32,
+    pub total: u32,
+}
+
+/// Notes processed between `reindex-progress` events
+const PROGRESS_INTERVAL: u32 = 50;
+
+/// Database handle shared between commands and the watcher thread
+struct IndexDb {
+    path: PathBuf,
+    pool: SqlitePool,
+    schema: OnceCell<()>,
+}
+
+/// Pool for a database file, connected on first use
+fn open_pool(db_path: &Path) -> SqlitePool {
+    let options = SqliteConnectOptions::new()
+        .filename(db_path)
+        .create_if_missing(true);
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_lazy_with(options)
+}
+
+impl IndexDb {
+    fn open(path: PathBuf) -> Self {
+        Self {
+            pool: open_pool(&path),
+            path,
+            schema: OnceCell::new(),
+        }
+    }
+
+    /// Run pending migrations once per process
+    async fn ensure_schema(&self) -> Result<(), String> {
+        self.schema
+            .get_or_try_init(|| async {
+                sqlx::raw_sql(
+                    "CREATE TABLE IF NOT EXISTS note_index_schema (
+                        version INTEGER PRIMARY KEY,
+                        description TEXT NOT NULL,
+                        applied_at INTEGER NOT NULL
+                    )",
+                )
+                .execute(&self.pool)
+                .await
+                .map_err(|e| e.to_string())?;
+
+                let current: i64 = sqlx::query("SELECT COALESCE(MAX(version), 0) FROM note_index_schema")
+                    .fetch_one(&self.pool)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .get(0);
+
+                for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+                    println!(
+                        "[Index] Applying migration {}: {}",
+                        migration.version, migration.description
+                    );
+                    // The migration and its version row are applied as one
+                    // script so a failure leaves the schema untouched.
+                    let script = format!(
+                        "BEGIN;\n{}\nINSERT INTO note_index_schema (version, description, applied_at) VALUES ({}, '{}', {});\nCOMMIT;",
+                        migration.sql,
+                        migration.version,
+                        migration.description.replace('\'', "''"),
+                        now_ms()
+                    );
+                    if let Err(e) = sqlx::raw_sql(&script).execute(&self.pool).await {
+                        let _ = sqlx::raw_sql("ROLLBACK").execute(&self.pool).await;
+                        return Err(format!("Migration {} failed: {}", migration.version, e));
+                    }
+                }
+
+                Ok::<(), String>(())
+            })
+            .await
+            .map(|_| ())
+    }
+
+    /// Insert or update the row for a single note file. Notes encrypted at
+    /// rest are indexed without a title, since the index has no vault keys.
+    async fn upsert_note(&self, vault_path: &Path, note_path: &Path) -> Result<(), String> {
+        let (notebook_path, filename) = vault::split_note_path(vault_path, note_path)
+            .ok_or("Note is outside the vault")?;
+        let content = fs::read(note_path).map_err(|e| e.to_string())?;
+        let text = String::from_utf8_lossy(&content);
+        let encrypted = local_crypto::is_encrypted_note(&text);
+        let title = if encrypted { None } else { vault::extract_note_title(&text) };
+
+        sqlx::query(
+            "INSERT INTO notes (vault_path, notebook_path, filename, title, encrypted, created_at, updated_at, content_hash)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(vault_path, notebook_path, filename) DO UPDATE SET
+                title = excluded.title,
+                encrypted = excluded.encrypted,
+                created_at = excluded.created_at,
+                updated_at = excluded.updated_at,
+                content_hash = excluded.content_hash",
+        )
+        .bind(vault_path.to_string_lossy().to_string())
+        .bind(notebook_path)
+        .bind(filename)
+        .bind(title)
+        .bind(encrypted)
+        .bind(vault::created_at_from_filename(note_path) as i64)
+        .bind(vault::modified_at_ms(note_path) as i64)
+        .bind(compute_hash(&content))
+        .execute(&self.pool)
+        .await
+        .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Remove the row for a single note file
+    async fn remove_note(&self, vault_path: &Path, note_path: &Path) -> Result<(), String> {
+        let (notebook_path, filename) = vault::split_note_path(vault_path, note_path)
+            .ok_or("Note is outside the vault")?;
+
+        sqlx::query("DELETE FROM notes WHERE vault_path = ? AND notebook_path = ? AND filename = ?")
+            .bind(vault_path.to_string_lossy().to_string())
+            .bind(notebook_path)
+            .bind(filename)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+
+    /// Apply a watcher event. The event kind is only a hint: renames and
+    /// atomic saves produce confusing sequences, so the file's current
+    /// existence decides between upsert and delete.
+    async fn apply_change(&self, change: &FileChange) -> Result<(), String> {
+        let is_note = vault::has_extension_in(Path::new(&change.relative_path), vault::NOTE_EXTENSIONS);
+        if !is_note || change.relative_path.starts_with("attachments/") {
+            return Ok(());
+        }
+
+        self.ensure_schema().await?;
+        let vault_path = PathBuf::from(&change.vault_path);
+        let note_path = vault_path.join(&change.relative_path);
+
+        if note_path.is_file() {
+            self.upsert_note(&vault_path, &note_path).await
+        } else {
+            self.remove_note(&vault_path, &note_path).await
+        }
+    }
+}
+
+/// Note index state managed by Tauri
+pub struct NoteIndex {
+    /// The frontend's `echopad.db`
+    app_pool: SqlitePool,
+    app_db_path: PathBuf,
+    /// Replaced when the index is relocated
+    db: Arc<RwLock<Arc<IndexDb>>>,
+    watcher: VaultWatcher,
+    /// Set by `cancel_reindex` to stop the running reindex
+    cancel_requested: AtomicBool,
+}
+
+impl NoteIndex {
+    /// Create the index in `index_path`, next to the frontend's database at
+    /// `app_db_path` (usually the same file). Connections are opened lazily
+    /// and migrations run on first use.
+    pub fn new(app_db_path: PathBuf, index_path: PathBuf) -> Self {
+        let db = Arc::new(RwLock::new(Arc::new(IndexDb::open(index_path))));
+
+        let watcher = VaultWatcher::new();
+        if let Some(rx) = watcher.take_receiver() {
+            let db = Arc::clone(&db);
+            std::thread::spawn(move || {
+                for change in rx {
+                    let current = Arc::clone(&db.read());
+                    if let Err(e) = tauri::async_runtime::block_on(current.apply_change(&change)) {
+                        eprintln!("[Index] Failed to apply change for {}: {}", change.relative_path, e);
+                    }
+                }
+            });
+        }
+
+        Self {
+            app_pool: open_pool(&app_db_path),
+            app_db_path,
+            db,
+            watcher,
+            cancel_requested: AtomicBool::new(false),
+        }
+    }
+
+    /// The connection pool for the frontend's `echopad.db`
+    pub(crate) fn pool(&self) -> &SqlitePool {
+        &self.app_pool
+    }
+
+    fn db(&self) -> Arc<IndexDb> {
+        Arc::clone(&self.db.read())
+    }
+
+    /// Delete every indexed note
+    pub async fn clear(&self) -> Result<(), String> {
+        let db = self.db();
+        db.ensure_schema().await?;
+        sqlx::query("DELETE FROM notes")
+            .execute(&db.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Move the index to `index_path`. The new index starts out empty and
+    /// is filled by reindexing; the old one is dropped (just its tables if
+    /// it shares the frontend's database). If the new database can't be
+    /// set up, the index stays where it was. Returns what could not be
+    /// cleaned up at the old location.
+    pub async fn relocate(&self, index_path: PathBuf) -> Result<Vec<String>, String> {
+        let new_db = Arc::new(IndexDb::open(index_path));
+        new_db.ensure_schema().await?;
+        let old_db = std::mem::replace(&mut *self.db.write(), new_db);
+
+        let mut errors = Vec::new();
+        if old_db.path == self.app_db_path {
+            if let Err(e) = sqlx::raw_sql("DROP TABLE IF EXISTS notes; DROP TABLE IF EXISTS note_index_schema;")
+                .execute(&old_db.pool)
+                .await
+            {
+                errors.push(format!("Could not clear the old note index: {}", e));
+            }
+            old_db.pool.close().await;
+        } else {
+            old_db.pool.close().await;
+            for suffix in ["", "-wal", "-shm"] {
+                let mut file = old_db.path.clone().into_os_string();
+                file.push(suffix);
+                if let Err(e) = fs::remove_file(&file) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        errors.push(format!("Could not delete {}: {}", Path::new(&file).display(), e));
+                    }
+                }
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Vaults whose rows are kept up to date
+    pub fn watched_vaults(&self) -> Vec<PathBuf> {
+        self.watcher.watched_vaults()
+    }
+
+    /// Watch for dead filesystem watches and restart them, emitting a
+    /// `watcher-restarted` event with the vault path so the frontend can
+    /// reindex anything changed while events were lost
+    pub fn start_watchdog(&self, app: AppHandle) {
+        self.watcher.start_watchdog(move |vault_path| {
+            let _ = app.emit("watcher-restarted", vault_path.to_string_lossy().to_string());
+        });
+    }
+
+    /// Indexed titles of a notebook's notes, keyed by filename, with the
+    /// modification time each row was indexed at so stale rows can be
+    /// told apart. Encrypted notes are left out, as their titles aren't
+    /// indexed. Empty if the index can't be opened.
+    pub async fn cached_titles(&self, vault_path: &Path, notebook_path: &str) -> HashMap<String, (Option<String>, u64)> {
+        let db = self.db();
+        if db.ensure_schema().await.is_err() {
+            return HashMap::new();
+        }
+
+        sqlx::query(
+            "SELECT filename, title, updated_at FROM notes
+             WHERE vault_path = ? AND notebook_path = ? AND encrypted = 0",
+        )
+        .bind(vault_path.to_string_lossy().to_string())
+        .bind(notebook_path)
+        .fetch_all(&db.pool)
+        .await
+        .map(|rows| {
+            rows.iter()
+                .map(|row| (row.get(0), (row.get(1), row.get::<i64, _>(2) as u64)))
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    /// Start keeping a vault's rows up to date from filesystem events
+    fn watch_vault(&self, vault_path: &Path) {
+        if self.watcher.watched_vaults().iter().any(|p| p == vault_path) {
+            return;
+        }
+        if let Err(e) = self.watcher.watch(vault_path) {
+            eprintln!("[Index] Failed to watch {:?}: {}", vault_path, e);
+        }
+    }
+
+    /// Stop the running reindex after the note it is on
+    pub fn cancel_reindex(&self) {
+        self.cancel_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Walk every note in the vault, upsert its row and drop rows for notes
+    /// that no longer exist. Unless `full` is set, notes whose modification
+    /// time matches their row are skipped without being read. `progress` is
+    /// called every [`PROGRESS_INTERVAL`] notes and at the end.
+    pub async fn reindex(
+        &self,
+        vault_path: &Path,
+        full: bool,
+        progress: impl Fn(ReindexProgress),
+    ) -> Result<ReindexResult, String> {
+        let db = self.db();
+        db.ensure_schema().await?;
+        self.cancel_requested.store(false, Ordering::SeqCst);
+
+        let vault_key = vault_path.to_string_lossy().to_string();
+        let note_paths = vault::collect_note_paths(vault_path)?;
+
+        let rows = sqlx::query("SELECT notebook_path, filename, updated_at FROM notes WHERE vault_path = ?")
+            .bind(&vault_key)
+            .fetch_all(&db.pool)
+            .await
+            .map_err(|e| e.to_string())?;
+        let indexed_at: HashMap<(String, String), i64> = rows
+            .iter()
+            .map(|row| ((row.get(0), row.get(1)), row.get(2)))
+            .collect();
+
+        let total = note_paths.len() as u32;
+        let report = |processed: u32| {
+            progress(ReindexProgress {
+                vault_path: vault_key.clone(),
+                processed,
+                total,
+            })
+        };
+
+        let mut result = ReindexResult {
+            indexed: 0,
+            removed: 0,
+            unchanged: 0,
+            cancelled: false,
+        };
+        let mut present = HashSet::new();
+        for (i, note_path) in note_paths.iter().enumerate() {
+            if self.cancel_requested.swap(false, Ordering::SeqCst) {
+                println!("[Index] Reindex of {} cancelled", vault_key);
+                result.cancelled = true;
+                report(i as u32);
+                return Ok(result);
+            }
+
+            let Some(key) = vault::split_note_path(vault_path, note_path) else {
+                continue;
+            };
+            let unchanged = !full
+                && indexed_at.get(&key) == Some(&(vault::modified_at_ms(note_path) as i64));
+            if unchanged {
+                result.unchanged += 1;
+                present.insert(key);
+            } else {
+                match db.upsert_note(vault_path, note_path).await {
+                    Ok(()) => {
+                        result.indexed += 1;
+                        present.insert(key);
+                    }
+                    Err(e) => eprintln!("[Index] Failed to index {:?}: {}", note_path, e),
+                }
+            }
+
+            if (i as u32 + 1) % PROGRESS_INTERVAL == 0 {
+                report(i as u32 + 1);
+            }
+        }
+
+        for key in indexed_at.keys() {
+            if present.contains(key) {
+                continue;
+            }
+            sqlx::query("DELETE FROM notes WHERE vault_path = ? AND notebook_path = ? AND filename = ?")
+                .bind(&vault_key)
+                .bind(&key.0)
+                .bind(&key.1)
+                .execute(&db.pool)
+                .await
+                .map_err(|e| e.to_string())?;
+            result.removed += 1;
+        }
+        report(total);
+
+        self.watch_vault(vault_path);
+
+        Ok(result)
+    }
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Bring the note index for a vault up to date and keep it updated from
+/// then on. Only changed notes are re-read unless `full` is set. Progress is
+/// reported through `reindex-progress` events.
+#[tauri::command]
+pub async fn reindex_vault(
+    app: AppHandle,
+    index: State<'_, NoteIndex>,
+    vault_path: String,
+    full: Option<bool>,
+) -> Result<ReindexResult, String> {
+    let path = PathBuf::from(&vault_path);
+    if !path.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+
+    index
+        .reindex(&path, full.unwrap_or(false), |progress| {
+            let _ = app.emit("reindex-progress", progress);
+        })
+        .await
+}
+
+/// Stop a running `reindex_vault`
+#[tauri::command]
+pub fn cancel_reindex(index: State<'_, NoteIndex>) {
+    index.cancel_reindex();
+}
@@ -2,11 +2,32 @@ use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindowBuilder, menu::{MenuBuilder, MenuItemBuilder}, tray::{TrayIconBuilder, TrayIconEvent}};
+use tauri_plugin_dialog::{DialogExt, MessageDialogKind};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
 
+mod attachments;
+mod autosave;
+mod batch;
+mod diff;
+mod duplicates;
+mod external_edit;
+mod health;
+mod import;
+mod index;
+mod links;
+mod local_crypto;
+mod note_cache;
+mod note_watch;
+mod og_image;
+mod purge;
 mod sync;
+mod trash;
+mod validate;
+mod vault;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Notebook {
@@ -22,12 +43,187 @@ pub struct NoteFile {
     pub filename: String,
     pub content: String,
     pub created_at: u64,
+    /// Set when the file on disk is not valid UTF-8 and `content` is a lossy
+    /// decode; saving it back would corrupt the original bytes
+    #[serde(default)]
+    pub invalid_utf8: bool,
+    /// Notebook the note was created in, when date-based filing put it in
+    /// a dated subfolder of the requested one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notebook_path: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NoteMetadata {
     pub filename: String,
     pub created_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    /// The note holds nothing but whitespace (and maybe frontmatter), so the
+    /// UI shows a placeholder instead of an empty preview
+    #[serde(default)]
+    pub is_empty: bool,
+    /// Display title (frontmatter `title:`, else the first line), only
+    /// filled in when requested with `with_title`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The note's content is still on the sync server; opening it should
+    /// download it with `sync_materialize_file`. Until then saves of it are
+    /// refused.
+    #[serde(default)]
+    pub placeholder: bool,
+}
+
+/// Maximum length of a `list_notes` preview, in characters
+const NOTE_PREVIEW_CHARS: usize = 200;
+
+/// Notes larger than this are never considered empty, so `list_notes`
+/// doesn't have to read them to find out
+const BLANK_NOTE_MAX_BYTES: u64 = 4096;
+
+/// Options for how notes are written
+///
+/// Empty notes are allowed by default and always sync like any other file,
+/// so clearing a note on one device clears it everywhere. With
+/// `reject_empty` set, `create_note` refuses to create a note without
+/// content; updates may still clear an existing note.
+///
+/// With `content_addressed_attachments` set, `save_image` names new
+/// attachments by the hash of their content instead of by timestamp.
+///
+/// `save_image` refuses images larger than `max_image_bytes`, so a runaway
+/// paste can't fill the disk.
+///
+/// The attachment naming and the image size limit are persisted next to the
+/// database, since a vault migrated to content-addressed names should keep
+/// getting them.
+pub struct NoteOptions {
+    path: PathBuf,
+    reject_empty: AtomicBool,
+    content_addressed_attachments: AtomicBool,
+    max_image_bytes: AtomicU64,
+}
+
+/// What `NoteOptions` persists
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PersistedNoteOptions {
+    content_addressed_attachments: bool,
+    max_image_bytes: Option<u64>,
+}
+
+/// Largest image `save_image` writes unless configured otherwise
+const DEFAULT_MAX_IMAGE_BYTES: u64 = 50 * 1024 * 1024;
+
+impl NoteOptions {
+    fn load(path: PathBuf) -> Self {
+        let persisted: PersistedNoteOptions = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            reject_empty: AtomicBool::new(false),
+            content_addressed_attachments: AtomicBool::new(persisted.content_addressed_attachments),
+            max_image_bytes: AtomicU64::new(
+                persisted.max_image_bytes.filter(|&bytes| bytes > 0).unwrap_or(DEFAULT_MAX_IMAGE_BYTES),
+            ),
+        }
+    }
+
+    fn persisted(&self) -> PersistedNoteOptions {
+        PersistedNoteOptions {
+            content_addressed_attachments: self.content_addressed_attachments.load(Ordering::Relaxed),
+            max_image_bytes: Some(self.max_image_bytes.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn save(&self, persisted: &PersistedNoteOptions) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(persisted).map_err(|e| e.to_string())?;
+        fs::write(&self.path, json).map_err(|e| e.to_string())
+    }
+}
+
+/// Refuse to create notes that are empty or whitespace-only
+#[tauri::command]
+fn set_reject_empty_notes(options: tauri::State<'_, NoteOptions>, enabled: bool) {
+    options.reject_empty.store(enabled, Ordering::Relaxed);
+}
+
+/// Name new attachments by their content hash instead of by timestamp
+#[tauri::command]
+fn set_content_addressed_attachments(options: tauri::State<'_, NoteOptions>, enabled: bool) -> Result<(), String> {
+    options.save(&PersistedNoteOptions {
+        content_addressed_attachments: enabled,
+        ..options.persisted()
+    })?;
+    options.content_addressed_attachments.store(enabled, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Largest image, in bytes, `save_image` will write
+#[tauri::command]
+fn set_max_image_size(options: tauri::State<'_, NoteOptions>, bytes: u64) -> Result<(), String> {
+    if bytes == 0 {
+        return Err("Maximum image size must be greater than zero".to_string());
+    }
+    options.save(&PersistedNoteOptions {
+        max_image_bytes: Some(bytes),
+        ..options.persisted()
+    })?;
+    options.max_image_bytes.store(bytes, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Vaults with date-based filing: new notes go into a `YYYY/MM` subfolder
+/// of the notebook they're created in. Persisted next to the database as a
+/// list of vault paths.
+pub struct DateFilingConfig {
+    path: PathBuf,
+    vaults: parking_lot::RwLock<std::collections::HashSet<String>>,
+}
+
+impl DateFilingConfig {
+    fn load(path: PathBuf) -> Self {
+        let vaults = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            vaults: parking_lot::RwLock::new(vaults),
+        }
+    }
+
+    fn is_enabled(&self, vault_path: &str) -> bool {
+        self.vaults.read().contains(vault_path)
+    }
+}
+
+#[tauri::command]
+fn get_date_filing(config: tauri::State<'_, DateFilingConfig>, vault_path: String) -> bool {
+    config.is_enabled(&vault_path)
+}
+
+/// File new notes of a vault into `YYYY/MM` subfolders, or stop doing so
+#[tauri::command]
+fn set_date_filing(
+    config: tauri::State<'_, DateFilingConfig>,
+    vault_path: String,
+    enabled: bool,
+) -> Result<(), String> {
+    let mut vaults = config.vaults.read().clone();
+    if enabled {
+        vaults.insert(vault_path);
+    } else {
+        vaults.remove(&vault_path);
+    }
+    let mut sorted: Vec<&String> = vaults.iter().collect();
+    sorted.sort();
+    let json = serde_json::to_string_pretty(&sorted).map_err(|e| e.to_string())?;
+    fs::write(&config.path, json).map_err(|e| e.to_string())?;
+    *config.vaults.write() = vaults;
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -64,37 +260,85 @@ fn extract_board_title(content: &str) -> Option<String> {
         if line.trim() == "---" {
             break;
         }
-        if line.starts_with("title:") {
-            return Some(line[6..].trim().to_string());
+        if let Some(title) = line.strip_prefix("title:") {
+            return Some(title.trim().to_string());
         }
     }
     None
 }
 
-fn scan_notebooks_recursive(dir_path: &PathBuf, vault_path: &PathBuf) -> Result<Vec<Notebook>, String> {
+/// A folder left out of `list_notebooks` because it could not be read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotebookScanWarning {
+    /// Folder path relative to the vault
+    pub relative_path: String,
+    pub error: String,
+}
+
+/// Result of `list_notebooks`: the notebooks that could be read, plus the
+/// folders that couldn't
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotebookTree {
+    pub notebooks: Vec<Notebook>,
+    pub warnings: Vec<NotebookScanWarning>,
+    /// The vault can be browsed but not changed, e.g. on read-only media
+    pub read_only: bool,
+}
+
+fn relative_notebook_path(path: &Path, vault_path: &Path) -> String {
+    path.strip_prefix(vault_path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Collect the notebooks under `dir_path`. Folders that can't be read are
+/// recorded in `warnings` and skipped, so one locked folder doesn't hide the
+/// rest of the tree.
+fn scan_notebooks_recursive(
+    dir_path: &Path,
+    vault_path: &Path,
+    compare: &dyn Fn(&str, &str) -> std::cmp::Ordering,
+    warnings: &mut Vec<NotebookScanWarning>,
+) -> Vec<Notebook> {
     let mut notebooks = Vec::new();
-    let entries = fs::read_dir(dir_path).map_err(|e| e.to_string())?;
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("[Notebooks] Failed to read {:?}: {}", dir_path, e);
+            warnings.push(NotebookScanWarning {
+                relative_path: relative_notebook_path(dir_path, vault_path),
+                error: e.to_string(),
+            });
+            return notebooks;
+        }
+    };
 
     for entry in entries {
-        let entry = entry.map_err(|e| e.to_string())?;
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("[Notebooks] Failed to read an entry in {:?}: {}", dir_path, e);
+                warnings.push(NotebookScanWarning {
+                    relative_path: relative_notebook_path(dir_path, vault_path),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
         let entry_path = entry.path();
         if entry_path.is_dir() {
             if let Some(name) = entry_path.file_name() {
                 let name_str = name.to_string_lossy().to_string();
                 if !name_str.starts_with('.') && name_str != "attachments" {
-                    let relative_path = entry_path
-                        .strip_prefix(vault_path)
-                        .map_err(|e| e.to_string())?
-                        .to_string_lossy()
-                        .to_string()
-                        .replace('\\', "/");
-                    
-                    let children = scan_notebooks_recursive(&entry_path, vault_path)?;
+                    let relative_path = relative_notebook_path(&entry_path, vault_path);
+
+                    let children = scan_notebooks_recursive(&entry_path, vault_path, compare, warnings);
                     let children_opt = if children.is_empty() { None } else { Some(children) };
                     
                     notebooks.push(Notebook {
                         name: name_str,
-                        path: entry_path.to_string_lossy().to_string(),
+                        path: vault::display_path(&entry_path),
                         relative_path,
                         children: children_opt,
                     });
@@ -103,22 +347,34 @@ fn scan_notebooks_recursive(dir_path: &PathBuf, vault_path: &PathBuf) -> Result<
         }
     }
 
-    notebooks.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()));
-    Ok(notebooks)
+    notebooks.sort_by(|a, b| compare(&a.name, &b.name));
+    notebooks
 }
 
+/// List the vault's notebook tree, each level sorted by name for `locale`
+/// (the system locale by default). Unreadable folders are skipped and
+/// reported as warnings; only an unreadable vault root is an error.
 #[tauri::command]
-fn list_notebooks(vault_path: String) -> Result<Vec<Notebook>, String> {
+fn list_notebooks(vault_path: String, locale: Option<String>) -> Result<NotebookTree, String> {
     let path = PathBuf::from(&vault_path);
     if !path.exists() {
         return Err("Vault path does not exist".to_string());
     }
-
-    scan_notebooks_recursive(&path, &path)
+    fs::read_dir(&path).map_err(|e| e.to_string())?;
+
+    let compare = vault::name_comparator(locale.as_deref());
+    let mut warnings = Vec::new();
+    let notebooks = scan_notebooks_recursive(&path, &path, &compare, &mut warnings);
+    Ok(NotebookTree {
+        notebooks,
+        warnings,
+        read_only: vault::is_read_only(&path),
+    })
 }
 
 #[tauri::command]
 fn create_notebook(vault_path: String, name: String, parent_path: Option<String>) -> Result<Notebook, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
     let vault = PathBuf::from(&vault_path);
     let path = match &parent_path {
         Some(parent) => vault.join(parent).join(&name),
@@ -129,7 +385,7 @@ fn create_notebook(vault_path: String, name: String, parent_path: Option<String>
         return Err("Notebook already exists".to_string());
     }
 
-    fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+    fs::create_dir_all(vault::long_path(&path)).map_err(|e| e.to_string())?;
 
     let relative_path = path
         .strip_prefix(&vault)
@@ -140,14 +396,23 @@ fn create_notebook(vault_path: String, name: String, parent_path: Option<String>
 
     Ok(Notebook {
         name,
-        path: path.to_string_lossy().to_string(),
+        path: vault::display_path(&path),
         relative_path,
         children: None,
     })
 }
 
+/// Rename a notebook folder. Sync state for the notes inside moves with it,
+/// so the next sync pushes the rename instead of unrelated deletes and creates.
 #[tauri::command]
-fn rename_notebook(vault_path: String, old_relative_path: String, new_name: String) -> Result<Notebook, String> {
+fn rename_notebook(
+    sync_state: tauri::State<'_, sync::SyncState>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    old_relative_path: String,
+    new_name: String,
+) -> Result<Notebook, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
     let vault = PathBuf::from(&vault_path);
     let old_path = vault.join(&old_relative_path);
     
@@ -161,7 +426,8 @@ fn rename_notebook(vault_path: String, old_relative_path: String, new_name: Stri
         return Err("A notebook with that name already exists".to_string());
     }
 
-    fs::rename(&old_path, &new_path).map_err(|e| e.to_string())?;
+    fs::rename(vault::long_path(&old_path), vault::long_path(&new_path)).map_err(|e| e.to_string())?;
+    pending.rename(&vault::long_path(&old_path), &vault::long_path(&new_path));
 
     let relative_path = new_path
         .strip_prefix(&vault)
@@ -170,139 +436,1416 @@ fn rename_notebook(vault_path: String, old_relative_path: String, new_name: Stri
         .to_string()
         .replace('\\', "/");
 
+    if let Some(vault_id) = sync_state.state_manager.get_vault_id_for_path(&vault_path) {
+        sync_state
+            .state_manager
+            .rename_path_prefix(&vault_id, &old_relative_path, &relative_path);
+    }
+
     Ok(Notebook {
         name: new_name,
-        path: new_path.to_string_lossy().to_string(),
+        path: vault::display_path(&new_path),
         relative_path,
         children: None,
     })
 }
 
+/// Delete a notebook by moving it to the vault trash, from where it can be
+/// restored until the trash is emptied. Sync is kept from pulling its notes
+/// back in; see `trash` for when the deletes are pushed. Held-back saves of
+/// its notes are written first, so they are restored with it.
 #[tauri::command]
-fn delete_notebook(vault_path: String, relative_path: String) -> Result<(), String> {
-    let path = PathBuf::from(&vault_path).join(&relative_path);
-    if !path.exists() {
-        return Err("Notebook does not exist".to_string());
+fn delete_notebook<R: tauri::Runtime>(
+    app: AppHandle<R>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    relative_path: String,
+) -> Result<trash::TrashEntry, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let dir = vault::long_path(vault::resolve_in_vault(Path::new(&vault_path), &relative_path)?);
+    let (_, errors) = pending.flush_in(&app, &dir);
+    if !errors.is_empty() {
+        return Err(format!("Failed to save: {}", errors.join("; ")));
     }
+    trash::move_to_trash(&sync_state, &vault_path, &relative_path)
+}
 
-    fs::remove_dir_all(&path).map_err(|e| e.to_string())?;
-    Ok(())
+/// Move notebooks holding no notes (see `vault::empty_notebooks`) to the
+/// trash, or with `dry_run` only list them. Returns the pruned paths.
+#[tauri::command]
+fn prune_empty_notebooks(
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    dry_run: bool,
+) -> Result<Vec<String>, String> {
+    let empty = vault::empty_notebooks(Path::new(&vault_path))?;
+    if dry_run {
+        return Ok(empty);
+    }
+
+    vault::ensure_writable(Path::new(&vault_path))?;
+    Ok(empty
+        .into_iter()
+        .filter(|relative_path| match trash::move_to_trash(&sync_state, &vault_path, relative_path) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("[Notebooks] Failed to prune {}: {}", relative_path, e);
+                false
+            }
+        })
+        .collect())
 }
 
+/// List the notes in a notebook. `extensions` narrows which note types are
+/// listed (default: all of `vault::NOTE_EXTENSIONS`); attachment types are
+/// never listed. Notes are ordered by creation time, then by filename for
+/// `locale` (the system locale by default). Titles, previews and the
+/// emptiness check use the decrypted content of encrypted notes; in a locked
+/// vault those notes get none.
 #[tauri::command]
-fn list_notes(vault_path: String, notebook_path: String) -> Result<Vec<NoteMetadata>, String> {
-    let path = PathBuf::from(&vault_path).join(&notebook_path);
+#[allow(clippy::too_many_arguments)]
+async fn list_notes(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    index: tauri::State<'_, index::NoteIndex>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    with_preview: Option<bool>,
+    with_title: Option<bool>,
+    extensions: Option<Vec<String>>,
+    locale: Option<String>,
+) -> Result<Vec<NoteMetadata>, String> {
+    let with_preview = with_preview.unwrap_or(false);
+    let with_title = with_title.unwrap_or(false);
+    let extensions: Vec<&str> = match &extensions {
+        Some(requested) => vault::NOTE_EXTENSIONS
+            .iter()
+            .copied()
+            .filter(|ext| requested.iter().any(|r| r.trim_start_matches('.').eq_ignore_ascii_case(ext)))
+            .collect(),
+        None => vault::NOTE_EXTENSIONS.to_vec(),
+    };
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path));
     if !path.exists() {
         return Err("Notebook does not exist".to_string());
     }
 
+    // Titles of notes unchanged since they were indexed come from the
+    // index; the rest are read from disk
+    let cached_titles = if with_title {
+        let notebook_key = notebook_path.replace('\\', "/").trim_matches('/').to_string();
+        index.cached_titles(Path::new(&vault_path), &notebook_key).await
+    } else {
+        std::collections::HashMap::new()
+    };
+
+    // Placeholders pulled from a remote vault are empty until opened
+    let placeholders = sync_state.state_manager.placeholder_paths(&vault_path);
+    let notebook_prefix = match notebook_path.replace('\\', "/").trim_matches('/') {
+        "" => String::new(),
+        notebook => format!("{}/", notebook),
+    };
+
     let mut notes = Vec::new();
     let entries = fs::read_dir(&path).map_err(|e| e.to_string())?;
 
     for entry in entries {
         let entry = entry.map_err(|e| e.to_string())?;
         let entry_path = entry.path();
-        if entry_path.is_file() {
-            if let Some(ext) = entry_path.extension() {
-                if ext == "md" {
-                    if let Some(filename) = entry_path.file_name() {
-                        let filename_str = filename.to_string_lossy().to_string();
-                        let stem = entry_path.file_stem()
-                            .map(|s| s.to_string_lossy().to_string())
-                            .unwrap_or_default();
-                        let created_at = stem.parse::<u64>().unwrap_or(0);
-                        notes.push(NoteMetadata {
-                            filename: filename_str,
-                            created_at,
-                        });
-                    }
-                }
+        if entry_path.is_file() && vault::has_extension_in(&entry_path, &extensions) {
+            if let Some(filename) = entry_path.file_name() {
+                let filename_str = filename.to_string_lossy().to_string();
+                let created_at = vault::created_at_from_filename(&entry_path);
+                let size = entry.metadata().map(|m| m.len()).ok();
+                let placeholder = !placeholders.is_empty()
+                    && placeholders.contains(&sync::scanner::normalize_path(&format!("{}{}", notebook_prefix, filename_str)));
+                // Placeholders are never shown as empty, and a zero-length
+                // file needs no read to tell it is
+                let check_empty = !placeholder && size.is_some_and(|size| size > 0 && size <= BLANK_NOTE_MAX_BYTES);
+                let cached_title = cached_titles
+                    .get(&filename_str)
+                    .filter(|(_, indexed_at)| *indexed_at == vault::modified_at_ms(&entry_path))
+                    .map(|(title, _)| title.clone());
+                let read_title = with_title && cached_title.is_none();
+                let content = if with_preview || check_empty || read_title {
+                    fs::read(&entry_path)
+                        .ok()
+                        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                        .and_then(|content| encryption.decode(&vault_path, content).ok())
+                } else {
+                    None
+                };
+                let is_empty = !placeholder
+                    && (size == Some(0) || (check_empty && content.as_deref().is_some_and(vault::is_blank_note)));
+                let title = if read_title {
+                    content.as_deref().and_then(vault::extract_note_title)
+                } else {
+                    cached_title.flatten()
+                };
+                let preview = if with_preview {
+                    content.map(|content| vault::note_preview(&content, NOTE_PREVIEW_CHARS))
+                } else {
+                    None
+                };
+                notes.push(NoteMetadata {
+                    filename: filename_str,
+                    created_at,
+                    preview,
+                    is_empty,
+                    title,
+                    placeholder,
+                });
             }
         }
     }
 
-    notes.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    let compare = vault::name_comparator(locale.as_deref());
+    notes.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| compare(&a.filename, &b.filename))
+    });
     Ok(notes)
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecentNote {
+    pub notebook_path: String,
+    pub filename: String,
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub preview: String,
+}
+
+/// List the most recently modified notes across every notebook in the vault.
+/// Previews of encrypted notes come from the decrypted content and are
+/// empty while the vault is locked.
+#[tauri::command]
+fn get_recent_notes(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    vault_path: String,
+    limit: usize,
+) -> Result<Vec<RecentNote>, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+
+    let mut notes: Vec<(u64, PathBuf)> = vault::collect_note_paths(&vault)?
+        .into_iter()
+        .map(|path| (vault::modified_at_ms(&path), path))
+        .collect();
+    notes.sort_by_key(|(modified_at, _)| std::cmp::Reverse(*modified_at));
+    notes.truncate(limit);
+
+    let recent = notes
+        .into_iter()
+        .filter_map(|(modified_at, path)| {
+            let (notebook_path, filename) = vault::split_note_path(&vault, &path)?;
+            let preview = fs::read_to_string(&path)
+                .ok()
+                .and_then(|content| encryption.decode(&vault_path, content).ok())
+                .and_then(|content| vault::extract_note_title(&content))
+                .unwrap_or_default();
+            Some(RecentNote {
+                notebook_path,
+                filename,
+                created_at: vault::created_at_from_filename(&path),
+                modified_at,
+                preview,
+            })
+        })
+        .collect();
+
+    Ok(recent)
+}
+
+/// Order of `list_all_notes`
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSort {
+    /// Newest first
+    #[default]
+    CreatedDesc,
+    CreatedAsc,
+    /// Most recently modified first
+    ModifiedDesc,
+    ModifiedAsc,
+    /// By notebook, then filename
+    Path,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VaultNote {
+    pub notebook_path: String,
+    pub filename: String,
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub size_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+}
+
+/// One page of `list_all_notes`
+#[derive(Debug, Serialize)]
+pub struct NotePage {
+    pub notes: Vec<VaultNote>,
+    /// Notes in the whole vault
+    pub total: usize,
+    pub offset: usize,
+    pub has_more: bool,
+}
+
+/// Default page size of `list_all_notes`
+const ALL_NOTES_PAGE_SIZE: usize = 100;
+
+/// List every note in the vault, across notebooks, one page at a time.
+/// Only file metadata is read, except for the previews of the returned page
+/// with `with_preview`. Previews of encrypted notes are taken from the
+/// decrypted content, and left out while the vault is locked.
+#[tauri::command]
+fn list_all_notes(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    vault_path: String,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    sort: Option<NoteSort>,
+    with_preview: Option<bool>,
+    locale: Option<String>,
+) -> Result<NotePage, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+    let offset = offset.unwrap_or(0);
+    let limit = limit.unwrap_or(ALL_NOTES_PAGE_SIZE);
+
+    let mut notes: Vec<(VaultNote, PathBuf)> = vault::collect_note_paths(&vault)?
+        .into_iter()
+        .filter_map(|path| {
+            let (notebook_path, filename) = vault::split_note_path(&vault, &path)?;
+            let size_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            let note = VaultNote {
+                notebook_path,
+                filename,
+                created_at: vault::created_at_from_filename(&path),
+                modified_at: vault::modified_at_ms(&path),
+                size_bytes,
+                preview: None,
+            };
+            Some((note, path))
+        })
+        .collect();
+
+    let compare = vault::name_comparator(locale.as_deref());
+    let by_path = |a: &VaultNote, b: &VaultNote| {
+        compare(&a.notebook_path, &b.notebook_path).then_with(|| compare(&a.filename, &b.filename))
+    };
+    notes.sort_by(|(a, _), (b, _)| match sort.unwrap_or_default() {
+        NoteSort::CreatedDesc => b.created_at.cmp(&a.created_at).then_with(|| by_path(a, b)),
+        NoteSort::CreatedAsc => a.created_at.cmp(&b.created_at).then_with(|| by_path(a, b)),
+        NoteSort::ModifiedDesc => b.modified_at.cmp(&a.modified_at).then_with(|| by_path(a, b)),
+        NoteSort::ModifiedAsc => a.modified_at.cmp(&b.modified_at).then_with(|| by_path(a, b)),
+        NoteSort::Path => by_path(a, b),
+    });
+
+    let total = notes.len();
+    let page = notes
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(mut note, path)| {
+            if with_preview.unwrap_or(false) {
+                note.preview = fs::read(&path)
+                    .ok()
+                    .and_then(|bytes| encryption.decode(&vault_path, String::from_utf8_lossy(&bytes).into_owned()).ok())
+                    .map(|content| vault::note_preview(&content, NOTE_PREVIEW_CHARS));
+            }
+            note
+        })
+        .collect();
+
+    Ok(NotePage {
+        notes: page,
+        total,
+        offset,
+        has_more: offset.saturating_add(limit) < total,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangedFile {
+    /// Folder relative to the vault (`attachments/...` for attachments)
+    pub notebook_path: String,
+    pub filename: String,
+    pub modified_at: u64,
+    pub is_attachment: bool,
+}
+
+/// List notes and attachments modified after `since_ms`, newest first.
+/// Only filesystem mtimes are compared, so no file content is read.
+#[tauri::command]
+fn notes_changed_since(vault_path: String, since_ms: u64) -> Result<Vec<ChangedFile>, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+
+    let notes = vault::collect_note_paths(&vault)?.into_iter().map(|p| (p, false));
+    let attachments = vault::collect_attachment_paths(&vault)?.into_iter().map(|p| (p, true));
+
+    let mut changed: Vec<ChangedFile> = notes
+        .chain(attachments)
+        .filter_map(|(path, is_attachment)| {
+            let modified_at = vault::modified_at_ms(&path);
+            if modified_at <= since_ms {
+                return None;
+            }
+            let (notebook_path, filename) = vault::split_note_path(&vault, &path)?;
+            Some(ChangedFile {
+                notebook_path,
+                filename,
+                modified_at,
+                is_attachment,
+            })
+        })
+        .collect();
+    changed.sort_by_key(|file| std::cmp::Reverse(file.modified_at));
+
+    Ok(changed)
+}
+
+#[tauri::command]
+fn read_note(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+) -> Result<NoteFile, String> {
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    let metadata = fs::metadata(&path).map_err(|_| "Note does not exist".to_string())?;
+
+    // An auto-save that hasn't been written yet is newer than the file
+    if let Some(content) = pending.get(&path) {
+        return Ok(NoteFile {
+            created_at: vault::created_at_from_filename(&path),
+            filename,
+            content,
+            invalid_utf8: false,
+            notebook_path: None,
+        });
+    }
+
+    let bytes = match cache.get(&path, &metadata) {
+        Some(bytes) => bytes,
+        None => {
+            let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+            cache.insert(&path, &metadata, &bytes);
+            bytes
+        }
+    };
+    let (content, invalid_utf8) = match String::from_utf8(bytes) {
+        Ok(content) => (encryption.decode(&vault_path, content)?, false),
+        Err(e) => (String::from_utf8_lossy(e.as_bytes()).to_string(), true),
+    };
+    let created_at = vault::created_at_from_filename(&path);
+
+    Ok(NoteFile {
+        filename,
+        content,
+        created_at,
+        invalid_utf8,
+        notebook_path: None,
+    })
+}
+
+/// Read a note's raw bytes as base64, for notes that aren't valid UTF-8
+#[tauri::command]
+fn read_note_bytes(
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+) -> Result<String, String> {
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+    ensure_materialized(&sync_state, &vault_path, &notebook_path, &filename)?;
+
+    let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+    Ok(BASE64.encode(bytes))
+}
+
+/// Details about a single note, without its content
+#[derive(Debug, Serialize)]
+pub struct NoteDetails {
+    pub filename: String,
+    pub created_at: u64,
+    pub modified_at: u64,
+    pub size_bytes: u64,
+    /// `None` while the note is encrypted and the vault is locked
+    pub word_count: Option<u32>,
+    /// Whether the note changed since it was last synced; `None` if the
+    /// vault isn't synced
+    pub has_unsynced_changes: Option<bool>,
+}
+
+/// Get one note's metadata without listing its notebook
+#[tauri::command]
+fn get_note_metadata(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+) -> Result<NoteDetails, String> {
+    let path = resolve_note_path(std::path::Path::new(&vault_path), &notebook_path, &filename)?;
+    if !path.is_file() {
+        return Err("Note does not exist".to_string());
+    }
+
+    let metadata = fs::metadata(&path).map_err(|e| e.to_string())?;
+    let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+
+    // Sync tracks the bytes on disk, so compare those rather than decoded
+    // text, the same way the scanner does
+    let relative_path = sync_relative_path(&notebook_path, &filename);
+    let has_unsynced_changes = sync_state.state_manager.get_vault_state(&vault_path).map(|vault_state| {
+        let body_only = vault_state.hash_note_body_only;
+        let info = sync::scanner::FileInfo {
+            content_hash: sync::scanner::compute_hash(&bytes),
+            body_hash: body_only.then(|| sync::scanner::compute_body_hash(&relative_path, &bytes)).flatten(),
+            relative_path: relative_path.clone(),
+            size_bytes: metadata.len(),
+            modified_at: vault::modified_at_ms(&path),
+        };
+        !sync_state
+            .state_manager
+            .get_file_state(&vault_path, &relative_path)
+            .is_some_and(|state| state.matches(&info, body_only))
+    });
+
+    let word_count = String::from_utf8(bytes)
+        .ok()
+        .and_then(|content| encryption.decode(&vault_path, content).ok())
+        .map(|content| vault::word_count(&content));
+
+    Ok(NoteDetails {
+        created_at: vault::created_at_from_filename(&path),
+        modified_at: vault::modified_at_ms(&path),
+        size_bytes: metadata.len(),
+        word_count,
+        has_unsynced_changes,
+        filename,
+    })
+}
+
+/// Write raw base64-encoded bytes to an existing note
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn write_note_bytes(
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    watches: tauri::State<'_, note_watch::NoteWatches>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    data: String,
+) -> Result<(), String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+    ensure_materialized(&sync_state, &vault_path, &notebook_path, &filename)?;
+
+    let bytes = BASE64.decode(&data).map_err(|e| e.to_string())?;
+    // A held-back auto-save is older than these bytes
+    pending.discard(&path);
+    watches.record_write(&path, &bytes);
+    fs::write(&path, &bytes).map_err(|e| e.to_string())?;
+    cache.invalidate(&path);
+    Ok(())
+}
+
+/// Create a note in an existing notebook. With date-based filing on for the
+/// vault, the note goes into the notebook's `YYYY/MM` subfolder for the
+/// current local date (`utc_offset_minutes` east of UTC), created as needed,
+/// and `notebook_path` of the result is that subfolder. Notebooks that are
+/// already such a subfolder aren't filed another level down.
+#[tauri::command]
+fn create_note(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    options: tauri::State<'_, NoteOptions>,
+    date_filing: tauri::State<'_, DateFilingConfig>,
+    vault_path: String,
+    notebook_path: String,
+    content: String,
+    utc_offset_minutes: Option<i32>,
+) -> Result<NoteFile, String> {
+    write_new_note(&encryption, &options, &date_filing, &vault_path, &notebook_path, content, utc_offset_minutes)
+        .map(|(note, _)| note)
+}
+
+/// `create_note`, also telling whether a dated subfolder had to be created
+fn write_new_note(
+    encryption: &local_crypto::LocalEncryption,
+    options: &NoteOptions,
+    date_filing: &DateFilingConfig,
+    vault_path: &str,
+    notebook_path: &str,
+    content: String,
+    utc_offset_minutes: Option<i32>,
+) -> Result<(NoteFile, bool), String> {
+    vault::ensure_writable(Path::new(vault_path))?;
+    if options.reject_empty.load(Ordering::Relaxed) && vault::is_blank_note(&content) {
+        return Err("Note is empty".to_string());
+    }
+
+    let mut full_notebook_path = vault::long_path(PathBuf::from(vault_path).join(notebook_path));
+    if !full_notebook_path.exists() {
+        return Err("Notebook does not exist".to_string());
+    }
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as u64;
+
+    let mut filed_notebook_path = None;
+    let mut folder_created = false;
+    if date_filing.is_enabled(vault_path) && !vault::is_dated_folder(notebook_path) {
+        let dated = vault::dated_folder(timestamp, utc_offset_minutes.unwrap_or(0));
+        full_notebook_path = full_notebook_path.join(&dated);
+        folder_created = !full_notebook_path.is_dir();
+        fs::create_dir_all(&full_notebook_path).map_err(|e| e.to_string())?;
+        filed_notebook_path = Some(match notebook_path.replace('\\', "/").trim_matches('/') {
+            "" => dated,
+            notebook => format!("{}/{}", notebook, dated),
+        });
+    }
+
+    let filename = vault::create_note_file(
+        &full_notebook_path,
+        timestamp,
+        encryption.encode(vault_path, &content)?.as_bytes(),
+    )
+    .map_err(|e| e.to_string())?;
+
+    let note = NoteFile {
+        filename,
+        content,
+        created_at: timestamp,
+        invalid_utf8: false,
+        notebook_path: filed_notebook_path,
+    };
+    Ok((note, folder_created))
+}
+
+/// Result of `create_note_ensure_notebook`
+#[derive(Debug, Serialize)]
+pub struct CapturedNote {
+    pub notebook: Notebook,
+    pub note: NoteFile,
+    /// The notebook (or part of its path) didn't exist before
+    pub notebook_created: bool,
+}
+
+/// Create a note in `notebook_path` (`/`-separated, relative to the vault),
+/// creating the notebook and any parents first. Meant for quick capture:
+/// creating the folders tolerates concurrent creators and the note file is
+/// created exclusively, so two captures at once neither fail nor overwrite
+/// each other. With date-based filing, `notebook` is the dated subfolder the
+/// note went into.
+#[tauri::command]
+fn create_note_ensure_notebook(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    options: tauri::State<'_, NoteOptions>,
+    date_filing: tauri::State<'_, DateFilingConfig>,
+    vault_path: String,
+    notebook_path: String,
+    content: String,
+    utc_offset_minutes: Option<i32>,
+) -> Result<CapturedNote, String> {
+    let notebook_path = notebook_path.replace('\\', "/").trim_matches('/').to_string();
+    vault::validate_notebook_path(&notebook_path)?;
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let vault = PathBuf::from(&vault_path);
+    if !vault.is_dir() {
+        return Err("Vault does not exist".to_string());
+    }
+    if options.reject_empty.load(Ordering::Relaxed) && vault::is_blank_note(&content) {
+        return Err("Note is empty".to_string());
+    }
+
+    let path = vault.join(&notebook_path);
+    let notebook_created = !path.is_dir();
+    fs::create_dir_all(vault::long_path(&path)).map_err(|e| e.to_string())?;
+    let (note, folder_created) =
+        write_new_note(&encryption, &options, &date_filing, &vault_path, &notebook_path, content, utc_offset_minutes)?;
+    let notebook_created = notebook_created || folder_created;
+    let (path, notebook_path) = match &note.notebook_path {
+        Some(filed) => (vault.join(filed), filed.clone()),
+        None => (path, notebook_path),
+    };
+
+    Ok(CapturedNote {
+        notebook: Notebook {
+            name: notebook_path.rsplit('/').next().unwrap_or(&notebook_path).to_string(),
+            path: vault::display_path(&path),
+            relative_path: notebook_path,
+            children: None,
+        },
+        note,
+        notebook_created,
+    })
+}
+
+/// A note's path as sync state is keyed by: vault-relative, with forward
+/// slashes and in NFC
+fn sync_relative_path(notebook_path: &str, filename: &str) -> String {
+    let relative_path = match notebook_path.replace('\\', "/").trim_matches('/') {
+        "" => filename.to_string(),
+        notebook => format!("{}/{}", notebook, filename),
+    };
+    sync::scanner::normalize_path(&relative_path)
+}
+
+/// Refuse to read or write a note that is still a sync placeholder. The
+/// file on disk is an empty stand-in, so an edit would start from nothing;
+/// the note has to be downloaded with `sync_materialize_file` first.
+pub(crate) fn ensure_materialized(
+    sync_state: &sync::SyncState,
+    vault_path: &str,
+    notebook_path: &str,
+    filename: &str,
+) -> Result<(), String> {
+    if sync_state.state_manager.is_placeholder(vault_path, &sync_relative_path(notebook_path, filename)) {
+        return Err("This note hasn't been downloaded yet".to_string());
+    }
+    Ok(())
+}
+
+/// Replace a note's content: drop its held-back auto-save, which is older,
+/// encode it for the vault and keep the note cache and watcher in step.
+/// Callers check that the vault is writable first.
+pub(crate) fn write_note_content(
+    encryption: &local_crypto::LocalEncryption,
+    cache: &note_cache::NoteCache,
+    pending: &autosave::PendingSaves,
+    watches: &note_watch::NoteWatches,
+    vault_path: &str,
+    path: &Path,
+    content: &str,
+) -> Result<(), String> {
+    pending.discard(path);
+    let encoded = encryption.encode(vault_path, content)?;
+    // Recorded first so the watcher never sees the write as external
+    watches.record_write(path, encoded.as_bytes());
+    fs::write(path, &encoded).map_err(|e| e.to_string())?;
+    cache.invalidate(path);
+    Ok(())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn update_note(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    watches: tauri::State<'_, note_watch::NoteWatches>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    content: String,
+) -> Result<NoteFile, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+    ensure_materialized(&sync_state, &vault_path, &notebook_path, &filename)?;
+
+    write_note_content(&encryption, &cache, &pending, &watches, &vault_path, &path, &content)?;
+
+    let created_at = vault::created_at_from_filename(&path);
+
+    Ok(NoteFile {
+        filename,
+        content,
+        created_at,
+        invalid_utf8: false,
+        notebook_path: None,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NoteStat {
+    pub filename: String,
+    pub created_at: u64,
+    pub modified_at: u64,
+}
+
+/// Set a note's modification time to now without touching its content.
+/// Sync compares content hashes, so this never causes a re-upload.
+#[tauri::command]
+fn touch_note(vault_path: String, notebook_path: String, filename: String) -> Result<NoteStat, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+
+    filetime::set_file_mtime(&path, filetime::FileTime::now()).map_err(|e| e.to_string())?;
+
+    Ok(NoteStat {
+        created_at: vault::created_at_from_filename(&path),
+        modified_at: vault::modified_at_ms(&path),
+        filename,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FrontmatterMigration {
+    pub dry_run: bool,
+    /// Notes that were (or, in a dry run, would be) given frontmatter,
+    /// relative to the vault
+    pub changed: Vec<String>,
+    /// Notes left alone because their filename isn't a timestamp
+    pub skipped: Vec<String>,
+}
+
+/// Give every note without frontmatter a `created_at` frontmatter field
+/// derived from its timestamp filename. Notes that already have frontmatter
+/// are never touched, so running this again is a no-op. Encrypted notes are
+/// decrypted to check and edit them and stay encrypted; in a locked vault
+/// they are skipped. Modification times are preserved.
+#[tauri::command]
+fn migrate_notes_to_frontmatter(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    vault_path: String,
+    dry_run: bool,
+) -> Result<FrontmatterMigration, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+    if !dry_run {
+        vault::ensure_writable(&vault)?;
+    }
+
+    let mut migration = FrontmatterMigration {
+        dry_run,
+        changed: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for path in vault::collect_note_paths(&vault)? {
+        let relative = path
+            .strip_prefix(&vault)
+            .unwrap_or(&path)
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        // Leave notes we can't decode alone rather than risk mangling them
+        let content = match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| encryption.decode(&vault_path, content))
+        {
+            Ok(content) => content,
+            Err(_) => {
+                migration.skipped.push(relative);
+                continue;
+            }
+        };
+        if vault::has_frontmatter(&content) {
+            continue;
+        }
+
+        let created_at = vault::created_at_from_filename(&path);
+        if created_at == 0 {
+            migration.skipped.push(relative);
+            continue;
+        }
+
+        if !dry_run {
+            let mtime = fs::metadata(&path)
+                .map(|m| filetime::FileTime::from_last_modification_time(&m))
+                .map_err(|e| e.to_string())?;
+            let migrated = vault::with_created_at_frontmatter(&content, created_at);
+            fs::write(&path, encryption.encode(&vault_path, &migrated)?).map_err(|e| e.to_string())?;
+            filetime::set_file_mtime(&path, mtime).map_err(|e| e.to_string())?;
+        }
+        migration.changed.push(relative);
+    }
+
+    migration.changed.sort();
+    migration.skipped.sort();
+    Ok(migration)
+}
+
+/// Earliest creation time `set_note_created_at` accepts (1980-01-01), which
+/// also catches timestamps given in seconds instead of milliseconds
+const MIN_NOTE_CREATED_AT: u64 = 315_532_800_000;
+
+/// Set a note's creation time, e.g. to keep the original date of an
+/// imported note. `created_at` is recorded in the frontmatter and, unless
+/// `rename` is false, a note named after its creation time is renamed after
+/// the new one, since that's where the app reads it from. Returns the
+/// note's metadata under its (possibly new) filename.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn set_note_created_at(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    watches: tauri::State<'_, note_watch::NoteWatches>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    timestamp_ms: u64,
+    rename: Option<bool>,
+) -> Result<NoteMetadata, String> {
+    let vault = PathBuf::from(&vault_path);
+    vault::ensure_writable(&vault)?;
+    let path = resolve_note_path(&vault, &notebook_path, &filename)?;
+    if !path.is_file() {
+        return Err("Note does not exist".to_string());
+    }
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as u64;
+    // Allow a day of clock skew between devices
+    if timestamp_ms < MIN_NOTE_CREATED_AT || timestamp_ms > now + 24 * 60 * 60 * 1000 {
+        return Err(format!("{} is not a plausible creation time in milliseconds", timestamp_ms));
+    }
+
+    // A held-back auto-save is newer than the note on disk; it is written
+    // now with the new frontmatter instead of bringing back the old one
+    let content = match pending.take(&path) {
+        Some(content) => content,
+        None => {
+            let bytes = fs::read(&path).map_err(|e| e.to_string())?;
+            let content = String::from_utf8(bytes).map_err(|_| "Note is not valid UTF-8".to_string())?;
+            encryption.decode(&vault_path, content)?
+        }
+    };
+    let content = vault::with_frontmatter_value(&content, "created_at", &timestamp_ms.to_string());
+
+    let encoded = encryption.encode(&vault_path, &content)?;
+    watches.record_write(&path, encoded.as_bytes());
+    fs::write(&path, &encoded).map_err(|e| e.to_string())?;
+    cache.invalidate(&path);
+
+    let current = vault::created_at_from_filename(&path);
+    let mut filename = filename;
+    if rename.unwrap_or(true) && current != 0 && current != timestamp_ms {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_string()).unwrap_or_default();
+        let dir = path.parent().ok_or("Note has no notebook")?;
+        filename = vault::free_note_filename(dir, timestamp_ms, &extension);
+        move_note_within(&sync_state, &pending, &vault, &path, &dir.join(&filename))?;
+    }
+
+    let small = content.len() as u64 <= BLANK_NOTE_MAX_BYTES;
+    Ok(NoteMetadata {
+        filename,
+        created_at: timestamp_ms,
+        preview: Some(vault::note_preview(&content, NOTE_PREVIEW_CHARS)),
+        is_empty: small && vault::is_blank_note(&content),
+        title: vault::extract_note_title(&content),
+        placeholder: false,
+    })
+}
+
+#[tauri::command]
+fn delete_note(
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+) -> Result<(), String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path).join(&filename));
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+
+    cache.invalidate(&path);
+    pending.discard(&path);
+    fs::remove_file(&path).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Split a note into one note per segment at `delimiter_mode`'s delimiters.
+/// The original keeps its frontmatter and first segment unless
+/// `keep_original` is false, in which case every segment becomes a new note,
+/// the first one carrying the frontmatter, and the original is removed. New
+/// notes get consecutive timestamp names,
+/// so they list in order, and their first lines serve as titles. A held-back
+/// auto-save is what gets split. Returns the resulting notes in order.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+fn split_note(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    watches: tauri::State<'_, note_watch::NoteWatches>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    delimiter_mode: vault::SplitMode,
+    keep_original: Option<bool>,
+) -> Result<Vec<NoteFile>, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let keep_original = keep_original.unwrap_or(true);
+    let notebook_dir = vault::long_path(PathBuf::from(&vault_path).join(&notebook_path));
+    let path = notebook_dir.join(&filename);
+    if !path.exists() {
+        return Err("Note does not exist".to_string());
+    }
+    let content = match pending.get(&path) {
+        Some(content) => content,
+        None => {
+            let raw = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            encryption.decode(&vault_path, raw)?
+        }
+    };
+
+    let body = vault::strip_frontmatter(&content);
+    let frontmatter = &content[..content.len() - body.len()];
+    let mut segments = vault::split_segments(body, delimiter_mode).into_iter();
+    if segments.len() < 2 {
+        return Err("Nothing to split".to_string());
+    }
+    // The held-back save is split here, so it must not overwrite the result
+    pending.discard(&path);
+
+    let mut notes = Vec::new();
+    let first = format!("{}{}", frontmatter, segments.next().unwrap_or_default());
+    let first_new = if keep_original {
+        let encoded = encryption.encode(&vault_path, &first)?;
+        watches.record_write(&path, encoded.as_bytes());
+        fs::write(&path, &encoded).map_err(|e| e.to_string())?;
+        cache.invalidate(&path);
+        notes.push(NoteFile {
+            created_at: vault::created_at_from_filename(&path),
+            filename: filename.clone(),
+            content: first,
+            invalid_utf8: false,
+            notebook_path: None,
+        });
+        None
+    } else {
+        Some(first)
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as u64;
+    for (segment, timestamp) in first_new.into_iter().chain(segments).zip(now..) {
+        let segment_filename =
+            vault::create_note_file(&notebook_dir, timestamp, encryption.encode(&vault_path, &segment)?.as_bytes())
+                .map_err(|e| e.to_string())?;
+        notes.push(NoteFile {
+            filename: segment_filename,
+            content: segment,
+            created_at: timestamp,
+            invalid_utf8: false,
+            notebook_path: None,
+        });
+    }
+
+    if !keep_original {
+        cache.invalidate(&path);
+        fs::remove_file(&path).map_err(|e| e.to_string())?;
+    }
+
+    Ok(notes)
+}
+
+/// Outcome for one file of a bulk note operation
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkNoteResult {
+    pub filename: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl BulkNoteResult {
+    fn from_result(filename: String, result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Self { filename, success: true, error: None },
+            Err(e) => Self { filename, success: false, error: Some(e) },
+        }
+    }
+}
+
+/// Resolve a note inside a notebook, rejecting paths that leave the vault
+fn resolve_note_path(vault: &std::path::Path, notebook_path: &str, filename: &str) -> Result<PathBuf, String> {
+    if !vault::is_plain_filename(filename) {
+        return Err(format!("Invalid note filename: {}", filename));
+    }
+    Ok(vault::resolve_in_vault(vault, notebook_path)?.join(filename))
+}
+
+/// Delete several notes from one notebook. Each file is handled on its own,
+/// so one failure doesn't stop the rest.
+#[tauri::command]
+fn delete_notes(
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    notebook_path: String,
+    filenames: Vec<String>,
+) -> Vec<BulkNoteResult> {
+    let vault = PathBuf::from(&vault_path);
+    let writable = vault::ensure_writable(&vault);
+    filenames
+        .into_iter()
+        .map(|filename| {
+            let result = writable
+                .clone()
+                .and_then(|()| resolve_note_path(&vault, &notebook_path, &filename))
+                .and_then(|path| {
+                    if !path.is_file() {
+                        return Err("Note does not exist".to_string());
+                    }
+                    let path = vault::long_path(&path);
+                    cache.invalidate(&path);
+                    pending.discard(&path);
+                    fs::remove_file(&path).map_err(|e| e.to_string())
+                });
+            BulkNoteResult::from_result(filename, result)
+        })
+        .collect()
+}
+
+/// Move one note between notebooks, carrying its sync state along
+fn move_note_file(
+    sync_state: &sync::SyncState,
+    pending: &autosave::PendingSaves,
+    vault: &std::path::Path,
+    from: &str,
+    to: &str,
+    filename: &str,
+) -> Result<(), String> {
+    let source = resolve_note_path(vault, from, filename)?;
+    let target = resolve_note_path(vault, to, filename)?;
+    if !source.is_file() {
+        return Err("Note does not exist".to_string());
+    }
+    if target.exists() {
+        return Err("A note with that name already exists in the target notebook".to_string());
+    }
+    move_note_within(sync_state, pending, vault, &source, &target)
+}
+
+/// Rename a note inside the vault, carrying its sync state and any held-back
+/// save over to the new path
+fn move_note_within(
+    sync_state: &sync::SyncState,
+    pending: &autosave::PendingSaves,
+    vault: &std::path::Path,
+    source: &std::path::Path,
+    target: &std::path::Path,
+) -> Result<(), String> {
+    fs::rename(source, target).map_err(|e| e.to_string())?;
+    pending.rename(&vault::long_path(source), &vault::long_path(target));
+
+    let vault_id = sync_state
+        .state_manager
+        .get_vault_id_for_path(&vault.to_string_lossy());
+    let relative = |path: &std::path::Path| {
+        vault::split_note_path(vault, path).map(|(notebook, name)| {
+            if notebook.is_empty() { name } else { format!("{}/{}", notebook, name) }
+        })
+    };
+    if let (Some(vault_id), Some(old_path), Some(new_path)) = (vault_id, relative(source), relative(target)) {
+        sync_state.state_manager.rename_path_prefix(&vault_id, &old_path, &new_path);
+    }
+    Ok(())
+}
+
+/// Move several notes from one notebook to another, never overwriting a note
+/// in the target. Each file is handled on its own, so one failure doesn't
+/// stop the rest.
 #[tauri::command]
-fn read_note(vault_path: String, notebook_path: String, filename: String) -> Result<NoteFile, String> {
-    let path = PathBuf::from(&vault_path).join(&notebook_path).join(&filename);
-    if !path.exists() {
-        return Err("Note does not exist".to_string());
+fn move_notes(
+    sync_state: tauri::State<'_, sync::SyncState>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    from: String,
+    filenames: Vec<String>,
+    to: String,
+) -> Result<Vec<BulkNoteResult>, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let vault = PathBuf::from(&vault_path);
+    if !vault::resolve_in_vault(&vault, &to)?.is_dir() {
+        return Err("Target notebook does not exist".to_string());
     }
 
-    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    let stem = path.file_stem()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_default();
-    let created_at = stem.parse::<u64>().unwrap_or(0);
-
-    Ok(NoteFile {
-        filename,
-        content,
-        created_at,
-    })
+    Ok(filenames
+        .into_iter()
+        .map(|filename| {
+            let result = move_note_file(&sync_state, &pending, &vault, &from, &to, &filename);
+            BulkNoteResult::from_result(filename, result)
+        })
+        .collect())
 }
 
+/// Copy a note into a notebook of another vault as a new note with a fresh
+/// timestamp. Attachments it references are copied into the destination
+/// vault (reusing files with the same content there) and the references
+/// rewritten to the copies; missing attachments are left as they are.
 #[tauri::command]
-fn create_note(vault_path: String, notebook_path: String, content: String) -> Result<NoteFile, String> {
-    let full_notebook_path = PathBuf::from(&vault_path).join(&notebook_path);
-    if !full_notebook_path.exists() {
+fn copy_note_to_vault(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    options: tauri::State<'_, NoteOptions>,
+    src_vault: String,
+    src_notebook: String,
+    filename: String,
+    dst_vault: String,
+    dst_notebook: String,
+) -> Result<NoteFile, String> {
+    let (source_vault, target_vault) = (PathBuf::from(&src_vault), PathBuf::from(&dst_vault));
+    if !source_vault.is_dir() || !target_vault.is_dir() {
+        return Err("Vault does not exist".to_string());
+    }
+    if !vault::is_plain_filename(&filename) {
+        return Err(format!("Invalid note filename: {}", filename));
+    }
+    vault::ensure_writable(&target_vault)?;
+
+    let source = vault::resolve_in_vault(&source_vault, &src_notebook)?.join(&filename);
+    if !source.is_file() {
+        return Err("Note does not exist".to_string());
+    }
+    let target_notebook = vault::resolve_in_vault(&target_vault, &dst_notebook)?;
+    if !target_notebook.is_dir() {
         return Err("Notebook does not exist".to_string());
     }
 
+    let content = fs::read_to_string(&source).map_err(|e| e.to_string())?;
+    let content = encryption.decode(&src_vault, content)?;
+
+    let content_addressed = options.content_addressed_attachments.load(Ordering::Relaxed);
+    let mut copied = std::collections::BTreeMap::new();
+    for reference in extract_attachment_refs(&content) {
+        match attachments::copy_from(&source_vault, &reference, &target_vault, content_addressed) {
+            Ok(copy) => {
+                copied.insert(reference, copy);
+            }
+            Err(e) => eprintln!("[Attachments] Not copying {}: {}", reference, e),
+        }
+    }
+    let content = attachments::rewrite_refs(&content, &copied).unwrap_or(content);
+
     let timestamp = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map_err(|e| e.to_string())?
         .as_millis() as u64;
-
-    let filename = format!("{}.md", timestamp);
-    let path = full_notebook_path.join(&filename);
-
-    fs::write(&path, &content).map_err(|e| e.to_string())?;
+    let filename = vault::create_note_file(
+        &target_notebook,
+        timestamp,
+        encryption.encode(&dst_vault, &content)?.as_bytes(),
+    )
+    .map_err(|e| e.to_string())?;
 
     Ok(NoteFile {
         filename,
         content,
         created_at: timestamp,
+        invalid_utf8: false,
+        notebook_path: None,
     })
 }
 
-#[tauri::command]
-fn update_note(vault_path: String, notebook_path: String, filename: String, content: String) -> Result<NoteFile, String> {
-    let path = PathBuf::from(&vault_path).join(&notebook_path).join(&filename);
-    if !path.exists() {
-        return Err("Note does not exist".to_string());
+/// Collect the unique attachment paths (decoded `attachments/...`) a note
+/// links to, in order of appearance
+fn extract_attachment_refs(content: &str) -> Vec<String> {
+    let mut refs: Vec<String> = Vec::new();
+    for reference in attachments::parse_attachment_refs(content) {
+        if !refs.contains(&reference.path) {
+            refs.push(reference.path);
+        }
     }
+    refs
+}
 
-    fs::write(&path, &content).map_err(|e| e.to_string())?;
+fn mime_type_for_extension(ext: &str) -> &'static str {
+    match ext.to_lowercase().as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
 
-    let stem = path.file_stem()
-        .map(|s| s.to_string_lossy().to_string())
-        .unwrap_or_default();
-    let created_at = stem.parse::<u64>().unwrap_or(0);
+/// An attachment a note references, if it is a file inside the vault. The
+/// path is checked again after resolving symlinks, so a link can't export
+/// files from elsewhere on disk.
+fn resolve_attachment(vault: &Path, reference: &str) -> Option<PathBuf> {
+    let path = vault::resolve_in_vault(vault, reference)
+        .and_then(|path| fs::canonicalize(path).map_err(|e| e.to_string()));
+    match path {
+        Ok(path) if path.is_file() && fs::canonicalize(vault).is_ok_and(|vault| path.starts_with(vault)) => Some(path),
+        Ok(_) => {
+            eprintln!("[Export] Skipping attachment outside the vault: {}", reference);
+            None
+        }
+        Err(e) => {
+            eprintln!("[Export] Skipping missing attachment {}: {}", reference, e);
+            None
+        }
+    }
+}
 
-    Ok(NoteFile {
-        filename,
-        content,
-        created_at,
-    })
+/// Create `filename` in `dir`, or `<stem>-<n>.<ext>` if that exists, without
+/// ever replacing a file
+fn create_free_file(dir: &Path, filename: &str) -> Result<(PathBuf, fs::File), String> {
+    let (stem, extension) = match filename.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (filename, String::new()),
+    };
+    for n in 0.. {
+        let candidate = if n == 0 {
+            dir.join(filename)
+        } else {
+            dir.join(format!("{}-{}{}", stem, n, extension))
+        };
+        match fs::OpenOptions::new().write(true).create_new(true).open(&candidate) {
+            Ok(file) => return Ok((candidate, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    unreachable!()
 }
 
+/// Export a single note together with the attachments it references.
+///
+/// With `inline_images` the attachments are embedded as base64 data URIs and a
+/// single `.md` file is written into `output_path`, under a free name if the
+/// note's is taken. Otherwise a folder named after the note is created
+/// containing the note and an `attachments/` copy. Attachments outside the
+/// vault are left out. The note is exported as plaintext, including a
+/// held-back auto-save; an encrypted note needs the vault to be unlocked.
 #[tauri::command]
-fn delete_note(vault_path: String, notebook_path: String, filename: String) -> Result<(), String> {
-    let path = PathBuf::from(&vault_path).join(&notebook_path).join(&filename);
-    if !path.exists() {
+fn export_note(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    output_path: String,
+    inline_images: bool,
+) -> Result<Vec<String>, String> {
+    let vault = PathBuf::from(&vault_path);
+    if !vault::is_plain_filename(&filename) {
+        return Err(format!("Invalid note filename: {}", filename));
+    }
+    let note_path = vault::resolve_in_vault(&vault, &notebook_path)?.join(&filename);
+    if !note_path.exists() {
         return Err("Note does not exist".to_string());
     }
 
-    fs::remove_file(&path).map_err(|e| e.to_string())?;
-    Ok(())
+    let output_dir = PathBuf::from(&output_path);
+    fs::create_dir_all(&output_dir).map_err(|e| e.to_string())?;
+
+    // A note that isn't valid UTF-8 is read lossily, as `read_note` does,
+    // and keeps its bytes unchanged in a bundle
+    let (content, invalid_bytes) = match pending.get(&note_path) {
+        Some(content) => (content, None),
+        None => {
+            let bytes = fs::read(&note_path).map_err(|e| e.to_string())?;
+            match String::from_utf8(bytes) {
+                Ok(raw) => (encryption.decode(&vault_path, raw)?, None),
+                Err(e) => (String::from_utf8_lossy(e.as_bytes()).to_string(), Some(e.into_bytes())),
+            }
+        }
+    };
+    let references: Vec<(String, PathBuf)> = extract_attachment_refs(&content)
+        .into_iter()
+        .filter_map(|reference| resolve_attachment(&vault, &reference).map(|source| (reference, source)))
+        .collect();
+    let mut exported = Vec::new();
+
+    if inline_images {
+        let mut data_uris = std::collections::HashMap::new();
+        for (reference, source) in &references {
+            let bytes = match fs::read(source) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    eprintln!("[Export] Skipping unreadable attachment {}: {}", reference, e);
+                    continue;
+                }
+            };
+            let ext = source
+                .extension()
+                .map(|e| e.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let data_uri = format!(
+                "data:{};base64,{}",
+                mime_type_for_extension(&ext),
+                BASE64.encode(&bytes)
+            );
+            data_uris.insert(reference.clone(), data_uri);
+        }
+        // Only link targets are replaced, not the same text elsewhere
+        let content = attachments::rewrite_refs_with(&content, |path| data_uris.get(path).cloned()).unwrap_or(content);
+
+        let (target, mut file) = create_free_file(&output_dir, &filename)?;
+        std::io::Write::write_all(&mut file, content.as_bytes()).map_err(|e| e.to_string())?;
+        exported.push(target.to_string_lossy().to_string());
+    } else {
+        let stem = note_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| filename.clone());
+        let bundle_dir = output_dir.join(&stem);
+        if bundle_dir.exists() {
+            return Err("Export folder already exists".to_string());
+        }
+        fs::create_dir_all(&bundle_dir).map_err(|e| e.to_string())?;
+
+        let target = bundle_dir.join(&filename);
+        fs::write(&target, invalid_bytes.as_deref().unwrap_or(content.as_bytes())).map_err(|e| e.to_string())?;
+        exported.push(target.to_string_lossy().to_string());
+
+        for (reference, source) in &references {
+            let destination = bundle_dir.join(reference);
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::copy(source, &destination).map_err(|e| e.to_string())?;
+            exported.push(destination.to_string_lossy().to_string());
+        }
+    }
+
+    Ok(exported)
 }
 
 #[tauri::command]
-fn save_image(vault_path: String, image_data: String, extension: String) -> Result<String, String> {
+fn save_image(
+    options: tauri::State<'_, NoteOptions>,
+    vault_path: String,
+    image_data: String,
+    extension: String,
+) -> Result<String, String> {
+    vault::ensure_writable(Path::new(&vault_path))?;
     let vault = PathBuf::from(&vault_path);
     if !vault.exists() {
         return Err("Vault does not exist".to_string());
@@ -318,35 +1861,252 @@ fn save_image(vault_path: String, image_data: String, extension: String) -> Resu
         .map_err(|e| e.to_string())?
         .as_millis() as u64;
 
-    let filename = format!("{}.{}", timestamp, extension);
-    let file_path = attachments_path.join(&filename);
+    // Checked before decoding so an oversized payload is never expanded.
+    // Standard base64 carries 3 bytes per 4 characters, less the padding.
+    let max_bytes = options.max_image_bytes.load(Ordering::Relaxed);
+    let image_size = image_data.trim_end_matches('=').len() as u64 * 3 / 4;
+    if image_size > max_bytes {
+        return Err(format!(
+            "Image is too large ({} bytes, the limit is {} bytes)",
+            image_size, max_bytes
+        ));
+    }
+    if vault::available_space(&attachments_path).is_some_and(|available| available < image_size) {
+        return Err("Not enough disk space to save the image".to_string());
+    }
 
     let image_bytes = BASE64.decode(&image_data).map_err(|e| e.to_string())?;
-    fs::write(&file_path, image_bytes).map_err(|e| e.to_string())?;
+
+    // Pasting the same image again links to the copy already in the vault
+    if let Some(existing) = attachments::find_existing(&vault, &image_bytes) {
+        return Ok(existing);
+    }
+
+    let filename = if options.content_addressed_attachments.load(Ordering::Relaxed) {
+        attachments::content_addressed_name(&image_bytes, &extension)
+    } else {
+        format!("{}.{}", timestamp, extension)
+    };
+    let file_path = attachments_path.join(&filename);
+    if let Err(e) = fs::write(&file_path, &image_bytes) {
+        // Don't leave a truncated image behind, e.g. when the disk filled up
+        let _ = fs::remove_file(&file_path);
+        return Err(e.to_string());
+    }
+    if let Err(e) = attachments::record(&vault, &filename, &image_bytes) {
+        eprintln!("[Attachments] Failed to update the index: {}", e);
+    }
 
     Ok(format!("attachments/{}", filename))
 }
 
+/// Well-known favicon locations tried when the page's declared icons fail
+const FAVICON_FALLBACK_PATHS: &[&str] = &["/favicon.ico", "/favicon.png", "/apple-touch-icon.png"];
+
+/// The well-known favicon URLs on the page's host, keeping its port; none
+/// for pages without a host.
+fn favicon_fallbacks(base: &url::Url) -> Vec<String> {
+    if !base.has_host() {
+        return Vec::new();
+    }
+    FAVICON_FALLBACK_PATHS
+        .iter()
+        .filter_map(|path| base.join(path).ok())
+        .map(|url| url.to_string())
+        .collect()
+}
+
+/// Request a URL for validation with a short timeout. Uses HEAD, falling
+/// back to GET for servers that don't allow HEAD.
+fn probe_url(client: &reqwest::blocking::Client, url: &str) -> Option<reqwest::blocking::Response> {
+    let timeout = std::time::Duration::from_secs(3);
+    match client.head(url).timeout(timeout).send() {
+        Ok(response) if response.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED => {
+            client.get(url).timeout(timeout).send().ok()
+        }
+        Ok(response) => Some(response),
+        Err(_) => None,
+    }
+}
+
+/// Whether a favicon URL resolves
+fn favicon_resolves(client: &reqwest::blocking::Client, url: &str) -> bool {
+    probe_url(client, url).is_some_and(|r| r.status().is_success())
+}
+
+/// Whether a URL answers 200 with an image content type
+fn is_reachable_image(client: &reqwest::blocking::Client, url: &str) -> bool {
+    probe_url(client, url).is_some_and(|r| {
+        r.status() == reqwest::StatusCode::OK
+            && r.headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.trim().to_ascii_lowercase().starts_with("image/"))
+    })
+}
+
+/// User agent sent by default: a desktop browser, which gets the same page
+/// a user would see
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+
+/// Crawler-style user agent; many sites serve bots lighter pages with
+/// complete metadata
+const BOT_USER_AGENT: &str = concat!("Mozilla/5.0 (compatible; EchopadBot/", env!("CARGO_PKG_VERSION"), "; link preview)");
+
+/// How link previews identify themselves when fetching pages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OgFetchSettings {
+    /// Custom User-Agent; overrides `bot_user_agent` when set
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Send a crawler user agent instead of the browser one
+    #[serde(default)]
+    pub bot_user_agent: bool,
+    /// Accept-Language value; `None` derives it from the OS locale
+    #[serde(default)]
+    pub accept_language: Option<String>,
+}
+
+impl OgFetchSettings {
+    fn effective_user_agent(&self) -> String {
+        match &self.user_agent {
+            Some(ua) if !ua.trim().is_empty() => ua.trim().to_string(),
+            _ if self.bot_user_agent => BOT_USER_AGENT.to_string(),
+            _ => BROWSER_USER_AGENT.to_string(),
+        }
+    }
+
+    fn effective_accept_language(&self) -> Option<String> {
+        match &self.accept_language {
+            Some(lang) if !lang.trim().is_empty() => Some(lang.trim().to_string()),
+            _ => sys_locale::get_locale().map(|locale| accept_language_for_locale(&locale)),
+        }
+    }
+}
+
+/// Accept-Language header for an OS locale, e.g. `en-US` becomes
+/// `en-US,en;q=0.9`. POSIX suffixes like `.UTF-8` are dropped.
+fn accept_language_for_locale(locale: &str) -> String {
+    let tag = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or(locale)
+        .replace('_', "-");
+    match tag.split_once('-') {
+        Some((language, _)) => format!("{},{};q=0.9", tag, language),
+        None => tag,
+    }
+}
+
+/// Link preview fetch settings, persisted next to the database
+pub struct OgFetchConfig {
+    path: PathBuf,
+    settings: parking_lot::RwLock<OgFetchSettings>,
+}
+
+impl OgFetchConfig {
+    fn load(path: PathBuf) -> Self {
+        let settings = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            settings: parking_lot::RwLock::new(settings),
+        }
+    }
+}
+
 #[tauri::command]
-fn fetch_og_metadata(url: String) -> Result<OgMetadata, String> {
-    let client = reqwest::blocking::Client::builder()
-        .timeout(std::time::Duration::from_secs(10))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .build()
+fn get_og_fetch_settings(config: tauri::State<'_, OgFetchConfig>) -> OgFetchSettings {
+    config.settings.read().clone()
+}
+
+#[tauri::command]
+fn set_og_fetch_settings(
+    config: tauri::State<'_, OgFetchConfig>,
+    settings: OgFetchSettings,
+) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+    fs::write(&config.path, json).map_err(|e| e.to_string())?;
+    *config.settings.write() = settings;
+    Ok(())
+}
+
+/// Fetch a page's link preview metadata. The page and the probes of its
+/// images are blocking requests, so they run on the blocking pool instead
+/// of holding up the main thread.
+#[tauri::command]
+async fn fetch_og_metadata(
+    config: tauri::State<'_, OgFetchConfig>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    url: String,
+    validate_images: Option<bool>,
+) -> Result<OgMetadata, String> {
+    let settings = config.settings.read().clone();
+    // Link previews go through the signed-in account's proxy override, if any
+    let proxy = sync_state
+        .proxy
+        .proxy_for(sync_state.auth.get_server_url().as_deref())
         .map_err(|e| e.to_string())?;
-    
-    let response = client.get(&url).send().map_err(|e| e.to_string())?;
-    let html_content = response.text().map_err(|e| e.to_string())?;
+    tauri::async_runtime::spawn_blocking(move || {
+        fetch_og_metadata_blocking(settings, proxy, url, validate_images.unwrap_or(false))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+fn fetch_og_metadata_blocking(
+    settings: OgFetchSettings,
+    proxy: Option<reqwest::Proxy>,
+    url: String,
+    validate_images: bool,
+) -> Result<OgMetadata, String> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(value) = settings
+        .effective_accept_language()
+        .and_then(|lang| reqwest::header::HeaderValue::from_str(&lang).ok())
+    {
+        headers.insert(reqwest::header::ACCEPT_LANGUAGE, value);
+    }
+
+    let mut builder = reqwest::blocking::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .user_agent(settings.effective_user_agent())
+        .default_headers(headers)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true);
+    if let Some(proxy) = proxy {
+        builder = builder.proxy(proxy);
+    }
+    let client = builder.build().map_err(|e| e.to_string())?;
+
+    let mut response = client
+        .get(&url)
+        .send()
+        .map_err(|e| sync::proxy::describe_error(&e))?;
+
+    // Read the (transparently decompressed) body. A corrupt or truncated
+    // compressed stream still leaves us with everything decoded so far,
+    // which is usually enough to find the <head> tags.
+    let mut body = Vec::new();
+    if let Err(e) = response.read_to_end(&mut body) {
+        if body.is_empty() {
+            return Err(e.to_string());
+        }
+        eprintln!("[OG] Partial body for {} after decode error: {}", url, e);
+    }
+    let html_content = String::from_utf8_lossy(&body);
     let document = Html::parse_document(&html_content);
     
     // Selectors for OG tags
     let og_title_selector = Selector::parse("meta[property='og:title']").unwrap();
     let og_desc_selector = Selector::parse("meta[property='og:description']").unwrap();
-    let og_image_selector = Selector::parse("meta[property='og:image']").unwrap();
     let og_site_selector = Selector::parse("meta[property='og:site_name']").unwrap();
     let title_selector = Selector::parse("title").unwrap();
     let desc_selector = Selector::parse("meta[name='description']").unwrap();
-    let favicon_selector = Selector::parse("link[rel='icon'], link[rel='shortcut icon']").unwrap();
+    let favicon_selector = Selector::parse("link[rel='icon'], link[rel='shortcut icon'], link[rel='apple-touch-icon']").unwrap();
     
     // Extract OG title or fallback to title tag
     let title = document
@@ -372,11 +2132,23 @@ fn fetch_og_metadata(url: String) -> Result<OgMetadata, String> {
                 .and_then(|el| el.value().attr("content").map(String::from))
         });
     
-    // Extract OG image
-    let image = document
-        .select(&og_image_selector)
-        .next()
-        .and_then(|el| el.value().attr("content").map(String::from));
+    // Extract OG image: the largest of the declared ones, measured from the
+    // files themselves when validating
+    let base_url = url::Url::parse(&url).ok();
+    let mut image_candidates = og_image::candidates(&document);
+    let image = if validate_images {
+        for candidate in &mut image_candidates {
+            if let Some(resolved) = base_url.as_ref().and_then(|base| base.join(&candidate.url).ok()) {
+                candidate.url = resolved.to_string();
+            }
+        }
+        og_image::largest(image_candidates, |candidate| {
+            let measured = og_image::fetch_dimensions(&client, &candidate.url)?;
+            Some(measured.or(candidate.width.zip(candidate.height)))
+        })
+    } else {
+        og_image::largest_declared(image_candidates)
+    };
     
     // Extract site name
     let site_name = document
@@ -384,25 +2156,37 @@ fn fetch_og_metadata(url: String) -> Result<OgMetadata, String> {
         .next()
         .and_then(|el| el.value().attr("content").map(String::from));
     
-    // Extract favicon
-    let base_url = url::Url::parse(&url).ok();
-    let favicon = document
+    // Extract favicon: declared icons first, then common well-known paths.
+    // Only a candidate that actually resolves (and, when validating images,
+    // is served as an image) is returned.
+    let mut favicon_candidates: Vec<String> = document
         .select(&favicon_selector)
-        .next()
-        .and_then(|el| el.value().attr("href").map(String::from))
+        .filter_map(|el| el.value().attr("href"))
         .map(|href| {
             if href.starts_with("http") {
-                href
+                href.to_string()
             } else if let Some(ref base) = base_url {
-                base.join(&href).map(|u| u.to_string()).unwrap_or(href)
+                base.join(href).map(|u| u.to_string()).unwrap_or_else(|_| href.to_string())
             } else {
-                href
+                href.to_string()
             }
         })
-        .or_else(|| {
-            base_url.map(|u| format!("{}://{}/favicon.ico", u.scheme(), u.host_str().unwrap_or("")))
+        .collect();
+    if let Some(ref base) = base_url {
+        favicon_candidates.extend(favicon_fallbacks(base));
+    }
+    let mut seen = std::collections::HashSet::new();
+    favicon_candidates.retain(|candidate| seen.insert(candidate.clone()));
+    let favicon = favicon_candidates
+        .into_iter()
+        .find(|candidate| {
+            if validate_images {
+                is_reachable_image(&client, candidate)
+            } else {
+                favicon_resolves(&client, candidate)
+            }
         });
-    
+
     Ok(OgMetadata {
         url,
         title,
@@ -415,7 +2199,7 @@ fn fetch_og_metadata(url: String) -> Result<OgMetadata, String> {
 
 #[tauri::command]
 fn list_boards(vault_path: String) -> Result<Vec<BoardMetadata>, String> {
-    let boards_path = PathBuf::from(&vault_path).join(".boards");
+    let boards_path = vault::long_path(PathBuf::from(&vault_path).join(".boards"));
 
     if !boards_path.exists() {
         return Ok(Vec::new());
@@ -453,13 +2237,13 @@ fn list_boards(vault_path: String) -> Result<Vec<BoardMetadata>, String> {
         }
     }
 
-    boards.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    boards.sort_by_key(|board| std::cmp::Reverse(board.created_at));
     Ok(boards)
 }
 
 #[tauri::command]
 fn read_board(vault_path: String, filename: String) -> Result<BoardFile, String> {
-    let path = PathBuf::from(&vault_path).join(".boards").join(&filename);
+    let path = vault::long_path(PathBuf::from(&vault_path).join(".boards").join(&filename));
 
     if !path.exists() {
         return Err("Board does not exist".to_string());
@@ -480,7 +2264,8 @@ fn read_board(vault_path: String, filename: String) -> Result<BoardFile, String>
 
 #[tauri::command]
 fn create_board(vault_path: String, title: String) -> Result<BoardFile, String> {
-    let boards_path = PathBuf::from(&vault_path).join(".boards");
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let boards_path = vault::long_path(PathBuf::from(&vault_path).join(".boards"));
 
     if !boards_path.exists() {
         fs::create_dir_all(&boards_path).map_err(|e| e.to_string())?;
@@ -510,7 +2295,8 @@ fn create_board(vault_path: String, title: String) -> Result<BoardFile, String>
 
 #[tauri::command]
 fn update_board(vault_path: String, filename: String, content: String) -> Result<BoardFile, String> {
-    let path = PathBuf::from(&vault_path).join(".boards").join(&filename);
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(".boards").join(&filename));
 
     if !path.exists() {
         return Err("Board does not exist".to_string());
@@ -532,7 +2318,8 @@ fn update_board(vault_path: String, filename: String, content: String) -> Result
 
 #[tauri::command]
 fn delete_board(vault_path: String, filename: String) -> Result<(), String> {
-    let path = PathBuf::from(&vault_path).join(".boards").join(&filename);
+    vault::ensure_writable(Path::new(&vault_path))?;
+    let path = vault::long_path(PathBuf::from(&vault_path).join(".boards").join(&filename));
 
     if !path.exists() {
         return Err("Board does not exist".to_string());
@@ -542,11 +2329,106 @@ fn delete_board(vault_path: String, filename: String) -> Result<(), String> {
     Ok(())
 }
 
+/// How long quitting waits for running syncs before saving state and exiting
+const SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// Presses of the quick capture shortcut closer together than this are
+/// coalesced into one
+const SHORTCUT_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(300);
+
+/// Coalesces repeated shortcut presses. Holding the key down makes some
+/// platforms send `Pressed` over and over without a `Released` in between,
+/// and the plugin doesn't mark those as auto-repeat, so every press, accepted
+/// or not, restarts the window: a held key fires once.
+#[derive(Default)]
+struct ShortcutDebounce {
+    last_press: parking_lot::Mutex<Option<std::time::Instant>>,
+}
+
+impl ShortcutDebounce {
+    /// Record a press and return whether it should trigger the shortcut
+    fn accept(&self) -> bool {
+        let now = std::time::Instant::now();
+        let mut last_press = self.last_press.lock();
+        let accept = last_press.map_or(true, |last| now.duration_since(last) >= SHORTCUT_DEBOUNCE);
+        *last_press = Some(now);
+        accept
+    }
+}
+
+fn default_always_on_top() -> bool {
+    true
+}
+
+/// Quick capture window preferences
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuickCaptureSettings {
+    /// Keep the window above other windows. Tiling window managers may
+    /// fight over focus with an always-on-top window.
+    #[serde(default = "default_always_on_top")]
+    pub always_on_top: bool,
+}
+
+impl Default for QuickCaptureSettings {
+    fn default() -> Self {
+        Self {
+            always_on_top: default_always_on_top(),
+        }
+    }
+}
+
+/// Quick capture settings, persisted next to the database
+pub struct QuickCaptureConfig {
+    path: PathBuf,
+    settings: parking_lot::RwLock<QuickCaptureSettings>,
+}
+
+impl QuickCaptureConfig {
+    fn load(path: PathBuf) -> Self {
+        let settings = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            settings: parking_lot::RwLock::new(settings),
+        }
+    }
+}
+
+/// Keep the quick capture window above other windows or not, applied to
+/// the open window right away
+#[tauri::command]
+fn set_quick_capture_always_on_top(
+    app: AppHandle,
+    config: tauri::State<'_, QuickCaptureConfig>,
+    enabled: bool,
+) -> Result<(), String> {
+    let settings = QuickCaptureSettings { always_on_top: enabled };
+    let json = serde_json::to_string_pretty(&settings).map_err(|e| e.to_string())?;
+    fs::write(&config.path, json).map_err(|e| e.to_string())?;
+    *config.settings.write() = settings;
+
+    if let Some(window) = app.get_webview_window("quick-capture") {
+        window.set_always_on_top(enabled).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Show the quick capture window. Focus is only taken when the window
+/// comes up from hidden or minimized; pressing the shortcut while it is
+/// already showing leaves focus alone, so it doesn't fight other windows.
 fn show_quick_capture(app: AppHandle) -> Result<(), String> {
+    let always_on_top = app.state::<QuickCaptureConfig>().settings.read().always_on_top;
     if let Some(window) = app.get_webview_window("quick-capture") {
+        let showing = window.is_visible().unwrap_or(false) && !window.is_minimized().unwrap_or(false);
+        if showing {
+            return Ok(());
+        }
+        window.unminimize().map_err(|e| e.to_string())?;
         window.show().map_err(|e| e.to_string())?;
-        window.set_focus().map_err(|e| e.to_string())?;
         window.center().map_err(|e| e.to_string())?;
+        window.set_focus().map_err(|e| e.to_string())?;
     } else {
         let window = WebviewWindowBuilder::new(
             &app,
@@ -559,7 +2441,7 @@ fn show_quick_capture(app: AppHandle) -> Result<(), String> {
         .resizable(false)
         .decorations(false)
         .transparent(true)
-        .always_on_top(true)
+        .always_on_top(always_on_top)
         .center()
         .skip_taskbar(true)
         .shadow(false)
@@ -596,6 +2478,79 @@ fn open_devtools(_app: AppHandle) -> Result<(), String> {
     Ok(())
 }
 
+/// Where app data (database, sync state) is stored for this session
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DataDirStatus {
+    pub path: String,
+    /// False when falling back to a temporary directory that may be wiped
+    pub persistent: bool,
+    pub warning: Option<String>,
+}
+
+/// Whether a directory exists (or can be created) and accepts writes
+fn is_writable_dir(dir: &std::path::Path) -> bool {
+    if fs::create_dir_all(dir).is_err() {
+        return false;
+    }
+    let probe = dir.join(".echopad-write-test");
+    let writable = fs::write(&probe, b"").is_ok();
+    let _ = fs::remove_file(&probe);
+    writable
+}
+
+/// Resolve the app data directory, falling back to a folder next to the
+/// executable and then to a temporary directory instead of panicking
+fn resolve_data_dir(app: &tauri::App) -> (PathBuf, DataDirStatus) {
+    let primary_error = match app.path().app_data_dir() {
+        Ok(dir) if is_writable_dir(&dir) => {
+            let status = DataDirStatus {
+                path: dir.to_string_lossy().to_string(),
+                persistent: true,
+                warning: None,
+            };
+            return (dir, status);
+        }
+        Ok(dir) => format!("{} is not writable", dir.display()),
+        Err(e) => e.to_string(),
+    };
+
+    eprintln!("[Warning] App data directory unavailable: {}", primary_error);
+
+    let beside_exe = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|p| p.join("echopad-data")));
+    if let Some(dir) = beside_exe.filter(|d| is_writable_dir(d)) {
+        let status = DataDirStatus {
+            path: dir.to_string_lossy().to_string(),
+            persistent: true,
+            warning: Some(format!(
+                "The app data directory is unavailable ({}). Data is stored in {} instead.",
+                primary_error,
+                dir.display()
+            )),
+        };
+        return (dir, status);
+    }
+
+    let dir = std::env::temp_dir().join("echopad");
+    let _ = fs::create_dir_all(&dir);
+    let status = DataDirStatus {
+        path: dir.to_string_lossy().to_string(),
+        persistent: false,
+        warning: Some(format!(
+            "The app data directory is unavailable ({}). Settings and sync state won't persist and sync is disabled until it is fixed.",
+            primary_error
+        )),
+    };
+    (dir, status)
+}
+
+/// Report where app data is stored and whether it persists
+#[tauri::command]
+fn get_data_dir_status(status: tauri::State<'_, DataDirStatus>) -> DataDirStatus {
+    status.inner().clone()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -610,19 +2565,65 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .setup(|app| {
             // Initialize sync state
-            let app_data_dir = app.path().app_data_dir()
-                .expect("Failed to get app data dir");
+            let (app_data_dir, data_dir_status) = resolve_data_dir(app);
             let db_path = app_data_dir.join("echopad.db");
-            let sync_state = sync::SyncState::new(db_path);
+            let mut sync_state = sync::SyncState::new(db_path.clone());
+            if let Some(warning) = &data_dir_status.warning {
+                if !data_dir_status.persistent {
+                    sync_state.disable(warning.clone());
+                }
+                app.dialog()
+                    .message(warning.clone())
+                    .title("Echopad storage warning")
+                    .kind(MessageDialogKind::Warning)
+                    .show(|_| {});
+            }
+            sync::commands::start_scheduler(app.handle().clone(), sync_state.clone());
+
+            // Repair attachment indexes that drifted while the app was closed
+            let vault_paths: Vec<String> = sync_state
+                .state_manager
+                .get_all_vault_states()
+                .into_iter()
+                .map(|v| v.vault_path)
+                .collect();
+            std::thread::spawn(move || {
+                for vault_path in vault_paths {
+                    let vault = PathBuf::from(&vault_path);
+                    if !vault.is_dir() {
+                        continue;
+                    }
+                    if let Err(e) = attachments::ensure_index_consistent(&vault) {
+                        eprintln!("[Attachments] Failed to check the index of {}: {}", vault_path, e);
+                    }
+                }
+            });
+            // The note index follows sync data moved out of the app data directory
+            let index_path = sync_state.auth.data_dir().join("echopad.db");
             app.manage(sync_state);
+            let note_index = index::NoteIndex::new(db_path, index_path);
+            note_index.start_watchdog(app.handle().clone());
+            app.manage(note_index);
+            app.manage(data_dir_status);
+            app.manage(local_crypto::LocalEncryption::new());
+            app.manage(external_edit::ExternalEdits::new());
+            app.manage(note_cache::NoteCache::new());
+            app.manage(note_watch::NoteWatches::new(app.handle().clone()));
+            app.manage(autosave::PendingSaves::new());
+            app.manage(NoteOptions::load(app_data_dir.join("note_options.json")));
+            app.manage(OgFetchConfig::load(app_data_dir.join("og_fetch.json")));
+            app.manage(DateFilingConfig::load(app_data_dir.join("date_filing.json")));
+            app.manage(QuickCaptureConfig::load(app_data_dir.join("quick_capture.json")));
 
             // Set up global shortcut
             let shortcut = Shortcut::new(Some(Modifiers::ALT), Code::Space);
             let app_handle = app.handle().clone();
 
+            let debounce = ShortcutDebounce::default();
+
             // Handle shortcut registration gracefully - don't crash if keybind is already taken
             if let Err(e) = app.global_shortcut().on_shortcut(shortcut, move |_app, _shortcut, event| {
-                if event.state == ShortcutState::Pressed {
+                if event.state == ShortcutState::Pressed && debounce.accept() {
                     let _ = show_quick_capture(app_handle.clone());
                 }
             }) {
@@ -688,41 +2689,528 @@ pub fn run() {
             create_notebook,
             rename_notebook,
             delete_notebook,
+            prune_empty_notebooks,
+            trash::list_trash,
+            trash::restore_notebook,
+            trash::empty_trash,
             list_notes,
+            get_recent_notes,
+            list_all_notes,
             read_note,
+            read_note_bytes,
+            write_note_bytes,
             create_note,
+            create_note_ensure_notebook,
             update_note,
+            autosave::save_note_debounced,
+            autosave::flush_pending_saves,
             delete_note,
+            split_note,
+            links::rename_note_with_links,
+            export_note,
+            touch_note,
+            get_note_metadata,
+            note_cache::set_note_cache_enabled,
+            set_reject_empty_notes,
+            set_content_addressed_attachments,
+            set_max_image_size,
+            get_date_filing,
+            set_date_filing,
+            delete_notes,
+            move_notes,
+            copy_note_to_vault,
+            batch::batch_execute,
+            notes_changed_since,
+            local_crypto::get_local_encryption_status,
+            local_crypto::enable_local_encryption,
+            local_crypto::disable_local_encryption,
+            local_crypto::unlock_vault,
+            local_crypto::lock_vault,
+            migrate_notes_to_frontmatter,
+            set_note_created_at,
+            diff::diff_notes,
+            health::vault_health_check,
+            validate::validate_vault,
+            duplicates::find_duplicate_notes,
+            attachments::rebuild_attachment_index,
+            attachments::reference_count_attachments,
+            attachments::cleanup_orphan_attachments,
+            attachments::migrate_attachments_to_content_addressed,
+            import::import_markdown_folder,
+            external_edit::export_note_to_temp,
+            external_edit::finish_external_edit,
+            note_watch::watch_note,
+            note_watch::unwatch_note,
             save_image,
             hide_quick_capture,
+            set_quick_capture_always_on_top,
             fetch_og_metadata,
+            get_og_fetch_settings,
+            set_og_fetch_settings,
             open_devtools,
+            get_data_dir_status,
             list_boards,
             read_board,
             create_board,
             update_board,
             delete_board,
+            index::reindex_vault,
+            index::cancel_reindex,
+            purge::purge_device_data,
             // Sync commands
             sync::commands::sync_login,
             sync::commands::sync_register,
             sync::commands::sync_restore_session,
             sync::commands::sync_logout,
             sync::commands::sync_get_status,
+            sync::commands::sync_broadcast_status,
+            sync::commands::sync_whoami,
+            sync::commands::sync_get_endpoints,
+            sync::commands::sync_benchmark_kdf,
             sync::commands::sync_enable_vault,
+            sync::commands::sync_migrate_server,
+            sync::commands::sync_rotate_vault_key,
             sync::commands::sync_disable_vault,
             sync::commands::sync_now,
+            sync::commands::sync_verify_integrity,
+            sync::commands::sync_get_unencrypted_notes,
+            sync::commands::sync_maintenance,
+            sync::commands::sync_get_server_config,
+            sync::commands::sync_get_file_state,
+            sync::commands::sync_diff_with_remote,
+            sync::commands::sync_estimate_upload,
+            sync::commands::get_storage_breakdown,
+            sync::commands::sync_set_max_file_size,
+            sync::commands::sync_set_pull_concurrency,
+            sync::commands::sync_set_pull_batch_size,
+            sync::commands::sync_set_include_dirs,
+            sync::commands::sync_set_follow_symlinks,
+            sync::commands::sync_set_hash_note_body_only,
+            sync::commands::sync_set_interval,
+            sync::commands::sync_set_offline,
+            sync::commands::sync_pause_all,
+            sync::commands::relocate_app_data,
+            sync::commands::sync_resume_all,
+            sync::commands::sync_get_last_result,
+            sync::commands::sync_validate_connection,
+            sync::commands::sync_refresh_vault_info,
+            sync::commands::sync_reset_status,
+            sync::commands::sync_get_orphaned_states,
+            sync::commands::sync_clear_orphaned_states,
+            sync::commands::sync_all,
+            sync::commands::sync_relocate_vault,
+            sync::commands::sync_repair_mappings,
+            sync::commands::sync_set_placeholder_mode,
+            sync::commands::sync_materialize_file,
             sync::commands::sync_get_conflicts,
+            sync::commands::sync_conflict_count,
             sync::commands::sync_resolve_conflict,
+            sync::commands::sync_resolve_all_conflicts,
+            sync::commands::sync_cleanup_conflicts,
+            sync::commands::sync_set_conflict_retention,
+            sync::commands::sync_set_push_trash_deletes_immediately,
+            sync::commands::sync_get_proxy,
+            sync::commands::sync_set_proxy,
             sync::commands::sync_get_devices,
             sync::commands::sync_revoke_device,
             sync::commands::sync_get_user,
             sync::commands::sync_is_logged_in,
+            sync::commands::sync_verify_password,
+            sync::commands::sync_list_local_vaults,
             sync::commands::sync_list_remote_vaults,
             sync::commands::sync_connect_vault,
             sync::commands::sync_detect_vault_connection,
+            sync::commands::sync_read_manifest,
+            sync::commands::sync_get_vault_id,
+            sync::commands::sync_list_connections,
             sync::commands::sync_auto_reconnect_vault,
+            sync::commands::sync_reconnect_all,
+            sync::commands::sync_start_auto_reconnect,
             sync::commands::sync_check_remote_pending,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                let (_, errors) = app.state::<autosave::PendingSaves>().flush_all(app);
+                for error in errors {
+                    eprintln!("[Notes] Failed to save on exit: {}", error);
+                }
+                app.state::<sync::SyncState>().shutdown(SHUTDOWN_GRACE);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tauri::test::MockRuntime;
+
+    /// A mock app managing the state note commands use, with an empty vault
+    fn mock_app_with_vault() -> (tauri::App<MockRuntime>, PathBuf) {
+        let root = std::env::temp_dir().join(format!("echopad-commands-{}", uuid::Uuid::new_v4()));
+        let vault = root.join("vault");
+        fs::create_dir_all(&vault).unwrap();
+
+        let app = tauri::test::mock_app();
+        app.manage(local_crypto::LocalEncryption::new());
+        app.manage(note_cache::NoteCache::new());
+        app.manage(note_watch::NoteWatches::new(app.handle().clone()));
+        app.manage(autosave::PendingSaves::new());
+        app.manage(sync::SyncState::new(root.join("echopad.db")));
+        (app, vault)
+    }
+
+    #[test]
+    fn test_set_note_created_at_keeps_pending_edit() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        fs::write(vault.join("Notes/1700000000000.md"), "---\ncreated_at: 1700000000000\n---\nsaved").unwrap();
+
+        // An edit still held back by auto-save
+        autosave::save_note_debounced(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            "Notes".into(),
+            "1700000000000.md".into(),
+            "---\ncreated_at: 1700000000000\n---\nedited".into(),
+            Some(60_000),
+        )
+        .unwrap();
+
+        let metadata = set_note_created_at(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            vault_path,
+            "Notes".into(),
+            "1700000000000.md".into(),
+            1_600_000_000_000,
+            Some(true),
+        )
+        .unwrap();
+
+        assert_eq!(metadata.filename, "1600000000000.md");
+        assert_eq!(metadata.created_at, 1_600_000_000_000);
+        assert!(!vault.join("Notes/1700000000000.md").exists());
+        assert_eq!(
+            fs::read_to_string(vault.join("Notes/1600000000000.md")).unwrap(),
+            "---\ncreated_at: 1600000000000\n---\nedited"
+        );
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_split_note_without_original_keeps_frontmatter() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        fs::write(vault.join("Notes/1700000000000.md"), "---\ntags: [a]\n---\nOne\n---\nTwo\n").unwrap();
+
+        let notes = split_note(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            vault_path,
+            "Notes".into(),
+            "1700000000000.md".into(),
+            vault::SplitMode::HorizontalRule,
+            Some(false),
+        )
+        .unwrap();
+
+        let contents: Vec<&str> = notes.iter().map(|note| note.content.as_str()).collect();
+        assert_eq!(contents, vec!["---\ntags: [a]\n---\nOne\n", "Two\n"]);
+        assert!(!vault.join("Notes/1700000000000.md").exists());
+        assert_eq!(
+            fs::read_to_string(vault.join("Notes").join(&notes[0].filename)).unwrap(),
+            "---\ntags: [a]\n---\nOne\n"
+        );
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_split_note_splits_pending_edit() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::write(vault.join("1700000000000.md"), "saved").unwrap();
+
+        autosave::save_note_debounced(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            String::new(),
+            "1700000000000.md".into(),
+            "One\n---\nTwo\n".into(),
+            Some(60_000),
+        )
+        .unwrap();
+
+        let notes = split_note(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            vault_path,
+            String::new(),
+            "1700000000000.md".into(),
+            vault::SplitMode::HorizontalRule,
+            Some(true),
+        )
+        .unwrap();
+
+        assert_eq!(notes.len(), 2);
+        assert_eq!(fs::read_to_string(vault.join("1700000000000.md")).unwrap(), "One\n");
+        assert_eq!(autosave::flush_pending_saves(app.handle().clone(), app.state()).unwrap(), 0);
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_note_metadata_compares_sync_state_like_the_scanner() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        // A notebook name in NFD, as macOS hands it out
+        let notebook = "Cafe\u{301}";
+        let synced = "---\ntags: [a]\n---\nbody";
+        fs::create_dir_all(vault.join(notebook)).unwrap();
+        fs::write(vault.join(notebook).join("1700000000000.md"), synced).unwrap();
+
+        let sync_state = app.state::<sync::SyncState>();
+        let manager = &sync_state.state_manager;
+        manager.enable_vault(&vault_path, "vault-1");
+        manager.set_hash_note_body_only(&vault_path, true);
+        let relative_path = "Caf\u{e9}/1700000000000.md";
+        manager.mark_synced_with_body_by_id(
+            "vault-1",
+            relative_path,
+            &sync::scanner::compute_hash(synced.as_bytes()),
+            sync::scanner::compute_body_hash(relative_path, synced.as_bytes()),
+            1,
+        );
+        let unsynced = || {
+            get_note_metadata(app.state(), app.state(), vault_path.clone(), notebook.into(), "1700000000000.md".into())
+                .unwrap()
+                .has_unsynced_changes
+        };
+        assert_eq!(unsynced(), Some(false));
+
+        // Only the frontmatter changed
+        fs::write(vault.join(notebook).join("1700000000000.md"), "---\ntags: [b]\n---\nbody").unwrap();
+        assert_eq!(unsynced(), Some(false));
+
+        fs::write(vault.join(notebook).join("1700000000000.md"), "---\ntags: [b]\n---\nedited").unwrap();
+        assert_eq!(unsynced(), Some(true));
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_favicon_fallbacks_keep_the_port() {
+        let base = url::Url::parse("http://localhost:8080/blog/post?id=1").unwrap();
+        assert_eq!(
+            favicon_fallbacks(&base),
+            vec![
+                "http://localhost:8080/favicon.ico",
+                "http://localhost:8080/favicon.png",
+                "http://localhost:8080/apple-touch-icon.png",
+            ]
+        );
+        assert!(favicon_fallbacks(&url::Url::parse("data:text/html,hi").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_note_bytes_of_a_placeholder_are_refused() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::write(vault.join("1700000000000.md"), "").unwrap();
+        let sync_state = app.state::<sync::SyncState>();
+        sync_state.state_manager.enable_vault(&vault_path, "vault-1");
+        sync_state
+            .state_manager
+            .mark_placeholder_by_id("vault-1", "1700000000000.md", "remote-hash", 1, "/download");
+
+        assert!(read_note_bytes(app.state(), vault_path.clone(), String::new(), "1700000000000.md".into()).is_err());
+        let written = write_note_bytes(
+            app.state(),
+            app.state(),
+            app.state(),
+            app.state(),
+            vault_path,
+            String::new(),
+            "1700000000000.md".into(),
+            BASE64.encode(b"edit"),
+        );
+        assert!(written.is_err());
+        assert_eq!(fs::read(vault.join("1700000000000.md")).unwrap(), b"");
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_pending_save_is_read_and_follows_a_move() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::create_dir_all(vault.join("Inbox")).unwrap();
+        fs::create_dir_all(vault.join("Archive")).unwrap();
+        fs::write(vault.join("Inbox/1700000000000.md"), "saved").unwrap();
+
+        autosave::save_note_debounced(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            "Inbox".into(),
+            "1700000000000.md".into(),
+            "edited".into(),
+            Some(60_000),
+        )
+        .unwrap();
+
+        let note = read_note(
+            app.state(),
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            "Inbox".into(),
+            "1700000000000.md".into(),
+        )
+        .unwrap();
+        assert_eq!(note.content, "edited");
+
+        let results = move_notes(
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            "Inbox".into(),
+            vec!["1700000000000.md".into()],
+            "Archive".into(),
+        )
+        .unwrap();
+        assert!(results[0].success);
+
+        assert_eq!(autosave::flush_pending_saves(app.handle().clone(), app.state()).unwrap(), 1);
+        assert!(!vault.join("Inbox/1700000000000.md").exists());
+        assert_eq!(fs::read_to_string(vault.join("Archive/1700000000000.md")).unwrap(), "edited");
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_deleted_note_drops_pending_save() {
+        let (app, vault) = mock_app_with_vault();
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::write(vault.join("1700000000000.md"), "saved").unwrap();
+
+        autosave::save_note_debounced(
+            app.handle().clone(),
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            String::new(),
+            "1700000000000.md".into(),
+            "edited".into(),
+            Some(60_000),
+        )
+        .unwrap();
+        delete_note(app.state(), app.state(), vault_path, String::new(), "1700000000000.md".into()).unwrap();
+
+        assert_eq!(autosave::flush_pending_saves(app.handle().clone(), app.state()).unwrap(), 0);
+        assert!(!vault.join("1700000000000.md").exists());
+
+        let _ = fs::remove_dir_all(vault.parent().unwrap());
+    }
+
+    #[test]
+    fn test_export_note_keeps_attachments_inside_the_vault() {
+        let (app, vault) = mock_app_with_vault();
+        let root = vault.parent().unwrap().to_path_buf();
+        let output = root.join("out");
+        fs::create_dir_all(vault.join("attachments")).unwrap();
+        fs::create_dir_all(&output).unwrap();
+        fs::write(vault.join("attachments/a.png"), b"png").unwrap();
+        fs::write(root.join("secret.png"), b"secret").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(root.join("secret.png"), vault.join("attachments/link.png")).unwrap();
+        fs::write(
+            vault.join("note.md"),
+            "![](attachments/a.png) ![](attachments/../../secret.png) ![](attachments/link.png)\nPlain attachments/a.png",
+        )
+        .unwrap();
+        fs::write(output.join("note.md"), "already here").unwrap();
+
+        let vault_path = vault.to_string_lossy().to_string();
+        let output_path = output.to_string_lossy().to_string();
+        let exported = export_note(
+            app.state(),
+            app.state(),
+            vault_path.clone(),
+            String::new(),
+            "note.md".into(),
+            output_path.clone(),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(exported, [output.join("note-1.md").to_string_lossy().to_string()]);
+        assert_eq!(fs::read_to_string(output.join("note.md")).unwrap(), "already here");
+        let inlined = fs::read_to_string(output.join("note-1.md")).unwrap();
+        assert!(inlined.starts_with(&format!("![](data:image/png;base64,{})", BASE64.encode(b"png"))));
+        assert!(inlined.contains("![](attachments/../../secret.png)"));
+        assert!(inlined.contains("![](attachments/link.png)"));
+        assert!(inlined.ends_with("Plain attachments/a.png"));
+
+        let bundled = export_note(
+            app.state(),
+            app.state(),
+            vault_path,
+            String::new(),
+            "note.md".into(),
+            output_path,
+            false,
+        )
+        .unwrap();
+        assert_eq!(bundled.len(), 2);
+        assert!(output.join("note/attachments/a.png").is_file());
+        assert!(!output.join("note/attachments/link.png").exists());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_export_note_that_is_not_utf8() {
+        let (app, vault) = mock_app_with_vault();
+        let root = vault.parent().unwrap().to_path_buf();
+        let output = root.join("out");
+        fs::create_dir_all(&output).unwrap();
+        fs::write(vault.join("note.md"), b"caf\xe9").unwrap();
+
+        let export = |inline_images| {
+            export_note(
+                app.state(),
+                app.state(),
+                vault.to_string_lossy().to_string(),
+                String::new(),
+                "note.md".into(),
+                output.to_string_lossy().to_string(),
+                inline_images,
+            )
+            .unwrap()
+        };
+        export(true);
+        assert_eq!(fs::read_to_string(output.join("note.md")).unwrap(), "caf\u{fffd}");
+        export(false);
+        assert_eq!(fs::read(output.join("note/note.md")).unwrap(), b"caf\xe9");
+
+        let _ = fs::remove_dir_all(&root);
+    }
 }
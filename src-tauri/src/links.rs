@@ -0,0 +1,332 @@
+//! `[[wikilink]]` rewriting for note renames
+//!
+//! A wikilink names a note by its filename without the extension, either
+//! bare (`[[note]]`) or with its notebook (`[[notebook/note]]`), optionally
+//! followed by a heading anchor (`[[note#Heading]]`) and an alias
+//! (`[[note|shown text]]`). Renaming a note rewrites the target and keeps
+//! the rest, so links keep reading the same. Fenced code blocks and inline
+//! code spans are left untouched. While another note has the old name too,
+//! only links naming the notebook are rewritten, since a bare one may mean
+//! the other note.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{autosave, local_crypto, note_cache, sync, vault};
+
+/// Result of `rename_note_with_links`
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteRename {
+    /// The note's new filename
+    pub filename: String,
+    /// Notes whose links were (or, in a dry run, would be) rewritten,
+    /// relative to the vault
+    pub updated: Vec<String>,
+    /// Notes that couldn't be checked, e.g. encrypted notes of a locked vault
+    pub skipped: Vec<String>,
+    pub dry_run: bool,
+}
+
+/// Which links [`rewrite_wikilinks`] rewrites
+#[derive(Debug, Clone, Copy)]
+pub struct LinkTarget<'a> {
+    pub notebook_path: &'a str,
+    pub old_stem: &'a str,
+    pub new_stem: &'a str,
+    /// Whether a bare `[[old_stem]]` means this note, i.e. no other note
+    /// has that name
+    pub bare_links: bool,
+}
+
+/// Rewrite the target of one link if it points at the renamed note. The
+/// target may carry the notebook path and an extension, which are kept.
+fn rewrite_target(target: &str, link: LinkTarget) -> Option<String> {
+    let LinkTarget { notebook_path, old_stem, new_stem, bare_links } = link;
+    let (dir, name) = match target.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, target),
+    };
+    match dir {
+        Some(dir) if dir.trim_matches('/') != notebook_path => return None,
+        None if !bare_links => return None,
+        _ => {}
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, ext)) if vault::NOTE_EXTENSIONS.contains(&ext.to_lowercase().as_str()) => (stem, Some(ext)),
+        _ => (name, None),
+    };
+    if stem != old_stem {
+        return None;
+    }
+
+    let mut rewritten = String::new();
+    if let Some(dir) = dir {
+        rewritten.push_str(dir);
+        rewritten.push('/');
+    }
+    rewritten.push_str(new_stem);
+    if let Some(ext) = extension {
+        rewritten.push('.');
+        rewritten.push_str(ext);
+    }
+    Some(rewritten)
+}
+
+/// Rewrite the wikilinks in one line outside inline code spans
+fn rewrite_line(line: &str, link: LinkTarget) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+
+    loop {
+        let open = rest.find("[[");
+        let code = rest.find('`');
+        match (open, code) {
+            // An inline code span starts first: copy it through unchanged
+            (_, Some(tick)) if open.map_or(true, |idx| tick < idx) => {
+                let run = rest[tick..].chars().take_while(|&c| c == '`').count();
+                let after = &rest[tick + run..];
+                let end = after.find(&"`".repeat(run)).map_or(rest.len(), |idx| tick + run + idx + run);
+                output.push_str(&rest[..end]);
+                rest = &rest[end..];
+            }
+            (Some(idx), _) => {
+                let after = &rest[idx + 2..];
+                let Some(close) = after.find("]]") else { break };
+                let inner = &after[..close];
+                let target_end = inner.find(['#', '|']).unwrap_or(inner.len());
+                let target = inner[..target_end].trim();
+
+                output.push_str(&rest[..idx + 2]);
+                match rewrite_target(target, link) {
+                    Some(new_target) => {
+                        output.push_str(&new_target);
+                        output.push_str(&inner[target_end..]);
+                    }
+                    None => output.push_str(inner),
+                }
+                output.push_str("]]");
+                rest = &after[close + 2..];
+            }
+            _ => break,
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Point every wikilink to `notebook_path/old_stem` at `new_stem` instead.
+/// Returns `None` when the content has no such links.
+pub fn rewrite_wikilinks(content: &str, link: LinkTarget) -> Option<String> {
+    let mut output = String::with_capacity(content.len());
+    let mut fence: Option<&str> = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        let marker = ["```", "~~~"].into_iter().find(|m| trimmed.starts_with(m));
+        match (fence, marker) {
+            (None, Some(marker)) => {
+                fence = Some(marker);
+                output.push_str(line);
+            }
+            (Some(open), Some(marker)) if open == marker => {
+                fence = None;
+                output.push_str(line);
+            }
+            (Some(_), _) => output.push_str(line),
+            (None, None) => output.push_str(&rewrite_line(line, link)),
+        }
+    }
+
+    (output != content).then_some(output)
+}
+
+fn file_stem(filename: &str) -> String {
+    Path::new(filename)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Rename a note and, with `update_links`, rewrite the `[[wikilinks]]`
+/// pointing at it across the vault. `new_name` is the new filename; the old
+/// extension is kept when it has none. Notes whose links couldn't be updated
+/// are listed as skipped. With `dry_run` nothing is changed and the result
+/// lists the notes that would be updated.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub fn rename_note_with_links(
+    encryption: tauri::State<'_, local_crypto::LocalEncryption>,
+    cache: tauri::State<'_, note_cache::NoteCache>,
+    pending: tauri::State<'_, autosave::PendingSaves>,
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    notebook_path: String,
+    old_filename: String,
+    new_name: String,
+    update_links: bool,
+    dry_run: Option<bool>,
+) -> Result<NoteRename, String> {
+    let dry_run = dry_run.unwrap_or(false);
+    let vault = PathBuf::from(&vault_path);
+    let notebook_path = notebook_path.replace('\\', "/").trim_matches('/').to_string();
+
+    let new_name = new_name.trim();
+    let new_filename = if Path::new(new_name).extension().is_some() {
+        new_name.to_string()
+    } else {
+        match Path::new(&old_filename).extension() {
+            Some(ext) => format!("{}.{}", new_name, ext.to_string_lossy()),
+            None => new_name.to_string(),
+        }
+    };
+    if !vault::is_plain_filename(&old_filename) || !vault::is_plain_filename(&new_filename) {
+        return Err("Invalid note filename".to_string());
+    }
+
+    if !dry_run {
+        vault::ensure_writable(&vault)?;
+    }
+
+    let notebook = vault::resolve_in_vault(&vault, &notebook_path)?;
+    let source = notebook.join(&old_filename);
+    let target = notebook.join(&new_filename);
+    if !source.is_file() {
+        return Err("Note does not exist".to_string());
+    }
+    if new_filename != old_filename && target.exists() {
+        return Err("A note with that name already exists".to_string());
+    }
+
+    let (old_stem, new_stem) = (file_stem(&old_filename), file_stem(&new_filename));
+    let mut rename = NoteRename {
+        filename: new_filename.clone(),
+        updated: Vec::new(),
+        skipped: Vec::new(),
+        dry_run,
+    };
+
+    // The note is renamed first, so a failure leaves every link as it was
+    if !dry_run && new_filename != old_filename {
+        fs::rename(&source, &target).map_err(|e| e.to_string())?;
+        cache.invalidate(&source);
+        pending.rename(&vault::long_path(&source), &vault::long_path(&target));
+
+        if let Some(vault_id) = sync_state.state_manager.get_vault_id_for_path(&vault_path) {
+            let relative = |filename: &str| {
+                if notebook_path.is_empty() {
+                    filename.to_string()
+                } else {
+                    format!("{}/{}", notebook_path, filename)
+                }
+            };
+            sync_state
+                .state_manager
+                .rename_path_prefix(&vault_id, &relative(&old_filename), &relative(&new_filename));
+        }
+    }
+
+    if update_links && old_stem != new_stem {
+        let renamed = if dry_run { &source } else { &target };
+        let notes = vault::collect_note_paths(&vault)?;
+        let link = LinkTarget {
+            notebook_path: &notebook_path,
+            old_stem: &old_stem,
+            new_stem: &new_stem,
+            bare_links: !notes
+                .iter()
+                .any(|path| {
+                    path.file_stem().is_some_and(|stem| stem.to_string_lossy() == old_stem)
+                        && vault::long_path(path) != *renamed
+                }),
+        };
+
+        for path in notes {
+            let relative = path.strip_prefix(&vault).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            let path = vault::long_path(&path);
+            // An edit still held back by auto-save is what the note will
+            // contain, so its links are the ones to rewrite
+            let content = match pending.get(&path).map(Ok).unwrap_or_else(|| {
+                fs::read_to_string(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| encryption.decode(&vault_path, content))
+            }) {
+                Ok(content) => content,
+                Err(_) => {
+                    rename.skipped.push(relative);
+                    continue;
+                }
+            };
+
+            let Some(rewritten) = rewrite_wikilinks(&content, link) else {
+                continue;
+            };
+            if !dry_run {
+                let written = encryption
+                    .encode(&vault_path, &rewritten)
+                    .and_then(|encoded| fs::write(&path, encoded).map_err(|e| e.to_string()));
+                cache.invalidate(&path);
+                if let Err(e) = written {
+                    eprintln!("[Notes] Failed to update links in {}: {}", relative, e);
+                    rename.skipped.push(relative);
+                    continue;
+                }
+                // The held-back edit was written above, links included
+                pending.discard(&path);
+            }
+            rename.updated.push(relative);
+        }
+    }
+
+    rename.updated.sort();
+    println!(
+        "[Notes] Renamed {} to {} ({} notes linking to it)",
+        old_filename,
+        new_filename,
+        rename.updated.len()
+    );
+    Ok(rename)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(notebook_path: &str, bare_links: bool) -> LinkTarget<'_> {
+        LinkTarget {
+            notebook_path,
+            old_stem: "old",
+            new_stem: "new",
+            bare_links,
+        }
+    }
+
+    #[test]
+    fn test_rewrites_links_with_alias_anchor_and_notebook() {
+        let content = "See [[old]], [[old|the plan]] and [[work/old#Goals]].\nNot [[older]] or [[other/old]].\n";
+        let rewritten = rewrite_wikilinks(content, link("work", true)).unwrap();
+        assert_eq!(
+            rewritten,
+            "See [[new]], [[new|the plan]] and [[work/new#Goals]].\nNot [[older]] or [[other/old]].\n"
+        );
+    }
+
+    #[test]
+    fn test_code_is_left_alone() {
+        let content = "```\n[[old]]\n```\nInline `[[old]]` but [[old]]\n";
+        let rewritten = rewrite_wikilinks(content, link("", true)).unwrap();
+        assert_eq!(rewritten, "```\n[[old]]\n```\nInline `[[old]]` but [[new]]\n");
+        assert_eq!(rewrite_wikilinks("no links here", link("", true)), None);
+    }
+
+    #[test]
+    fn test_bare_links_are_kept_while_another_note_has_the_name() {
+        let content = "[[old]] and [[work/old|plan]]\n";
+        let rewritten = rewrite_wikilinks(content, link("work", false)).unwrap();
+        assert_eq!(rewritten, "[[old]] and [[work/new|plan]]\n");
+        assert_eq!(rewrite_wikilinks("[[old]]", link("work", false)), None);
+    }
+}
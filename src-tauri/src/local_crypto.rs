@@ -0,0 +1,481 @@
+//! Optional at-rest encryption of note files
+//!
+//! When enabled for a vault, note content is stored on disk as ciphertext
+//! (XChaCha20-Poly1305 via the sync crypto helpers) under a key derived from
+//! a vault passphrase. The salt and a passphrase verifier live in
+//! `echopad-encryption.json` at the vault root, which syncs like any other
+//! file so other devices can unlock the vault with the same passphrase.
+//! Keys are only held in memory, from `unlock_vault` until `lock_vault` or
+//! app exit.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::sync::crypto::{self, CryptoKey, Salt};
+use crate::vault;
+
+/// Per-vault encryption settings file, relative to the vault root
+pub const ENCRYPTION_CONFIG_FILENAME: &str = "echopad-encryption.json";
+
+/// First line of every encrypted note
+pub const ENCRYPTED_NOTE_HEADER: &str = "echopad-encrypted:v1\n";
+
+/// Plaintext encrypted into the config to check a passphrase on unlock
+const VERIFIER_PLAINTEXT: &str = "echopad-local-encryption";
+
+/// Contents of `echopad-encryption.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptionConfig {
+    version: u32,
+    /// Argon2 salt for the passphrase (base64)
+    salt: String,
+    /// `VERIFIER_PLAINTEXT` encrypted with the vault key
+    verifier: String,
+}
+
+/// Whether note content is in the encrypted on-disk format
+pub fn is_encrypted_note(content: &str) -> bool {
+    content.starts_with(ENCRYPTED_NOTE_HEADER)
+}
+
+fn config_path(vault_path: &str) -> PathBuf {
+    Path::new(vault_path).join(ENCRYPTION_CONFIG_FILENAME)
+}
+
+fn read_config(vault_path: &str) -> Option<EncryptionConfig> {
+    let json = fs::read_to_string(config_path(vault_path)).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Encrypted on-disk form of a note
+fn encrypt_note(key: &CryptoKey, content: &str) -> Result<String, String> {
+    let ciphertext = crypto::encrypt_string(key, content).map_err(|e| e.to_string())?;
+    Ok(format!("{}{}\n", ENCRYPTED_NOTE_HEADER, ciphertext))
+}
+
+/// Temp file a rewritten note is staged in, next to the note and hidden
+fn staged_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.echopad-rewrite", name))
+}
+
+/// Payload of `vault-key-derivation` events
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultKeyDerivationProgress {
+    pub vault_path: String,
+    pub elapsed_ms: u64,
+}
+
+/// Derive the note key for a passphrase. Argon2 is slow, so it runs on the
+/// blocking pool and `vault-key-derivation` events report the elapsed time
+/// meanwhile, for a spinner.
+async fn derive_key(app: &AppHandle, vault_path: &str, passphrase: String, salt: Salt) -> Result<CryptoKey, String> {
+    let master = crypto::derive_master_key_blocking(passphrase, salt, crypto::KEY_DERIVATION_TIMEOUT, |elapsed| {
+        let _ = app.emit(
+            "vault-key-derivation",
+            VaultKeyDerivationProgress {
+                vault_path: vault_path.to_string(),
+                elapsed_ms: elapsed.as_millis() as u64,
+            },
+        );
+    })
+    .await
+    .map_err(|e| e.to_string())?;
+    crypto::derive_keys(&master)
+        .map(|keys| keys.encryption_key)
+        .map_err(|e| e.to_string())
+}
+
+/// Unlocked vault keys, keyed by canonical vault path
+#[derive(Default)]
+pub struct LocalEncryption {
+    keys: RwLock<HashMap<PathBuf, CryptoKey>>,
+}
+
+impl LocalEncryption {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map key for a vault, so the same folder spelled differently (trailing
+    /// separator, `..`, symlink) finds the same unlocked key
+    fn key(vault_path: &str) -> PathBuf {
+        fs::canonicalize(vault_path).unwrap_or_else(|_| PathBuf::from(vault_path))
+    }
+
+    fn unlock(&self, vault_path: &str, key: CryptoKey) {
+        self.keys.write().insert(Self::key(vault_path), key);
+    }
+
+    fn lock(&self, vault_path: &str) {
+        self.keys.write().remove(&Self::key(vault_path));
+    }
+
+    fn is_unlocked(&self, vault_path: &str) -> bool {
+        self.keys.read().contains_key(&Self::key(vault_path))
+    }
+
+    /// Turn stored note content into plaintext. Plain notes pass through
+    /// unchanged; encrypted ones need the vault to be unlocked.
+    pub fn decode(&self, vault_path: &str, content: String) -> Result<String, String> {
+        let Some(ciphertext) = content.strip_prefix(ENCRYPTED_NOTE_HEADER) else {
+            return Ok(content);
+        };
+        let keys = self.keys.read();
+        let key = keys.get(&Self::key(vault_path)).ok_or("Vault is locked")?;
+        crypto::decrypt_string(key, ciphertext.trim_end()).map_err(|e| e.to_string())
+    }
+
+    /// Turn plaintext into what should be written to disk: ciphertext when
+    /// the vault has local encryption enabled, the plaintext otherwise
+    pub fn encode(&self, vault_path: &str, content: &str) -> Result<String, String> {
+        if !config_path(vault_path).exists() {
+            return Ok(content.to_string());
+        }
+        let keys = self.keys.read();
+        let key = keys.get(&Self::key(vault_path)).ok_or("Vault is locked")?;
+        encrypt_note(key, content)
+    }
+}
+
+/// Outcome of turning local encryption on or off
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NoteRewriteReport {
+    /// Notes encrypted or decrypted
+    pub rewritten: u32,
+    /// Notes left as they were because they aren't valid UTF-8, relative to
+    /// the vault root
+    pub skipped: Vec<String>,
+}
+
+/// Rewrite every note in the vault through `convert`, then run `finish`
+/// (which switches the config). All or nothing: the converted notes are
+/// staged in temp files first and renamed over the originals only once
+/// every note converted; if a rename or `finish` fails, the notes already
+/// replaced get their old content back. Notes that aren't valid UTF-8 can't
+/// be in the encrypted format and can't be encrypted as text, so they are
+/// left alone and listed in the report.
+fn rewrite_notes(
+    vault_path: &str,
+    convert: impl Fn(&str) -> Result<Option<String>, String>,
+    finish: impl FnOnce() -> Result<(), String>,
+) -> Result<NoteRewriteReport, String> {
+    let mut staged: Vec<(PathBuf, PathBuf, String)> = Vec::new();
+    let mut skipped = Vec::new();
+    let discard = |staged: &[(PathBuf, PathBuf, String)]| {
+        for (_, temp, _) in staged {
+            let _ = fs::remove_file(temp);
+        }
+    };
+
+    for path in vault::collect_note_paths(Path::new(vault_path))? {
+        let content = match fs::read(&path).map(String::from_utf8) {
+            Ok(Ok(content)) => content,
+            Ok(Err(_)) => {
+                let relative = path.strip_prefix(vault_path).unwrap_or(&path);
+                skipped.push(relative.to_string_lossy().replace('\\', "/"));
+                continue;
+            }
+            Err(e) => {
+                discard(&staged);
+                return Err(format!("{:?}: {}", path, e));
+            }
+        };
+        let prepared = convert(&content).and_then(|converted| match converted {
+            Some(converted) => {
+                let temp = staged_path(&path);
+                fs::write(&temp, converted).map_err(|e| format!("{:?}: {}", temp, e))?;
+                Ok(Some(temp))
+            }
+            None => Ok(None),
+        });
+        match prepared {
+            Ok(Some(temp)) => staged.push((path, temp, content)),
+            Ok(None) => {}
+            Err(e) => {
+                discard(&staged);
+                return Err(e);
+            }
+        }
+    }
+
+    let mut replaced = 0;
+    let mut result = Ok(());
+    for (path, temp, _) in &staged {
+        if let Err(e) = fs::rename(temp, path) {
+            result = Err(format!("{:?}: {}", path, e));
+            break;
+        }
+        replaced += 1;
+    }
+    let result = result.and_then(|()| finish());
+    if let Err(e) = result {
+        for (path, _, original) in &staged[..replaced] {
+            if let Err(restore) = fs::write(path, original) {
+                eprintln!("[Encryption] Failed to restore {:?}: {}", path, restore);
+            }
+        }
+        discard(&staged[replaced..]);
+        return Err(e);
+    }
+    Ok(NoteRewriteReport {
+        rewritten: staged.len() as u32,
+        skipped,
+    })
+}
+
+/// Status of local encryption for a vault
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalEncryptionStatus {
+    pub enabled: bool,
+    pub unlocked: bool,
+}
+
+#[tauri::command]
+pub fn get_local_encryption_status(
+    encryption: State<'_, LocalEncryption>,
+    vault_path: String,
+) -> LocalEncryptionStatus {
+    LocalEncryptionStatus {
+        enabled: config_path(&vault_path).exists(),
+        unlocked: encryption.is_unlocked(&vault_path),
+    }
+}
+
+/// Turn on local encryption for a vault and encrypt its existing notes.
+/// Reports the notes encrypted and those skipped.
+#[tauri::command]
+pub async fn enable_local_encryption(
+    app: AppHandle,
+    encryption: State<'_, LocalEncryption>,
+    vault_path: String,
+    passphrase: String,
+) -> Result<NoteRewriteReport, String> {
+    if config_path(&vault_path).exists() {
+        return Err("Local encryption is already enabled for this vault".to_string());
+    }
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".to_string());
+    }
+
+    let salt = Salt::generate();
+    let salt_b64 = salt.to_base64();
+    let key = derive_key(&app, &vault_path, passphrase, salt).await?;
+
+    let config = EncryptionConfig {
+        version: 1,
+        salt: salt_b64,
+        verifier: crypto::encrypt_string(&key, VERIFIER_PLAINTEXT).map_err(|e| e.to_string())?,
+    };
+    let json = serde_json::to_string_pretty(&config).map_err(|e| e.to_string())?;
+
+    // The config goes last: until it exists notes are read and written as
+    // plaintext, and a failure leaves the vault as it was
+    let encrypted = rewrite_notes(
+        &vault_path,
+        |content| {
+            if is_encrypted_note(content) {
+                Ok(None)
+            } else {
+                encrypt_note(&key, content).map(Some)
+            }
+        },
+        || fs::write(config_path(&vault_path), json).map_err(|e| e.to_string()),
+    )?;
+    encryption.unlock(&vault_path, key);
+    println!(
+        "[Encryption] Encrypted {} notes in {} ({} skipped, not UTF-8)",
+        encrypted.rewritten,
+        vault_path,
+        encrypted.skipped.len()
+    );
+    Ok(encrypted)
+}
+
+/// Decrypt every note in an unlocked vault and turn local encryption off.
+/// Reports the notes decrypted and those skipped.
+#[tauri::command]
+pub fn disable_local_encryption(
+    encryption: State<'_, LocalEncryption>,
+    vault_path: String,
+) -> Result<NoteRewriteReport, String> {
+    if !config_path(&vault_path).exists() {
+        return Err("Local encryption is not enabled for this vault".to_string());
+    }
+    if !encryption.is_unlocked(&vault_path) {
+        return Err("Vault is locked".to_string());
+    }
+
+    let decrypted = rewrite_notes(
+        &vault_path,
+        |content| {
+            if is_encrypted_note(content) {
+                encryption.decode(&vault_path, content.to_string()).map(Some)
+            } else {
+                Ok(None)
+            }
+        },
+        || fs::remove_file(config_path(&vault_path)).map_err(|e| e.to_string()),
+    )?;
+    encryption.lock(&vault_path);
+    Ok(decrypted)
+}
+
+/// Derive the vault key from its passphrase and keep it in memory so notes
+/// can be read and written
+#[tauri::command]
+pub async fn unlock_vault(
+    app: AppHandle,
+    encryption: State<'_, LocalEncryption>,
+    vault_path: String,
+    passphrase: String,
+) -> Result<(), String> {
+    let config = read_config(&vault_path).ok_or("Local encryption is not enabled for this vault")?;
+    let salt = Salt::from_base64(&config.salt).map_err(|e| e.to_string())?;
+    let key = derive_key(&app, &vault_path, passphrase, salt).await?;
+
+    match crypto::decrypt_string(&key, &config.verifier) {
+        Ok(plaintext) if plaintext == VERIFIER_PLAINTEXT => {
+            encryption.unlock(&vault_path, key);
+            Ok(())
+        }
+        _ => Err("Incorrect passphrase".to_string()),
+    }
+}
+
+/// Forget a vault's key
+#[tauri::command]
+pub fn lock_vault(encryption: State<'_, LocalEncryption>, vault_path: String) {
+    encryption.lock(&vault_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_notes_rolls_back_when_finish_fails() {
+        let vault = std::env::temp_dir().join(format!("echopad-crypto-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        fs::write(vault.join("a.md"), "a").unwrap();
+        fs::write(vault.join("Notes/b.md"), "b").unwrap();
+        let vault_path = vault.to_string_lossy().to_string();
+
+        let result = rewrite_notes(
+            &vault_path,
+            |content| Ok(Some(content.to_uppercase())),
+            || Err("config not written".to_string()),
+        );
+        assert_eq!(result, Err("config not written".to_string()));
+        assert_eq!(fs::read_to_string(vault.join("a.md")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(vault.join("Notes/b.md")).unwrap(), "b");
+        assert!(!staged_path(&vault.join("a.md")).exists());
+
+        let rewritten = rewrite_notes(&vault_path, |content| Ok(Some(content.to_uppercase())), || Ok(())).unwrap();
+        assert_eq!(rewritten.rewritten, 2);
+        assert_eq!(fs::read_to_string(vault.join("Notes/b.md")).unwrap(), "B");
+
+        let _ = fs::remove_dir_all(&vault);
+    }
+
+    #[test]
+    fn test_rewrite_notes_rolls_back_when_a_note_fails_to_convert() {
+        let vault = std::env::temp_dir().join(format!("echopad-crypto-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("a.md"), "a").unwrap();
+        fs::write(vault.join("b.md"), "b").unwrap();
+        fs::write(vault.join("c.md"), "bad").unwrap();
+        let vault_path = vault.to_string_lossy().to_string();
+
+        let result = rewrite_notes(
+            &vault_path,
+            |content| {
+                if content == "bad" {
+                    Err("cannot convert".to_string())
+                } else {
+                    Ok(Some(content.to_uppercase()))
+                }
+            },
+            || panic!("finish must not run"),
+        );
+        assert_eq!(result, Err("cannot convert".to_string()));
+        for name in ["a.md", "b.md", "c.md"] {
+            assert!(!staged_path(&vault.join(name)).exists());
+        }
+        assert_eq!(fs::read_to_string(vault.join("a.md")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(vault.join("b.md")).unwrap(), "b");
+
+        let _ = fs::remove_dir_all(&vault);
+    }
+
+    #[test]
+    fn test_rewrite_notes_skips_notes_that_are_not_utf8() {
+        let vault = std::env::temp_dir().join(format!("echopad-crypto-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        fs::write(vault.join("a.md"), "a").unwrap();
+        fs::write(vault.join("Notes/latin1.md"), b"caf\xe9").unwrap();
+        let vault_path = vault.to_string_lossy().to_string();
+
+        let report = rewrite_notes(&vault_path, |content| Ok(Some(content.to_uppercase())), || Ok(())).unwrap();
+        assert_eq!(
+            report,
+            NoteRewriteReport {
+                rewritten: 1,
+                skipped: vec!["Notes/latin1.md".to_string()],
+            }
+        );
+        assert_eq!(fs::read_to_string(vault.join("a.md")).unwrap(), "A");
+        assert_eq!(fs::read(vault.join("Notes/latin1.md")).unwrap(), b"caf\xe9");
+
+        let _ = fs::remove_dir_all(&vault);
+    }
+
+    #[test]
+    fn test_locked_vault_refuses_encrypted_notes() {
+        let vault = std::env::temp_dir().join(format!("echopad-crypto-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(config_path(&vault.to_string_lossy()), "{}").unwrap();
+        let vault_path = vault.to_string_lossy().to_string();
+        let encryption = LocalEncryption::new();
+        let key = CryptoKey::generate();
+        let stored = encrypt_note(&key, "secret").unwrap();
+
+        assert_eq!(encryption.encode(&vault_path, "secret"), Err("Vault is locked".to_string()));
+        assert_eq!(encryption.decode(&vault_path, stored.clone()), Err("Vault is locked".to_string()));
+        assert_eq!(encryption.decode(&vault_path, "plain".to_string()), Ok("plain".to_string()));
+
+        encryption.unlock(&vault_path, key);
+        assert_eq!(encryption.decode(&vault_path, stored.clone()), Ok("secret".to_string()));
+
+        encryption.lock(&vault_path);
+        assert!(!encryption.is_unlocked(&vault_path));
+        assert_eq!(encryption.decode(&vault_path, stored), Err("Vault is locked".to_string()));
+
+        let _ = fs::remove_dir_all(&vault);
+    }
+
+    #[test]
+    fn test_unlocked_key_is_found_under_another_spelling_of_the_vault_path() {
+        let vault = std::env::temp_dir().join(format!("echopad-crypto-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        let vault_path = vault.to_string_lossy().to_string();
+        let encryption = LocalEncryption::new();
+        let key = CryptoKey::generate();
+        let stored = encrypt_note(&key, "secret").unwrap();
+
+        encryption.unlock(&format!("{}/", vault_path), key);
+        assert!(encryption.is_unlocked(&vault_path));
+        let dotted = vault.join("Notes").join("..").to_string_lossy().to_string();
+        assert_eq!(encryption.decode(&dotted, stored), Ok("secret".to_string()));
+
+        encryption.lock(&dotted);
+        assert!(!encryption.is_unlocked(&vault_path));
+
+        let _ = fs::remove_dir_all(&vault);
+    }
+}
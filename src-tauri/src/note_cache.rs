@@ -0,0 +1,146 @@
+//! Small in-memory cache of recently read note files
+//!
+//! Flipping between a handful of open notes would otherwise re-read each
+//! one from disk every time. Entries hold the raw file bytes and are only
+//! served while the file's modification time and size still match, so edits
+//! made outside the app are picked up. The cache is bounded by entry count
+//! and total size and evicts the least recently used note first.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+
+/// Most notes kept at once
+const MAX_ENTRIES: usize = 32;
+
+/// Upper bound for the bytes held by all entries together
+const MAX_TOTAL_BYTES: usize = 8 * 1024 * 1024;
+
+/// Notes larger than this are never cached
+const MAX_ENTRY_BYTES: usize = MAX_TOTAL_BYTES / 4;
+
+struct CachedNote {
+    modified: Option<SystemTime>,
+    len: u64,
+    bytes: Vec<u8>,
+}
+
+#[derive(Default)]
+struct CacheInner {
+    entries: HashMap<PathBuf, CachedNote>,
+    /// Least recently used first
+    order: VecDeque<PathBuf>,
+    total_bytes: usize,
+}
+
+impl CacheInner {
+    fn touch(&mut self, key: &Path) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            let key = self.order.remove(pos).unwrap_or_default();
+            self.order.push_back(key);
+        }
+    }
+
+    fn remove(&mut self, key: &Path) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.total_bytes -= entry.bytes.len();
+            self.order.retain(|k| k != key);
+        }
+    }
+
+    fn evict_to_fit(&mut self, incoming: usize) {
+        while self.entries.len() >= MAX_ENTRIES || self.total_bytes + incoming > MAX_TOTAL_BYTES {
+            let Some(oldest) = self.order.pop_front() else { break };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.total_bytes -= entry.bytes.len();
+            }
+        }
+    }
+}
+
+/// LRU cache of note contents, keyed by canonical path
+pub struct NoteCache {
+    enabled: AtomicBool,
+    inner: Mutex<CacheInner>,
+}
+
+impl NoteCache {
+    pub fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(true),
+            inner: Mutex::new(CacheInner::default()),
+        }
+    }
+
+    fn key(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    /// Cached bytes for `path`, if present and still matching `metadata`
+    pub fn get(&self, path: &Path, metadata: &Metadata) -> Option<Vec<u8>> {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        let key = Self::key(path);
+        let mut inner = self.inner.lock();
+        let fresh = inner
+            .entries
+            .get(&key)
+            .map(|entry| entry.modified == metadata.modified().ok() && entry.len == metadata.len())?;
+        if !fresh {
+            inner.remove(&key);
+            return None;
+        }
+
+        inner.touch(&key);
+        inner.entries.get(&key).map(|entry| entry.bytes.clone())
+    }
+
+    /// Remember the bytes just read from `path`
+    pub fn insert(&self, path: &Path, metadata: &Metadata, bytes: &[u8]) {
+        if !self.enabled.load(Ordering::Relaxed) || bytes.len() > MAX_ENTRY_BYTES {
+            return;
+        }
+
+        let key = Self::key(path);
+        let mut inner = self.inner.lock();
+        inner.remove(&key);
+        inner.evict_to_fit(bytes.len());
+        inner.total_bytes += bytes.len();
+        inner.order.push_back(key.clone());
+        inner.entries.insert(
+            key,
+            CachedNote {
+                modified: metadata.modified().ok(),
+                len: metadata.len(),
+                bytes: bytes.to_vec(),
+            },
+        );
+    }
+
+    /// Drop the entry for a note about to be deleted or moved, or just
+    /// written. Call it while the file still exists so its path resolves.
+    pub fn invalidate(&self, path: &Path) {
+        let key = Self::key(path);
+        self.inner.lock().remove(&key);
+    }
+
+    /// Turn caching on or off; turning it off also empties the cache
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            *self.inner.lock() = CacheInner::default();
+        }
+    }
+}
+
+/// Enable or disable the in-memory cache of recently read notes
+#[tauri::command]
+pub fn set_note_cache_enabled(cache: tauri::State<'_, NoteCache>, enabled: bool) {
+    cache.set_enabled(enabled);
+}
@@ -0,0 +1,230 @@
+//! Change notifications for open notes
+//!
+//! A note open in the editor can be changed on disk by another editor or by
+//! a sync pulling an update. `watch_note` registers the note and a
+//! `note-changed` event is emitted whenever its content on disk changes, so
+//! the editor can offer to reload instead of overwriting the change on its
+//! next save. Writes made by Echopad itself are recorded and don't trigger
+//! the event.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+
+use crate::local_crypto;
+use crate::sync::scanner::compute_hash;
+use crate::sync::watcher::{FileChange, VaultWatcher};
+use crate::vault;
+
+/// Payload of the `note-changed` event
+#[derive(Debug, Clone, Serialize)]
+pub struct NoteChanged {
+    pub vault_path: String,
+    pub notebook_path: String,
+    pub filename: String,
+    /// The note was deleted or moved away
+    pub deleted: bool,
+    /// New content, or `None` when it can't be read (e.g. the vault is
+    /// locked); the editor should then reload the note
+    pub content: Option<String>,
+}
+
+struct WatchedNote {
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+    /// Hash of the content last seen or written by Echopad (`None` while
+    /// the file is missing)
+    last_hash: Option<String>,
+}
+
+/// Notes registered with `watch_note`, keyed by their path on disk
+pub struct NoteWatches {
+    watcher: VaultWatcher,
+    notes: Arc<Mutex<HashMap<PathBuf, WatchedNote>>>,
+}
+
+fn read_hash(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|bytes| compute_hash(&bytes))
+}
+
+impl NoteWatches {
+    /// Create the registry and emit `note-changed` events from its
+    /// filesystem events on a background thread
+    pub fn new<R: Runtime>(app: AppHandle<R>) -> Self {
+        let watcher = VaultWatcher::new();
+        let notes: Arc<Mutex<HashMap<PathBuf, WatchedNote>>> = Arc::new(Mutex::new(HashMap::new()));
+
+        if let Some(rx) = watcher.take_receiver() {
+            let notes = Arc::clone(&notes);
+            std::thread::spawn(move || {
+                for change in rx {
+                    if let Some(changed) = Self::detect_change(&notes, &change) {
+                        let _ = app.emit("note-changed", Self::with_content(&app, changed));
+                    }
+                }
+            });
+        }
+
+        Self { watcher, notes }
+    }
+
+    /// The watched note a filesystem event is about, if its content really
+    /// changed since it was last seen
+    fn detect_change(notes: &Mutex<HashMap<PathBuf, WatchedNote>>, change: &FileChange) -> Option<NoteChanged> {
+        let path = vault::long_path(Path::new(&change.vault_path).join(&change.relative_path));
+        let mut notes = notes.lock();
+        let note = notes.get_mut(&path)?;
+
+        let hash = read_hash(&path);
+        if hash == note.last_hash {
+            return None;
+        }
+        note.last_hash = hash;
+        Some(NoteChanged {
+            vault_path: note.vault_path.clone(),
+            notebook_path: note.notebook_path.clone(),
+            filename: note.filename.clone(),
+            deleted: note.last_hash.is_none(),
+            content: None,
+        })
+    }
+
+    /// Fill in the new content where it can be read
+    fn with_content<R: Runtime>(app: &AppHandle<R>, mut changed: NoteChanged) -> NoteChanged {
+        if changed.deleted {
+            return changed;
+        }
+        let path = vault::long_path(
+            Path::new(&changed.vault_path)
+                .join(&changed.notebook_path)
+                .join(&changed.filename),
+        );
+        changed.content = fs::read(&path)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|content| {
+                app.state::<local_crypto::LocalEncryption>()
+                    .decode(&changed.vault_path, content)
+                    .ok()
+            });
+        changed
+    }
+
+    /// Record content Echopad is about to write to a note, so the write
+    /// isn't reported as an external change
+    pub fn record_write(&self, path: &Path, bytes: &[u8]) {
+        if let Some(note) = self.notes.lock().get_mut(path) {
+            note.last_hash = Some(compute_hash(bytes));
+        }
+    }
+
+    fn watch(&self, vault_path: &str, notebook_path: &str, filename: &str) -> Result<(), String> {
+        let vault = PathBuf::from(vault_path);
+        let path = vault::resolve_in_vault(&vault, notebook_path)?.join(filename);
+        if !path.is_file() {
+            return Err("Note does not exist".to_string());
+        }
+
+        if !self.watcher.watched_vaults().contains(&vault) {
+            self.watcher.watch(&vault).map_err(|e| e.to_string())?;
+        }
+        self.notes.lock().insert(
+            path.clone(),
+            WatchedNote {
+                vault_path: vault_path.to_string(),
+                notebook_path: notebook_path.to_string(),
+                filename: filename.to_string(),
+                last_hash: read_hash(&path),
+            },
+        );
+        Ok(())
+    }
+
+    /// Forget a note; the vault stops being watched with its last note
+    fn unwatch(&self, vault_path: &str, notebook_path: &str, filename: &str) -> Result<(), String> {
+        let vault = PathBuf::from(vault_path);
+        let path = vault::resolve_in_vault(&vault, notebook_path)?.join(filename);
+
+        let mut notes = self.notes.lock();
+        notes.remove(&path);
+        if !notes.values().any(|note| note.vault_path == vault_path) {
+            self.watcher.unwatch(&vault);
+        }
+        Ok(())
+    }
+}
+
+/// Emit `note-changed` when a note's content changes on disk, until
+/// `unwatch_note` is called for it
+#[tauri::command]
+pub fn watch_note(
+    watches: State<'_, NoteWatches>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+) -> Result<(), String> {
+    if !vault::is_plain_filename(&filename) {
+        return Err(format!("Invalid note filename: {}", filename));
+    }
+    watches.watch(&vault_path, &notebook_path, &filename)
+}
+
+/// Stop reporting changes of a note registered with `watch_note`
+#[tauri::command]
+pub fn unwatch_note(
+    watches: State<'_, NoteWatches>,
+    vault_path: String,
+    notebook_path: String,
+    filename: String,
+) -> Result<(), String> {
+    watches.unwatch(&vault_path, &notebook_path, &filename)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::types::ChangeOperation;
+
+    #[test]
+    fn test_only_real_content_changes_are_reported() {
+        let vault = std::env::temp_dir().join(format!("echopad-note-watch-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        let path = vault.join("Notes").join("a.md");
+        fs::write(&path, "one").unwrap();
+
+        let notes = Mutex::new(HashMap::new());
+        notes.lock().insert(
+            path.clone(),
+            WatchedNote {
+                vault_path: vault.to_string_lossy().to_string(),
+                notebook_path: "Notes".into(),
+                filename: "a.md".into(),
+                last_hash: read_hash(&path),
+            },
+        );
+        let change = |relative: &str| FileChange {
+            vault_path: vault.to_string_lossy().to_string(),
+            relative_path: relative.to_string(),
+            operation: ChangeOperation::Update,
+        };
+
+        assert!(NoteWatches::detect_change(&notes, &change("Notes/a.md")).is_none());
+        fs::write(&path, "two").unwrap();
+        let changed = NoteWatches::detect_change(&notes, &change("Notes/a.md")).unwrap();
+        assert!(!changed.deleted);
+        // A second event for the same write is not reported again
+        assert!(NoteWatches::detect_change(&notes, &change("Notes/a.md")).is_none());
+        assert!(NoteWatches::detect_change(&notes, &change("Notes/b.md")).is_none());
+
+        fs::remove_file(&path).unwrap();
+        assert!(NoteWatches::detect_change(&notes, &change("Notes/a.md")).unwrap().deleted);
+
+        let _ = fs::remove_dir_all(vault);
+    }
+}
@@ -0,0 +1,210 @@
+//! Choosing the link preview image among a page's `og:image` tags
+//!
+//! Pages often declare several `og:image`s (different sizes, or a logo
+//! first), so taking the first one can give a tiny preview. Every candidate
+//! is collected with its declared `og:image:width`/`og:image:height`, and
+//! the largest wins. When images are validated, each candidate's real size
+//! is read from the first bytes of the file instead.
+
+use std::io::Read;
+
+use scraper::{Html, Selector};
+
+/// Bytes fetched from an image to read its dimensions
+const HEADER_BYTES: u64 = 64 * 1024;
+
+/// One `og:image` declared by a page
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCandidate {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl ImageCandidate {
+    fn declared_size(&self) -> Option<(u32, u32)> {
+        Some((self.width?, self.height?))
+    }
+}
+
+fn area((width, height): (u32, u32)) -> u64 {
+    u64::from(width) * u64::from(height)
+}
+
+/// The page's `og:image`s in document order. `og:image:width` and
+/// `og:image:height` describe the `og:image` before them.
+pub fn candidates(document: &Html) -> Vec<ImageCandidate> {
+    let selector = Selector::parse("meta[property^='og:image']").unwrap();
+    let mut candidates: Vec<ImageCandidate> = Vec::new();
+    for element in document.select(&selector) {
+        let (Some(property), Some(content)) = (element.value().attr("property"), element.value().attr("content"))
+        else {
+            continue;
+        };
+        let content = content.trim();
+        match property {
+            "og:image" if !content.is_empty() => candidates.push(ImageCandidate {
+                url: content.to_string(),
+                width: None,
+                height: None,
+            }),
+            "og:image:width" => {
+                if let Some(last) = candidates.last_mut() {
+                    last.width = content.parse().ok();
+                }
+            }
+            "og:image:height" => {
+                if let Some(last) = candidates.last_mut() {
+                    last.height = content.parse().ok();
+                }
+            }
+            _ => {}
+        }
+    }
+    candidates
+}
+
+/// The candidate with the largest size, where `size_of` gives a
+/// candidate's size or skips it by returning `None`. Candidates of unknown
+/// size count as 0x0; on a tie the earlier one wins.
+pub fn largest(
+    candidates: Vec<ImageCandidate>,
+    mut size_of: impl FnMut(&ImageCandidate) -> Option<Option<(u32, u32)>>,
+) -> Option<String> {
+    let mut best: Option<(u64, String)> = None;
+    for candidate in candidates {
+        let Some(size) = size_of(&candidate) else {
+            continue;
+        };
+        let area = size.map_or(0, area);
+        if best.as_ref().map_or(true, |(best_area, _)| area > *best_area) {
+            best = Some((area, candidate.url));
+        }
+    }
+    best.map(|(_, url)| url)
+}
+
+/// The candidate with the largest declared dimensions
+pub fn largest_declared(candidates: Vec<ImageCandidate>) -> Option<String> {
+    largest(candidates, |candidate| Some(candidate.declared_size()))
+}
+
+/// Width and height from the start of a PNG, GIF, JPEG or WebP file
+pub fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+    let u16_be = |at: usize| Some(u32::from(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?)));
+    let u16_le = |at: usize| Some(u32::from(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?)));
+    let u32_be = |at: usize| Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let u24_le = |at: usize| {
+        let b = bytes.get(at..at + 3)?;
+        Some(u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16)
+    };
+
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((u32_be(16)?, u32_be(20)?));
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some((u16_le(6)?, u16_le(8)?));
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return match bytes.get(12..16)? {
+            b"VP8 " => Some((u16_le(26)? & 0x3fff, u16_le(28)? & 0x3fff)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(bytes.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+            }
+            b"VP8X" => Some((u24_le(24)? + 1, u24_le(27)? + 1)),
+            _ => None,
+        };
+    }
+    if bytes.starts_with(&[0xff, 0xd8]) {
+        // Walk the segments up to the start-of-frame marker
+        let mut at = 2;
+        while *bytes.get(at)? == 0xff {
+            let marker = *bytes.get(at + 1)?;
+            let is_frame = matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc);
+            if is_frame {
+                return Some((u16_be(at + 7)?, u16_be(at + 5)?));
+            }
+            at += 2 + u16_be(at + 2)? as usize;
+        }
+    }
+    None
+}
+
+/// Fetch the first bytes of an image and read its dimensions. `None` means
+/// the URL doesn't serve an image; `Some(None)` is an image whose size
+/// couldn't be read.
+pub fn fetch_dimensions(client: &reqwest::blocking::Client, url: &str) -> Option<Option<(u32, u32)>> {
+    let response = client
+        .get(url)
+        .header(reqwest::header::RANGE, format!("bytes=0-{}", HEADER_BYTES - 1))
+        .timeout(std::time::Duration::from_secs(3))
+        .send()
+        .ok()?;
+    let is_image = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().to_ascii_lowercase().starts_with("image/"));
+    if !response.status().is_success() || !is_image {
+        return None;
+    }
+
+    let mut header = Vec::new();
+    let _ = response.take(HEADER_BYTES).read_to_end(&mut header);
+    Some(image_dimensions(&header))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_largest_declared_og_image_is_chosen() {
+        let document = Html::parse_document(
+            r#"<head>
+            <meta property="og:image" content="https://example.com/logo.png">
+            <meta property="og:image:width" content="64">
+            <meta property="og:image:height" content="64">
+            <meta property="og:image" content="https://example.com/hero.jpg">
+            <meta property="og:image:width" content="1200">
+            <meta property="og:image:height" content="630">
+            <meta property="og:image" content="https://example.com/unsized.jpg">
+            </head>"#,
+        );
+        let found = candidates(&document);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[1].declared_size(), Some((1200, 630)));
+        assert_eq!(found[2].declared_size(), None);
+        assert_eq!(largest_declared(found).as_deref(), Some("https://example.com/hero.jpg"));
+
+        // Without declared sizes the first image is kept
+        let document = Html::parse_document(
+            r#"<meta property="og:image" content="a.png"><meta property="og:image" content="b.png">"#,
+        );
+        assert_eq!(largest_declared(candidates(&document)).as_deref(), Some("a.png"));
+    }
+
+    #[test]
+    fn test_image_dimensions_from_headers() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&800u32.to_be_bytes());
+        png.extend_from_slice(&600u32.to_be_bytes());
+        assert_eq!(image_dimensions(&png), Some((800, 600)));
+
+        let gif = [b"GIF89a".as_slice(), &[0x20, 0x03, 0x58, 0x02]].concat();
+        assert_eq!(image_dimensions(&gif), Some((800, 600)));
+
+        // SOI, an APP0 segment, then SOF0 with height 600 and width 800
+        let jpeg = [
+            [0xff, 0xd8].as_slice(),
+            &[0xff, 0xe0, 0x00, 0x04, 0x00, 0x00],
+            &[0xff, 0xc0, 0x00, 0x11, 0x08, 0x02, 0x58, 0x03, 0x20],
+        ]
+        .concat();
+        assert_eq!(image_dimensions(&jpeg), Some((800, 600)));
+
+        assert_eq!(image_dimensions(b"<html>"), None);
+        assert_eq!(image_dimensions(&png[..18]), None);
+    }
+}
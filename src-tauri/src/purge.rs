@@ -0,0 +1,165 @@
+//! Wiping local Echopad data from this device
+//!
+//! Consolidates the per-subsystem `clear` calls into one operation that
+//! reports exactly what it removed, for "start fresh" and device hand-off.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sqlx::Row;
+use tauri::State;
+
+use crate::index::NoteIndex;
+use crate::sync::SyncState;
+
+/// Phrase that must be passed as `confirmation` before vault files are deleted
+pub const PURGE_VAULT_FILES_CONFIRMATION: &str = "delete my vault files";
+
+/// Tables in the frontend's `echopad.db` holding data that can be rebuilt.
+/// The note index is cleared separately, as it may live elsewhere.
+const CACHE_TABLES: &[&str] = &["og_cache"];
+
+/// What `purge_device_data` removed
+#[derive(Debug, Default, Serialize)]
+pub struct PurgeReport {
+    /// Files deleted from the app data directory
+    pub removed_files: Vec<String>,
+    /// Database tables that were emptied
+    pub cleared_tables: Vec<String>,
+    /// Vault folders whose contents were deleted
+    pub removed_vaults: Vec<String>,
+    /// Steps that failed; the rest of the purge still ran
+    pub errors: Vec<String>,
+}
+
+/// Names of the given tables that exist in the database
+async fn existing_tables(index: &NoteIndex, tables: &[&str]) -> Result<Vec<String>, String> {
+    let rows = sqlx::query("SELECT name FROM sqlite_master WHERE type = 'table'")
+        .fetch_all(index.pool())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| row.get::<String, _>(0))
+        .filter(|name| tables.contains(&name.as_str()))
+        .collect())
+}
+
+/// Delete everything inside a vault folder, keeping the folder itself
+fn remove_vault_contents(vault_path: &Path) -> Result<(), String> {
+    for entry in fs::read_dir(vault_path).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path).map_err(|e| e.to_string())?;
+        } else {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Wipe local Echopad data: signs out, deletes the stored session and sync
+/// state (including its backup) and empties the caches. Vault contents are
+/// only deleted when `include_vault_files` is set and `confirmation` matches
+/// [`PURGE_VAULT_FILES_CONFIRMATION`].
+#[tauri::command]
+pub async fn purge_device_data(
+    sync_state: State<'_, SyncState>,
+    index: State<'_, NoteIndex>,
+    include_vault_files: bool,
+    confirmation: Option<String>,
+) -> Result<PurgeReport, String> {
+    if include_vault_files && confirmation.as_deref() != Some(PURGE_VAULT_FILES_CONFIRMATION) {
+        return Err("Deleting vault files requires explicit confirmation".to_string());
+    }
+
+    let mut report = PurgeReport::default();
+
+    // Collect vault folders before the sync state that knows them is gone
+    let mut vault_paths: Vec<String> = sync_state
+        .state_manager
+        .get_all_vault_states()
+        .into_iter()
+        .map(|v| v.vault_path)
+        .collect();
+    if include_vault_files {
+        match existing_tables(&index, &["known_vaults"]).await {
+            Ok(tables) if !tables.is_empty() => {
+                match sqlx::query("SELECT path FROM known_vaults").fetch_all(index.pool()).await {
+                    Ok(rows) => vault_paths.extend(rows.into_iter().map(|row| row.get::<String, _>(0))),
+                    Err(e) => report.errors.push(format!("Failed to list known vaults: {}", e)),
+                }
+            }
+            Ok(_) => {}
+            Err(e) => report.errors.push(format!("Failed to list known vaults: {}", e)),
+        }
+    }
+    vault_paths.sort();
+    vault_paths.dedup();
+
+    // Sign out, revoking this device's session on a best-effort basis
+    if let Ok(client) = sync_state.get_client() {
+        let _ = client.logout().await;
+    }
+    let auth_file = sync_state.auth.auth_file_path();
+    let had_auth_file = auth_file.exists();
+    match sync_state.auth.clear_from_disk() {
+        Ok(()) if had_auth_file => report.removed_files.push(auth_file.to_string_lossy().to_string()),
+        Ok(()) => {}
+        Err(e) => report.errors.push(format!("Failed to remove stored session: {}", e)),
+    }
+    sync_state.auth.clear();
+    *sync_state.client.write() = None;
+
+    match sync_state.state_manager.purge() {
+        Ok(removed) => report
+            .removed_files
+            .extend(removed.iter().map(|p| p.to_string_lossy().to_string())),
+        Err(e) => report.errors.push(format!("Failed to remove sync state: {}", e)),
+    }
+
+    let mut tables = CACHE_TABLES.to_vec();
+    if include_vault_files {
+        tables.push("known_vaults");
+    }
+    match existing_tables(&index, &tables).await {
+        Ok(existing) => {
+            for table in existing {
+                match sqlx::raw_sql(&format!("DELETE FROM {}", table)).execute(index.pool()).await {
+                    Ok(_) => report.cleared_tables.push(table),
+                    Err(e) => report.errors.push(format!("Failed to clear {}: {}", table, e)),
+                }
+            }
+        }
+        Err(e) => report.errors.push(format!("Failed to clear caches: {}", e)),
+    }
+    match index.clear().await {
+        Ok(()) => report.cleared_tables.push("notes".to_string()),
+        Err(e) => report.errors.push(format!("Failed to clear notes: {}", e)),
+    }
+
+    if include_vault_files {
+        for vault_path in vault_paths {
+            let path = PathBuf::from(&vault_path);
+            if !path.is_dir() {
+                continue;
+            }
+            match remove_vault_contents(&path) {
+                Ok(()) => report.removed_vaults.push(vault_path),
+                Err(e) => report.errors.push(format!("Failed to remove {}: {}", vault_path, e)),
+            }
+        }
+    }
+
+    println!(
+        "[Purge] Removed {} files, cleared {} tables, removed {} vaults ({} errors)",
+        report.removed_files.len(),
+        report.cleared_tables.len(),
+        report.removed_vaults.len(),
+        report.errors.len()
+    );
+
+    Ok(report)
+}
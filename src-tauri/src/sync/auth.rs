@@ -2,13 +2,18 @@
 
 #![allow(dead_code)]
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, MutexGuard, RwLock};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
-use super::crypto::{derive_keys, derive_master_key, hash_auth_key, CryptoKey, Salt};
+use super::crypto::{
+    derive_keys, derive_master_key, derive_master_key_blocking, hash_auth_key, CryptoKey, Salt,
+    KEY_DERIVATION_TIMEOUT,
+};
+use super::client::normalize_server_url;
 use super::error::{SyncError, SyncResult};
 use super::types::{AuthResponse, DeviceType, UserInfo};
 
@@ -49,7 +54,12 @@ const AUTH_FILE_NAME: &str = "sync-auth.json";
 pub struct AuthManager {
     state: Arc<RwLock<Option<AuthState>>>,
     encryption_key: Arc<RwLock<Option<CryptoKey>>>,
-    data_dir: PathBuf,
+    /// Auth hash confirmed by the server, used to re-verify the password
+    /// locally (in memory only, cleared on logout)
+    password_verifier: Arc<RwLock<Option<String>>>,
+    data_dir: RwLock<PathBuf>,
+    /// Held while the auth file is read, written or moved
+    file_lock: Mutex<()>,
 }
 
 impl AuthManager {
@@ -58,13 +68,32 @@ impl AuthManager {
         Self {
             state: Arc::new(RwLock::new(None)),
             encryption_key: Arc::new(RwLock::new(None)),
-            data_dir,
+            password_verifier: Arc::new(RwLock::new(None)),
+            data_dir: RwLock::new(data_dir),
+            file_lock: Mutex::new(()),
         }
     }
 
+    /// Folder the auth file is kept in
+    pub fn data_dir(&self) -> PathBuf {
+        self.data_dir.read().clone()
+    }
+
+    /// Keep the auth file in `dir` from now on; the file itself is copied
+    /// there by the caller
+    pub fn set_data_dir(&self, dir: PathBuf) {
+        *self.data_dir.write() = dir;
+    }
+
+    /// Keep the auth file from being saved, loaded or cleared until the
+    /// guard is dropped, e.g. while it is moved to another folder
+    pub fn lock_file(&self) -> MutexGuard<'_, ()> {
+        self.file_lock.lock()
+    }
+
     /// Get the path to the auth file
-    fn auth_file_path(&self) -> PathBuf {
-        self.data_dir.join(AUTH_FILE_NAME)
+    pub fn auth_file_path(&self) -> PathBuf {
+        self.data_dir().join(AUTH_FILE_NAME)
     }
 
     /// Save auth state to disk
@@ -80,14 +109,16 @@ impl AuthManager {
             };
 
             // Ensure data directory exists
-            if !self.data_dir.exists() {
-                fs::create_dir_all(&self.data_dir).map_err(|e| SyncError::Io(e))?;
+            let data_dir = self.data_dir();
+            if !data_dir.exists() {
+                fs::create_dir_all(&data_dir).map_err(SyncError::Io)?;
             }
 
             let json = serde_json::to_string_pretty(&persisted)
                 .map_err(|e| SyncError::InvalidData(format!("Failed to serialize auth: {}", e)))?;
 
-            fs::write(self.auth_file_path(), json).map_err(|e| SyncError::Io(e))?;
+            let _file = self.lock_file();
+            fs::write(self.auth_file_path(), json).map_err(SyncError::Io)?;
         }
 
         Ok(())
@@ -95,13 +126,14 @@ impl AuthManager {
 
     /// Load auth state from disk
     pub fn load_from_disk(&self) -> SyncResult<Option<PersistedAuth>> {
+        let _file = self.lock_file();
         let path = self.auth_file_path();
 
         if !path.exists() {
             return Ok(None);
         }
 
-        let json = fs::read_to_string(&path).map_err(|e| SyncError::Io(e))?;
+        let json = fs::read_to_string(&path).map_err(SyncError::Io)?;
 
         let persisted: PersistedAuth = serde_json::from_str(&json)
             .map_err(|e| SyncError::InvalidData(format!("Failed to parse auth: {}", e)))?;
@@ -111,10 +143,11 @@ impl AuthManager {
 
     /// Clear persisted auth from disk
     pub fn clear_from_disk(&self) -> SyncResult<()> {
+        let _file = self.lock_file();
         let path = self.auth_file_path();
 
         if path.exists() {
-            fs::remove_file(&path).map_err(|e| SyncError::Io(e))?;
+            fs::remove_file(&path).map_err(SyncError::Io)?;
         }
 
         Ok(())
@@ -183,9 +216,15 @@ impl AuthManager {
         self.state.read().as_ref().map(|s| s.refresh_token.clone())
     }
 
-    /// Get server URL
+    /// Get server URL, normalized (sessions saved by older versions may
+    /// have a trailing slash)
     pub fn get_server_url(&self) -> Option<String> {
-        self.state.read().as_ref().map(|s| s.server_url.clone())
+        self.state.read().as_ref().map(|s| normalize_server_url(&s.server_url))
+    }
+
+    /// When the access token expires (Unix seconds)
+    pub fn get_token_expires_at(&self) -> Option<u64> {
+        self.state.read().as_ref().map(|s| s.token_expires_at)
     }
 
     /// Get the encryption key
@@ -221,6 +260,32 @@ impl AuthManager {
         })
     }
 
+    /// Prepare login credentials without blocking the async runtime
+    pub async fn prepare_login_async<F>(
+        password: &str,
+        salt_b64: &str,
+        on_progress: F,
+    ) -> SyncResult<LoginData>
+    where
+        F: FnMut(Duration),
+    {
+        let salt = Salt::from_base64(salt_b64)?;
+        let master_key = derive_master_key_blocking(
+            password.to_string(),
+            salt,
+            KEY_DERIVATION_TIMEOUT,
+            on_progress,
+        )
+        .await?;
+        let keys = derive_keys(&master_key)?;
+        let auth_hash = hash_auth_key(&keys.auth_key)?;
+
+        Ok(LoginData {
+            auth_hash,
+            encryption_key: keys.encryption_key,
+        })
+    }
+
     /// Set auth state after successful login (with encryption key for E2E)
     pub fn set_auth_state(
         &self,
@@ -268,6 +333,17 @@ impl AuthManager {
         // No encryption key for simplified auth
     }
 
+    /// The current session, to put back with [`Self::restore_session`]
+    /// when switching to another server has to be undone
+    pub fn session(&self) -> Option<AuthState> {
+        self.state.read().clone()
+    }
+
+    /// Make `session` (from [`Self::session`]) current again
+    pub fn restore_session(&self, session: Option<AuthState>) {
+        *self.state.write() = session;
+    }
+
     /// Update tokens after refresh
     pub fn update_tokens(&self, access_token: String, refresh_token: String, expires_in: u64) {
         if let Some(state) = self.state.write().as_mut() {
@@ -281,16 +357,27 @@ impl AuthManager {
         }
     }
 
+    /// Get the cached password verifier (auth hash), if any
+    pub fn get_password_verifier(&self) -> Option<String> {
+        self.password_verifier.read().clone()
+    }
+
+    /// Cache a server-confirmed auth hash for local password re-verification
+    pub fn set_password_verifier(&self, auth_hash: String) {
+        *self.password_verifier.write() = Some(auth_hash);
+    }
+
     /// Clear auth state (logout)
     pub fn clear(&self) {
         *self.state.write() = None;
         *self.encryption_key.write() = None;
+        *self.password_verifier.write() = None;
     }
 
     /// Check if token needs refresh (within 5 minutes of expiry)
     pub fn needs_token_refresh(&self) -> bool {
         let state = self.state.read();
-        state.as_ref().map_or(false, |s| {
+        state.as_ref().is_some_and(|s| {
             let now = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
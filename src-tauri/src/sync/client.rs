@@ -2,12 +2,15 @@
 
 #![allow(dead_code)]
 
+use futures_util::StreamExt;
 use reqwest::{Client, StatusCode};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Duration;
 
 use super::auth::AuthManager;
+use super::proxy::{self, ProxyConfig, PROXY_AUTH_REQUIRED};
+use super::rate_limit::{retry_after_secs, RateLimitHold, DEFAULT_RETRY_AFTER_SECS};
 use super::error::{SyncError, SyncResult};
 use super::types::*;
 
@@ -15,18 +18,21 @@ use super::types::*;
 pub struct SyncClient {
     client: Client,
     auth: Arc<AuthManager>,
+    /// Shared with every other client and sync engine of the app
+    rate_limit: Arc<RateLimitHold>,
 }
 
 impl SyncClient {
     /// Create a new sync client
-    pub fn new(auth: Arc<AuthManager>) -> SyncResult<Self> {
-        let client = Client::builder()
+    pub fn new(auth: Arc<AuthManager>, proxy: &ProxyConfig, rate_limit: Arc<RateLimitHold>) -> SyncResult<Self> {
+        let client = proxy
+            .client_builder(auth.get_server_url().as_deref())?
             .timeout(Duration::from_secs(30))
             .user_agent("Echopad/1.0")
             .build()
             .map_err(|e| SyncError::Network(format!("Failed to create HTTP client: {}", e)))?;
 
-        Ok(Self { client, auth })
+        Ok(Self { client, auth, rate_limit })
     }
 
     /// Get the base URL for API requests
@@ -35,12 +41,24 @@ impl SyncClient {
             .ok_or(SyncError::AuthRequired)
     }
 
-    /// Make an authenticated request
+    /// Make an authenticated request, after any rate-limit hold
     async fn request<T: DeserializeOwned, B: Serialize>(
         &self,
         method: reqwest::Method,
         endpoint: &str,
         body: Option<&B>,
+    ) -> SyncResult<T> {
+        self.rate_limit.wait().await?;
+        let result = self.send_request(method, endpoint, body).await;
+        self.rate_limit.observe(&result);
+        result
+    }
+
+    async fn send_request<T: DeserializeOwned, B: Serialize>(
+        &self,
+        method: reqwest::Method,
+        endpoint: &str,
+        body: Option<&B>,
     ) -> SyncResult<T> {
         let base_url = self.base_url()?;
         let url = format!("{}{}", base_url, endpoint);
@@ -57,7 +75,7 @@ impl SyncClient {
         }
 
         let response = request.send().await
-            .map_err(|e| SyncError::Network(format!("Request failed: {}", e)))?;
+            .map_err(|e| SyncError::Network(format!("Request failed: {}", proxy::describe_error(&e))))?;
 
         let status = response.status();
 
@@ -65,32 +83,41 @@ impl SyncClient {
             return Err(SyncError::SessionExpired);
         }
 
+        if status == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            return Err(SyncError::Network(PROXY_AUTH_REQUIRED.to_string()));
+        }
+
         if status == StatusCode::TOO_MANY_REQUESTS {
-            let retry_after = response
-                .headers()
-                .get("Retry-After")
-                .and_then(|h| h.to_str().ok())
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(60);
-            return Err(SyncError::RateLimited(retry_after));
+            return Err(SyncError::RateLimited(retry_after_secs(response.headers())));
         }
 
         if !status.is_success() {
             let error_text = response.text().await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(SyncError::Server(format!("{}: {}", status, error_text)));
+            return Err(parse_server_error(status, &error_text));
         }
 
-        response.json().await
-            .map_err(|e| SyncError::Server(format!("Failed to parse response: {}", e)))
+        read_json(response, "response").await
     }
 
-    /// Make an unauthenticated request
+    /// Make an unauthenticated request, after any rate-limit hold
     async fn request_unauth<T: DeserializeOwned, B: Serialize>(
         &self,
         method: reqwest::Method,
         url: &str,
         body: Option<&B>,
+    ) -> SyncResult<T> {
+        self.rate_limit.wait().await?;
+        let result = self.send_request_unauth(method, url, body).await;
+        self.rate_limit.observe(&result);
+        result
+    }
+
+    async fn send_request_unauth<T: DeserializeOwned, B: Serialize>(
+        &self,
+        method: reqwest::Method,
+        url: &str,
+        body: Option<&B>,
     ) -> SyncResult<T> {
         let mut request = self.client.request(method, url);
 
@@ -99,28 +126,25 @@ impl SyncClient {
         }
 
         let response = request.send().await
-            .map_err(|e| SyncError::Network(format!("Request failed: {}", e)))?;
+            .map_err(|e| SyncError::Network(format!("Request failed: {}", proxy::describe_error(&e))))?;
 
         let status = response.status();
 
+        if status == StatusCode::PROXY_AUTHENTICATION_REQUIRED {
+            return Err(SyncError::Network(PROXY_AUTH_REQUIRED.to_string()));
+        }
+
         if status == StatusCode::TOO_MANY_REQUESTS {
-            let retry_after = response
-                .headers()
-                .get("Retry-After")
-                .and_then(|h| h.to_str().ok())
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(60);
-            return Err(SyncError::RateLimited(retry_after));
+            return Err(SyncError::RateLimited(retry_after_secs(response.headers())));
         }
 
         if !status.is_success() {
             let error_text = response.text().await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(SyncError::Server(format!("{}: {}", status, error_text)));
+            return Err(parse_server_error(status, &error_text));
         }
 
-        response.json().await
-            .map_err(|e| SyncError::Server(format!("Failed to parse response: {}", e)))
+        read_json(response, "response").await
     }
 
     // ==========================================
@@ -162,6 +186,17 @@ impl SyncClient {
         self.request_unauth(reqwest::Method::POST, &url, Some(&request)).await
     }
 
+    /// Check the signed-in user's password without starting a new session
+    pub async fn verify_password(&self, password: &str) -> SyncResult<bool> {
+        let request = VerifyPasswordRequest {
+            password: password.to_string(),
+        };
+        let response: VerifyPasswordResponse = self
+            .request(reqwest::Method::POST, "/api/v1/auth/verify", Some(&request))
+            .await?;
+        Ok(response.valid)
+    }
+
     /// Logout (revoke refresh token)
     pub async fn logout(&self) -> SyncResult<()> {
         self.request::<EmptyResponse, ()>(reqwest::Method::POST, "/api/v1/auth/logout", None).await?;
@@ -277,8 +312,13 @@ impl SyncClient {
         Ok(())
     }
 
-    /// Download file from presigned URL
-    pub async fn download_file(&self, download_url: &str) -> SyncResult<Vec<u8>> {
+    /// Download file from presigned URL, reporting bytes received and the
+    /// expected total (if known) as the body arrives
+    pub async fn download_file(
+        &self,
+        download_url: &str,
+        on_progress: impl FnMut(u64, Option<u64>),
+    ) -> SyncResult<Vec<u8>> {
         let response = self.client
             .get(download_url)
             .send()
@@ -292,9 +332,16 @@ impl SyncClient {
             )));
         }
 
-        response.bytes().await
-            .map(|b| b.to_vec())
-            .map_err(|e| SyncError::Network(format!("Failed to read download: {}", e)))
+        read_body(response, on_progress).await
+    }
+
+    // ==========================================
+    // Server configuration
+    // ==========================================
+
+    /// Get the sync settings the server recommends to clients
+    pub async fn get_server_config(&self) -> SyncResult<ServerSyncConfig> {
+        self.request(reqwest::Method::GET, "/api/v1/config", None::<&()>).await
     }
 
     // ==========================================
@@ -338,6 +385,16 @@ pub struct SaltResponse {
     pub salt: String,
 }
 
+#[derive(Debug, Serialize)]
+pub struct VerifyPasswordRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyPasswordResponse {
+    pub valid: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct TokenRefreshRequest {
     pub refresh_token: String,
@@ -391,3 +448,223 @@ pub struct UsageResponse {
 #[derive(Debug, Deserialize)]
 struct EmptyResponse {}
 
+/// Error body returned by the server. The API uses a flat
+/// `{error, code}` shape; a nested `{error: {code, message}}` is accepted too.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ServerErrorBody {
+    Nested {
+        error: ServerErrorDetail,
+    },
+    Flat {
+        error: String,
+        #[serde(default)]
+        code: Option<String>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct ServerErrorDetail {
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+/// Map a failed response to a specific `SyncError`, falling back to the raw
+/// body when it isn't a recognizable error payload
+fn parse_server_error(status: StatusCode, text: &str) -> SyncError {
+    let (code, message) = match serde_json::from_str::<ServerErrorBody>(text) {
+        Ok(ServerErrorBody::Nested { error }) => (error.code, error.message),
+        Ok(ServerErrorBody::Flat { error, code }) => (code, Some(error)),
+        Err(_) => return SyncError::Server(format!("{}: {}", status, text)),
+    };
+    let message = message.unwrap_or_else(|| status.to_string());
+
+    match code.as_deref() {
+        Some("QUOTA_EXCEEDED") => SyncError::QuotaExceeded,
+        Some("INVALID_CREDENTIALS") => SyncError::InvalidCredentials,
+        Some("UNAUTHORIZED") => SyncError::SessionExpired,
+        Some("RATE_LIMITED") => SyncError::RateLimited(DEFAULT_RETRY_AFTER_SECS),
+        Some("VAULT_NOT_FOUND") => SyncError::VaultNotFound(message),
+        Some("FILE_NOT_FOUND") => SyncError::FileNotFound(message),
+        Some("DEVICE_NOT_FOUND") => SyncError::DeviceNotRegistered,
+        Some(code) => SyncError::Server(format!("{} ({}): {}", status, code, message)),
+        None => SyncError::Server(format!("{}: {}", status, message)),
+    }
+}
+
+/// API prefix every endpoint path starts with
+const API_PREFIX: &str = "/api/v1";
+
+/// Canonical form of a server URL that endpoint paths are appended to:
+/// surrounding whitespace and trailing slashes are removed, and so is a
+/// pasted `/api/v1` suffix. A base path (`https://host/echopad`) is kept,
+/// so `https://host` and `https://host/` are the same server.
+pub fn normalize_server_url(server_url: &str) -> String {
+    let mut url = server_url.trim().trim_end_matches('/');
+    if let Some(base) = url.strip_suffix(API_PREFIX) {
+        url = base.trim_end_matches('/');
+    }
+    url.to_string()
+}
+
+/// The URLs requests to `server_url` go to, joined exactly the way
+/// `SyncClient` and the sync engine join them
+pub fn endpoints(server_url: &str, vault_id: Option<&str>) -> SyncEndpoints {
+    let mut warnings = Vec::new();
+    if !server_url.starts_with("https://") && !server_url.starts_with("http://") {
+        warnings.push("The server URL has no http:// or https:// scheme".to_string());
+    }
+    let server_url = normalize_server_url(server_url);
+
+    let vault = vault_id.map(|vault_id| {
+        let sync = format!("{}/api/v1/vaults/{}/sync", server_url, vault_id);
+        VaultEndpoints {
+            vault_id: vault_id.to_string(),
+            pull: format!("{}/pull", sync),
+            push: format!("{}/push", sync),
+            confirm: format!("{}/confirm", sync),
+            has: format!("{}/has", sync),
+            status: format!("{}/status", sync),
+            key: format!("{}/api/v1/vaults/{}/key", server_url, vault_id),
+        }
+    });
+
+    SyncEndpoints {
+        server_url: server_url.to_string(),
+        health: format!("{}/health", server_url),
+        login: format!("{}/api/v1/auth/login", server_url),
+        refresh: format!("{}/api/v1/auth/refresh", server_url),
+        vaults: format!("{}/api/v1/vaults", server_url),
+        devices: format!("{}/api/v1/devices", server_url),
+        config: format!("{}/api/v1/config", server_url),
+        vault,
+        warnings,
+    }
+}
+
+/// Longest excerpt of an unparseable response body quoted in errors
+const BODY_SNIPPET_CHARS: usize = 200;
+
+/// Parse a successful response's body as JSON. Proxies and captive portals
+/// sometimes answer with an HTML page and a 200 status; instead of serde's
+/// baffling "missing field", the error then quotes the start of the body.
+pub(crate) async fn read_json<T: DeserializeOwned>(response: reqwest::Response, what: &str) -> SyncResult<T> {
+    let text = response
+        .text()
+        .await
+        .map_err(|e| SyncError::Network(format!("Failed to read {}: {}", what, e)))?;
+    parse_json(&text, what)
+}
+
+fn parse_json<T: DeserializeOwned>(text: &str, what: &str) -> SyncResult<T> {
+    serde_json::from_str(text).map_err(|e| {
+        let snippet = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let mut excerpt: String = snippet.chars().take(BODY_SNIPPET_CHARS).collect();
+        if excerpt.len() < snippet.len() {
+            excerpt.push('…');
+        }
+
+        let start = text.trim_start().chars().take(14).collect::<String>().to_lowercase();
+        SyncError::InvalidData(if excerpt.is_empty() {
+            format!("Failed to parse {}: the server sent an empty body", what)
+        } else if start.starts_with("<!doctype html") || start.starts_with("<html") {
+            format!(
+                "Failed to parse {}: got an HTML page instead of JSON, so a proxy or login page may be in the way: {}",
+                what, excerpt
+            )
+        } else {
+            format!("Failed to parse {}: {} (body: {})", what, e, excerpt)
+        })
+    })
+}
+
+/// Read a response body chunk by chunk, calling `on_progress` after each
+/// chunk with the bytes received so far and the `Content-Length`, if the
+/// server sent one
+pub(crate) async fn read_body(
+    response: reqwest::Response,
+    mut on_progress: impl FnMut(u64, Option<u64>),
+) -> SyncResult<Vec<u8>> {
+    let total = response.content_length();
+    let mut body = Vec::with_capacity(total.unwrap_or(0).min(64 * 1024 * 1024) as usize);
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| SyncError::Network(format!("Failed to read download: {}", e)))?;
+        body.extend_from_slice(&chunk);
+        on_progress(body.len() as u64, total);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints_are_built_from_the_normalized_url() {
+        let clean = endpoints("https://sync.example.com", Some("v1"));
+        assert!(clean.warnings.is_empty());
+        assert_eq!(clean.vault.unwrap().pull, "https://sync.example.com/api/v1/vaults/v1/sync/pull");
+
+        let slashed = endpoints("https://sync.example.com/", None);
+        assert_eq!(slashed.login, "https://sync.example.com/api/v1/auth/login");
+        assert_eq!(slashed.health, "https://sync.example.com/health");
+        assert!(slashed.vault.is_none());
+
+        assert_eq!(endpoints("sync.example.com", None).warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_normalize_server_url() {
+        for (input, expected) in [
+            ("https://host", "https://host"),
+            ("https://host/", "https://host"),
+            ("https://host///", "https://host"),
+            ("  https://host/ \n", "https://host"),
+            ("https://host/echopad", "https://host/echopad"),
+            ("https://host/echopad/", "https://host/echopad"),
+            ("https://host/api/v1", "https://host"),
+            ("https://host/echopad/api/v1/", "https://host/echopad"),
+            ("http://localhost:8787/", "http://localhost:8787"),
+        ] {
+            assert_eq!(normalize_server_url(input), expected, "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_flat_server_error() {
+        let err = parse_server_error(
+            StatusCode::FORBIDDEN,
+            r#"{"error": "Storage quota exceeded", "code": "QUOTA_EXCEEDED"}"#,
+        );
+        assert!(matches!(err, SyncError::QuotaExceeded));
+    }
+
+    #[test]
+    fn test_parse_nested_server_error() {
+        let err = parse_server_error(
+            StatusCode::NOT_FOUND,
+            r#"{"error": {"code": "VAULT_NOT_FOUND", "message": "Vault not found"}}"#,
+        );
+        assert!(matches!(err, SyncError::VaultNotFound(ref m) if m == "Vault not found"));
+    }
+
+    #[test]
+    fn test_html_instead_of_json_is_reported_with_snippet() {
+        let page = format!("<!DOCTYPE html>\n<html><title>Sign in</title>{}</html>", "x".repeat(500));
+        let err = parse_json::<UserInfo>(&page, "account").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("HTML page instead of JSON"));
+        assert!(message.contains("<title>Sign in</title>"));
+        assert!(message.chars().count() < 400);
+    }
+
+    #[test]
+    fn test_unparseable_server_error_keeps_raw_text() {
+        let err = parse_server_error(StatusCode::BAD_GATEWAY, "<html>Bad gateway</html>");
+        assert!(matches!(err, SyncError::Server(ref m) if m.contains("<html>Bad gateway</html>")));
+    }
+}
+
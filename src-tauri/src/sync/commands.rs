@@ -2,23 +2,42 @@
 
 #![allow(dead_code)]
 
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use parking_lot::RwLock;
-use tauri::State;
+use tauri::{AppHandle, Emitter, Manager, State};
 
 use super::auth::AuthManager;
-use super::client::SyncClient;
-use super::state::SyncStateManager;
+use super::client::{PutVaultKeyRequest, SyncClient};
+use super::state::{FileSyncState, JsonFileStore, SyncStateManager, VaultState, AUTO_SYNC_PAUSED_WARNING, MISSING_FOLDER_ERROR, REMOTE_VAULT_MISSING_ERROR, STATE_FILE_NAME};
 use super::conflict::{ConflictManager, ConflictResolution};
 use super::types::*;
-use super::error::SyncResult;
+use super::error::{SyncError, SyncResult};
+use super::client::{normalize_server_url, read_json};
+use super::proxy::{self, ProxyConfig, ProxySettings};
+use super::health::ServerProbe;
+use super::rate_limit::RateLimitHold;
+use super::data_dir;
+use super::crypto::{self, constant_time_eq, CryptoKey};
+use super::scanner::{compute_hash, scan_vault, scan_vault_including, validate_include_dir, ScanError, SkippedFile, SymlinkEntry, DEFAULT_MAX_FILE_SIZE};
+use super::lock::SyncLocks;
+use crate::index::NoteIndex;
+use super::scheduler::{SyncScheduler, SCHEDULER_TICK};
 
 /// Write the sync manifest to a vault folder
 fn write_sync_manifest(vault_path: &str, manifest: &VaultSyncManifest) -> Result<(), String> {
-    let manifest_path = Path::new(vault_path).join(SYNC_MANIFEST_FILENAME);
-    let json = serde_json::to_string_pretty(manifest)
+    // The manifest only speeds up reconnecting, so a read-only vault goes without
+    if crate::vault::is_read_only(Path::new(vault_path)) {
+        println!("[Sync] {} is read-only, not writing its sync manifest", vault_path);
+        return Ok(());
+    }
+
+    let manifest_path = sync_manifest_path(vault_path);
+    let json = serde_json::to_string_pretty(&manifest.with_checksum())
         .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
     fs::write(&manifest_path, json)
         .map_err(|e| format!("Failed to write manifest: {}", e))?;
@@ -27,17 +46,30 @@ fn write_sync_manifest(vault_path: &str, manifest: &VaultSyncManifest) -> Result
     #[cfg(target_os = "windows")]
     {
         use std::process::Command;
-        let _ = Command::new("attrib")
-            .args(["+H", manifest_path.to_str().unwrap_or_default()])
-            .output();
+        // Passed as an OsStr so non-ASCII vault paths reach attrib intact
+        let _ = Command::new("attrib").arg("+H").arg(&manifest_path).output();
     }
     
     Ok(())
 }
 
+/// Path of the vault's manifest file, renaming a manifest written under the
+/// legacy filename first
+fn sync_manifest_path(vault_path: &str) -> std::path::PathBuf {
+    let manifest_path = Path::new(vault_path).join(SYNC_MANIFEST_FILENAME);
+    let legacy_path = Path::new(vault_path).join(LEGACY_SYNC_MANIFEST_FILENAME);
+    if !manifest_path.exists() && legacy_path.exists() {
+        if let Err(e) = fs::rename(&legacy_path, &manifest_path) {
+            eprintln!("[Sync] Failed to rename legacy manifest in {}: {}", vault_path, e);
+            return legacy_path;
+        }
+    }
+    manifest_path
+}
+
 /// Read the sync manifest from a vault folder
 fn read_sync_manifest(vault_path: &str) -> Option<VaultSyncManifest> {
-    let manifest_path = Path::new(vault_path).join(SYNC_MANIFEST_FILENAME);
+    let manifest_path = sync_manifest_path(vault_path);
     if !manifest_path.exists() {
         return None;
     }
@@ -48,50 +80,219 @@ fn read_sync_manifest(vault_path: &str) -> Option<VaultSyncManifest> {
 
 /// Delete the sync manifest from a vault folder
 fn delete_sync_manifest(vault_path: &str) -> Result<(), String> {
-    let manifest_path = Path::new(vault_path).join(SYNC_MANIFEST_FILENAME);
-    if manifest_path.exists() {
-        fs::remove_file(&manifest_path)
-            .map_err(|e| format!("Failed to delete manifest: {}", e))?;
+    for filename in [SYNC_MANIFEST_FILENAME, LEGACY_SYNC_MANIFEST_FILENAME] {
+        let manifest_path = Path::new(vault_path).join(filename);
+        if manifest_path.exists() {
+            fs::remove_file(&manifest_path)
+                .map_err(|e| format!("Failed to delete manifest: {}", e))?;
+        }
     }
     Ok(())
 }
 
 /// Global sync state
+///
+/// Cloning is cheap and shares the same underlying state, which lets
+/// background tasks outlive the command that spawned them.
+#[derive(Clone)]
 pub struct SyncState {
     pub auth: Arc<AuthManager>,
     pub state_manager: Arc<SyncStateManager>,
     pub client: Arc<RwLock<Option<SyncClient>>>,
+    /// Set while a background reconnect loop is running
+    reconnecting: Arc<AtomicBool>,
+    /// Next-run bookkeeping for scheduled background syncs
+    scheduler: Arc<SyncScheduler>,
+    /// When set, scheduled syncs are skipped
+    offline: Arc<AtomicBool>,
+    /// When set, every sync (manual, batch and scheduled) is refused until
+    /// resumed; pending changes keep accumulating
+    paused: Arc<AtomicBool>,
+    /// Keeps two syncs of the same vault from running at once
+    sync_locks: Arc<SyncLocks>,
+    /// Outcome of the latest sync of each vault, keyed by vault path
+    last_results: Arc<RwLock<HashMap<String, LastSyncResult>>>,
+    /// Where the last server-recommended settings are cached
+    server_config_path: Arc<RwLock<std::path::PathBuf>>,
+    /// App data directory, which records where sync data was moved to
+    app_data_dir: std::path::PathBuf,
+    /// Per-server proxy overrides
+    pub proxy: Arc<ProxyConfig>,
+    /// Recent reachability of the server, checked before each sync
+    server_probe: Arc<ServerProbe>,
+    /// Shared deadline after the server answered 429, respected by every
+    /// request of the app
+    rate_limit: Arc<RateLimitHold>,
+    /// Why sync is unavailable (e.g. no persistent data directory)
+    unavailable: Option<String>,
 }
 
 impl SyncState {
     pub fn new(db_path: std::path::PathBuf) -> Self {
-        // Use the parent directory of db_path as the data directory
-        let data_dir = db_path.parent()
+        // Use the parent directory of db_path as the data directory, unless
+        // sync data was moved elsewhere
+        let app_data_dir = db_path.parent()
             .map(|p| p.to_path_buf())
             .unwrap_or_else(|| std::path::PathBuf::from("."));
-        
+        let (data_dir, unavailable) = match data_dir::moved_data_dir(&app_data_dir) {
+            Some(dir) if dir.is_dir() => (dir, None),
+            Some(dir) => {
+                let reason = format!("the sync data folder {} is not available", dir.display());
+                eprintln!("[Sync] Sync disabled: {}", reason);
+                (app_data_dir.clone(), Some(reason))
+            }
+            None => (app_data_dir.clone(), None),
+        };
+
+        let state_manager = Arc::new(SyncStateManager::with_store(Arc::new(JsonFileStore::new(
+            data_dir.join(STATE_FILE_NAME),
+        ))));
+        let server_config_path = data_dir.join(SERVER_CONFIG_FILE_NAME);
+        let proxy = Arc::new(ProxyConfig::load(data_dir.join(PROXY_FILE_NAME)));
+        if let Some(config) = read_cached_server_config(&server_config_path) {
+            state_manager.set_server_config(config);
+        }
+
         Self {
             auth: Arc::new(AuthManager::new(data_dir)),
-            state_manager: Arc::new(SyncStateManager::new(db_path)),
+            state_manager,
             client: Arc::new(RwLock::new(None)),
+            reconnecting: Arc::new(AtomicBool::new(false)),
+            scheduler: Arc::new(SyncScheduler::new()),
+            offline: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            sync_locks: Arc::new(SyncLocks::new()),
+            last_results: Arc::new(RwLock::new(HashMap::new())),
+            server_config_path: Arc::new(RwLock::new(server_config_path)),
+            app_data_dir,
+            proxy,
+            server_probe: Arc::new(ServerProbe::new()),
+            rate_limit: Arc::new(RateLimitHold::new()),
+            unavailable,
+        }
+    }
+
+    /// Turn off sync features for this session, e.g. because state can't be
+    /// persisted and would be lost on restart
+    pub fn disable(&mut self, reason: String) {
+        self.unavailable = Some(reason);
+    }
+
+    fn ensure_available(&self) -> Result<(), String> {
+        match &self.unavailable {
+            Some(reason) => Err(format!("Sync is unavailable: {}", reason)),
+            None => Ok(()),
+        }
+    }
+
+    fn ensure_not_paused(&self) -> Result<(), String> {
+        if self.paused.load(Ordering::SeqCst) {
+            return Err("Sync is paused".to_string());
         }
+        Ok(())
     }
 
-    fn get_client(&self) -> SyncResult<SyncClient> {
+    pub(crate) fn get_client(&self) -> SyncResult<SyncClient> {
         let client = self.client.read();
         match client.as_ref() {
-            Some(_) => Ok(SyncClient::new(Arc::clone(&self.auth))?),
+            Some(_) => Ok(SyncClient::new(Arc::clone(&self.auth), &self.proxy, Arc::clone(&self.rate_limit))?),
             None => Err(super::error::SyncError::AuthRequired),
         }
     }
 
+    /// Remember the outcome of a finished sync. A sync that failed outright
+    /// is recorded as an unsuccessful result carrying the error.
+    fn record_result(&self, vault_path: &str, result: &Result<SyncOperationResult, String>) {
+        let result = match result {
+            Ok(result) => result.clone(),
+            Err(e) => SyncOperationResult {
+                success: false,
+                files_uploaded: 0,
+                files_downloaded: 0,
+                files_deleted: 0,
+                conflicts: vec![],
+                errors: vec![e.clone()],
+                duration_ms: 0,
+            },
+        };
+        self.last_results.write().insert(
+            vault_path.to_string(),
+            LastSyncResult {
+                result,
+                finished_at: now_ms(),
+            },
+        );
+    }
+
+    /// Prepare for the app exiting: stop new syncs from starting, give
+    /// running ones (uploads and their confirms) up to `grace` to finish,
+    /// then write the sync state. Bounded so quitting never hangs on a slow
+    /// network; an interrupted sync is picked up by the next one.
+    pub fn shutdown(&self, grace: Duration) {
+        self.paused.store(true, Ordering::SeqCst);
+
+        let deadline = std::time::Instant::now() + grace;
+        while self.sync_locks.any_syncing() && std::time::Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        if self.sync_locks.any_syncing() {
+            println!("[Sync] Quitting with a sync still running; it resumes on next launch");
+        }
+
+        if let Err(e) = self.state_manager.flush() {
+            eprintln!("[Sync] Failed to save sync state on exit: {}", e);
+        }
+        if self.auth.is_logged_in() {
+            if let Err(e) = self.auth.save_to_disk() {
+                eprintln!("[Sync] Failed to save session on exit: {}", e);
+            }
+        }
+    }
+
+    /// HTTP client for requests to `server_url`, through its proxy if set
+    pub(crate) fn http_client(&self, server_url: &str) -> Result<reqwest::Client, String> {
+        self.proxy.client(Some(server_url)).map_err(|e| e.to_string())
+    }
+
     fn init_client(&self) -> SyncResult<()> {
-        let client = SyncClient::new(Arc::clone(&self.auth))?;
+        let client = SyncClient::new(Arc::clone(&self.auth), &self.proxy, Arc::clone(&self.rate_limit))?;
         *self.client.write() = Some(client);
         Ok(())
     }
 }
 
+/// Cache of the server-recommended sync settings, in the app data directory
+const SERVER_CONFIG_FILE_NAME: &str = "sync-server-config.json";
+
+/// Per-server proxy overrides, in the app data directory
+const PROXY_FILE_NAME: &str = "sync-proxy.json";
+
+fn read_cached_server_config(path: &Path) -> Option<ServerSyncConfig> {
+    let json = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Fetch the server's recommended settings and apply them as defaults. A
+/// server without the endpoint (or an unreachable one) keeps the cached
+/// settings.
+async fn refresh_server_config(state: &SyncState) {
+    let config = match state.get_client() {
+        Ok(client) => client.get_server_config().await,
+        Err(e) => Err(e),
+    };
+    match config {
+        Ok(config) => {
+            if let Ok(json) = serde_json::to_string_pretty(&config) {
+                if let Err(e) = fs::write(&*state.server_config_path.read(), json) {
+                    eprintln!("[Sync] Failed to cache server config: {}", e);
+                }
+            }
+            state.state_manager.set_server_config(config);
+        }
+        Err(e) => println!("[Sync] Server config unavailable, keeping defaults: {}", e),
+    }
+}
+
 /// Login to sync service
 #[tauri::command]
 pub async fn sync_login(
@@ -100,9 +301,44 @@ pub async fn sync_login(
     password: String,
     server_url: String,
 ) -> Result<AuthResponse, String> {
-    let client = reqwest::Client::new();
+    state.ensure_available()?;
+    log_in(&state, &email, &password, normalize_server_url(&server_url)).await
+}
+
+/// Log in to a server and make it the current session
+async fn log_in(state: &SyncState, email: &str, password: &str, server_url: String) -> Result<AuthResponse, String> {
+    let auth_response = request_login(state, email, password, &server_url)
+        .await?
+        .map_err(|error_text| format!("Login failed: {}", error_text))?;
+    start_session(state, auth_response.clone(), server_url).await?;
+    Ok(auth_response)
+}
+
+/// Make a successful login the current session
+async fn start_session(state: &SyncState, auth_response: AuthResponse, server_url: String) -> Result<(), String> {
+    // Store auth state (no encryption key needed for simplified auth)
+    state.auth.set_auth_state_simple(auth_response, server_url);
+
+    // Save auth to disk for persistence across restarts
+    state.auth.save_to_disk().map_err(|e| e.to_string())?;
+
+    // Initialize client
+    state.init_client().map_err(|e| e.to_string())?;
+    refresh_server_config(state).await;
+    Ok(())
+}
+
+/// Post credentials to the login endpoint. The inner error is the server's
+/// response when it rejects the email or password.
+async fn request_login(
+    state: &SyncState,
+    email: &str,
+    password: &str,
+    server_url: &str,
+) -> Result<Result<AuthResponse, String>, String> {
+    let client = state.http_client(server_url)?;
     let login_url = format!("{}/api/v1/auth/login", server_url);
-    
+
     let login_response = client
         .post(&login_url)
         .json(&serde_json::json!({
@@ -111,29 +347,21 @@ pub async fn sync_login(
         }))
         .send()
         .await
-        .map_err(|e| format!("Login request failed: {}", e))?;
+        .map_err(|e| format!("Login request failed: {}", proxy::describe_error(&e)))?;
 
-    if !login_response.status().is_success() {
+    let status = login_response.status();
+    if !status.is_success() {
         let error_text = login_response.text().await.unwrap_or_default();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(Err(error_text));
+        }
         return Err(format!("Login failed: {}", error_text));
     }
 
-    let auth_response: AuthResponse = login_response.json().await
-        .map_err(|e| format!("Failed to parse login response: {}", e))?;
-
-    // Store auth state (no encryption key needed for simplified auth)
-    state.auth.set_auth_state_simple(
-        auth_response.clone(),
-        server_url,
-    );
-
-    // Save auth to disk for persistence across restarts
-    state.auth.save_to_disk().map_err(|e| e.to_string())?;
-
-    // Initialize client
-    state.init_client().map_err(|e| e.to_string())?;
-
-    Ok(auth_response)
+    read_json(login_response, "login response")
+        .await
+        .map(Ok)
+        .map_err(|e| e.to_string())
 }
 
 /// Register new account
@@ -144,7 +372,10 @@ pub async fn sync_register(
     password: String,
     server_url: String,
 ) -> Result<AuthResponse, String> {
-    let client = reqwest::Client::new();
+    state.ensure_available()?;
+
+    let server_url = normalize_server_url(&server_url);
+    let client = state.http_client(&server_url)?;
     let register_url = format!("{}/api/v1/auth/register", server_url);
     
     let register_response = client
@@ -155,15 +386,15 @@ pub async fn sync_register(
         }))
         .send()
         .await
-        .map_err(|e| format!("Registration request failed: {}", e))?;
+        .map_err(|e| format!("Registration request failed: {}", proxy::describe_error(&e)))?;
 
     if !register_response.status().is_success() {
         let error_text = register_response.text().await.unwrap_or_default();
         return Err(format!("Registration failed: {}", error_text));
     }
 
-    let auth_response: AuthResponse = register_response.json().await
-        .map_err(|e| format!("Failed to parse registration response: {}", e))?;
+    let auth_response: AuthResponse = read_json(register_response, "registration response").await
+        .map_err(|e| e.to_string())?;
 
     // Store auth state
     state.auth.set_auth_state_simple(
@@ -176,6 +407,7 @@ pub async fn sync_register(
 
     // Initialize client
     state.init_client().map_err(|e| e.to_string())?;
+    refresh_server_config(&state).await;
 
     Ok(auth_response)
 }
@@ -193,6 +425,14 @@ pub struct RestoredSession {
 pub async fn sync_restore_session(
     state: State<'_, SyncState>,
 ) -> Result<Option<RestoredSession>, String> {
+    state.ensure_available()?;
+    restore_session(&state).await.map_err(|e| e.to_string())
+}
+
+/// Refresh the persisted session. Network failures and server errors are
+/// returned as errors and keep the stored credentials so the restore can be
+/// retried; a rejected refresh token clears them and returns `None`.
+async fn restore_session(state: &SyncState) -> SyncResult<Option<RestoredSession>> {
     // Try to load persisted auth
     let persisted = match state.auth.load_from_disk() {
         Ok(Some(p)) => p,
@@ -206,7 +446,7 @@ pub async fn sync_restore_session(
     println!("[Sync] Found persisted auth for {}, attempting token refresh", persisted.user.email);
 
     // Try to refresh the token
-    let client = reqwest::Client::new();
+    let client = state.proxy.client(Some(&persisted.server_url))?;
     let refresh_url = format!("{}/api/v1/auth/refresh", persisted.server_url);
     
     let refresh_response = client
@@ -216,7 +456,14 @@ pub async fn sync_restore_session(
         }))
         .send()
         .await
-        .map_err(|e| format!("Token refresh request failed: {}", e))?;
+        .map_err(|e| SyncError::Network(format!("Token refresh request failed: {}", e)))?;
+
+    if refresh_response.status().is_server_error() {
+        return Err(SyncError::Server(format!(
+            "Token refresh failed: {}",
+            refresh_response.status()
+        )));
+    }
 
     if !refresh_response.status().is_success() {
         // Token refresh failed - clear stored auth
@@ -232,8 +479,7 @@ pub async fn sync_restore_session(
         expires_in: u64,
     }
 
-    let token_response: TokenRefreshResponse = refresh_response.json().await
-        .map_err(|e| format!("Failed to parse token refresh response: {}", e))?;
+    let token_response: TokenRefreshResponse = read_json(refresh_response, "token refresh response").await?;
 
     // Restore auth state with new tokens
     state.auth.restore_from_persisted(
@@ -244,10 +490,11 @@ pub async fn sync_restore_session(
     );
 
     // Save updated tokens to disk
-    state.auth.save_to_disk().map_err(|e| e.to_string())?;
+    state.auth.save_to_disk()?;
 
     // Initialize client
-    state.init_client().map_err(|e| e.to_string())?;
+    state.init_client()?;
+    refresh_server_config(state).await;
 
     println!("[Sync] Session restored successfully for {}", persisted.user.email);
 
@@ -270,6 +517,10 @@ pub async fn sync_logout(state: State<'_, SyncState>) -> Result<(), String> {
     // Clear persisted auth from disk
     let _ = state.auth.clear_from_disk();
 
+    // Forget the server's settings along with the account
+    let _ = fs::remove_file(&*state.server_config_path.read());
+    state.state_manager.set_server_config(ServerSyncConfig::default());
+
     // Clear local state
     state.auth.clear();
     state.state_manager.clear();
@@ -282,23 +533,98 @@ pub async fn sync_logout(state: State<'_, SyncState>) -> Result<(), String> {
 /// Get sync status
 #[tauri::command]
 pub async fn sync_get_status(state: State<'_, SyncState>) -> Result<SyncStatus, String> {
+    Ok(build_status(&state))
+}
+
+/// Send the current sync status to every window as a `sync-status` event,
+/// e.g. for a window that opened after the last sync finished
+#[tauri::command]
+pub async fn sync_broadcast_status(app: AppHandle, state: State<'_, SyncState>) -> Result<(), String> {
+    app.emit("sync-status", build_status(&state)).map_err(|e| e.to_string())
+}
+
+/// Status of every vault, as returned by `sync_get_status`
+fn build_status(state: &SyncState) -> SyncStatus {
     let is_logged_in = state.auth.is_logged_in();
     let user = state.auth.get_user();
 
     let vault_states = state.state_manager.get_all_vault_states();
+    let conflicts = ConflictManager::new(state.auth.get_device_id().unwrap_or_else(|| "unknown".to_string()));
     let vaults: Vec<VaultSyncStatus> = vault_states
         .iter()
         .map(|v| {
-            let pending = state.state_manager.count_pending_changes(&v.vault_path);
-            v.to_status(pending)
+            let pending = state.state_manager.pending_changes(&v.vault_path);
+            let mut status = v.to_status(pending.count);
+            status.warnings = pending
+                .errors
+                .iter()
+                .map(|e| format!("Cannot read {}: {}", e.relative_path, e.error))
+                .chain(pending.skipped.iter().map(|f| {
+                    format!("Skipped {}: {} bytes exceeds the size limit", f.relative_path, f.size_bytes)
+                }))
+                .collect();
+            status.next_sync_at = state.scheduler.next_sync_at(&v.vault_path);
+            if v.enabled && v.auto_sync_paused(now_ms()) {
+                status.auto_sync_paused_until = v.circuit_open_until;
+                status.warnings.insert(0, AUTO_SYNC_PAUSED_WARNING.to_string());
+            }
+            status.read_only = v.enabled && crate::vault::is_read_only(Path::new(&v.vault_path));
+            if v.enabled {
+                status.conflict_count = conflicts.count_conflicts(Path::new(&v.vault_path)).unwrap_or(0) as u32;
+            }
+            status.last_result = state.last_results.read().get(&v.vault_path).cloned();
+            status
         })
         .collect();
+    let total_conflicts = vaults.iter().map(|v| v.conflict_count).sum();
 
-    Ok(SyncStatus {
+    SyncStatus {
         is_logged_in,
         user,
         vaults,
         last_error: None,
+        paused: state.paused.load(Ordering::SeqCst),
+        total_conflicts,
+    }
+}
+
+/// Describe the current session for account screens; `None` when signed out
+#[tauri::command]
+pub async fn sync_whoami(state: State<'_, SyncState>) -> Result<Option<SessionInfo>, String> {
+    let (Some(user), Some(device_id), Some(server_url), Some(token_expires_at)) = (
+        state.auth.get_user(),
+        state.auth.get_device_id(),
+        state.auth.get_server_url(),
+        state.auth.get_token_expires_at(),
+    ) else {
+        return Ok(None);
+    };
+
+    Ok(Some(SessionInfo {
+        user,
+        device_id,
+        server_url,
+        token_expires_at,
+        expires_in_seconds: token_expires_at.saturating_sub(now_ms() / 1000),
+        refresh_due: state.auth.needs_token_refresh(),
+    }))
+}
+
+/// Time one master key derivation on this device, so settings can explain
+/// the delay when logging in
+#[tauri::command]
+pub async fn sync_benchmark_kdf() -> Result<KdfBenchmark, String> {
+    let duration = tokio::task::spawn_blocking(crypto::benchmark_master_key)
+        .await
+        .map_err(|e| format!("Key derivation task failed: {}", e))?
+        .map_err(|e| e.to_string())?;
+
+    Ok(KdfBenchmark {
+        duration_ms: duration.as_millis() as u64,
+        memory_kb: crypto::ARGON2_MEMORY_KB,
+        iterations: crypto::ARGON2_ITERATIONS,
+        parallelism: crypto::ARGON2_PARALLELISM,
+        slow: duration >= crypto::SLOW_KEY_DERIVATION,
     })
 }
 
@@ -309,23 +635,72 @@ pub async fn sync_enable_vault(
     vault_path: String,
     vault_name: String,
 ) -> Result<String, String> {
+    state.ensure_available()?;
     if !state.auth.is_logged_in() {
         return Err("Not logged in".to_string());
     }
 
+    ensure_no_vault_overlap(&state, &vault_path)?;
+
     let server_url = state.auth.get_server_url().ok_or("No server URL")?;
     let token = state.auth.get_access_token().ok_or("No access token")?;
     let user = state.auth.get_user().ok_or("No user info")?;
 
-    let client = reqwest::Client::new();
+    let vault_info = create_remote_vault(&state, &server_url, &token, &vault_name).await?;
+
+    // Enable sync for this vault
+    state.state_manager.enable_vault(&vault_path, &vault_info.id);
+    state.state_manager.set_vault_encrypted(&vault_info.id, vault_info.encrypted);
+    state.state_manager.set_prefixed_paths(&vault_info.id);
+
+    // Write the sync manifest to the vault folder for reconnection
+    let manifest = VaultSyncManifest::new(
+        vault_info.id.clone(),
+        server_url,
+        user.id,
+        vault_info.encrypted,
+    );
+    write_sync_manifest(&vault_path, &manifest)?;
+
+    Ok(vault_info.id)
+}
+
+/// Create an empty vault on the server. When the session holds an account
+/// encryption key, the vault is created encrypted: a new vault key is
+/// generated, stored on the server wrapped with the account key, and kept
+/// unlocked for this session. Sessions without one (e.g. the simplified
+/// sign-in) create plaintext vaults.
+async fn create_remote_vault(
+    state: &SyncState,
+    server_url: &str,
+    token: &str,
+    vault_name: &str,
+) -> Result<VaultInfo, String> {
+    let client = state.http_client(server_url)?;
     let create_url = format!("{}/api/v1/vaults", server_url);
+
+    let vault_key = match state.auth.get_encryption_key() {
+        Some(account_key) => {
+            let key = CryptoKey::generate();
+            let wrapped = crypto::encrypt_vault_key(&account_key, &key).map_err(|e| e.to_string())?;
+            Some((key, wrapped))
+        }
+        None => None,
+    };
+    let mut body = serde_json::json!({
+        "name": vault_name,
+        "encrypted": vault_key.is_some(),
+        "path_scheme": PREFIXED_PATH_SCHEME,
+    });
+    if let Some((_, (encrypted_key, key_nonce))) = &vault_key {
+        body["encrypted_key"] = serde_json::json!(encrypted_key);
+        body["key_nonce"] = serde_json::json!(key_nonce);
+    }
     
     let create_response = client
         .post(&create_url)
         .header("Authorization", format!("Bearer {}", token))
-        .json(&serde_json::json!({
-            "name": vault_name,
-        }))
+        .json(&body)
         .send()
         .await
         .map_err(|e| format!("Failed to create vault: {}", e))?;
@@ -335,80 +710,1626 @@ pub async fn sync_enable_vault(
         return Err(format!("Failed to create vault: {}", error_text));
     }
 
-    let vault_info: VaultInfo = create_response.json().await
-        .map_err(|e| format!("Failed to parse vault response: {}", e))?;
+    let mut vault_info: VaultInfo = read_json(create_response, "vault response").await
+        .map_err(|e| e.to_string())?;
+    if let Some((key, _)) = vault_key {
+        vault_info.encrypted = true;
+        state.state_manager.set_vault_key(&vault_info.id, key);
+    }
+    Ok(vault_info)
+}
 
-    // Enable sync for this vault
-    state.state_manager.enable_vault(&vault_path, &vault_info.id);
+/// Error for an encrypted vault whose key can't be unlocked on this device
+const VAULT_LOCKED_ERROR: &str =
+    "This vault is encrypted but its key isn't unlocked on this device; sign in with your password to sync it";
 
-    // Write the sync manifest to the vault folder for reconnection
-    let manifest = VaultSyncManifest::new(
-        vault_info.id.clone(),
-        server_url,
-        user.id,
-    );
-    write_sync_manifest(&vault_path, &manifest)?;
+/// Key to sync `vault` with: `None` for plaintext vaults. The key of an
+/// encrypted vault is fetched from the server and unwrapped with the account
+/// key the first time it's needed in a session.
+async fn unlock_vault_key(state: &SyncState, vault: &VaultState) -> Result<Option<CryptoKey>, String> {
+    if !vault.encrypted {
+        return Ok(None);
+    }
+    if let Some(key) = state.state_manager.get_vault_key(&vault.vault_id) {
+        return Ok(Some(key));
+    }
+
+    let account_key = state.auth.get_encryption_key().ok_or(VAULT_LOCKED_ERROR)?;
+    let wrapped = state
+        .get_client()
+        .map_err(|e| e.to_string())?
+        .get_vault_key(&vault.vault_id)
+        .await
+        .map_err(|e| format!("Failed to fetch the vault key: {}", e))?;
+    let key = crypto::decrypt_vault_key(&account_key, &wrapped.encrypted_key, &wrapped.key_nonce)
+        .map_err(|e| format!("Failed to unlock the vault key: {}", e))?;
+    state.state_manager.set_vault_key(&vault.vault_id, key.clone());
+    Ok(Some(key))
+}
+
+/// Replace an encrypted vault's key with a new one. Every file on the
+/// server is re-encrypted with the new key and uploaded again, then the key
+/// is wrapped with the account key and stored on the server in place of the
+/// old one. The new key and the files already done are kept in the vault's
+/// sync state, so running this again after an interruption resumes the
+/// rotation instead of starting another. Other devices fetch the new key
+/// once content they download no longer opens with the old one. Progress is
+/// reported through `sync-rotate-key-progress` events.
+#[tauri::command]
+pub async fn sync_rotate_vault_key(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<KeyRotationResult, String> {
+    state.ensure_available()?;
+    state.ensure_not_paused()?;
+
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    if !vault_state.enabled {
+        return Err("Sync not enabled for this vault".to_string());
+    }
+    if !vault_state.encrypted {
+        return Err("This vault isn't encrypted, so it has no key to rotate".to_string());
+    }
+    let vault_id = vault_state.vault_id.clone();
+    state
+        .sync_locks
+        .run(&vault_id, rotate_vault_key(&app, &state, &vault_path))
+        .await
+}
+
+async fn rotate_vault_key(app: &AppHandle, state: &SyncState, vault_path: &str) -> Result<KeyRotationResult, String> {
+    // Read again under the lock, in case a sync or rotation just finished
+    let vault_state = state.state_manager.get_vault_state(vault_path)
+        .ok_or("Vault not found")?;
+    let vault_id = vault_state.vault_id.clone();
+    let account_key = state.auth.get_encryption_key().ok_or(VAULT_LOCKED_ERROR)?;
+
+    let rotation = match vault_state.key_rotation.clone() {
+        Some(rotation) => {
+            println!("[Sync] Resuming key rotation of {} ({} files done)", vault_path, rotation.rekeyed.len());
+            rotation
+        }
+        None => {
+            let (encrypted_key, key_nonce) = crypto::encrypt_vault_key(&account_key, &CryptoKey::generate())
+                .map_err(|e| e.to_string())?;
+            state.state_manager.start_key_rotation(&vault_id, encrypted_key, key_nonce);
+            state.state_manager.get_vault_state(vault_path)
+                .and_then(|v| v.key_rotation)
+                .ok_or("Vault not found")?
+        }
+    };
+    let new_key = crypto::decrypt_vault_key(&account_key, &rotation.encrypted_key, &rotation.key_nonce)
+        .map_err(|e| format!("Failed to unlock the new vault key: {}", e))?;
+
+    // An interrupted run may have stored the new key on the server already;
+    // everything was re-encrypted before that, so only the cleanup is left
+    let current_key = unlock_vault_key(state, &vault_state).await?.ok_or(VAULT_LOCKED_ERROR)?;
+    let mut files_reencrypted = 0;
+    if !constant_time_eq(current_key.as_bytes(), new_key.as_bytes()) {
+        let server_url = state.auth.get_server_url().ok_or("Not logged in")?;
+        let access_token = state.auth.get_access_token().ok_or("No access token")?;
+        let mut engine = super::engine::SyncEngine::with_state_manager(
+            server_url.clone(),
+            access_token,
+            vault_id.clone(),
+            vault_path.to_string(),
+            Arc::clone(&state.state_manager),
+        );
+        engine.set_http_client(state.http_client(&server_url)?);
+        engine.set_rate_limit(Arc::clone(&state.rate_limit));
+        engine.set_pull_batch_size(vault_state.effective_pull_batch_size());
+        engine.set_vault_key(Some(current_key));
+        engine.set_download_progress(Some(download_progress_emitter(app)));
+
+        files_reencrypted = engine
+            .rekey_remote_files(&new_key, &rotation.rekeyed, |file_id, version, progress| {
+                state.state_manager.record_rekeyed_file(&vault_id, file_id, version);
+                let _ = app.emit("sync-rotate-key-progress", progress);
+            })
+            .await
+            .map_err(|e| format!("Key rotation stopped (run it again to resume): {}", e))?;
+
+        state
+            .get_client()
+            .map_err(|e| e.to_string())?
+            .put_vault_key(
+                &vault_id,
+                &PutVaultKeyRequest {
+                    encrypted_key: rotation.encrypted_key.clone(),
+                    key_nonce: rotation.key_nonce.clone(),
+                },
+            )
+            .await
+            .map_err(|e| format!("Failed to store the new vault key (run the rotation again to resume): {}", e))?;
+    }
+
+    state.state_manager.set_vault_key(&vault_id, new_key);
+    state.state_manager.finish_key_rotation(&vault_id);
+    println!("[Sync] Rotated the key of {}, re-encrypted {} files", vault_path, files_reencrypted);
+    Ok(KeyRotationResult { files_reencrypted })
+}
+
+/// Move a vault to another server, e.g. from the managed backend to a
+/// self-hosted one. Logs in to `new_server_url`, creates the vault there and
+/// uploads every local file. The new session and vault ID are kept in memory
+/// only until the upload succeeded; then the session becomes the current one
+/// and the auth file and manifest are written. If any step fails, the vault
+/// and session are switched back to the old server and the half-filled new
+/// vault is deleted. With `delete_from_old`, the vault is then deleted on the
+/// old server using the old session's token. Requires `confirm`, since other
+/// vaults on the old server stop syncing until they are migrated too.
+/// Progress is reported through `sync-migrate-progress` events.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn sync_migrate_server(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    vault_path: String,
+    new_server_url: String,
+    email: String,
+    password: String,
+    delete_from_old: bool,
+    confirm: bool,
+) -> Result<ServerMigration, String> {
+    state.ensure_available()?;
+    state.ensure_not_paused()?;
+    if !confirm {
+        return Err("Migrating a vault to another server must be confirmed".to_string());
+    }
+
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    if !vault_state.enabled {
+        return Err("Sync not enabled for this vault".to_string());
+    }
+    if vault_state.local_path_missing() {
+        return Err(MISSING_FOLDER_ERROR.to_string());
+    }
+    let new_server_url = normalize_server_url(&new_server_url);
+    let old_server_url = state.auth.get_server_url();
+    if old_server_url.as_deref() == Some(new_server_url.as_str()) {
+        return Err("The vault is already synced with this server".to_string());
+    }
+    let old_token = state.auth.get_access_token();
+    let old_vault_id = vault_state.vault_id.clone();
+
+    let report = |stage: MigrationStage| {
+        let _ = app.emit(
+            "sync-migrate-progress",
+            ServerMigrationProgress {
+                vault_path: vault_path.clone(),
+                stage,
+            },
+        );
+    };
+
+    report(MigrationStage::LoggingIn);
+    let auth_response = request_login(&state, &email, &password, &new_server_url)
+        .await?
+        .map_err(|error_text| format!("Login failed: {}", error_text))?;
+    let token = auth_response.access_token.clone();
+    let user_id = auth_response.user.id.clone();
+
+    report(MigrationStage::CreatingVault);
+    let vault_name = Path::new(&vault_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "Vault".to_string());
+    let vault_info = create_remote_vault(&state, &new_server_url, &token, &vault_name).await?;
+
+    let old_session = state.auth.session();
+    let snapshot = state.state_manager.snapshot_vault(&vault_path).ok_or("Vault not found")?;
+    state.auth.set_auth_state_simple(auth_response, new_server_url.clone());
+    state.state_manager.move_vault_to_remote(&vault_path, &vault_info.id);
+    state.state_manager.set_vault_encrypted(&vault_info.id, vault_info.encrypted);
+    state.state_manager.set_prefixed_paths(&vault_info.id);
+
+    report(MigrationStage::Uploading);
+    let committed = match run_sync(&app, &state, &vault_path).await {
+        Ok(result) if result.success => state
+            .auth
+            .save_to_disk()
+            .map_err(|e| e.to_string())
+            .and_then(|()| {
+                write_sync_manifest(
+                    &vault_path,
+                    &VaultSyncManifest::new(vault_info.id.clone(), new_server_url.clone(), user_id, vault_info.encrypted),
+                )
+            })
+            .map(|()| result),
+        Ok(result) => Err(format!("the upload finished with errors: {}", result.errors.join("; "))),
+        Err(e) => Err(e),
+    };
+    let result = match committed {
+        Ok(result) => result,
+        Err(e) => {
+            state.auth.restore_session(old_session);
+            state.state_manager.restore_vault(snapshot);
+            // A token refresh during the upload may have saved the new session
+            let restored = if state.auth.is_logged_in() {
+                state.auth.save_to_disk()
+            } else {
+                state.auth.clear_from_disk()
+            };
+            if let Err(e) = restored.and_then(|()| state.init_client()) {
+                eprintln!("[Sync] Failed to restore the old session: {}", e);
+            }
+            if let Err(e) = delete_remote_vault(&state, &new_server_url, &token, &vault_info.id).await {
+                eprintln!("[Sync] Could not delete the new vault on {}: {}", new_server_url, e);
+            }
+            eprintln!("[Sync] Migrating {} to {} failed, switched back: {}", vault_path, new_server_url, e);
+            return Err(format!("Migration failed, the vault still syncs with the old server: {}", e));
+        }
+    };
+    state.init_client().map_err(|e| e.to_string())?;
+    refresh_server_config(&state).await;
+
+    let mut warnings = Vec::new();
+    let mut old_vault_deleted = false;
+    if delete_from_old {
+        report(MigrationStage::DeletingOld);
+        match (old_server_url, old_token) {
+            (Some(old_server_url), Some(old_token)) => {
+                match delete_remote_vault(&state, &old_server_url, &old_token, &old_vault_id).await {
+                    Ok(()) => old_vault_deleted = true,
+                    Err(e) => warnings.push(format!("Could not delete the vault on the old server: {}", e)),
+                }
+            }
+            _ => warnings.push("Not signed in to the old server; its copy of the vault was kept".to_string()),
+        }
+    }
+
+    let others: Vec<String> = state
+        .state_manager
+        .get_all_vault_states()
+        .into_iter()
+        .filter(|v| v.enabled && v.vault_path != vault_path)
+        .map(|v| v.vault_path)
+        .collect();
+    if !others.is_empty() {
+        warnings.push(format!(
+            "These vaults are still on the old server and won't sync until migrated: {}",
+            others.join(", ")
+        ));
+    }
+
+    report(MigrationStage::Done);
+    println!("[Sync] Migrated {} to {}", vault_path, new_server_url);
+
+    Ok(ServerMigration {
+        vault_id: vault_info.id,
+        server_url: new_server_url,
+        result,
+        old_vault_deleted,
+        warnings,
+    })
+}
+
+/// Delete a vault on `server_url` with `token`, which need not be the
+/// current session's
+async fn delete_remote_vault(state: &SyncState, server_url: &str, token: &str, vault_id: &str) -> Result<(), String> {
+    let response = state
+        .http_client(server_url)?
+        .delete(format!("{}/api/v1/vaults/{}", server_url, vault_id))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !response.status().is_success() {
+        return Err(response.status().to_string());
+    }
+    Ok(())
+}
+
+/// Reject a vault folder that is nested inside, or contains, another
+/// connected vault; syncing overlapping folders double-syncs their files
+fn ensure_no_vault_overlap(state: &SyncState, vault_path: &str) -> Result<(), String> {
+    match state.state_manager.find_overlapping_vault(vault_path) {
+        Some(other) if super::state::path_is_nested_in(vault_path, &other.vault_path) => Err(format!(
+            "This folder is inside the synced vault at {}. Disconnect that vault or choose a folder outside it.",
+            other.vault_path
+        )),
+        Some(other) => Err(format!(
+            "This folder contains the synced vault at {}. Disconnect that vault or choose a folder that doesn't contain it.",
+            other.vault_path
+        )),
+        None => Ok(()),
+    }
+}
+
+/// A locally connected vault folder
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VaultConnection {
+    pub vault_id: String,
+    pub vault_path: String,
+    pub enabled: bool,
+    pub last_sync_at: Option<u64>,
+    /// Paths of other connected vaults nested inside or around this one
+    pub overlaps_with: Vec<String>,
+}
+
+/// List every vault known to this device and flag overlapping folders
+#[tauri::command]
+pub async fn sync_list_connections(
+    state: State<'_, SyncState>,
+) -> Result<Vec<VaultConnection>, String> {
+    let vaults = state.state_manager.get_all_vault_states();
+
+    let mut connections: Vec<VaultConnection> = vaults
+        .iter()
+        .map(|v| VaultConnection {
+            vault_id: v.vault_id.clone(),
+            vault_path: v.vault_path.clone(),
+            enabled: v.enabled,
+            last_sync_at: v.last_sync_at,
+            overlaps_with: vaults
+                .iter()
+                .filter(|other| other.vault_id != v.vault_id)
+                .filter(|other| super::state::paths_overlap(&other.vault_path, &v.vault_path))
+                .map(|other| other.vault_path.clone())
+                .collect(),
+        })
+        .collect();
+    connections.sort_by(|a, b| a.vault_path.cmp(&b.vault_path));
+
+    Ok(connections)
+}
+
+/// Disable sync for a vault
+#[tauri::command]
+pub async fn sync_disable_vault(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<(), String> {
+    // Disable sync in state manager
+    state.state_manager.disable_vault(&vault_path);
+    
+    // Clear file states for this vault
+    state.state_manager.clear_vault_file_states(&vault_path);
+    
+    // Delete the sync manifest from the vault folder
+    delete_sync_manifest(&vault_path)?;
+    
+    Ok(())
+}
+
+/// Trigger sync for a vault. Also resumes background syncs paused after
+/// repeated errors.
+#[tauri::command]
+pub async fn sync_now(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<SyncOperationResult, String> {
+    state.ensure_available()?;
+    state.ensure_not_paused()?;
+    state.state_manager.close_circuit(&vault_path);
+    run_sync(&app, &state, &vault_path).await
+}
+
+/// Sync a vault once; shared by `sync_now` and the scheduler. Progress of
+/// large uploads and downloads is reported through `sync-upload-progress`
+/// and `download-progress` events.
+async fn run_sync(app: &AppHandle, state: &SyncState, vault_path: &str) -> Result<SyncOperationResult, String> {
+    let vault_state = state.state_manager.get_vault_state(vault_path)
+        .ok_or("Vault not found")?;
+
+    if !vault_state.enabled {
+        return Err("Sync not enabled for this vault".to_string());
+    }
+    if vault_state.local_path_missing() {
+        return Err(MISSING_FOLDER_ERROR.to_string());
+    }
+
+    // vault_id is now always present (it's the primary key in VaultState)
+    let vault_id = vault_state.vault_id.clone();
+
+    let server_url = state.auth.get_server_url()
+        .ok_or("Not logged in")?;
+    
+    let access_token = state.auth.get_access_token()
+        .ok_or("No access token")?;
+
+    // Create sync engine with state manager for incremental sync
+    let http_client = state.http_client(&server_url)?;
+    let vault_key = unlock_vault_key(state, &vault_state).await?;
+    let mut engine = super::engine::SyncEngine::with_state_manager(
+        server_url,
+        access_token,
+        vault_id,
+        vault_path.to_string(),
+        Arc::clone(&state.state_manager),
+    );
+    engine.set_http_client(http_client);
+    engine.set_placeholder_mode(vault_state.placeholder_mode);
+    engine.set_max_file_size(state.state_manager.max_file_size_for(&vault_state));
+    engine.set_allowed_extensions(state.state_manager.allowed_extensions());
+    engine.set_pull_concurrency(vault_state.effective_pull_concurrency());
+    engine.set_pull_batch_size(vault_state.effective_pull_batch_size());
+    engine.set_include_dirs(vault_state.include_dirs.clone());
+    engine.set_follow_symlinks(vault_state.effective_follow_symlinks());
+    engine.set_body_hash_only(vault_state.hash_note_body_only);
+    engine.set_vault_key(vault_key);
+    engine.set_read_only(crate::vault::is_read_only(Path::new(vault_path)));
+    engine.set_server_probe(Arc::clone(&state.server_probe));
+    engine.set_rate_limit(Arc::clone(&state.rate_limit));
+    let progress_app = app.clone();
+    engine.set_upload_progress(Some(Arc::new(move |progress: UploadProgress| {
+        let _ = progress_app.emit("sync-upload-progress", progress);
+    })));
+    engine.set_download_progress(Some(download_progress_emitter(app)));
+
+    let result = state
+        .sync_locks
+        .run(&vault_state.vault_id, engine.sync())
+        .await;
+    // Being offline says nothing about the vault, so it doesn't count
+    // towards the circuit breaker
+    if !matches!(result, Err(SyncError::Offline(_))) {
+        let success = result.as_ref().is_ok_and(|result| result.success);
+        if state.state_manager.record_sync_outcome(vault_path, success, now_ms()) {
+            eprintln!("[Sync] {}: {}", vault_path, AUTO_SYNC_PAUSED_WARNING);
+        }
+    }
+    // The server not knowing the vault may mean it was deleted remotely;
+    // check, so the vault shows that instead of the raw error
+    let not_found = result.as_ref().is_err_and(is_vault_not_found);
+    let result = result.map_err(|e| e.to_string());
+    state.record_result(vault_path, &result);
+    if not_found {
+        if let Err(e) = check_remote_vault(state, &vault_state.vault_id).await {
+            eprintln!("[Sync] Could not check the remote vault: {}", e);
+        }
+    }
+    let result = result?;
+
+    // Update last sync time on success
+    if result.success {
+        state.state_manager.update_last_sync(vault_path);
+    }
+
+    if let Some(days) = vault_state.conflict_retention_days {
+        let device_id = state.auth.get_device_id().unwrap_or_else(|| "unknown".to_string());
+        match ConflictManager::new(device_id).cleanup_conflicts(Path::new(vault_path), days) {
+            Ok(0) => {}
+            Ok(deleted) => println!("[Sync] Cleaned up {} old conflict files", deleted),
+            Err(e) => eprintln!("[Sync] Conflict cleanup failed: {}", e),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Whether a connected vault's remote vault still exists
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RemoteVaultCheck {
+    pub vault_id: String,
+    /// `false` when the server no longer has the vault. The vault then
+    /// shows [`REMOTE_VAULT_MISSING_ERROR`] until it is connected to a new
+    /// remote vault or disconnected.
+    pub remote_exists: bool,
+    /// Name of the remote vault, if it exists
+    pub remote_name: Option<String>,
+}
+
+/// Whether the server answered a vault request with "not found": a
+/// `VAULT_NOT_FOUND` error, or a bare 404 from a vault endpoint
+fn is_vault_not_found(error: &SyncError) -> bool {
+    match error {
+        SyncError::VaultNotFound(_) => true,
+        SyncError::Server(message) => message.starts_with("404"),
+        _ => false,
+    }
+}
+
+/// Look up a vault on the server, recording on the vault whether it is gone
+async fn check_remote_vault(state: &SyncState, vault_id: &str) -> Result<RemoteVaultCheck, String> {
+    let remote_name = lookup_remote_vault(state, vault_id).await?.map(|vault| vault.name);
+    Ok(RemoteVaultCheck {
+        vault_id: vault_id.to_string(),
+        remote_exists: remote_name.is_some(),
+        remote_name,
+    })
+}
+
+/// Fetch a vault from the server (`None` if it no longer exists), recording
+/// on the local vault whether it is gone
+async fn lookup_remote_vault(state: &SyncState, vault_id: &str) -> Result<Option<VaultInfo>, String> {
+    let client = state.get_client().map_err(|e| e.to_string())?;
+    let remote = match client.get_vault(vault_id).await {
+        Ok(vault) => Some(vault),
+        Err(e) if is_vault_not_found(&e) => None,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let vault_state = state.state_manager.get_vault_state_by_id(vault_id);
+    if remote.is_none() {
+        eprintln!("[Sync] Remote vault {} no longer exists", vault_id);
+        state.state_manager.set_vault_error_by_id(vault_id, Some(REMOTE_VAULT_MISSING_ERROR.to_string()));
+    } else if let Some(vault_state) = vault_state.filter(|v| v.last_error.as_deref() == Some(REMOTE_VAULT_MISSING_ERROR)) {
+        state.state_manager.set_vault_error_by_id(vault_id, None);
+        if vault_state.enabled {
+            state.state_manager.update_vault_status_by_id(vault_id, VaultSyncState::Idle);
+        }
+    }
+
+    Ok(remote)
+}
+
+/// Check that a connected vault still exists on the server. If it was
+/// deleted (e.g. from another device), the vault gets an error asking to
+/// connect it to a new remote vault or disconnect it. Also run after a sync
+/// fails with a not-found error.
+#[tauri::command]
+pub async fn sync_validate_connection(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<RemoteVaultCheck, String> {
+    state.ensure_available()?;
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    check_remote_vault(&state, &vault_state.vault_id).await
+}
+
+/// Fresh server-side stats of a remote vault, from `sync_refresh_vault_info`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RefreshedVaultInfo {
+    #[serde(flatten)]
+    pub vault: VaultInfo,
+    /// When a file in the vault last changed on the server (ms since epoch)
+    pub last_modified: Option<u64>,
+}
+
+/// Fetch a remote vault's current file count, size and last change from
+/// the server, e.g. to show accurate stats after other devices synced
+/// without waiting for a sync here. A vault the server no longer has gets
+/// the missing-vault error, as with `sync_validate_connection`.
+#[tauri::command]
+pub async fn sync_refresh_vault_info(
+    state: State<'_, SyncState>,
+    vault_id: String,
+) -> Result<RefreshedVaultInfo, String> {
+    state.ensure_available()?;
+    if !state.auth.is_logged_in() {
+        return Err("Not logged in".to_string());
+    }
+
+    let mut vault = lookup_remote_vault(&state, &vault_id)
+        .await?
+        .ok_or_else(|| format!("Remote vault {} no longer exists", vault_id))?;
+    // The vault record's counts can lag behind; the sync status is current
+    let client = state.get_client().map_err(|e| e.to_string())?;
+    let status = client.get_sync_status(&vault_id).await.map_err(|e| e.to_string())?;
+    vault.file_count = status.file_count;
+    vault.total_size_bytes = status.total_size_bytes;
+
+    Ok(RefreshedVaultInfo {
+        vault,
+        last_modified: status.last_modified,
+    })
+}
+
+/// Clear a `Syncing` status no running sync accounts for, e.g. one left by
+/// an interrupted sync. Returns whether the status was reset.
+#[tauri::command]
+pub async fn sync_reset_status(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<bool, String> {
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    if state.sync_locks.is_syncing(&vault_state.vault_id) {
+        return Err("A sync is running for this vault".to_string());
+    }
+    let reset = state.state_manager.clear_syncing_status(&vault_state.vault_id);
+    if reset {
+        println!("[Sync] Reset stuck syncing status of {}", vault_path);
+    }
+    Ok(reset)
+}
+
+/// File states whose file was removed outside the app and that haven't
+/// been synced for a while, so they no longer describe anything on disk
+#[tauri::command]
+pub async fn sync_get_orphaned_states(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<Vec<FileSyncState>, String> {
+    let vault_id = state.state_manager.get_vault_id_for_path(&vault_path)
+        .ok_or("Vault not found")?;
+    Ok(state.state_manager.orphaned_file_states_by_id(&vault_id, Path::new(&vault_path), now_ms()))
+}
+
+/// Remove the file states `sync_get_orphaned_states` reports, returning how
+/// many were removed
+#[tauri::command]
+pub async fn sync_clear_orphaned_states(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<u32, String> {
+    let vault_id = state.state_manager.get_vault_id_for_path(&vault_path)
+        .ok_or("Vault not found")?;
+    if state.sync_locks.is_syncing(&vault_id) {
+        return Err("A sync is running for this vault".to_string());
+    }
+    Ok(clear_orphaned_states(&state, &vault_id, &vault_path).len() as u32)
+}
+
+/// Remove a vault's orphaned file states, returning their paths
+fn clear_orphaned_states(state: &SyncState, vault_id: &str, vault_path: &str) -> Vec<String> {
+    let orphaned: Vec<String> = state
+        .state_manager
+        .orphaned_file_states_by_id(vault_id, Path::new(vault_path), now_ms())
+        .into_iter()
+        .map(|file_state| file_state.relative_path)
+        .collect();
+    let removed = state.state_manager.remove_file_states_by_id(vault_id, &orphaned);
+    if removed > 0 {
+        println!("[Sync] Cleared {} orphaned file states of {}", removed, vault_path);
+    }
+    orphaned
+}
+
+/// Get the outcome of the most recent sync of a vault this session, however
+/// it was started
+#[tauri::command]
+pub async fn sync_get_last_result(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<Option<LastSyncResult>, String> {
+    Ok(state.last_results.read().get(&vault_path).cloned())
+}
+
+/// Set how often a vault syncs in the background; 0 means manual only
+#[tauri::command]
+pub async fn sync_set_interval(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    seconds: u64,
+) -> Result<(), String> {
+    if !state.state_manager.set_sync_interval(&vault_path, seconds) {
+        return Err("Vault not found".to_string());
+    }
+    state.scheduler.reset(&vault_path);
+    Ok(())
+}
+
+/// Pause or resume scheduled background syncs
+#[tauri::command]
+pub fn sync_set_offline(state: State<'_, SyncState>, offline: bool) {
+    state.offline.store(offline, Ordering::SeqCst);
+    if !offline {
+        state.server_probe.reset();
+    }
+}
+
+/// Pause all sync activity without changing which vaults are enabled
+#[tauri::command]
+pub fn sync_pause_all(state: State<'_, SyncState>) {
+    state.paused.store(true, Ordering::SeqCst);
+    println!("[Sync] All syncing paused");
+}
+
+/// Resume syncing after `sync_pause_all` and catch up on every enabled
+/// vault in the background. Each result is sent as a `sync-scheduled` event.
+#[tauri::command]
+pub fn sync_resume_all(app: AppHandle, state: State<'_, SyncState>) {
+    if !state.paused.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    println!("[Sync] Syncing resumed");
+
+    let state = state.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        if state.unavailable.is_some() || !state.auth.is_logged_in() {
+            return;
+        }
+        for vault in state.state_manager.get_all_vault_states() {
+            if !vault.enabled || vault.local_path_missing() || vault.auto_sync_paused(now_ms()) {
+                continue;
+            }
+            let event = match run_sync(&app, &state, &vault.vault_path).await {
+                Ok(result) => {
+                    state.scheduler.record_success(&vault.vault_path, now_ms());
+                    VaultSyncOutcome {
+                        vault_path: vault.vault_path,
+                        result: Some(result),
+                        error: None,
+                    }
+                }
+                Err(e) => VaultSyncOutcome {
+                    vault_path: vault.vault_path,
+                    result: None,
+                    error: Some(e),
+                },
+            };
+            let _ = app.emit("sync-scheduled", event);
+        }
+    });
+}
+
+/// Outcome of syncing one vault, returned by `sync_all` and sent as the
+/// payload of `sync-scheduled` events
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VaultSyncOutcome {
+    pub vault_path: String,
+    pub result: Option<SyncOperationResult>,
+    pub error: Option<String>,
+}
+
+/// Start the background loop that syncs each enabled vault on its own
+/// interval. Results are reported through `sync-scheduled` events.
+pub fn start_scheduler(app: AppHandle, state: SyncState) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(SCHEDULER_TICK).await;
+            run_scheduled_syncs(&app, &state).await;
+        }
+    });
+}
+
+async fn run_scheduled_syncs(app: &AppHandle, state: &SyncState) {
+    if state.unavailable.is_some()
+        || state.offline.load(Ordering::SeqCst)
+        || state.paused.load(Ordering::SeqCst)
+        || !state.auth.is_logged_in()
+    {
+        return;
+    }
+
+    for vault in state.state_manager.get_all_vault_states() {
+        if !vault.enabled || vault.local_path_missing() {
+            state.scheduler.reset(&vault.vault_path);
+            continue;
+        }
+        if vault.auto_sync_paused(now_ms())
+            || !state.scheduler.is_due(&vault.vault_path, vault.sync_interval_seconds, now_ms())
+        {
+            continue;
+        }
+
+        let outcome = match run_sync(app, state, &vault.vault_path).await {
+            Ok(result) if result.success => Ok(result),
+            Ok(result) => Err((Some(result), "Sync finished with errors".to_string())),
+            Err(e) => Err((None, e)),
+        };
+
+        let event = match outcome {
+            Ok(result) => {
+                state.scheduler.record_success(&vault.vault_path, now_ms());
+                VaultSyncOutcome {
+                    vault_path: vault.vault_path.clone(),
+                    result: Some(result),
+                    error: None,
+                }
+            }
+            Err((result, error)) => {
+                println!("[Sync] Scheduled sync of {} failed: {}", vault.vault_path, error);
+                state.scheduler.record_failure(&vault.vault_path, now_ms());
+                VaultSyncOutcome {
+                    vault_path: vault.vault_path.clone(),
+                    result,
+                    error: Some(error),
+                }
+            }
+        };
+        let _ = app.emit("sync-scheduled", event);
+    }
+}
+
+/// Sync every enabled vault in turn. Vaults whose local folder is missing
+/// are skipped and reported with an error instead of failing the batch.
+#[tauri::command]
+pub async fn sync_all(app: AppHandle, state: State<'_, SyncState>) -> Result<Vec<VaultSyncOutcome>, String> {
+    state.ensure_available()?;
+    state.ensure_not_paused()?;
+
+    let mut outcomes = Vec::new();
+    for vault in state.state_manager.get_all_vault_states() {
+        if !vault.enabled {
+            continue;
+        }
+        if vault.local_path_missing() {
+            println!("[Sync] Skipping {}: local folder not found", vault.vault_path);
+            outcomes.push(VaultSyncOutcome {
+                vault_path: vault.vault_path,
+                result: None,
+                error: Some(MISSING_FOLDER_ERROR.to_string()),
+            });
+            continue;
+        }
+
+        let outcome = match run_sync(&app, &state, &vault.vault_path).await {
+            Ok(result) => VaultSyncOutcome {
+                vault_path: vault.vault_path,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => VaultSyncOutcome {
+                vault_path: vault.vault_path,
+                result: None,
+                error: Some(e),
+            },
+        };
+        outcomes.push(outcome);
+    }
+
+    Ok(outcomes)
+}
+
+/// Move sync state, auth, the cached server settings and proxy overrides to
+/// `new_dir`, e.g. onto an encrypted volume, which must be empty. Running
+/// syncs are waited for and new ones held back meanwhile, as are writes of
+/// the auth file. The files are copied and checked before anything switches over, and the
+/// old copies are only deleted once state was saved in the new folder; any
+/// earlier failure leaves everything where it was. The note index then
+/// moves too: it is rebuilt in the new folder in the background rather than
+/// copied. The frontend's own tables in `echopad.db` stay in the app data
+/// directory.
+#[tauri::command]
+pub async fn relocate_app_data(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    index: State<'_, NoteIndex>,
+    new_dir: String,
+) -> Result<AppDataRelocation, String> {
+    state.ensure_available()?;
+    let new_dir = PathBuf::from(new_dir);
+    if !new_dir.is_absolute() {
+        return Err("Choose an absolute folder".to_string());
+    }
+    fs::create_dir_all(&new_dir).map_err(|e| format!("Cannot create {}: {}", new_dir.display(), e))?;
+    let is_empty = fs::read_dir(&new_dir)
+        .map_err(|e| format!("Cannot read {}: {}", new_dir.display(), e))?
+        .next()
+        .is_none();
+    if !is_empty {
+        return Err("Choose an empty folder".to_string());
+    }
+
+    let old_dir = state.auth.data_dir();
+    let same_dir = match (fs::canonicalize(&old_dir), fs::canonicalize(&new_dir)) {
+        (Ok(old), Ok(new)) => old == new,
+        _ => old_dir == new_dir,
+    };
+    if same_dir {
+        return Err("Sync data is already stored there".to_string());
+    }
+
+    let was_paused = state.paused.swap(true, Ordering::SeqCst);
+    let vault_ids: Vec<String> = state
+        .state_manager
+        .get_all_vault_states()
+        .into_iter()
+        .map(|vault| vault.vault_id)
+        .collect();
+    let syncs = state.sync_locks.hold_all(&vault_ids).await;
+    let result = move_sync_data(&state, &old_dir, &new_dir);
+    drop(syncs);
+    state.paused.store(was_paused, Ordering::SeqCst);
+    let mut relocation = result?;
+
+    match index.relocate(new_dir.join("echopad.db")).await {
+        Ok(errors) => {
+            relocation.warnings.extend(errors);
+            let vaults = index.watched_vaults();
+            tauri::async_runtime::spawn(async move {
+                let index = app.state::<NoteIndex>();
+                for vault in vaults {
+                    let reindexed = index
+                        .reindex(&vault, false, |progress| {
+                            let _ = app.emit("reindex-progress", progress);
+                        })
+                        .await;
+                    if let Err(e) = reindexed {
+                        eprintln!("[Index] Failed to rebuild the index of {:?}: {}", vault, e);
+                    }
+                }
+            });
+        }
+        Err(e) => relocation
+            .warnings
+            .push(format!("The note index could not be moved and will be rebuilt on restart: {}", e)),
+    }
+    Ok(relocation)
+}
+
+/// Copy, switch over and clean up for `relocate_app_data`, with syncs held
+fn move_sync_data(state: &SyncState, old_dir: &Path, new_dir: &Path) -> Result<AppDataRelocation, String> {
+    let _auth_file = state.auth.lock_file();
+    state.state_manager.flush().map_err(|e| e.to_string())?;
+
+    let mut files = state.state_manager.data_files();
+    files.push(state.auth.auth_file_path());
+    files.push(state.server_config_path.read().clone());
+    files.push(state.proxy.path());
+    let copied = data_dir::copy_verified(&files, new_dir)?;
+
+    if let Err(e) = data_dir::write_location(&state.app_data_dir, new_dir) {
+        data_dir::remove_files(&copied);
+        return Err(format!("Failed to record the new location: {}", e));
+    }
+
+    let switch_to = |dir: &Path| {
+        state.state_manager.set_data_dir(dir);
+        state.auth.set_data_dir(dir.to_path_buf());
+        *state.server_config_path.write() = dir.join(SERVER_CONFIG_FILE_NAME);
+        state.proxy.set_path(dir.join(PROXY_FILE_NAME));
+    };
+    switch_to(new_dir);
+    // Anything changed since the copy was made
+    if let Err(e) = state.state_manager.flush() {
+        switch_to(old_dir);
+        let _ = data_dir::write_location(&state.app_data_dir, old_dir);
+        data_dir::remove_files(&copied);
+        return Err(format!("Failed to save sync state in the new folder: {}", e));
+    }
+
+    let warnings: Vec<String> = data_dir::remove_files(&files)
+        .into_iter()
+        .map(|e| format!("Could not delete the old copy of {}", e))
+        .collect();
+    println!("[Sync] Moved sync data from {} to {}", old_dir.display(), new_dir.display());
+
+    Ok(AppDataRelocation {
+        old_dir: old_dir.to_string_lossy().to_string(),
+        new_dir: new_dir.to_string_lossy().to_string(),
+        moved_files: copied
+            .iter()
+            .filter_map(|file| file.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect(),
+        warnings,
+    })
+}
+
+/// Rebuild the local path -> vault mapping from the stored vaults and report
+/// what was wrong with it
+#[tauri::command]
+pub async fn sync_repair_mappings(state: State<'_, SyncState>) -> Result<MappingRepair, String> {
+    Ok(state.state_manager.repair_mappings())
+}
+
+/// Point a connected vault at a new local folder, e.g. after it was moved
+/// or its original folder was deleted and restored elsewhere. File states
+/// are keyed by vault ID and relative path, so they carry over unchanged.
+#[tauri::command]
+pub async fn sync_relocate_vault(
+    state: State<'_, SyncState>,
+    old_path: String,
+    new_path: String,
+) -> Result<(), String> {
+    let vault_state = state.state_manager.get_vault_state(&old_path)
+        .ok_or("Vault not found")?;
+
+    if !Path::new(&new_path).is_dir() {
+        return Err("New vault folder does not exist".to_string());
+    }
+
+    if let Some(manifest) = read_sync_manifest(&new_path) {
+        if manifest.remote_vault_id != vault_state.vault_id {
+            return Err("That folder is connected to a different vault".to_string());
+        }
+    }
+
+    if let Some(other) = state
+        .state_manager
+        .get_all_vault_states()
+        .into_iter()
+        .filter(|v| v.enabled && v.vault_id != vault_state.vault_id)
+        .find(|v| super::state::paths_overlap(&v.vault_path, &new_path) || v.vault_path == new_path)
+    {
+        return Err(format!(
+            "That folder overlaps the synced vault at {}",
+            other.vault_path
+        ));
+    }
+
+    state.state_manager.update_vault_path(&vault_state.vault_id, &new_path);
+    state.state_manager.set_vault_error_by_id(&vault_state.vault_id, None);
+    if vault_state.enabled {
+        state.state_manager.update_vault_status_by_id(&vault_state.vault_id, VaultSyncState::Idle);
+    }
+    state.scheduler.reset(&old_path);
+
+    if let (Some(server_url), Some(user)) = (state.auth.get_server_url(), state.auth.get_user()) {
+        let manifest = VaultSyncManifest::new(vault_state.vault_id.clone(), server_url, user.id, vault_state.encrypted);
+        write_sync_manifest(&new_path, &manifest)?;
+    }
+
+    println!("[Sync] Relocated vault {} from {} to {}", vault_state.vault_id, old_path, new_path);
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// Enable or disable placeholder (on-demand) downloads for a vault
+#[tauri::command]
+pub async fn sync_set_placeholder_mode(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    enabled: bool,
+) -> Result<(), String> {
+    if !state.state_manager.set_placeholder_mode(&vault_path, enabled) {
+        return Err("Vault not found".to_string());
+    }
+    Ok(())
+}
+
+/// Report large downloads through `download-progress` events
+fn download_progress_emitter(app: &AppHandle) -> super::engine::DownloadProgressFn {
+    let app = app.clone();
+    Arc::new(move |progress: DownloadProgress| {
+        let _ = app.emit("download-progress", progress);
+    })
+}
+
+/// Download the real content of a placeholder file, e.g. when a note is opened
+#[tauri::command]
+pub async fn sync_materialize_file(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    vault_path: String,
+    relative_path: String,
+) -> Result<(), String> {
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    crate::vault::ensure_writable(Path::new(&vault_path))?;
+
+    let server_url = state.auth.get_server_url()
+        .ok_or("Not logged in")?;
+    let access_token = state.auth.get_access_token()
+        .ok_or("No access token")?;
+
+    let http_client = state.http_client(&server_url)?;
+    let vault_key = unlock_vault_key(&state, &vault_state).await?;
+    let mut engine = super::engine::SyncEngine::with_state_manager(
+        server_url,
+        access_token,
+        vault_state.vault_id,
+        vault_path,
+        Arc::clone(&state.state_manager),
+    );
+    engine.set_http_client(http_client);
+    engine.set_rate_limit(Arc::clone(&state.rate_limit));
+    engine.set_vault_key(vault_key);
+    engine.set_download_progress(Some(download_progress_emitter(&app)));
+
+    engine.materialize_file(&relative_path).await.map_err(|e| e.to_string())
+}
+
+/// Integrity report for a vault's local files
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IntegrityReport {
+    pub vault_path: String,
+    pub file_count: usize,
+    pub total_size: u64,
+    /// Files and folders the scanner could not read (they will never sync)
+    pub unreadable: Vec<ScanError>,
+    /// Files over the size limit (they will never sync)
+    pub oversized: Vec<SkippedFile>,
+    /// Size limit applied to the scan, in bytes
+    pub max_file_size: u64,
+    /// Symlinks, each noted as synced as a copy or skipped
+    pub symlinks: Vec<SymlinkEntry>,
+}
+
+/// Scan a vault and report files that are silently excluded from sync
+#[tauri::command]
+pub async fn sync_verify_integrity(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<IntegrityReport, String> {
+    verify_integrity(&state, vault_path)
+}
+
+fn verify_integrity(state: &SyncState, vault_path: String) -> Result<IntegrityReport, String> {
+    let path = Path::new(&vault_path);
+    if !path.exists() {
+        return Err("Vault path does not exist".to_string());
+    }
+
+    // Vaults that aren't connected yet are checked against the default limit
+    let (scan, max_file_size) = match state.state_manager.get_vault_state(&vault_path) {
+        Some(v) => {
+            let limit = state.state_manager.max_file_size_for(&v);
+            (scan_vault_including(path, limit, &v.include_dirs, v.effective_follow_symlinks(), false), limit)
+        }
+        None => (scan_vault(path), DEFAULT_MAX_FILE_SIZE),
+    };
+    let scan = scan.map_err(|e| e.to_string())?;
+
+    Ok(IntegrityReport {
+        vault_path,
+        file_count: scan.file_count,
+        total_size: scan.total_size,
+        unreadable: scan.errors,
+        oversized: scan.skipped,
+        max_file_size,
+        symlinks: scan.symlinks,
+    })
+}
+
+/// Notes of a vault with `encrypt: false` in their frontmatter, which an
+/// encrypted vault uploads as plaintext while encrypting everything else
+#[tauri::command]
+pub async fn sync_get_unencrypted_notes(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<Vec<String>, String> {
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    let path = Path::new(&vault_path);
+    let scan = scan_vault_including(
+        path,
+        state.state_manager.max_file_size_for(&vault_state),
+        &vault_state.include_dirs,
+        vault_state.effective_follow_symlinks(),
+        false,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut notes: Vec<String> = scan
+        .files
+        .into_keys()
+        .filter(|relative_path| {
+            super::crypto::is_note_path(relative_path)
+                && fs::read(crate::vault::long_path(path.join(relative_path)))
+                    .is_ok_and(|content| super::crypto::keeps_plaintext(relative_path, &content))
+        })
+        .collect();
+    notes.sort();
+    Ok(notes)
+}
+
+/// Everything `sync_maintenance` fixed in a vault, and what is left for
+/// the user to look at
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct MaintenanceReport {
+    pub vault_path: String,
+    /// Vaults whose leftover file states were dropped
+    pub compacted_vaults: Vec<String>,
+    pub mappings: MappingRepair,
+    /// Orphaned file states that were removed
+    pub orphaned_states: Vec<String>,
+    /// Conflict copies deleted because they matched their original
+    pub resolved_conflicts: u32,
+    pub integrity: IntegrityReport,
+    /// Problems maintenance can't fix by itself
+    pub needs_attention: Vec<String>,
+}
+
+/// Run every sync maintenance step on a vault: compact the sync state,
+/// repair the path mappings, clear orphaned file states, delete resolved
+/// conflict copies and verify which files sync skips. Each step can also be
+/// run on its own through its command.
+#[tauri::command]
+pub async fn sync_maintenance(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<MaintenanceReport, String> {
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    if state.sync_locks.is_syncing(&vault_state.vault_id) {
+        return Err("A sync is running for this vault".to_string());
+    }
+
+    let compacted_vaults = state.state_manager.compact();
+    let mappings = state.state_manager.repair_mappings();
+    let orphaned_states = clear_orphaned_states(&state, &vault_state.vault_id, &vault_path);
+    let device_id = state.auth.get_device_id().unwrap_or_else(|| "unknown".to_string());
+    let conflicts = ConflictManager::new(device_id);
+    let resolved_conflicts = conflicts
+        .remove_resolved_conflicts(Path::new(&vault_path))
+        .map_err(|e| e.to_string())?;
+    let integrity = verify_integrity(&state, vault_path.clone())?;
+
+    let mut needs_attention = Vec::new();
+    for duplicate in &mappings.duplicates {
+        needs_attention.push(format!(
+            "{} vaults are connected to {}",
+            duplicate.vault_ids.len(),
+            duplicate.vault_path
+        ));
+    }
+    if let Some(error) = state.state_manager.get_vault_state(&vault_path).and_then(|v| v.last_error) {
+        needs_attention.push(format!("Last sync failed: {}", error));
+    }
+    match conflicts.count_conflicts(Path::new(&vault_path)) {
+        Ok(0) => {}
+        Ok(count) => needs_attention.push(format!("{} conflicts need resolving", count)),
+        Err(e) => needs_attention.push(format!("Conflicts could not be listed: {}", e)),
+    }
+    if !integrity.unreadable.is_empty() {
+        needs_attention.push(format!("{} files can't be read", integrity.unreadable.len()));
+    }
+    if !integrity.oversized.is_empty() {
+        needs_attention.push(format!("{} files are over the size limit", integrity.oversized.len()));
+    }
+
+    println!(
+        "[Sync] Maintenance of {}: {} orphaned states, {} resolved conflicts, {} items need attention",
+        vault_path,
+        orphaned_states.len(),
+        resolved_conflicts,
+        needs_attention.len()
+    );
+    Ok(MaintenanceReport {
+        vault_path,
+        compacted_vaults,
+        mappings,
+        orphaned_states,
+        resolved_conflicts,
+        integrity,
+        needs_attention,
+    })
+}
+
+/// A file's stored sync state next to its current content, for diagnosing
+/// files that won't sync or keep re-uploading
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FileStateReport {
+    pub relative_path: String,
+    /// What sync last recorded for the file, if anything
+    pub stored: Option<FileSyncState>,
+    /// Hash of the file on disk now; `None` if it doesn't exist
+    pub current_hash: Option<String>,
+    /// Whether the file on disk is what was last synced
+    pub matches_synced: bool,
+}
+
+/// Estimate how much the next sync of a vault would upload and delete,
+/// without any network traffic
+#[tauri::command]
+pub async fn sync_estimate_upload(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<UploadEstimate, String> {
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    if vault_state.local_path_missing() {
+        return Err(MISSING_FOLDER_ERROR.to_string());
+    }
+
+    // The engine only scans here, so no server or token is needed
+    let mut engine = super::engine::SyncEngine::with_state_manager(
+        String::new(),
+        String::new(),
+        vault_state.vault_id.clone(),
+        vault_path,
+        Arc::clone(&state.state_manager),
+    );
+    engine.set_max_file_size(state.state_manager.max_file_size_for(&vault_state));
+    engine.set_allowed_extensions(state.state_manager.allowed_extensions());
+    engine.set_include_dirs(vault_state.include_dirs.clone());
+    engine.set_follow_symlinks(vault_state.effective_follow_symlinks());
+    engine.set_body_hash_only(vault_state.hash_note_body_only);
+
+    engine.estimate_upload().map_err(|e| e.to_string())
+}
+
+/// Storage used against the account quota, next to the local size of each
+/// synced vault and how much of it isn't uploaded yet. Remote figures are
+/// left out when the server can't be reached, so the local side still shows
+/// offline.
+#[tauri::command]
+pub async fn get_storage_breakdown(state: State<'_, SyncState>) -> Result<StorageBreakdown, String> {
+    let client = state.get_client().ok();
+    let usage = match &client {
+        Some(client) => client.get_usage().await.ok(),
+        None => None,
+    };
+
+    let mut vaults = Vec::new();
+    for vault_state in state.state_manager.get_all_vault_states() {
+        if !vault_state.enabled {
+            continue;
+        }
+
+        let mut storage = VaultStorage {
+            vault_path: vault_state.vault_path.clone(),
+            vault_id: vault_state.vault_id.clone(),
+            local_bytes: 0,
+            local_files: 0,
+            remote_bytes: None,
+            remote_files: None,
+            unsynced_bytes: 0,
+            unsynced_files: 0,
+            error: None,
+        };
+
+        if vault_state.local_path_missing() {
+            storage.error = Some(MISSING_FOLDER_ERROR.to_string());
+        } else {
+            let mut engine = super::engine::SyncEngine::with_state_manager(
+                String::new(),
+                String::new(),
+                vault_state.vault_id.clone(),
+                vault_state.vault_path.clone(),
+                Arc::clone(&state.state_manager),
+            );
+            engine.set_max_file_size(state.state_manager.max_file_size_for(&vault_state));
+            engine.set_allowed_extensions(state.state_manager.allowed_extensions());
+            engine.set_include_dirs(vault_state.include_dirs.clone());
+            engine.set_follow_symlinks(vault_state.effective_follow_symlinks());
+            engine.set_body_hash_only(vault_state.hash_note_body_only);
+
+            match engine.scan_with_estimate() {
+                Ok((scan, estimate)) => {
+                    storage.local_bytes = scan.total_size;
+                    storage.local_files = scan.file_count as u32;
+                    storage.unsynced_bytes = estimate.bytes_to_upload;
+                    storage.unsynced_files = estimate.files_to_upload;
+                }
+                Err(e) => storage.error = Some(e.to_string()),
+            }
+        }
+
+        if let Some(client) = &client {
+            match client.get_sync_status(&vault_state.vault_id).await {
+                Ok(status) => {
+                    storage.remote_bytes = Some(status.total_size_bytes);
+                    storage.remote_files = Some(status.file_count);
+                }
+                Err(e) => {
+                    storage.error.get_or_insert_with(|| e.to_string());
+                }
+            }
+        }
+        vaults.push(storage);
+    }
+
+    Ok(StorageBreakdown {
+        storage_used_bytes: usage.as_ref().map(|u| u.storage_used_bytes),
+        storage_quota_bytes: usage.as_ref().map(|u| u.storage_quota_bytes),
+        remote_vault_count: usage.as_ref().map(|u| u.vault_count),
+        local_bytes: vaults.iter().map(|v| v.local_bytes).sum(),
+        unsynced_bytes: vaults.iter().map(|v| v.unsynced_bytes).sum(),
+        vaults,
+    })
+}
+
+/// Get the stored sync state of one file and its current on-disk hash
+#[tauri::command]
+pub async fn sync_get_file_state(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    relative_path: String,
+) -> Result<FileStateReport, String> {
+    let vault_id = state.state_manager.get_vault_id_for_path(&vault_path)
+        .ok_or("Vault not found")?;
+    let path = crate::vault::resolve_in_vault(Path::new(&vault_path), &relative_path)?;
+
+    let stored = state.state_manager.get_file_state_by_id(&vault_id, &relative_path);
+    let current_hash = fs::read(&path).ok().map(|content| compute_hash(&content));
+    let matches_synced = match (&stored, &current_hash) {
+        (Some(stored), Some(hash)) => stored.local_hash.as_ref() == Some(hash),
+        (None, None) => true,
+        _ => false,
+    };
+
+    Ok(FileStateReport {
+        relative_path,
+        stored,
+        current_hash,
+        matches_synced,
+    })
+}
+
+/// The URLs sync requests for a vault would go to, without making any
+/// request, to debug reverse proxies and path rewriting
+#[tauri::command]
+pub async fn sync_get_endpoints(state: State<'_, SyncState>, vault_path: String) -> Result<SyncEndpoints, String> {
+    let server_url = state.auth.get_server_url().ok_or("Not logged in")?;
+    let vault_id = state.state_manager.get_vault_id_for_path(&vault_path);
+    Ok(super::client::endpoints(&server_url, vault_id.as_deref()))
+}
+
+/// Differences between a file on this device and on the server
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RemoteFileDiff {
+    pub relative_path: String,
+    pub local_exists: bool,
+    pub remote_exists: bool,
+    pub identical: bool,
+    /// One side isn't UTF-8 text, so no line diff is given
+    pub binary: bool,
+    /// Line hunks from the server's version to the local one: local-only
+    /// lines are added, server-only lines removed
+    pub hunks: Vec<crate::diff::DiffHunk>,
+}
+
+/// Diff a file's local content against the server's current version,
+/// decrypted for encrypted vaults. A file missing on one side diffs against
+/// empty content.
+#[tauri::command]
+pub async fn sync_diff_with_remote(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    relative_path: String,
+) -> Result<RemoteFileDiff, String> {
+    let vault_state = state.state_manager.get_vault_state(&vault_path)
+        .ok_or("Vault not found")?;
+    let path = crate::vault::resolve_in_vault(Path::new(&vault_path), &relative_path)?;
+
+    let server_url = state.auth.get_server_url()
+        .ok_or("Not logged in")?;
+    let access_token = state.auth.get_access_token()
+        .ok_or("No access token")?;
+
+    let http_client = state.http_client(&server_url)?;
+    let vault_key = unlock_vault_key(&state, &vault_state).await?;
+    let mut engine = super::engine::SyncEngine::new(
+        server_url,
+        access_token,
+        vault_state.vault_id.clone(),
+        vault_path,
+    );
+    engine.set_http_client(http_client);
+    engine.set_rate_limit(Arc::clone(&state.rate_limit));
+    engine.set_vault_key(vault_key);
+    engine.set_pull_batch_size(vault_state.effective_pull_batch_size());
+
+    let remote = engine.fetch_remote_file(&relative_path).await.map_err(|e| e.to_string())?;
+    let local = fs::read(crate::vault::long_path(&path)).ok();
+
+    let local_text = local.as_deref().map(std::str::from_utf8).unwrap_or(Ok(""));
+    let remote_text = remote.as_deref().map(std::str::from_utf8).unwrap_or(Ok(""));
+    let (binary, hunks) = match (remote_text, local_text) {
+        (Ok(remote_text), Ok(local_text)) => (false, crate::diff::diff_lines(remote_text, local_text)),
+        _ => (true, Vec::new()),
+    };
+
+    Ok(RemoteFileDiff {
+        relative_path,
+        local_exists: local.is_some(),
+        remote_exists: remote.is_some(),
+        identical: local == remote,
+        binary,
+        hunks,
+    })
+}
+
+/// Get the sync settings recommended by the server (cached from the last
+/// login)
+#[tauri::command]
+pub async fn sync_get_server_config(state: State<'_, SyncState>) -> Result<ServerSyncConfig, String> {
+    Ok(state.state_manager.server_config())
+}
+
+/// Set the maximum size of files synced for a vault. `None` restores the
+/// default limit.
+#[tauri::command]
+pub async fn sync_set_max_file_size(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    max_bytes: Option<u64>,
+) -> Result<(), String> {
+    if max_bytes == Some(0) {
+        return Err("Maximum file size must be greater than zero".to_string());
+    }
+    if !state.state_manager.set_max_file_size(&vault_path, max_bytes) {
+        return Err("Vault not found".to_string());
+    }
+    Ok(())
+}
+
+/// Set how many remote changes are downloaded at once when pulling a vault.
+/// `None` restores the default.
+#[tauri::command]
+pub async fn sync_set_pull_concurrency(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    concurrency: Option<usize>,
+) -> Result<(), String> {
+    if concurrency == Some(0) {
+        return Err("Pull concurrency must be greater than zero".to_string());
+    }
+    if !state.state_manager.set_pull_concurrency(&vault_path, concurrency) {
+        return Err("Vault not found".to_string());
+    }
+    Ok(())
+}
 
-    Ok(vault_info.id)
+/// Sync normally skipped directories (e.g. `.obsidian`) for a vault. An
+/// empty list restores the default. Returns warnings about what the listed
+/// directories contain, for the UI to show before the next sync.
+#[tauri::command]
+pub async fn sync_set_include_dirs(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    include_dirs: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let mut dirs: Vec<String> = Vec::new();
+    for dir in include_dirs {
+        let dir = dir.trim().to_string();
+        validate_include_dir(&dir)?;
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+
+    let warnings = dirs.iter().map(|dir| include_dir_warning(dir)).collect();
+    if !state.state_manager.set_include_dirs(&vault_path, dirs) {
+        return Err("Vault not found".to_string());
+    }
+    Ok(warnings)
 }
 
-/// Disable sync for a vault
+/// Choose whether a vault's symlinked files and folders sync as copies of
+/// their targets (the default) or are skipped. The server can't store links,
+/// so either way `sync_verify_integrity` lists them.
 #[tauri::command]
-pub async fn sync_disable_vault(
+pub async fn sync_set_follow_symlinks(
     state: State<'_, SyncState>,
     vault_path: String,
+    follow: Option<bool>,
 ) -> Result<(), String> {
-    // Disable sync in state manager
-    state.state_manager.disable_vault(&vault_path);
-    
-    // Clear file states for this vault
-    state.state_manager.clear_vault_file_states(&vault_path);
-    
-    // Delete the sync manifest from the vault folder
-    delete_sync_manifest(&vault_path)?;
-    
+    if !state.state_manager.set_follow_symlinks(&vault_path, follow) {
+        return Err("Vault not found".to_string());
+    }
     Ok(())
 }
 
-/// Trigger sync for a vault
+/// Choose whether a vault's notes count as changed only when their body
+/// changes. Frontmatter-only edits (e.g. a bumped timestamp) then don't
+/// upload by themselves; the full file still goes up with the next body
+/// change.
+///
+/// Until then the local frontmatter edit exists only on this device: pulls
+/// don't check for conflicts, so if another device changes the note's body
+/// first, the pulled note replaces the local one and its tags and other
+/// metadata edits are lost.
 #[tauri::command]
-pub async fn sync_now(
+pub async fn sync_set_hash_note_body_only(
     state: State<'_, SyncState>,
     vault_path: String,
-) -> Result<SyncOperationResult, String> {
-    let vault_state = state.state_manager.get_vault_state(&vault_path)
-        .ok_or("Vault not found")?;
-
-    if !vault_state.enabled {
-        return Err("Sync not enabled for this vault".to_string());
+    enabled: bool,
+) -> Result<(), String> {
+    if !state.state_manager.set_hash_note_body_only(&vault_path, enabled) {
+        return Err("Vault not found".to_string());
     }
+    Ok(())
+}
 
-    // vault_id is now always present (it's the primary key in VaultState)
-    let vault_id = vault_state.vault_id;
-
-    let server_url = state.auth.get_server_url()
-        .ok_or("Not logged in")?;
-    
-    let access_token = state.auth.get_access_token()
-        .ok_or("No access token")?;
-
-    // Create sync engine with state manager for incremental sync
-    let engine = super::engine::SyncEngine::with_state_manager(
-        server_url,
-        access_token,
-        vault_id,
-        vault_path.clone(),
-        Arc::clone(&state.state_manager),
-    );
-
-    let result = engine.sync().await.map_err(|e| e.to_string())?;
-
-    // Update last sync time on success
-    if result.success {
-        state.state_manager.update_last_sync(&vault_path);
+/// What syncing a normally skipped directory means for the user
+fn include_dir_warning(dir: &str) -> String {
+    match dir {
+        ".obsidian" => "Obsidian's app config (themes, plugins, workspace layout) will sync; \
+            changes on one device overwrite the others"
+            .to_string(),
+        ".git" => ".git will sync; git history can grow large and concurrent commits \
+            on two devices can corrupt the repository"
+            .to_string(),
+        "node_modules" => "node_modules will sync; it is usually large and can be reinstalled".to_string(),
+        _ => format!("{} is normally skipped; its files will sync to every device", dir),
     }
+}
 
-    Ok(result)
+/// Set how many remote changes each pull request asks for in a vault.
+/// Values outside what the server accepts are clamped; `None` restores the
+/// default. Returns the batch size that will be used.
+#[tauri::command]
+pub async fn sync_set_pull_batch_size(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    batch_size: Option<u32>,
+) -> Result<u32, String> {
+    if !state.state_manager.set_pull_batch_size(&vault_path, batch_size) {
+        return Err("Vault not found".to_string());
+    }
+    state
+        .state_manager
+        .get_vault_state(&vault_path)
+        .map(|vault_state| vault_state.effective_pull_batch_size())
+        .ok_or_else(|| "Vault not found".to_string())
 }
 
 /// Get conflicts for a vault
@@ -427,6 +2348,67 @@ pub async fn sync_get_conflicts(
         .map_err(|e| e.to_string())
 }
 
+/// Count a vault's conflicts for badges, without building the full
+/// `sync_get_conflicts` list
+#[tauri::command]
+pub async fn sync_conflict_count(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<u32, String> {
+    let device_id = state.auth.get_device_id()
+        .unwrap_or_else(|| "unknown".to_string());
+
+    ConflictManager::new(device_id)
+        .count_conflicts(Path::new(&vault_path))
+        .map(|count| count as u32)
+        .map_err(|e| e.to_string())
+}
+
+/// Delete conflict files older than `older_than_days`. Conflicts whose
+/// original file is gone are restored to the original path instead.
+/// Returns the number of files deleted.
+#[tauri::command]
+pub async fn sync_cleanup_conflicts(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    older_than_days: u32,
+) -> Result<u32, String> {
+    let device_id = state.auth.get_device_id()
+        .unwrap_or_else(|| "unknown".to_string());
+
+    ConflictManager::new(device_id)
+        .cleanup_conflicts(Path::new(&vault_path), older_than_days)
+        .map_err(|e| e.to_string())
+}
+
+/// Clean up conflict files older than `days` after every sync of a vault;
+/// `None` turns automatic cleanup off
+#[tauri::command]
+pub async fn sync_set_conflict_retention(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    days: Option<u32>,
+) -> Result<(), String> {
+    if !state.state_manager.set_conflict_retention(&vault_path, days) {
+        return Err("Vault not found".to_string());
+    }
+    Ok(())
+}
+
+/// Choose whether deleting a notebook (moving it to the trash) pushes its
+/// deletes on the next sync, or only once the trash is emptied
+#[tauri::command]
+pub async fn sync_set_push_trash_deletes_immediately(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    immediately: bool,
+) -> Result<(), String> {
+    if !state.state_manager.set_push_trash_deletes_immediately(&vault_path, immediately) {
+        return Err("Vault not found".to_string());
+    }
+    Ok(())
+}
+
 /// Resolve a sync conflict
 #[tauri::command]
 pub async fn sync_resolve_conflict(
@@ -448,11 +2430,102 @@ pub async fn sync_resolve_conflict(
         .map_err(|e| e.to_string())
 }
 
-/// Get list of devices
+/// Resolve every conflict in a vault the same way. A failure on one file
+/// doesn't stop the rest; each file's outcome is returned.
+#[tauri::command]
+pub async fn sync_resolve_all_conflicts(
+    state: State<'_, SyncState>,
+    vault_path: String,
+    keep: String,
+) -> Result<Vec<ConflictResolutionResult>, String> {
+    let device_id = state.auth.get_device_id()
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let conflict_manager = ConflictManager::new(device_id);
+    let vault = std::path::Path::new(&vault_path);
+
+    let resolution: ConflictResolution = keep.parse()
+        .map_err(|e: super::error::SyncError| e.to_string())?;
+
+    let conflicts = conflict_manager.list_conflicts(vault)
+        .map_err(|e| e.to_string())?;
+
+    let results: Vec<ConflictResolutionResult> = conflicts
+        .into_iter()
+        .map(|conflict| {
+            let outcome = conflict_manager.resolve_conflict(vault, &conflict.conflict_path, resolution);
+            ConflictResolutionResult {
+                original_path: conflict.original_path,
+                conflict_path: conflict.conflict_path,
+                success: outcome.is_ok(),
+                error: outcome.err().map(|e| e.to_string()),
+            }
+        })
+        .collect();
+
+    let failed = results.iter().filter(|r| !r.success).count();
+    println!(
+        "[Sync] Resolved {} conflicts in {} ({} failed)",
+        results.len() - failed,
+        vault_path,
+        failed
+    );
+    Ok(results)
+}
+
+/// Proxy override for a server (the signed-in one by default). The stored
+/// password is never returned.
+#[tauri::command]
+pub async fn sync_get_proxy(
+    state: State<'_, SyncState>,
+    server_url: Option<String>,
+) -> Result<Option<ProxySettings>, String> {
+    let server_url = server_url
+        .or_else(|| state.auth.get_server_url())
+        .ok_or("No server URL")?;
+    Ok(state.proxy.get(&server_url).map(|settings| ProxySettings {
+        password: None,
+        ..settings
+    }))
+}
+
+/// Set or clear (`None`) the proxy override for a server (the signed-in one
+/// by default). Without an override the `HTTP_PROXY`/`HTTPS_PROXY`
+/// environment variables apply. Leaving out the password keeps the stored
+/// one for the same username.
+#[tauri::command]
+pub async fn sync_set_proxy(
+    state: State<'_, SyncState>,
+    server_url: Option<String>,
+    proxy: Option<ProxySettings>,
+) -> Result<(), String> {
+    let server_url = server_url
+        .or_else(|| state.auth.get_server_url())
+        .ok_or("No server URL")?;
+    state.proxy.set(&server_url, proxy).map_err(|e| e.to_string())?;
+
+    // The API client picks up the proxy when it's created
+    if state.auth.get_server_url() == Some(normalize_server_url(&server_url))
+        && state.client.read().is_some()
+    {
+        state.init_client().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Get list of devices, with this device marked current and listed first
 #[tauri::command]
 pub async fn sync_get_devices(state: State<'_, SyncState>) -> Result<Vec<DeviceInfo>, String> {
     let client = state.get_client().map_err(|e| e.to_string())?;
-    client.list_devices().await.map_err(|e| e.to_string())
+    let mut devices = client.list_devices().await.map_err(|e| e.to_string())?;
+
+    // The server's flag isn't reliable; this device is the one we registered as
+    let current_id = state.auth.get_device_id();
+    for device in &mut devices {
+        device.is_current = current_id.as_deref() == Some(device.id.as_str());
+    }
+    devices.sort_by_key(|device| !device.is_current);
+    Ok(devices)
 }
 
 /// Revoke a device
@@ -477,6 +2550,120 @@ pub fn sync_is_logged_in(state: State<'_, SyncState>) -> bool {
     state.auth.is_logged_in()
 }
 
+/// Progress payload emitted while a password-derived key is computed
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct KeyDerivationProgress {
+    pub elapsed_ms: u64,
+}
+
+/// Re-verify the current user's password before a sensitive action
+///
+/// With a verifier cached from an earlier check, the auth hash is derived
+/// from the account salt (off the async runtime) and compared locally,
+/// emitting `sync-key-derivation` events while Argon2 runs so the UI can
+/// show a spinner. Otherwise the server checks the password, without
+/// touching the current session, and the verifier for later checks is
+/// derived in the background.
+#[tauri::command]
+pub async fn sync_verify_password(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    password: String,
+) -> Result<bool, String> {
+    if !state.auth.is_logged_in() {
+        return Err("Not logged in".to_string());
+    }
+
+    let server_url = state.auth.get_server_url().ok_or("No server URL")?;
+    let user = state.auth.get_user().ok_or("No user info")?;
+    let client = state.get_client().map_err(|e| e.to_string())?;
+
+    if let Some(verifier) = state.auth.get_password_verifier() {
+        let salt = client
+            .get_salt(&server_url, &user.email)
+            .await
+            .map_err(|e| e.to_string())?;
+        let login_data = AuthManager::prepare_login_async(&password, &salt.salt, |elapsed| {
+            let _ = app.emit(
+                "sync-key-derivation",
+                KeyDerivationProgress {
+                    elapsed_ms: elapsed.as_millis() as u64,
+                },
+            );
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+        return Ok(constant_time_eq(verifier.as_bytes(), login_data.auth_hash.as_bytes()));
+    }
+
+    let valid = client.verify_password(&password).await.map_err(|e| e.to_string())?;
+    if valid {
+        let auth = Arc::clone(&state.auth);
+        tauri::async_runtime::spawn(async move {
+            let salt = match client.get_salt(&server_url, &user.email).await {
+                Ok(salt) => salt,
+                Err(e) => {
+                    eprintln!("[Sync] Failed to cache password verifier: {}", e);
+                    return;
+                }
+            };
+            match AuthManager::prepare_login_async(&password, &salt.salt, |_| {}).await {
+                Ok(login_data) => auth.set_password_verifier(login_data.auth_hash),
+                Err(e) => eprintln!("[Sync] Failed to cache password verifier: {}", e),
+            }
+        });
+    }
+    Ok(valid)
+}
+
+/// A vault this device keeps sync state for, as listed by
+/// `sync_list_local_vaults`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LocalVaultInfo {
+    pub vault_id: String,
+    pub vault_path: String,
+    pub enabled: bool,
+    pub status: VaultSyncState,
+    pub last_sync_at: Option<u64>,
+    pub last_error: Option<String>,
+    /// Local changes not yet synced (always 0 for disabled vaults)
+    pub pending_changes: u32,
+    /// Files with sync state on this device
+    pub tracked_files: usize,
+    /// The local folder no longer exists
+    pub folder_missing: bool,
+    pub encrypted: bool,
+}
+
+/// List every vault known on this device, disabled ones included, without
+/// contacting the server (unlike `sync_list_remote_vaults`)
+#[tauri::command]
+pub async fn sync_list_local_vaults(state: State<'_, SyncState>) -> Result<Vec<LocalVaultInfo>, String> {
+    let mut vaults: Vec<LocalVaultInfo> = state
+        .state_manager
+        .get_all_vault_states()
+        .into_iter()
+        .map(|vault| {
+            let pending_changes = state.state_manager.pending_changes_for(&vault).count;
+            let status = vault.to_status(pending_changes);
+            LocalVaultInfo {
+                tracked_files: state.state_manager.file_state_count_by_id(&vault.vault_id),
+                folder_missing: vault.local_path_missing(),
+                vault_id: vault.vault_id,
+                vault_path: vault.vault_path,
+                enabled: vault.enabled,
+                status: status.status,
+                last_sync_at: vault.last_sync_at,
+                last_error: status.last_error,
+                pending_changes,
+                encrypted: vault.encrypted,
+            }
+        })
+        .collect();
+    vaults.sort_by(|a, b| a.vault_path.cmp(&b.vault_path).then_with(|| a.vault_id.cmp(&b.vault_id)));
+    Ok(vaults)
+}
+
 /// List remote vaults (for connecting to existing synced vaults)
 #[tauri::command]
 pub async fn sync_list_remote_vaults(
@@ -492,7 +2679,7 @@ pub async fn sync_list_remote_vaults(
     let access_token = state.auth.get_access_token()
         .ok_or("No access token")?;
 
-    let client = reqwest::Client::new();
+    let client = state.http_client(&server_url)?;
     let url = format!("{}/api/v1/vaults", server_url);
     
     let response = client
@@ -507,8 +2694,8 @@ pub async fn sync_list_remote_vaults(
         return Err(format!("Failed to list vaults: {}", error_text));
     }
 
-    let vaults: Vec<VaultInfo> = response.json().await
-        .map_err(|e| format!("Failed to parse vaults: {}", e))?;
+    let vaults: Vec<VaultInfo> = read_json(response, "vaults").await
+        .map_err(|e| e.to_string())?;
 
     Ok(vaults)
 }
@@ -519,7 +2706,9 @@ pub async fn sync_connect_vault(
     state: State<'_, SyncState>,
     vault_path: String,
     remote_vault_id: String,
+    metadata_only: Option<bool>,
 ) -> Result<(), String> {
+    state.ensure_available()?;
     if !state.auth.is_logged_in() {
         return Err("Not logged in".to_string());
     }
@@ -532,31 +2721,72 @@ pub async fn sync_connect_vault(
     
     let user = state.auth.get_user().ok_or("No user info")?;
 
+    ensure_no_vault_overlap(&state, &vault_path)?;
+
+    let vault_info = state
+        .get_client()
+        .map_err(|e| e.to_string())?
+        .get_vault(&remote_vault_id)
+        .await
+        .map_err(|e| e.to_string())?;
+
     // Enable sync for this vault with the existing remote vault ID
     state.state_manager.enable_vault(&vault_path, &remote_vault_id);
+    state.state_manager.set_vault_encrypted(&remote_vault_id, vault_info.encrypted);
+    if vault_info.path_scheme.as_deref() == Some(PREFIXED_PATH_SCHEME) {
+        state.state_manager.set_prefixed_paths(&remote_vault_id);
+    }
+    // Pull only the file list; notes show up at once as placeholders and
+    // their content is downloaded when opened
+    if metadata_only.unwrap_or(false) {
+        state.state_manager.set_placeholder_mode(&vault_path, true);
+    }
 
     // Write the sync manifest to the vault folder for reconnection
     let manifest = VaultSyncManifest::new(
         remote_vault_id.clone(),
         server_url.clone(),
         user.id,
+        vault_info.encrypted,
     );
     write_sync_manifest(&vault_path, &manifest)?;
 
     // Create sync engine with additive pull mode (won't overwrite existing files)
+    let http_client = state.http_client(&server_url)?;
     let mut engine = super::engine::SyncEngine::with_state_manager(
         server_url,
         access_token,
-        remote_vault_id,
+        remote_vault_id.clone(),
         vault_path.clone(),
         Arc::clone(&state.state_manager),
     );
+    engine.set_http_client(http_client);
     
     // Enable additive-only mode for initial connection
     engine.set_additive_only(true);
+    engine.set_read_only(crate::vault::is_read_only(Path::new(&vault_path)));
+    engine.set_server_probe(Arc::clone(&state.server_probe));
+    engine.set_rate_limit(Arc::clone(&state.rate_limit));
+    if let Some(vault_state) = state.state_manager.get_vault_state(&vault_path) {
+        engine.set_vault_key(unlock_vault_key(&state, &vault_state).await?);
+        engine.set_placeholder_mode(vault_state.placeholder_mode);
+        engine.set_max_file_size(state.state_manager.max_file_size_for(&vault_state));
+        engine.set_allowed_extensions(state.state_manager.allowed_extensions());
+        engine.set_pull_concurrency(vault_state.effective_pull_concurrency());
+        engine.set_pull_batch_size(vault_state.effective_pull_batch_size());
+        engine.set_include_dirs(vault_state.include_dirs.clone());
+        engine.set_follow_symlinks(vault_state.effective_follow_symlinks());
+        engine.set_body_hash_only(vault_state.hash_note_body_only);
+    }
 
     // Perform sync to download existing files (additive only)
-    let result = engine.sync().await.map_err(|e| e.to_string())?;
+    let result = state
+        .sync_locks
+        .run(&remote_vault_id, engine.sync())
+        .await
+        .map_err(|e| e.to_string());
+    state.record_result(&vault_path, &result);
+    let result = result?;
 
     if result.success {
         state.state_manager.update_last_sync(&vault_path);
@@ -584,6 +2814,17 @@ pub struct VaultConnectionInfo {
     pub has_existing_state: bool,
 }
 
+/// Read a folder's sync manifest as stored, so the UI can show which remote
+/// vault and server it is connected to and since when
+#[tauri::command]
+pub async fn sync_read_manifest(vault_path: String) -> Result<ManifestInfo, String> {
+    let manifest_exists = sync_manifest_path(&vault_path).exists();
+    Ok(ManifestInfo {
+        manifest_exists,
+        manifest: read_sync_manifest(&vault_path),
+    })
+}
+
 /// Detect if a vault has a sync manifest (was previously connected)
 /// Returns connection info if a manifest exists, None otherwise.
 /// 
@@ -631,6 +2872,65 @@ pub async fn sync_detect_vault_connection(
     }))
 }
 
+/// How the in-memory vault mapping and the on-disk manifest agree
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultIdStatus {
+    /// State and manifest point at the same remote vault
+    Connected,
+    /// Neither state nor manifest know about this folder
+    NotConnected,
+    /// Only the manifest exists (e.g. folder copied from another device)
+    ManifestOnly,
+    /// Only the in-memory state exists (e.g. manifest deleted)
+    StateOnly,
+    /// State and manifest point at different remote vaults
+    Conflict,
+}
+
+/// Vault ID lookup result for a local folder
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VaultIdInfo {
+    pub status: VaultIdStatus,
+    /// Vault ID from the in-memory path mapping
+    pub state_vault_id: Option<String>,
+    /// Vault ID recorded in the folder's sync manifest
+    pub manifest_vault_id: Option<String>,
+}
+
+/// Get the remote vault ID a folder is connected to, reconciling the
+/// in-memory mapping with the on-disk manifest. A `conflict` status means
+/// the two drifted apart (e.g. after a restore) and the user should choose.
+#[tauri::command]
+pub async fn sync_get_vault_id(
+    state: State<'_, SyncState>,
+    vault_path: String,
+) -> Result<VaultIdInfo, String> {
+    let state_vault_id = state.state_manager.get_vault_id_for_path(&vault_path);
+    let manifest_vault_id = read_sync_manifest(&vault_path).map(|m| m.remote_vault_id);
+
+    let status = match (&state_vault_id, &manifest_vault_id) {
+        (Some(a), Some(b)) if a == b => VaultIdStatus::Connected,
+        (Some(_), Some(_)) => VaultIdStatus::Conflict,
+        (Some(_), None) => VaultIdStatus::StateOnly,
+        (None, Some(_)) => VaultIdStatus::ManifestOnly,
+        (None, None) => VaultIdStatus::NotConnected,
+    };
+
+    if status == VaultIdStatus::Conflict {
+        eprintln!(
+            "[Sync] Vault {} mapping mismatch: state {:?}, manifest {:?}",
+            vault_path, state_vault_id, manifest_vault_id
+        );
+    }
+
+    Ok(VaultIdInfo {
+        status,
+        state_vault_id,
+        manifest_vault_id,
+    })
+}
+
 /// Check for pending remote changes without downloading them
 /// Returns the count of changes available on the server since last sync
 #[tauri::command]
@@ -654,7 +2954,7 @@ pub async fn sync_check_remote_pending(
     // Get current cursor from state manager
     let cursor = state.state_manager.get_cursor_by_id(&vault_id);
 
-    let client = reqwest::Client::new();
+    let client = state.http_client(&server_url)?;
     let url = format!("{}/api/v1/vaults/{}/sync/pending", server_url, vault_id);
 
     let response = client
@@ -676,8 +2976,8 @@ pub async fn sync_check_remote_pending(
         pending_changes: u32,
     }
 
-    let result: PendingResponse = response.json().await
-        .map_err(|e| format!("Invalid response: {}", e))?;
+    let result: PendingResponse = read_json(response, "pending changes").await
+        .map_err(|e| e.to_string())?;
 
     Ok(result.pending_changes)
 }
@@ -693,14 +2993,28 @@ pub async fn sync_auto_reconnect_vault(
     state: State<'_, SyncState>,
     vault_path: String,
 ) -> Result<bool, String> {
+    reconnect_vault(&state, &vault_path).map(|outcome| outcome == ReconnectOutcome::Connected)
+}
+
+/// Outcome of reconnecting a single vault from its manifest
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReconnectOutcome {
+    Connected,
+    /// No manifest in the folder, nothing to reconnect
+    NoManifest,
+    /// The manifest belongs to a different account
+    UserMismatch,
+}
+
+fn reconnect_vault(state: &SyncState, vault_path: &str) -> Result<ReconnectOutcome, String> {
     if !state.auth.is_logged_in() {
         return Err("Not logged in".to_string());
     }
 
     // Read the manifest
-    let manifest = match read_sync_manifest(&vault_path) {
+    let manifest = match read_sync_manifest(vault_path) {
         Some(m) => m,
-        None => return Ok(false), // No manifest, nothing to reconnect
+        None => return Ok(ReconnectOutcome::NoManifest),
     };
 
     // Verify current user matches
@@ -709,14 +3023,14 @@ pub async fn sync_auto_reconnect_vault(
         // Different user - don't auto-reconnect
         println!("[Sync] Vault manifest user {} doesn't match current user {}", 
             manifest.user_id, current_user.id);
-        return Ok(false);
+        return Ok(ReconnectOutcome::UserMismatch);
     }
 
     // Check if already connected at this path
-    if let Some(vault_state) = state.state_manager.get_vault_state(&vault_path) {
+    if let Some(vault_state) = state.state_manager.get_vault_state(vault_path) {
         if vault_state.enabled && vault_state.vault_id == manifest.remote_vault_id {
             println!("[Sync] Vault already connected to {}", manifest.remote_vault_id);
-            return Ok(true);
+            return Ok(ReconnectOutcome::Connected);
         }
     }
 
@@ -728,29 +3042,287 @@ pub async fn sync_auto_reconnect_vault(
                 manifest.remote_vault_id, existing_state.vault_path, vault_path
             );
             // Update the path mapping - this preserves the file sync state
-            state.state_manager.update_vault_path(&manifest.remote_vault_id, &vault_path);
+            state.state_manager.update_vault_path(&manifest.remote_vault_id, vault_path);
         }
         
         // Ensure it's enabled
         if !existing_state.enabled {
-            state.state_manager.enable_vault(&vault_path, &manifest.remote_vault_id);
+            state.state_manager.enable_vault(vault_path, &manifest.remote_vault_id);
         }
     } else {
         // First time connecting this vault on this device
         println!("[Sync] Auto-reconnecting vault {} to remote {}", vault_path, manifest.remote_vault_id);
-        state.state_manager.enable_vault(&vault_path, &manifest.remote_vault_id);
+        state.state_manager.enable_vault(vault_path, &manifest.remote_vault_id);
+        state.state_manager.set_vault_encrypted(&manifest.remote_vault_id, manifest.encrypted);
     }
 
     // Update manifest with current server URL if it changed
     let current_server_url = state.auth.get_server_url().ok_or("No server URL")?;
-    if current_server_url != manifest.server_url {
+    if current_server_url != normalize_server_url(&manifest.server_url) {
         let updated_manifest = VaultSyncManifest::new(
             manifest.remote_vault_id.clone(),
             current_server_url,
             current_user.id,
+            manifest.encrypted,
         );
-        write_sync_manifest(&vault_path, &updated_manifest)?;
+        write_sync_manifest(vault_path, &updated_manifest)?;
+    }
+
+    Ok(ReconnectOutcome::Connected)
+}
+
+/// What `sync_reconnect_all` did with one vault
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultReconnectStatus {
+    Reconnected,
+    /// Nothing to reconnect, e.g. the folder has no manifest
+    Skipped,
+    Failed,
+}
+
+/// Outcome of reconnecting one vault in `sync_reconnect_all`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VaultReconnectResult {
+    pub vault_path: String,
+    pub status: VaultReconnectStatus,
+    /// Why the vault was skipped or failed
+    pub reason: Option<String>,
+}
+
+/// Reconnect every known vault from its manifest in one call, e.g. after
+/// the session is restored at startup, instead of calling
+/// `sync_auto_reconnect_vault` for each vault path
+#[tauri::command]
+pub async fn sync_reconnect_all(state: State<'_, SyncState>) -> Result<Vec<VaultReconnectResult>, String> {
+    state.ensure_available()?;
+    if !state.auth.is_logged_in() {
+        return Err("Not logged in".to_string());
+    }
+
+    let mut paths: Vec<String> = state
+        .state_manager
+        .get_all_vault_states()
+        .into_iter()
+        .map(|vault| vault.vault_path)
+        .collect();
+    paths.sort();
+    paths.dedup();
+
+    let results: Vec<VaultReconnectResult> = paths
+        .into_iter()
+        .map(|vault_path| {
+            let (status, reason) = if !Path::new(&vault_path).is_dir() {
+                (VaultReconnectStatus::Failed, Some(MISSING_FOLDER_ERROR.to_string()))
+            } else {
+                match reconnect_vault(&state, &vault_path) {
+                    Ok(ReconnectOutcome::Connected) => (VaultReconnectStatus::Reconnected, None),
+                    Ok(ReconnectOutcome::NoManifest) => {
+                        (VaultReconnectStatus::Skipped, Some("No sync manifest in the folder".to_string()))
+                    }
+                    Ok(ReconnectOutcome::UserMismatch) => (
+                        VaultReconnectStatus::Skipped,
+                        Some("The vault belongs to a different account".to_string()),
+                    ),
+                    Err(e) => (VaultReconnectStatus::Failed, Some(e)),
+                }
+            };
+            VaultReconnectResult { vault_path, status, reason }
+        })
+        .collect();
+
+    let reconnected = results.iter().filter(|r| r.status == VaultReconnectStatus::Reconnected).count();
+    println!("[Sync] Reconnected {} of {} vaults", reconnected, results.len());
+    Ok(results)
+}
+
+/// Maximum attempts made by the background reconnect loop
+const RECONNECT_MAX_ATTEMPTS: u32 = 6;
+/// Delay before the first retry; doubled after every failed attempt
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(2);
+/// Upper bound for the retry delay
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Phase reported by `sync-reconnecting` events
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReconnectStatus {
+    /// An attempt failed transiently; another is scheduled
+    Retrying,
+    /// Session and vaults are connected
+    Connected,
+    /// A permanent failure stopped the loop (e.g. account mismatch)
+    Failed,
+    /// Every attempt failed transiently
+    GaveUp,
+}
+
+/// Payload of the `sync-reconnecting` event
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ReconnectEvent {
+    pub status: ReconnectStatus,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// Delay before the next attempt when retrying
+    pub next_retry_ms: Option<u64>,
+    pub message: Option<String>,
+}
+
+/// Why a reconnect attempt failed
+enum ReconnectFailure {
+    /// Worth retrying (network down, server error)
+    Transient(String),
+    /// Retrying won't help
+    Permanent(String),
+}
+
+/// Start a background loop that restores the session and reconnects the
+/// given vaults (plus every enabled vault), retrying transient failures with
+/// exponential backoff. Progress is reported through `sync-reconnecting`
+/// events. Returns `false` if a loop is already running.
+#[tauri::command]
+pub async fn sync_start_auto_reconnect(
+    app: AppHandle,
+    state: State<'_, SyncState>,
+    vault_paths: Vec<String>,
+) -> Result<bool, String> {
+    state.ensure_available()?;
+    if state.reconnecting.swap(true, Ordering::SeqCst) {
+        return Ok(false);
     }
 
+    let state = state.inner().clone();
+    tauri::async_runtime::spawn(async move {
+        run_reconnect_loop(&app, &state, vault_paths).await;
+        state.reconnecting.store(false, Ordering::SeqCst);
+    });
+
     Ok(true)
 }
+
+async fn run_reconnect_loop(app: &AppHandle, state: &SyncState, vault_paths: Vec<String>) {
+    let emit = |status, attempt, next_retry: Option<Duration>, message: Option<String>| {
+        let _ = app.emit(
+            "sync-reconnecting",
+            ReconnectEvent {
+                status,
+                attempt,
+                max_attempts: RECONNECT_MAX_ATTEMPTS,
+                next_retry_ms: next_retry.map(|d| d.as_millis() as u64),
+                message,
+            },
+        );
+    };
+
+    let mut delay = RECONNECT_INITIAL_DELAY;
+    for attempt in 1..=RECONNECT_MAX_ATTEMPTS {
+        match reconnect_attempt(state, &vault_paths).await {
+            Ok(()) => {
+                println!("[Sync] Reconnected on attempt {}", attempt);
+                emit(ReconnectStatus::Connected, attempt, None, None);
+                return;
+            }
+            Err(ReconnectFailure::Permanent(message)) => {
+                println!("[Sync] Reconnect stopped: {}", message);
+                emit(ReconnectStatus::Failed, attempt, None, Some(message));
+                return;
+            }
+            Err(ReconnectFailure::Transient(message)) if attempt == RECONNECT_MAX_ATTEMPTS => {
+                println!("[Sync] Giving up reconnecting after {} attempts: {}", attempt, message);
+                emit(ReconnectStatus::GaveUp, attempt, None, Some(message));
+                return;
+            }
+            Err(ReconnectFailure::Transient(message)) => {
+                println!(
+                    "[Sync] Reconnect attempt {} failed ({}), retrying in {:?}",
+                    attempt, message, delay
+                );
+                emit(ReconnectStatus::Retrying, attempt, Some(delay), Some(message));
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(RECONNECT_MAX_DELAY);
+            }
+        }
+    }
+}
+
+/// A single reconnect attempt: restore the session if needed, then
+/// reconnect every requested and enabled vault. Vaults of another account
+/// are skipped.
+async fn reconnect_attempt(state: &SyncState, vault_paths: &[String]) -> Result<(), ReconnectFailure> {
+    if !state.auth.is_logged_in() {
+        match restore_session(state).await {
+            Ok(Some(_)) => {}
+            Ok(None) => {
+                return Err(ReconnectFailure::Permanent(
+                    "No valid stored session".to_string(),
+                ))
+            }
+            Err(e @ (SyncError::Network(_) | SyncError::Server(_) | SyncError::RateLimited(_))) => {
+                return Err(ReconnectFailure::Transient(e.to_string()))
+            }
+            Err(e) => return Err(ReconnectFailure::Permanent(e.to_string())),
+        }
+    }
+
+    let mut paths: Vec<String> = vault_paths.to_vec();
+    for vault in state.state_manager.get_all_vault_states() {
+        if vault.enabled && !paths.contains(&vault.vault_path) {
+            paths.push(vault.vault_path);
+        }
+    }
+
+    for path in &paths {
+        match reconnect_vault(state, path) {
+            // Only that vault stays disconnected, as with `sync_reconnect_all`
+            Ok(ReconnectOutcome::UserMismatch) => {
+                println!("[Sync] Not reconnecting {}: it belongs to a different account", path);
+            }
+            Ok(_) => {}
+            Err(e) => return Err(ReconnectFailure::Transient(e)),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_vault_not_found() {
+        assert!(is_vault_not_found(&SyncError::VaultNotFound("Vault not found".into())));
+        assert!(is_vault_not_found(&SyncError::Server("404 Not Found: no such vault".into())));
+        assert!(!is_vault_not_found(&SyncError::FileNotFound("a.md".into())));
+        assert!(!is_vault_not_found(&SyncError::Server("500 Internal Server Error: 404 Not Found".into())));
+        assert!(!is_vault_not_found(&SyncError::InvalidData("Vault not found".into())));
+    }
+
+    #[test]
+    fn test_move_sync_data_rolls_back_when_the_location_is_not_recorded() {
+        let root = std::env::temp_dir().join(format!("echopad-relocate-{}", uuid::Uuid::new_v4()));
+        let (old_dir, new_dir) = (root.join("old"), root.join("new"));
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::create_dir_all(&new_dir).unwrap();
+        let state = SyncState::new(old_dir.join("echopad.db"));
+        state.state_manager.enable_vault("/notes", "vault-1");
+
+        // The pointer file can't be written over a folder
+        fs::create_dir(old_dir.join(data_dir::DATA_LOCATION_FILE)).unwrap();
+        assert!(move_sync_data(&state, &old_dir, &new_dir).is_err());
+        assert!(old_dir.join(STATE_FILE_NAME).is_file());
+        assert_eq!(fs::read_dir(&new_dir).unwrap().count(), 0);
+        assert_eq!(state.auth.data_dir(), old_dir);
+
+        fs::remove_dir(old_dir.join(data_dir::DATA_LOCATION_FILE)).unwrap();
+        let relocation = move_sync_data(&state, &old_dir, &new_dir).unwrap();
+        assert!(relocation.moved_files.contains(&STATE_FILE_NAME.to_string()));
+        assert!(new_dir.join(STATE_FILE_NAME).is_file());
+        assert!(!old_dir.join(STATE_FILE_NAME).exists());
+        assert_eq!(state.auth.data_dir(), new_dir);
+        assert_eq!(data_dir::moved_data_dir(&old_dir), Some(new_dir.clone()));
+        assert!(state.state_manager.get_vault_state("/notes").is_some());
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
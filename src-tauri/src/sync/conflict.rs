@@ -6,11 +6,15 @@ use std::path::{Path, PathBuf};
 use std::fs;
 
 use super::error::{SyncError, SyncResult};
+use super::scanner::compute_hash;
 use super::types::ConflictInfo;
 
 /// Conflict file suffix pattern
 const CONFLICT_SUFFIX: &str = ".sync-conflict-";
 
+/// Number of content hash characters encoded in a conflict filename
+const CONFLICT_HASH_LEN: usize = 12;
+
 /// Conflict manager for handling sync conflicts
 pub struct ConflictManager {
     /// Device identifier for conflict naming
@@ -57,6 +61,56 @@ impl ConflictManager {
         original_path.with_file_name(conflict_name)
     }
 
+    /// Generate a conflict file path that encodes the remote content hash,
+    /// so the same remote version always maps back to the same conflict
+    pub fn generate_versioned_conflict_path(&self, original_path: &Path, content_hash: &str) -> PathBuf {
+        let base = self.generate_conflict_path(original_path);
+        let tag = Self::conflict_tag(content_hash);
+
+        let stem = base
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let ext = base
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+
+        base.with_file_name(format!("{}-{}{}", stem, tag, ext))
+    }
+
+    /// Find an existing conflict file for `original_path` holding the given
+    /// remote content hash
+    pub fn find_existing_conflict(original_path: &Path, content_hash: &str) -> Option<PathBuf> {
+        let parent = original_path.parent()?;
+        let stem = original_path.file_stem()?.to_string_lossy().to_string();
+        let ext = original_path
+            .extension()
+            .map(|e| format!(".{}", e.to_string_lossy()))
+            .unwrap_or_default();
+
+        let prefix = format!("{}{}", stem, CONFLICT_SUFFIX);
+        let suffix = format!("-{}{}", Self::conflict_tag(content_hash), ext);
+
+        fs::read_dir(parent)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .map(|n| n.to_string_lossy())
+                    .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(&suffix))
+            })
+    }
+
+    fn conflict_tag(content_hash: &str) -> String {
+        content_hash
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .take(CONFLICT_HASH_LEN)
+            .collect()
+    }
+
     /// Check if a path is a conflict file
     pub fn is_conflict_file(path: &Path) -> bool {
         path.file_name()
@@ -83,7 +137,11 @@ impl ConflictManager {
         }
     }
 
-    /// Create a conflict file from content
+    /// Create a conflict file from content.
+    ///
+    /// Idempotent per (original path, remote content): if a conflict file for
+    /// the same remote version already exists it is returned unchanged, so
+    /// repeated syncs of an unresolved divergence don't pile up copies.
     pub fn create_conflict_file(
         &self,
         vault_path: &Path,
@@ -91,8 +149,13 @@ impl ConflictManager {
         content: &[u8],
     ) -> SyncResult<PathBuf> {
         let original_path = vault_path.join(relative_path);
-        let conflict_path = self.generate_conflict_path(&original_path);
+        let content_hash = compute_hash(content);
 
+        if let Some(existing) = Self::find_existing_conflict(&original_path, &content_hash) {
+            return Ok(existing);
+        }
+
+        let conflict_path = self.generate_versioned_conflict_path(&original_path, &content_hash);
         fs::write(&conflict_path, content)?;
 
         Ok(conflict_path)
@@ -101,15 +164,32 @@ impl ConflictManager {
     /// List all conflict files in a vault
     pub fn list_conflicts(&self, vault_path: &Path) -> SyncResult<Vec<ConflictInfo>> {
         let mut conflicts = Vec::new();
-        self.scan_conflicts_recursive(vault_path, vault_path, &mut conflicts)?;
+        Self::visit_conflict_files(vault_path, &mut |path| {
+            if let Some(info) = self.parse_conflict_info(vault_path, path)? {
+                conflicts.push(info);
+            }
+            Ok(())
+        })?;
         Ok(conflicts)
     }
 
-    fn scan_conflicts_recursive(
-        &self,
-        base_path: &Path,
+    /// Number of conflicts `list_conflicts` would return, without reading
+    /// any file metadata
+    pub fn count_conflicts(&self, vault_path: &Path) -> SyncResult<usize> {
+        let mut count = 0;
+        Self::visit_conflict_files(vault_path, &mut |path| {
+            if Self::get_original_path(path).is_some() {
+                count += 1;
+            }
+            Ok(())
+        })?;
+        Ok(count)
+    }
+
+    /// Call `visit` with every conflict file under `current_path`
+    fn visit_conflict_files(
         current_path: &Path,
-        conflicts: &mut Vec<ConflictInfo>,
+        visit: &mut dyn FnMut(&Path) -> SyncResult<()>,
     ) -> SyncResult<()> {
         if !current_path.is_dir() {
             return Ok(());
@@ -125,12 +205,10 @@ impl ConflictManager {
                     .map(|n| n.to_string_lossy().starts_with('.'))
                     .unwrap_or(false)
                 {
-                    self.scan_conflicts_recursive(base_path, &path, conflicts)?;
+                    Self::visit_conflict_files(&path, visit)?;
                 }
             } else if Self::is_conflict_file(&path) {
-                if let Some(info) = self.parse_conflict_info(base_path, &path)? {
-                    conflicts.push(info);
-                }
+                visit(&path)?;
             }
         }
 
@@ -182,8 +260,10 @@ impl ConflictManager {
             // Skip device ID (8 chars) and parse timestamp
             if after_suffix.len() > 8 {
                 let timestamp_str = &after_suffix[8..];
-                // Find where the extension starts
-                let timestamp_end = timestamp_str.find('.').unwrap_or(timestamp_str.len());
+                // Find where the hash tag or extension starts
+                let timestamp_end = timestamp_str
+                    .find(['-', '.'])
+                    .unwrap_or(timestamp_str.len());
                 timestamp_str[..timestamp_end].parse::<u64>().unwrap_or(0) * 1000
             } else {
                 0
@@ -218,18 +298,18 @@ impl ConflictManager {
             .ok_or_else(|| SyncError::InvalidState("Not a conflict file".to_string()))?;
 
         match keep {
-            ConflictResolution::KeepLocal => {
+            ConflictResolution::Local => {
                 // Delete conflict file, keep original
                 fs::remove_file(&conflict_path)?;
             }
-            ConflictResolution::KeepRemote => {
+            ConflictResolution::Remote => {
                 // Replace original with conflict, delete conflict
                 if conflict_path.exists() {
                     fs::copy(&conflict_path, &original_path)?;
                     fs::remove_file(&conflict_path)?;
                 }
             }
-            ConflictResolution::KeepBoth => {
+            ConflictResolution::Both => {
                 // Rename conflict to a non-conflict name
                 let stem = original_path
                     .file_stem()
@@ -255,6 +335,65 @@ impl ConflictManager {
         Ok(())
     }
 
+    /// Delete conflict files created more than `older_than_days` days ago,
+    /// judged by the timestamp in their name. A conflict whose original file
+    /// is gone may be the only copy left, so instead of being deleted the
+    /// newest such conflict is renamed back to the original path. Conflicts
+    /// without a readable timestamp are left alone. Returns the number of
+    /// files deleted.
+    pub fn cleanup_conflicts(&self, vault_path: &Path, older_than_days: u32) -> SyncResult<u32> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let cutoff = now.saturating_sub(u64::from(older_than_days) * 24 * 60 * 60 * 1000);
+
+        let mut conflicts = self.list_conflicts(vault_path)?;
+        // Newest first, so the most recent copy is the one promoted
+        conflicts.sort_by_key(|c| std::cmp::Reverse(c.created_at));
+
+        let mut deleted = 0;
+        for conflict in conflicts {
+            if conflict.created_at == 0 || conflict.created_at > cutoff {
+                continue;
+            }
+
+            let conflict_path = vault_path.join(&conflict.conflict_path);
+            let original_path = vault_path.join(&conflict.original_path);
+            if original_path.exists() {
+                fs::remove_file(&conflict_path)?;
+                deleted += 1;
+            } else {
+                fs::rename(&conflict_path, &original_path)?;
+                println!(
+                    "[Sync] Restored {} from conflict copy {}",
+                    conflict.original_path, conflict.conflict_path
+                );
+            }
+        }
+
+        Ok(deleted)
+    }
+
+    /// Delete conflict files whose content is the same as their original
+    /// file's, i.e. conflicts that were resolved by merging the copy in by
+    /// hand. Returns the number of files deleted.
+    pub fn remove_resolved_conflicts(&self, vault_path: &Path) -> SyncResult<u32> {
+        let mut deleted = 0;
+        for conflict in self.list_conflicts(vault_path)? {
+            let conflict_path = vault_path.join(&conflict.conflict_path);
+            let (Ok(copy), Ok(original)) = (fs::read(&conflict_path), fs::read(vault_path.join(&conflict.original_path)))
+            else {
+                continue;
+            };
+            if compute_hash(&copy) == compute_hash(&original) {
+                fs::remove_file(&conflict_path)?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
+
     /// Delete all conflict files for a specific original path
     pub fn delete_conflicts_for(&self, vault_path: &Path, original_relative_path: &str) -> SyncResult<u32> {
         let conflicts = self.list_conflicts(vault_path)?;
@@ -278,11 +417,11 @@ impl ConflictManager {
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ConflictResolution {
     /// Keep the local version, discard remote
-    KeepLocal,
+    Local,
     /// Keep the remote version, discard local
-    KeepRemote,
+    Remote,
     /// Keep both versions (rename conflict file)
-    KeepBoth,
+    Both,
 }
 
 impl std::str::FromStr for ConflictResolution {
@@ -290,9 +429,9 @@ impl std::str::FromStr for ConflictResolution {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "local" | "keep_local" | "keeplocal" => Ok(ConflictResolution::KeepLocal),
-            "remote" | "keep_remote" | "keepremote" => Ok(ConflictResolution::KeepRemote),
-            "both" | "keep_both" | "keepboth" => Ok(ConflictResolution::KeepBoth),
+            "local" | "keep_local" | "keeplocal" => Ok(ConflictResolution::Local),
+            "remote" | "keep_remote" | "keepremote" => Ok(ConflictResolution::Remote),
+            "both" | "keep_both" | "keepboth" => Ok(ConflictResolution::Both),
             _ => Err(SyncError::InvalidState(format!("Unknown resolution: {}", s))),
         }
     }
@@ -321,6 +460,72 @@ mod tests {
         assert!(name.ends_with(".md"));
     }
 
+    #[test]
+    fn test_repeated_conflicts_are_not_duplicated() {
+        let vault = std::env::temp_dir().join(format!("echopad-conflict-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("note.md"), "local").unwrap();
+
+        let manager = ConflictManager::new("device123".to_string());
+        let first = manager.create_conflict_file(&vault, "note.md", b"remote v2").unwrap();
+        let second = manager.create_conflict_file(&vault, "note.md", b"remote v2").unwrap();
+        assert_eq!(first, second);
+
+        let other = manager.create_conflict_file(&vault, "note.md", b"remote v3").unwrap();
+        assert_ne!(first, other);
+
+        let conflicts = manager.list_conflicts(&vault).unwrap();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(manager.count_conflicts(&vault).unwrap(), 2);
+        assert!(conflicts.iter().all(|c| c.original_path == "note.md" && c.created_at > 0));
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn test_cleanup_keeps_orphaned_conflicts() {
+        let vault = std::env::temp_dir().join(format!("echopad-conflict-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("kept.md"), "local").unwrap();
+        fs::write(vault.join("gone.md"), "local").unwrap();
+
+        let manager = ConflictManager::new("device123".to_string());
+        manager.create_conflict_file(&vault, "kept.md", b"remote").unwrap();
+        manager.create_conflict_file(&vault, "gone.md", b"only copy").unwrap();
+        fs::remove_file(vault.join("gone.md")).unwrap();
+
+        // Nothing is old enough yet
+        assert_eq!(manager.cleanup_conflicts(&vault, 30).unwrap(), 0);
+        assert_eq!(manager.list_conflicts(&vault).unwrap().len(), 2);
+
+        assert_eq!(manager.cleanup_conflicts(&vault, 0).unwrap(), 1);
+        assert!(manager.list_conflicts(&vault).unwrap().is_empty());
+        assert_eq!(fs::read_to_string(vault.join("gone.md")).unwrap(), "only copy");
+        assert_eq!(fs::read_to_string(vault.join("kept.md")).unwrap(), "local");
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn test_resolved_conflicts_are_removed() {
+        let vault = std::env::temp_dir().join(format!("echopad-conflict-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("merged.md"), "merged").unwrap();
+        fs::write(vault.join("open.md"), "local").unwrap();
+
+        let manager = ConflictManager::new("device123".to_string());
+        manager.create_conflict_file(&vault, "merged.md", b"remote").unwrap();
+        manager.create_conflict_file(&vault, "open.md", b"remote").unwrap();
+        fs::write(vault.join("merged.md"), "remote").unwrap();
+
+        assert_eq!(manager.remove_resolved_conflicts(&vault).unwrap(), 1);
+        let left = manager.list_conflicts(&vault).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].original_path, "open.md");
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
     #[test]
     fn test_get_original_path() {
         let conflict = PathBuf::from("/vault/test.sync-conflict-abc123451234567890.md");
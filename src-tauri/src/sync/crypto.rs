@@ -18,6 +18,7 @@ use hkdf::Hkdf;
 use sha2::Sha256;
 use rand::RngCore;
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::time::{Duration, Instant};
 
 use super::error::{SyncError, SyncResult};
 
@@ -33,6 +34,15 @@ pub const ARGON2_MEMORY_KB: u32 = 65536;
 pub const ARGON2_ITERATIONS: u32 = 3;
 /// Argon2 parallelism
 pub const ARGON2_PARALLELISM: u32 = 1;
+/// Upper bound for a master key derivation before it is abandoned
+pub const KEY_DERIVATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// Master key derivations slower than this make logging in feel stuck
+pub const SLOW_KEY_DERIVATION: Duration = Duration::from_secs(2);
+/// Prefix of file content uploaded unencrypted to an encrypted vault.
+/// Ciphertext starts with a random nonce, so it can't be mistaken for it.
+pub const PLAINTEXT_MARKER: &[u8] = b"ECHOPAD-PLAINTEXT\n";
+/// Interval between progress callbacks while deriving keys
+const KEY_DERIVATION_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
 
 /// Cryptographic key wrapper
 #[derive(Clone)]
@@ -162,6 +172,53 @@ pub fn derive_master_key(password: &str, salt: &Salt) -> SyncResult<CryptoKey> {
     Ok(CryptoKey::from_bytes(output))
 }
 
+/// Time one master key derivation with a throwaway password and salt
+pub fn benchmark_master_key() -> SyncResult<Duration> {
+    let start = Instant::now();
+    derive_master_key("echopad-benchmark", &Salt::generate())?;
+    Ok(start.elapsed())
+}
+
+/// Derive master key on the blocking thread pool
+///
+/// Argon2 with 64 MB memory cost can take seconds on weak hardware, which would
+/// stall the async runtime (and the UI) if run inline. `on_progress` receives
+/// the elapsed time periodically so callers can drive a spinner. If `timeout`
+/// elapses first the derivation is abandoned and an error is returned; the
+/// blocking thread finishes in the background and its result is dropped.
+pub async fn derive_master_key_blocking<F>(
+    password: String,
+    salt: Salt,
+    timeout: Duration,
+    mut on_progress: F,
+) -> SyncResult<CryptoKey>
+where
+    F: FnMut(Duration),
+{
+    let start = Instant::now();
+    let mut handle = tokio::task::spawn_blocking(move || derive_master_key(&password, &salt));
+    let mut ticker = tokio::time::interval(KEY_DERIVATION_PROGRESS_INTERVAL);
+
+    loop {
+        tokio::select! {
+            result = &mut handle => {
+                return result
+                    .map_err(|e| SyncError::KeyDerivation(format!("Key derivation task failed: {}", e)))?;
+            }
+            _ = ticker.tick() => {
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Err(SyncError::KeyDerivation(format!(
+                        "Key derivation timed out after {}s",
+                        elapsed.as_secs()
+                    )));
+                }
+                on_progress(elapsed);
+            }
+        }
+    }
+}
+
 /// Derive auth and encryption keys from master key using HKDF
 pub fn derive_keys(master_key: &CryptoKey) -> SyncResult<DerivedKeys> {
     let hk = Hkdf::<Sha256>::new(None, master_key.as_bytes());
@@ -240,6 +297,46 @@ pub fn decrypt(key: &CryptoKey, ciphertext: &[u8]) -> SyncResult<Vec<u8>> {
         .map_err(|e| SyncError::Decryption(format!("Decryption failed: {}", e)))
 }
 
+/// Whether a file is a note that opted out of encryption and goes to an
+/// encrypted vault as plaintext. Notes encrypted at rest can't be read
+/// here and stay encrypted.
+pub fn keeps_plaintext(relative_path: &str, content: &[u8]) -> bool {
+    is_note_path(relative_path) && std::str::from_utf8(content).is_ok_and(crate::vault::opts_out_of_encryption)
+}
+
+/// Whether a path has one of the note extensions, the only files that can
+/// opt out of encryption
+pub fn is_note_path(relative_path: &str) -> bool {
+    crate::vault::has_extension_in(std::path::Path::new(relative_path), crate::vault::NOTE_EXTENSIONS)
+}
+
+/// Content to upload unencrypted to an encrypted vault, marked as such
+pub fn mark_plaintext(plaintext: &[u8]) -> Vec<u8> {
+    [PLAINTEXT_MARKER, plaintext].concat()
+}
+
+/// Downloaded content of the file at `relative_path`, decrypted with `key`
+/// when the vault is encrypted. A body marked as plaintext is only taken
+/// as is when it is a note that opted out of encryption; anyone able to
+/// write to the server could otherwise slip unencrypted content into any
+/// file of an encrypted vault.
+pub fn open_content(key: Option<&CryptoKey>, relative_path: &str, body: Vec<u8>) -> SyncResult<Vec<u8>> {
+    if let Some(plaintext) = body.strip_prefix(PLAINTEXT_MARKER) {
+        if keeps_plaintext(relative_path, plaintext) {
+            return Ok(plaintext.to_vec());
+        }
+        if key.is_some() {
+            return Err(SyncError::Decryption(
+                "Content marked as plaintext doesn't opt out of encryption".into(),
+            ));
+        }
+    }
+    match key {
+        Some(key) => decrypt(key, &body),
+        None => Ok(body),
+    }
+}
+
 /// Encrypt string and encode as base64
 pub fn encrypt_string(key: &CryptoKey, plaintext: &str) -> SyncResult<String> {
     let encrypted = encrypt(key, plaintext.as_bytes())?;
@@ -313,6 +410,14 @@ pub fn decrypt_vault_key(
     Ok(CryptoKey::from_bytes(key_bytes))
 }
 
+/// Compare two byte strings without short-circuiting on the first mismatch
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 /// Compute BLAKE3 hash of data
 pub fn hash_content(data: &[u8]) -> String {
     let hash = blake3::hash(data);
@@ -345,6 +450,24 @@ mod tests {
         assert_eq!(master_key.as_bytes(), master_key2.as_bytes());
     }
 
+    #[tokio::test]
+    async fn test_blocking_key_derivation_matches_sync() {
+        let password = "test-password-123";
+        let salt = Salt::generate();
+
+        let expected = derive_master_key(password, &salt).unwrap();
+        let derived = derive_master_key_blocking(
+            password.to_string(),
+            salt.clone(),
+            KEY_DERIVATION_TIMEOUT,
+            |_| {},
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(expected.as_bytes(), derived.as_bytes());
+    }
+
     #[test]
     fn test_encryption_roundtrip() {
         let key = CryptoKey::generate();
@@ -356,6 +479,30 @@ mod tests {
         assert_eq!(plaintext.as_slice(), decrypted.as_slice());
     }
 
+    #[test]
+    fn test_marked_plaintext_opens_next_to_ciphertext() {
+        let key = CryptoKey::generate();
+        let note = b"---\nencrypt: false\n---\nReading list";
+
+        let marked = mark_plaintext(note);
+        assert!(marked.starts_with(PLAINTEXT_MARKER));
+        assert_eq!(open_content(Some(&key), "list.md", marked.clone()).unwrap(), note);
+        assert_eq!(open_content(None, "list.md", marked).unwrap(), note);
+        assert_eq!(open_content(Some(&key), "list.md", encrypt(&key, note).unwrap()).unwrap(), note);
+        assert_eq!(open_content(None, "list.md", note.to_vec()).unwrap(), note);
+    }
+
+    #[test]
+    fn test_marked_plaintext_must_opt_out() {
+        let key = CryptoKey::generate();
+
+        // A note that doesn't opt out, and a file that isn't a note
+        let secret = mark_plaintext(b"---\ntitle: Diary\n---\nSecret");
+        assert!(matches!(open_content(Some(&key), "diary.md", secret), Err(SyncError::Decryption(_))));
+        let opted_out = mark_plaintext(b"---\nencrypt: false\n---\n");
+        assert!(matches!(open_content(Some(&key), "image.png", opted_out), Err(SyncError::Decryption(_))));
+    }
+
     #[test]
     fn test_string_encryption() {
         let key = CryptoKey::generate();
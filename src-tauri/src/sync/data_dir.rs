@@ -0,0 +1,106 @@
+//! Where sync keeps its files
+//!
+//! Sync state, auth, the cached server settings, proxy overrides and the note
+//! index live in the app data directory unless the user moved them elsewhere
+//! (e.g. onto an encrypted volume). The new location is recorded in a small
+//! pointer file in the app data directory, which is read at startup.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::scanner::compute_hash;
+
+/// Pointer file inside the app data directory
+pub const DATA_LOCATION_FILE: &str = "sync-data-location.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DataLocation {
+    sync_data_dir: PathBuf,
+}
+
+/// Folder sync data was moved to, if it was
+pub fn moved_data_dir(app_data_dir: &Path) -> Option<PathBuf> {
+    let json = fs::read_to_string(app_data_dir.join(DATA_LOCATION_FILE)).ok()?;
+    serde_json::from_str::<DataLocation>(&json).ok().map(|location| location.sync_data_dir)
+}
+
+/// Record where sync data lives; moving it back into the app data
+/// directory removes the pointer
+pub fn write_location(app_data_dir: &Path, dir: &Path) -> Result<(), String> {
+    let pointer = app_data_dir.join(DATA_LOCATION_FILE);
+    if dir == app_data_dir {
+        return match fs::remove_file(&pointer) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+            _ => Ok(()),
+        };
+    }
+    let json = serde_json::to_string_pretty(&DataLocation {
+        sync_data_dir: dir.to_path_buf(),
+    })
+    .map_err(|e| e.to_string())?;
+    fs::write(pointer, json).map_err(|e| e.to_string())
+}
+
+/// Copy the existing `files` into `dir` and read each copy back to check it.
+/// Returns the copies; on failure the ones already made are removed.
+pub fn copy_verified(files: &[PathBuf], dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut copied = Vec::new();
+    for file in files.iter().filter(|file| file.is_file()) {
+        let Some(name) = file.file_name() else { continue };
+        let target = dir.join(name);
+        let result = fs::read(file).map_err(|e| e.to_string()).and_then(|bytes| {
+            fs::write(&target, &bytes).map_err(|e| e.to_string())?;
+            copied.push(target.clone());
+            match fs::read(&target) {
+                Ok(copy) if compute_hash(&copy) == compute_hash(&bytes) => Ok(()),
+                Ok(_) => Err("copy does not match the original".to_string()),
+                Err(e) => Err(e.to_string()),
+            }
+        });
+        if let Err(e) = result {
+            remove_files(&copied);
+            return Err(format!("Failed to copy {}: {}", file.display(), e));
+        }
+    }
+    Ok(copied)
+}
+
+/// Delete files, ignoring ones that are already gone. Returns the errors.
+pub fn remove_files(files: &[PathBuf]) -> Vec<String> {
+    files
+        .iter()
+        .filter_map(|file| match fs::remove_file(file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Some(format!("{}: {}", file.display(), e)),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_copy_and_location_round_trip() {
+        let root = std::env::temp_dir().join(format!("echopad-data-dir-{}", uuid::Uuid::new_v4()));
+        let (old_dir, new_dir) = (root.join("old"), root.join("new"));
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::create_dir_all(&new_dir).unwrap();
+        fs::write(old_dir.join("sync_state.json"), b"{}").unwrap();
+
+        let files = vec![old_dir.join("sync_state.json"), old_dir.join("missing.json")];
+        let copied = copy_verified(&files, &new_dir).unwrap();
+        assert_eq!(copied, vec![new_dir.join("sync_state.json")]);
+        assert_eq!(fs::read(&copied[0]).unwrap(), b"{}");
+
+        assert_eq!(moved_data_dir(&old_dir), None);
+        write_location(&old_dir, &new_dir).unwrap();
+        assert_eq!(moved_data_dir(&old_dir), Some(new_dir.clone()));
+        write_location(&old_dir, &old_dir).unwrap();
+        assert_eq!(moved_data_dir(&old_dir), None);
+
+        let _ = fs::remove_dir_all(root);
+    }
+}
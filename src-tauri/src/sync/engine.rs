@@ -1,28 +1,61 @@
 //! Sync engine - orchestrates the sync process
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use futures_util::stream::{self, StreamExt};
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 
+use super::client::{read_body, read_json};
+use super::crypto::{self, CryptoKey};
+use super::health::ServerProbe;
+use super::proxy;
+use super::rate_limit::{retry_after_secs, RateLimitHold};
 use super::error::{SyncError, SyncResult};
-use super::scanner::{compute_hash, detect_changes, scan_vault, ChangeSet, ScanResult};
-use super::state::SyncStateManager;
-use super::types::SyncOperationResult;
+use super::scanner::{
+    compute_body_hash, compute_hash, detect_changes, is_syncable_path, normalize_path, resolve_local_path, scan_vault_including, ChangeSet, FileInfo,
+    ScanResult, DEFAULT_MAX_FILE_SIZE,
+};
+use super::state::{FileSyncState, SyncStateManager};
+use super::types::{
+    DownloadProgress, KeyRotationProgress, SyncOperationResult, UploadEstimate, UploadProgress, VaultSyncState,
+};
 
-/// Remote file metadata from server
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct RemoteFile {
-    pub id: String,
-    pub encrypted_path: String,
-    pub content_hash: String,
-    pub size: u64,
-    pub modified_at: u64,
-    pub version: i32,
-    pub download_url: Option<String>,
+/// Default number of remote changes applied at the same time during pull
+pub const DEFAULT_PULL_CONCURRENCY: usize = 4;
+
+/// Files at least this large are streamed from disk during upload, with
+/// progress reports; smaller ones are sent from memory in one piece
+pub const STREAM_UPLOAD_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// Size of the chunks a streamed upload reads and reports progress for
+const STREAM_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Receives progress of streamed uploads
+pub type UploadProgressFn = Arc<dyn Fn(UploadProgress) + Send + Sync>;
+
+/// Downloads of at least this many bytes report progress
+pub const DOWNLOAD_PROGRESS_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// Receives progress of large downloads
+pub type DownloadProgressFn = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+
+/// Default number of remote changes requested per pull page
+pub const DEFAULT_PULL_BATCH_SIZE: u32 = 100;
+
+/// Largest pull page the server hands out; bigger requests are capped there
+pub const MAX_PULL_BATCH_SIZE: u32 = 500;
+
+/// Files asked about per `sync/has` request, the most the server accepts
+const HAS_FILES_BATCH_SIZE: usize = 500;
+
+/// Clamp a requested pull page size into `1..=MAX_PULL_BATCH_SIZE`
+pub fn clamp_pull_batch_size(batch_size: u32) -> u32 {
+    batch_size.clamp(1, MAX_PULL_BATCH_SIZE)
 }
 
 /// Pull response from server
@@ -40,8 +73,6 @@ pub struct RemoteChange {
     pub encrypted_path: String,
     pub operation: String,
     pub content_hash: String,
-    pub size: u64,
-    pub modified_at: u64,
     pub version: i32,
     pub download_url: Option<String>,
 }
@@ -50,7 +81,6 @@ pub struct RemoteChange {
 #[derive(Debug, Clone, Deserialize)]
 pub struct PushResponse {
     pub results: Vec<PushResult>,
-    pub conflicts: Vec<serde_json::Value>,
 }
 
 /// Result of a single push operation
@@ -61,7 +91,34 @@ pub struct PushResult {
     pub upload_url: Option<String>,
     pub new_version: Option<i32>,
     pub file_id: Option<String>,
-    pub error: Option<String>,
+}
+
+/// A file the server already holds, from the `sync/has` endpoint
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeldFile {
+    pub encrypted_path: String,
+    pub content_hash: String,
+    pub version: i32,
+}
+
+/// Answer of the `sync/has` endpoint: the asked-for files the server holds
+/// confirmed content for, with that content's hash
+#[derive(Debug, Clone, Deserialize)]
+pub struct HasFilesResponse {
+    #[serde(default)]
+    pub files: Vec<HeldFile>,
+}
+
+/// What a push accomplished
+#[derive(Debug, Default)]
+struct PushOutcome {
+    uploaded: u32,
+    deleted: u32,
+    /// Changes the server returned no result for. Their local state is left
+    /// untouched, so the next sync detects and sends them again.
+    unanswered: Vec<String>,
+    /// Uploads whose confirm the server refused for good
+    rejected_confirms: Vec<String>,
 }
 
 /// Sync engine configuration
@@ -78,6 +135,38 @@ pub struct SyncEngine {
     pub state_manager: Option<std::sync::Arc<SyncStateManager>>,
     /// When true, pull operations will not overwrite existing local files
     additive_only: bool,
+    /// When true, pull writes empty placeholders instead of downloading content
+    placeholder_mode: bool,
+    /// Files larger than this (in bytes) are skipped and never uploaded
+    max_file_size: u64,
+    /// How many remote changes pull applies at the same time
+    pull_concurrency: usize,
+    /// Number of remote changes requested per pull page
+    pull_batch_size: u32,
+    /// When set, only files with these extensions are synced
+    allowed_extensions: Option<Vec<String>>,
+    /// Called as streamed uploads progress
+    upload_progress: Option<UploadProgressFn>,
+    /// Called as downloads above [`DOWNLOAD_PROGRESS_THRESHOLD`] progress
+    download_progress: Option<DownloadProgressFn>,
+    /// Normally skipped directories this vault syncs
+    include_dirs: Vec<String>,
+    /// Whether symlinks are scanned through and synced as copies
+    follow_symlinks: bool,
+    /// Notes whose body is unchanged since the last sync aren't uploaded
+    /// for frontmatter edits alone
+    body_hash_only: bool,
+    /// Key of an encrypted vault; file content is encrypted before upload
+    /// and decrypted after download. `None` transfers plaintext.
+    vault_key: Option<CryptoKey>,
+    /// The vault folder can't be written: remote changes aren't pulled
+    /// (the cursor stays put so they arrive once it's writable again) and
+    /// only local changes are pushed
+    read_only: bool,
+    /// Checked before syncing so an unreachable server fails fast
+    server_probe: Option<Arc<ServerProbe>>,
+    /// App-wide hold after the server answered 429
+    rate_limit: Option<Arc<RateLimitHold>>,
     client: Client,
 }
 
@@ -95,6 +184,20 @@ impl SyncEngine {
             vault_path,
             state_manager: None,
             additive_only: false,
+            placeholder_mode: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            pull_concurrency: DEFAULT_PULL_CONCURRENCY,
+            pull_batch_size: DEFAULT_PULL_BATCH_SIZE,
+            allowed_extensions: None,
+            upload_progress: None,
+            download_progress: None,
+            include_dirs: Vec::new(),
+            follow_symlinks: true,
+            body_hash_only: false,
+            vault_key: None,
+            read_only: false,
+            server_probe: None,
+            rate_limit: None,
             client: Client::new(),
         }
     }
@@ -114,18 +217,138 @@ impl SyncEngine {
             vault_path,
             state_manager: Some(state_manager),
             additive_only: false,
+            placeholder_mode: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            pull_concurrency: DEFAULT_PULL_CONCURRENCY,
+            pull_batch_size: DEFAULT_PULL_BATCH_SIZE,
+            allowed_extensions: None,
+            upload_progress: None,
+            download_progress: None,
+            include_dirs: Vec::new(),
+            follow_symlinks: true,
+            body_hash_only: false,
+            vault_key: None,
+            read_only: false,
+            server_probe: None,
+            rate_limit: None,
             client: Client::new(),
         }
     }
 
+    /// Use `client` for requests, e.g. one configured with a proxy
+    pub fn set_http_client(&mut self, client: Client) {
+        self.client = client;
+    }
+
     /// Set additive-only mode for pull operations
     /// When enabled, existing local files will not be overwritten
     pub fn set_additive_only(&mut self, additive: bool) {
         self.additive_only = additive;
     }
 
-    /// Perform a full sync cycle
+    /// Set placeholder mode for pull operations
+    /// When enabled, files missing locally are created empty and only their
+    /// metadata is recorded; see [`SyncEngine::materialize_file`]
+    pub fn set_placeholder_mode(&mut self, placeholder: bool) {
+        self.placeholder_mode = placeholder;
+    }
+
+    /// Set the maximum size of files that are scanned and uploaded
+    pub fn set_max_file_size(&mut self, max_file_size: u64) {
+        self.max_file_size = max_file_size;
+    }
+
+    /// Restrict sync to files with the given extensions (`None` syncs every
+    /// supported file)
+    pub fn set_allowed_extensions(&mut self, extensions: Option<Vec<String>>) {
+        self.allowed_extensions = extensions;
+    }
+
+    /// Also sync these normally skipped directories (e.g. `.obsidian`)
+    pub fn set_include_dirs(&mut self, include_dirs: Vec<String>) {
+        self.include_dirs = include_dirs;
+    }
+
+    /// Set whether symlinked files and folders are synced as copies of their
+    /// targets or skipped
+    pub fn set_follow_symlinks(&mut self, follow: bool) {
+        self.follow_symlinks = follow;
+    }
+
+    /// Set whether notes count as changed only when their body changes
+    pub fn set_body_hash_only(&mut self, enabled: bool) {
+        self.body_hash_only = enabled;
+    }
+
+    /// Encrypt transfers with `key` (`None` for plaintext vaults)
+    pub fn set_vault_key(&mut self, key: Option<CryptoKey>) {
+        self.vault_key = key;
+    }
+
+    /// Probe the server with `probe` before syncing
+    pub fn set_server_probe(&mut self, probe: Arc<ServerProbe>) {
+        self.server_probe = Some(probe);
+    }
+
+    /// Wait out `hold` before requests and extend it on 429 answers
+    pub fn set_rate_limit(&mut self, hold: Arc<RateLimitHold>) {
+        self.rate_limit = Some(hold);
+    }
+
+    /// Treat the vault as read-only: skip pulling and only push
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Set how many remote changes pull applies concurrently (at least 1)
+    pub fn set_pull_concurrency(&mut self, concurrency: usize) {
+        self.pull_concurrency = concurrency.max(1);
+    }
+
+    /// Report the progress of uploads of files at or above
+    /// [`STREAM_UPLOAD_THRESHOLD`]
+    pub fn set_upload_progress(&mut self, progress: Option<UploadProgressFn>) {
+        self.upload_progress = progress;
+    }
+
+    /// Report the progress of downloads of at least
+    /// [`DOWNLOAD_PROGRESS_THRESHOLD`] bytes
+    pub fn set_download_progress(&mut self, progress: Option<DownloadProgressFn>) {
+        self.download_progress = progress;
+    }
+
+    /// Set how many remote changes each pull request asks for, clamped to
+    /// what the server accepts
+    pub fn set_pull_batch_size(&mut self, batch_size: u32) {
+        self.pull_batch_size = clamp_pull_batch_size(batch_size);
+    }
+
+    /// Perform a full sync cycle. The vault shows as `Syncing` while it
+    /// runs, and as `Idle` or `Error` afterwards.
     pub async fn sync(&self) -> SyncResult<SyncOperationResult> {
+        let Some(state_manager) = &self.state_manager else {
+            return self.run_sync().await;
+        };
+        state_manager.update_vault_status_by_id(&self.vault_id, VaultSyncState::Syncing);
+        let result = self.run_sync().await;
+        let error = match &result {
+            Ok(outcome) if !outcome.success => Some(outcome.errors.join("; ")),
+            // Being offline says nothing about the vault
+            Ok(_) | Err(SyncError::Offline(_)) => None,
+            Err(e) => Some(e.to_string()),
+        };
+        if error.is_some() {
+            state_manager.set_vault_error_by_id(&self.vault_id, error);
+        } else {
+            if result.is_ok() {
+                state_manager.set_vault_error_by_id(&self.vault_id, None);
+            }
+            state_manager.clear_syncing_status(&self.vault_id);
+        }
+        result
+    }
+
+    async fn run_sync(&self) -> SyncResult<SyncOperationResult> {
         let start = std::time::Instant::now();
         let mut files_uploaded = 0u32;
         let mut files_downloaded = 0u32;
@@ -138,15 +361,42 @@ impl SyncEngine {
         println!("[Sync] Server URL: {}", self.server_url);
         println!("[Sync] Vault ID: {}", self.vault_id);
 
+        if let Some(probe) = &self.server_probe {
+            probe.check(&self.client, &self.server_url).await?;
+        }
+
+        // 0. Finish uploads from earlier syncs whose confirm failed, so the
+        // server can serve them before we pull
+        if let Err(e) = self.retry_pending_confirms().await {
+            println!("[Sync] Confirm retry error: {}", e);
+            errors.push(format!("Confirm failed: {}", e));
+        }
+
         // 1. Initial scan to know what we have locally before sync
-        let initial_scan = scan_vault(vault_path)?;
+        let initial_scan = self.scan(vault_path)?;
         println!("[Sync] Found {} local files before pull", initial_scan.file_count);
+        if !initial_scan.skipped.is_empty() {
+            println!(
+                "[Sync] Skipping {} files over the {} byte size limit",
+                initial_scan.skipped.len(),
+                self.max_file_size
+            );
+        }
         
         // 2. Detect ALL local changes BEFORE pulling
         // This includes: new files, modified files, and deleted files
         // We need to protect these from being overwritten by pull
         let local_changes = self.get_local_changes(&initial_scan);
-        let locally_deleted = self.detect_local_deletes(&initial_scan);
+        let mut locally_deleted = self.detect_local_deletes(&initial_scan);
+
+        // Files moved by a notebook rename are deleted at the path they were
+        // synced under and created at their new path
+        let renames = self.pending_renames();
+        for (from, _) in &renames {
+            if !locally_deleted.contains(from) {
+                locally_deleted.push(from.clone());
+            }
+        }
         
         // Build set of paths that have local changes (should not be overwritten by pull)
         let mut protected_paths: Vec<String> = local_changes.changed
@@ -177,19 +427,23 @@ impl SyncEngine {
         
         // 3. Pull remote changes, but skip files that have local changes
         // This prevents overwriting local modifications with older server versions
-        match self.pull_changes_excluding(vault_path, &protected_paths).await {
-            Ok(downloaded) => {
-                files_downloaded = downloaded;
-                println!("[Sync] Downloaded {} files", downloaded);
-            }
-            Err(e) => {
-                println!("[Sync] Pull error: {}", e);
-                errors.push(format!("Pull failed: {}", e));
+        if self.read_only {
+            println!("[Sync] Vault is read-only, skipping pull");
+        } else {
+            match self.pull_changes_excluding(vault_path, &protected_paths).await {
+                Ok(downloaded) => {
+                    files_downloaded = downloaded;
+                    println!("[Sync] Downloaded {} files", downloaded);
+                }
+                Err(e) => {
+                    println!("[Sync] Pull error: {}", e);
+                    errors.push(format!("Pull failed: {}", e));
+                }
             }
         }
 
         // 4. Re-scan AFTER pulling to include downloaded files
-        let final_scan = scan_vault(vault_path)?;
+        let final_scan = self.scan(vault_path)?;
         println!("[Sync] Found {} local files after pull", final_scan.file_count);
 
         // 5. Build final change set for push
@@ -203,6 +457,14 @@ impl SyncEngine {
                 final_changes.deleted.push(deleted_path.clone());
             }
         }
+
+        for (_, to) in &renames {
+            if let Some(info) = final_scan.files.get(to) {
+                if !final_changes.changed.iter().any(|f| f.relative_path == *to) {
+                    final_changes.changed.push(info.clone());
+                }
+            }
+        }
         
         println!("[Sync] Final: {} changed files, {} deleted files to push", 
             final_changes.changed.len(), final_changes.deleted.len());
@@ -215,12 +477,18 @@ impl SyncEngine {
             println!("[Sync]   ... and {} more files to upload", final_changes.changed.len() - 5);
         }
 
-        // 6. Push changes (including deletes)
+        // 6. Skip files an earlier, interrupted sync already uploaded, then
+        // push the rest (including deletes)
+        self.skip_files_held_by_server(&mut final_changes).await;
         match self.push_changes_incremental(&final_changes, &final_scan).await {
-            Ok((uploaded, deleted)) => {
-                files_uploaded = uploaded;
-                files_deleted = deleted;
-                println!("[Sync] Uploaded {} files, deleted {}", uploaded, deleted);
+            Ok(outcome) => {
+                files_uploaded = outcome.uploaded;
+                files_deleted = outcome.deleted;
+                println!("[Sync] Uploaded {} files, deleted {}", outcome.uploaded, outcome.deleted);
+                for path in outcome.unanswered {
+                    errors.push(format!("Server returned no push result for {}; it will be retried", path));
+                }
+                errors.extend(outcome.rejected_confirms);
             }
             Err(e) => {
                 println!("[Sync] Push error: {}", e);
@@ -243,6 +511,53 @@ impl SyncEngine {
         })
     }
 
+    /// Scan the vault with the size limit and extension policy applied
+    fn scan(&self, vault_path: &Path) -> SyncResult<ScanResult> {
+        let mut scan = scan_vault_including(vault_path, self.max_file_size, &self.include_dirs, self.follow_symlinks, self.body_hash_only)?;
+        if let Some(ref allowed) = self.allowed_extensions {
+            scan.retain_extensions(allowed);
+        }
+        Ok(scan)
+    }
+
+    /// Work out what the next sync would push, from a local scan and the
+    /// stored file states only. Remote changes pulled first aren't known
+    /// here, so this is an upper bound for the upload.
+    pub fn estimate_upload(&self) -> SyncResult<UploadEstimate> {
+        self.scan_with_estimate().map(|(_, estimate)| estimate)
+    }
+
+    /// Scan the vault and estimate the next upload from that scan
+    pub fn scan_with_estimate(&self) -> SyncResult<(ScanResult, UploadEstimate)> {
+        let scan = self.scan(Path::new(&self.vault_path))?;
+        let mut changes = self.get_local_changes(&scan);
+        let mut deleted = self.detect_local_deletes(&scan);
+
+        // Renamed files are deleted at their old path and uploaded at the new one
+        for (from, to) in self.pending_renames() {
+            if !deleted.contains(&from) {
+                deleted.push(from);
+            }
+            if let Some(info) = scan.files.get(&to) {
+                if !changes.changed.iter().any(|f| f.relative_path == to) {
+                    changes.changed.push(info.clone());
+                }
+            }
+        }
+        for path in changes.deleted {
+            if !deleted.contains(&path) {
+                deleted.push(path);
+            }
+        }
+
+        let estimate = UploadEstimate {
+            files_to_upload: changes.changed.len() as u32,
+            bytes_to_upload: changes.changed.iter().map(|f| f.size_bytes).sum(),
+            files_to_delete: deleted.len() as u32,
+        };
+        Ok((scan, estimate))
+    }
+
     /// Get local changes by comparing with stored state
     fn get_local_changes(&self, scan_result: &ScanResult) -> ChangeSet {
         // If we have a state manager, use it for incremental sync
@@ -250,15 +565,44 @@ impl SyncEngine {
             // Use vault_id for state lookups (not vault_path)
             let file_states = state_manager.get_all_file_states_by_id(&self.vault_id);
             
-            // Build previous state map from stored file states
+            // Build previous state map from stored file states. Trashed
+            // files are left out so they don't count as deleted yet.
             let previous: HashMap<String, String> = file_states
                 .iter()
+                .filter(|fs| !fs.trashed)
                 .filter_map(|fs| {
                     fs.local_hash.as_ref().map(|hash| (fs.relative_path.clone(), hash.clone()))
                 })
                 .collect();
 
-            detect_changes(scan_result, &previous)
+            let mut changes = detect_changes(scan_result, &previous);
+
+            // A placeholder's content lives on the server; what's on disk is
+            // an empty stand-in, so it's never pushed until materialized
+            let placeholders: HashSet<&str> = file_states
+                .iter()
+                .filter(|fs| fs.placeholder)
+                .map(|fs| fs.relative_path.as_str())
+                .collect();
+            if !placeholders.is_empty() {
+                changes.changed.retain(|info| {
+                    let held = placeholders.contains(info.relative_path.as_str());
+                    if held {
+                        eprintln!("[Sync] Not pushing placeholder {}: it must be downloaded first", info.relative_path);
+                    }
+                    !held
+                });
+                changes.deleted.retain(|path| !placeholders.contains(path.as_str()));
+            }
+
+            if self.body_hash_only {
+                let states: HashMap<&str, &FileSyncState> =
+                    file_states.iter().map(|fs| (fs.relative_path.as_str(), fs)).collect();
+                changes.changed.retain(|info| {
+                    !states.get(info.relative_path.as_str()).is_some_and(|fs| !fs.trashed && fs.matches(info, true))
+                });
+            }
+            changes
         } else {
             // No state manager - treat all files as changed (full sync)
             ChangeSet {
@@ -277,10 +621,17 @@ impl SyncEngine {
             file_states
                 .iter()
                 .filter(|fs| {
-                    // Has been synced before (has local_hash)
-                    fs.local_hash.is_some() &&
+                    // Has been synced before (has local_hash) or was deleted
+                    // together with its notebook
+                    (fs.local_hash.is_some() || fs.pending_delete) &&
+                    // Trashed files are deleted once the trash is emptied
+                    !fs.trashed &&
+                    // Placeholders are never pushed, deletes included
+                    !fs.placeholder &&
                     // But no longer exists on disk
-                    !scan_result.files.contains_key(&fs.relative_path)
+                    !scan_result.files.contains_key(&fs.relative_path) &&
+                    // (oversized files still exist, they're just not synced)
+                    !scan_result.is_skipped(&fs.relative_path)
                 })
                 .map(|fs| fs.relative_path.clone())
                 .collect()
@@ -289,6 +640,150 @@ impl SyncEngine {
         }
     }
 
+    /// Renames recorded by the state manager that still need pushing
+    fn pending_renames(&self) -> Vec<(String, String)> {
+        self.state_manager
+            .as_ref()
+            .map(|sm| sm.pending_renames_by_id(&self.vault_id))
+            .unwrap_or_default()
+    }
+
+    /// Confirm uploads whose content reached the server but whose confirm
+    /// failed. Returns the number confirmed; the first error is reported
+    /// after trying all of them. Confirms the server rejects for good are
+    /// dropped rather than retried on every sync.
+    async fn retry_pending_confirms(&self) -> SyncResult<u32> {
+        let Some(ref state_manager) = self.state_manager else {
+            return Ok(0);
+        };
+
+        let mut confirmed = 0u32;
+        let mut first_error = None;
+        for (path, file_id) in state_manager.pending_confirms_by_id(&self.vault_id) {
+            match self.confirm_upload(&file_id).await {
+                Ok(()) => {
+                    state_manager.set_pending_confirm_by_id(&self.vault_id, &path, None);
+                    confirmed += 1;
+                }
+                Err(e) => {
+                    eprintln!("[Sync] Confirm retry failed for {}: {}", path, e);
+                    if matches!(e, SyncError::Rejected(_)) {
+                        state_manager.set_pending_confirm_by_id(&self.vault_id, &path, None);
+                    }
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        if confirmed > 0 {
+            println!("[Sync] Confirmed {} previously unconfirmed uploads", confirmed);
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(confirmed),
+        }
+    }
+
+    /// Drop changed files the server already holds with the same content
+    /// from the change set and mark them synced. A sync that uploaded a file
+    /// but failed before recording it would otherwise upload it again.
+    /// Servers without the `sync/has` endpoint, or any error asking it, just
+    /// leave the change set as it is. Returns the number of files skipped.
+    async fn skip_files_held_by_server(&self, change_set: &mut ChangeSet) -> u32 {
+        let Some(ref state_manager) = self.state_manager else {
+            return 0;
+        };
+        if change_set.changed.is_empty() {
+            return 0;
+        }
+
+        let files: Vec<serde_json::Value> = change_set
+            .changed
+            .iter()
+            .map(|info| {
+                serde_json::json!({
+                    "encrypted_path": self.encode_path(&info.relative_path),
+                    "content_hash": info.content_hash,
+                })
+            })
+            .collect();
+        let mut held = Vec::new();
+        for batch in files.chunks(HAS_FILES_BATCH_SIZE) {
+            match self.fetch_held_files(batch).await {
+                Some(files) => held.extend(files),
+                None => break,
+            }
+        }
+        if held.is_empty() {
+            return 0;
+        }
+
+        let encoded: Vec<String> = change_set
+            .changed
+            .iter()
+            .map(|info| self.encode_path(&info.relative_path))
+            .collect();
+        let versions = held_versions(&change_set.changed, &encoded, &held);
+        let mut skipped = 0u32;
+        let mut encoded = encoded.into_iter();
+        change_set.changed.retain(|info| {
+            let encoded = encoded.next().unwrap_or_default();
+            match versions.get(&info.relative_path) {
+                Some(&version) => {
+                    state_manager.mark_synced_with_body_by_id(
+                        &self.vault_id,
+                        &info.relative_path,
+                        &info.content_hash,
+                        info.body_hash.clone(),
+                        version,
+                    );
+                    state_manager.set_remote_path_by_id(&self.vault_id, &info.relative_path, &encoded);
+                    skipped += 1;
+                    false
+                }
+                None => true,
+            }
+        });
+        if skipped > 0 {
+            println!("[Sync] Skipped {} files the server already has", skipped);
+        }
+        skipped
+    }
+
+    /// Ask the `sync/has` endpoint which of `files` the server holds. `None`
+    /// if the server doesn't answer, e.g. because it lacks the endpoint.
+    async fn fetch_held_files(&self, files: &[serde_json::Value]) -> Option<Vec<HeldFile>> {
+        self.wait_for_rate_limit().await.ok()?;
+        let url = format!("{}/api/v1/vaults/{}/sync/has", self.server_url, self.vault_id);
+        let response = match self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .json(&serde_json::json!({ "files": files }))
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => response,
+            Ok(response) => {
+                if response.status() != reqwest::StatusCode::NOT_FOUND && self.check_rate_limit(&response).is_ok() {
+                    println!("[Sync] Held-file check failed: {}", response.status());
+                }
+                return None;
+            }
+            Err(e) => {
+                println!("[Sync] Held-file check failed: {}", proxy::describe_error(&e));
+                return None;
+            }
+        };
+        match read_json::<HasFilesResponse>(response, "held-file response").await {
+            Ok(held) => Some(held.files),
+            Err(e) => {
+                println!("[Sync] Held-file check failed: {}", e);
+                None
+            }
+        }
+    }
+
     /// Pull remote changes, excluding files that were locally deleted
     async fn pull_changes_excluding(&self, vault_path: &Path, exclude_paths: &[String]) -> SyncResult<u32> {
         let exclude_set: std::collections::HashSet<&str> = exclude_paths.iter().map(|s| s.as_str()).collect();
@@ -299,13 +794,13 @@ impl SyncEngine {
             .as_ref()
             .and_then(|sm| sm.get_cursor_by_id(&self.vault_id));
         
-        if cursor.is_some() {
-            println!("[Sync] Resuming from cursor: {}", cursor.as_ref().unwrap());
+        if let Some(cursor) = &cursor {
+            println!("[Sync] Resuming from cursor: {}", cursor);
         } else {
             println!("[Sync] Starting fresh pull (no cursor)");
         }
         
-        let mut final_cursor: Option<String> = cursor.clone();
+        let mut final_cursor: Option<String>;
 
         loop {
             let url = format!(
@@ -313,18 +808,20 @@ impl SyncEngine {
                 self.server_url, self.vault_id
             );
 
+            self.wait_for_rate_limit().await?;
             let response = self
                 .client
                 .post(&url)
                 .header("Authorization", format!("Bearer {}", self.access_token))
                 .json(&serde_json::json!({
                     "cursor": cursor,
-                    "limit": 100
+                    "limit": self.pull_batch_size
                 }))
                 .send()
                 .await
-                .map_err(|e| SyncError::Network(e.to_string()))?;
+                .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
 
+            self.check_rate_limit(&response)?;
             if !response.status().is_success() {
                 let status = response.status();
                 let text = response.text().await.unwrap_or_default();
@@ -334,42 +831,27 @@ impl SyncEngine {
                 )));
             }
 
-            let pull_response: PullResponse = response
-                .json()
-                .await
-                .map_err(|e| SyncError::InvalidData(e.to_string()))?;
+            let pull_response: PullResponse = read_json(response, "pull response").await?;
 
             // Process each change
             if !pull_response.changes.is_empty() {
                 println!("[Sync] Processing {} remote changes", pull_response.changes.len());
             }
-            for change in &pull_response.changes {
-                // Decode path to check if it should be excluded (has local changes)
-                if let Ok(path) = decode_path(&change.encrypted_path) {
-                    if exclude_set.contains(path.as_str()) {
-                        println!("[Sync]   Skipping (has local changes): {}", path);
-                        continue;
-                    }
-                }
-                
-                match self.apply_remote_change(vault_path, change).await {
-                    Ok(()) => {
-                        downloaded += 1;
-                        // Decode path for logging
-                        if let Ok(path) = decode_path(&change.encrypted_path) {
-                            println!("[Sync]   Downloaded: {} (op: {})", path, change.operation);
-                        }
-                    }
-                    Err(e) => {
-                        if let Ok(path) = decode_path(&change.encrypted_path) {
-                            eprintln!("[Sync]   Failed to apply change for {}: {}", path, e);
-                        } else {
-                            eprintln!("[Sync]   Failed to apply change for {}: {}", change.encrypted_path, e);
+            let changes: Vec<&RemoteChange> = pull_response.changes
+                .iter()
+                .filter(|change| {
+                    // Decode path to check if it should be excluded (has local changes)
+                    match decode_path(&change.encrypted_path) {
+                        Ok(path) if exclude_set.contains(path.as_str()) => {
+                            println!("[Sync]   Skipping (has local changes): {}", path);
+                            false
                         }
+                        _ => true,
                     }
-                }
-            }
-            
+                })
+                .collect();
+            downloaded += self.apply_remote_changes(vault_path, changes).await;
+
             // Track the cursor for saving later
             final_cursor = Some(pull_response.next_cursor.clone());
 
@@ -388,6 +870,70 @@ impl SyncEngine {
         Ok(downloaded)
     }
 
+    /// Apply a page of remote changes using up to `pull_concurrency` workers.
+    /// Changes to the same path are applied one after another in the order
+    /// of [`order_path_changes`], so a delete followed by a re-create (or the
+    /// reverse) ends up in the right state whatever order the server sent
+    /// them in. Returns the number of changes applied.
+    async fn apply_remote_changes(&self, vault_path: &Path, changes: Vec<&RemoteChange>) -> u32 {
+        // The same file may arrive under different encodings of its path
+        let mut by_path: Vec<Vec<&RemoteChange>> = Vec::new();
+        let mut group_of: HashMap<String, usize> = HashMap::new();
+        for change in changes {
+            let path = decode_path(&change.encrypted_path).unwrap_or_else(|_| change.encrypted_path.clone());
+            match group_of.get(&path) {
+                Some(&index) => by_path[index].push(change),
+                None => {
+                    group_of.insert(path, by_path.len());
+                    by_path.push(vec![change]);
+                }
+            }
+        }
+
+        let groups: Vec<_> = by_path
+            .into_iter()
+            .map(|mut group| {
+                order_path_changes(&mut group);
+                self.apply_path_changes(vault_path, group)
+            })
+            .collect();
+        let applied: Vec<u32> = stream::iter(groups)
+            .buffer_unordered(self.pull_concurrency)
+            .collect()
+            .await;
+        applied.into_iter().sum()
+    }
+
+    /// Apply the changes to one path in order, returning how many succeeded
+    async fn apply_path_changes(&self, vault_path: &Path, changes: Vec<&RemoteChange>) -> u32 {
+        let mut applied = 0u32;
+        for change in changes {
+            match self.apply_remote_change(vault_path, change).await {
+                Ok(()) => {
+                    applied += 1;
+                    // Pushes of this file must keep the server's encoding
+                    if change.operation != "delete" {
+                        if let (Some(sm), Ok(path)) = (&self.state_manager, decode_path(&change.encrypted_path)) {
+                            sm.set_remote_path_by_id(&self.vault_id, &path, &change.encrypted_path);
+                        }
+                    }
+                    // Decode path for logging
+                    if let Ok(path) = decode_path(&change.encrypted_path) {
+                        println!("[Sync]   Downloaded: {} (op: {})", path, change.operation);
+                    }
+                }
+                Err(e) => {
+                    if let Ok(path) = decode_path(&change.encrypted_path) {
+                        eprintln!("[Sync]   Failed to apply change for {}: {}", path, e);
+                    } else {
+                        eprintln!("[Sync]   Failed to apply change for {}: {}", change.encrypted_path, e);
+                    }
+                }
+            }
+        }
+        applied
+    }
+
     /// Pull remote changes and apply them locally (legacy method, kept for compatibility)
     #[allow(dead_code)]
     async fn pull_changes(&self, vault_path: &Path) -> SyncResult<u32> {
@@ -400,18 +946,20 @@ impl SyncEngine {
                 self.server_url, self.vault_id
             );
 
+            self.wait_for_rate_limit().await?;
             let response = self
                 .client
                 .post(&url)
                 .header("Authorization", format!("Bearer {}", self.access_token))
                 .json(&serde_json::json!({
                     "cursor": cursor,
-                    "limit": 100
+                    "limit": self.pull_batch_size
                 }))
                 .send()
                 .await
-                .map_err(|e| SyncError::Network(e.to_string()))?;
+                .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
 
+            self.check_rate_limit(&response)?;
             if !response.status().is_success() {
                 let status = response.status();
                 let text = response.text().await.unwrap_or_default();
@@ -421,10 +969,7 @@ impl SyncEngine {
                 )));
             }
 
-            let pull_response: PullResponse = response
-                .json()
-                .await
-                .map_err(|e| SyncError::InvalidData(e.to_string()))?;
+            let pull_response: PullResponse = read_json(response, "pull response").await?;
 
             // Process each change
             println!("[Sync] Processing {} remote changes", pull_response.changes.len());
@@ -464,7 +1009,23 @@ impl SyncEngine {
     ) -> SyncResult<()> {
         // Decode the path (it's base64 encoded on server, but for simplified sync it's just the path)
         let relative_path = decode_path(&change.encrypted_path)?;
-        let local_path = vault_path.join(&relative_path);
+
+        // Another device already sends prefixed paths, so this one follows
+        if has_path_scheme(&change.encrypted_path) {
+            if let Some(sm) = &self.state_manager {
+                sm.set_prefixed_paths(&self.vault_id);
+            }
+        }
+
+        // Never write outside the vault or create files we wouldn't sync
+        if !is_syncable_path(&relative_path, &self.include_dirs) {
+            eprintln!("[Sync] Rejecting remote change with invalid path: {:?}", relative_path);
+            return Err(SyncError::InvalidData(format!(
+                "Remote path {:?} is not a syncable vault path",
+                relative_path
+            )));
+        }
+        let local_path = crate::vault::long_path(resolve_local_path(vault_path, &relative_path));
 
         match change.operation.as_str() {
             "delete" => {
@@ -483,6 +1044,14 @@ impl SyncEngine {
                 }
             }
             "create" | "update" => {
+                // Don't resurrect files whose folder was deleted locally
+                if let Some(ref state_manager) = self.state_manager {
+                    if state_manager.is_pull_blocked_by_id(&self.vault_id, &relative_path) {
+                        println!("[Sync] Skipping pull of locally deleted file: {}", relative_path);
+                        return Ok(());
+                    }
+                }
+
                 // In additive-only mode, skip files that already exist locally
                 if self.additive_only && local_path.exists() {
                     println!("[Sync] Skipping existing file in additive mode: {}", relative_path);
@@ -522,15 +1091,52 @@ impl SyncEngine {
                     }
                 };
 
-                // Build full download URL (server returns relative path)
-                let full_download_url = if download_url.starts_with('/') {
-                    format!("{}{}", self.server_url, download_url)
-                } else {
-                    download_url.clone()
-                };
+                // In placeholder mode, record metadata for files we don't have
+                // (or only have as placeholders) instead of downloading them
+                if self.placeholder_mode {
+                    if let Some(ref state_manager) = self.state_manager {
+                        let is_placeholder = state_manager
+                            .get_file_state_by_id(&self.vault_id, &relative_path)
+                            .is_some_and(|fs| fs.placeholder);
+
+                        if !local_path.exists() || is_placeholder {
+                            if let Some(parent) = local_path.parent() {
+                                fs::create_dir_all(parent).map_err(SyncError::Io)?;
+                            }
+                            // Something written into a placeholder is kept;
+                            // materializing reports it as a conflict
+                            if !fs::metadata(&local_path).is_ok_and(|m| m.len() > 0) {
+                                fs::write(&local_path, []).map_err(SyncError::Io)?;
+                            }
+                            state_manager.mark_placeholder_by_id(
+                                &self.vault_id,
+                                &relative_path,
+                                &change.content_hash,
+                                change.version as u32,
+                                download_url,
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+
+                // A local copy that already matches needs no download or
+                // write; rewriting it would only wake the watcher
+                if let Some(local) = fs::read(&local_path).ok().filter(|local| compute_hash(local) == change.content_hash) {
+                    if let Some(ref state_manager) = self.state_manager {
+                        state_manager.mark_synced_with_body_by_id(
+                            &self.vault_id,
+                            &relative_path,
+                            &change.content_hash,
+                            self.body_hash_only.then(|| compute_body_hash(&relative_path, &local)).flatten(),
+                            change.version as u32
+                        );
+                    }
+                    return Ok(());
+                }
 
                 // Download the file
-                let content = self.download_file(&full_download_url).await?;
+                let content = self.download_file(&self.full_url(download_url), &relative_path).await?;
 
                 // Verify hash
                 let hash = compute_hash(&content);
@@ -550,10 +1156,11 @@ impl SyncEngine {
 
                 // Update local state to mark as synced (use vault_id)
                 if let Some(ref state_manager) = self.state_manager {
-                    state_manager.mark_synced_by_id(
-                        &self.vault_id, 
-                        &relative_path, 
-                        &hash, 
+                    state_manager.mark_synced_with_body_by_id(
+                        &self.vault_id,
+                        &relative_path,
+                        &hash,
+                        self.body_hash_only.then(|| compute_body_hash(&relative_path, &content)).flatten(),
                         change.version as u32
                     );
                 }
@@ -569,16 +1176,245 @@ impl SyncEngine {
         Ok(())
     }
 
-    /// Download a file from the given URL
-    async fn download_file(&self, url: &str) -> SyncResult<Vec<u8>> {
+    /// Download the real content of a placeholder file and mark it synced
+    pub async fn materialize_file(&self, relative_path: &str) -> SyncResult<()> {
+        let state_manager = self.state_manager.as_ref().ok_or_else(|| {
+            SyncError::InvalidState("Materializing requires a state manager".to_string())
+        })?;
+        let file_state = state_manager
+            .get_file_state_by_id(&self.vault_id, relative_path)
+            .ok_or_else(|| SyncError::FileNotFound(relative_path.to_string()))?;
+
+        if !file_state.placeholder {
+            return Ok(());
+        }
+
+        let local_path = crate::vault::long_path(resolve_local_path(Path::new(&self.vault_path), relative_path));
+        if fs::metadata(&local_path).is_ok_and(|m| m.len() > 0) {
+            return Err(SyncError::Conflict(relative_path.to_string()));
+        }
+
+        let (download_url, remote_hash, version) = match (
+            file_state.download_url,
+            file_state.remote_hash,
+            file_state.remote_version,
+        ) {
+            (Some(url), Some(hash), Some(version)) => (url, hash, version),
+            _ => {
+                return Err(SyncError::InvalidState(format!(
+                    "Placeholder '{}' is missing remote metadata",
+                    relative_path
+                )))
+            }
+        };
+
+        let content = self.download_file(&self.full_url(&download_url), relative_path).await?;
+        let hash = compute_hash(&content);
+        if hash != remote_hash {
+            return Err(SyncError::InvalidData(
+                "Downloaded file hash mismatch".to_string(),
+            ));
+        }
+
+        if let Some(parent) = local_path.parent() {
+            fs::create_dir_all(parent).map_err(SyncError::Io)?;
+        }
+        fs::write(&local_path, &content).map_err(SyncError::Io)?;
+        state_manager.mark_synced_by_id(&self.vault_id, relative_path, &hash, version);
+
+        println!("[Sync] Materialized placeholder: {}", relative_path);
+        Ok(())
+    }
+
+    /// Download the server's current version of one file, found by reading
+    /// the change log from the start. Returns `None` when the server doesn't
+    /// have the file: it was never uploaded, or its latest change deletes it.
+    pub async fn fetch_remote_file(&self, relative_path: &str) -> SyncResult<Option<Vec<u8>>> {
+        let target = normalize_path(relative_path);
+        match self.remote_files().await?.remove(&target) {
+            Some(change) => {
+                let download_url = change.download_url.ok_or_else(|| {
+                    SyncError::InvalidData(format!("No download URL for {}", target))
+                })?;
+                let content = self.download_file(&self.full_url(&download_url), &target).await?;
+                Ok(Some(content))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Every file the server holds, by decoded path, as of its latest change.
+    /// Pulls the whole change history from the start without touching the
+    /// vault's cursor.
+    async fn remote_files(&self) -> SyncResult<BTreeMap<String, RemoteChange>> {
+        let url = format!("{}/api/v1/vaults/{}/sync/pull", self.server_url, self.vault_id);
+        let mut files = BTreeMap::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            self.wait_for_rate_limit().await?;
+            let response = self
+                .client
+                .post(&url)
+                .header("Authorization", format!("Bearer {}", self.access_token))
+                .json(&serde_json::json!({
+                    "cursor": cursor,
+                    "limit": self.pull_batch_size
+                }))
+                .send()
+                .await
+                .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
+
+            self.check_rate_limit(&response)?;
+            if !response.status().is_success() {
+                let status = response.status();
+                let text = response.text().await.unwrap_or_default();
+                return Err(SyncError::Server(format!(
+                    "Pull failed: {} - {}",
+                    status, text
+                )));
+            }
+
+            let pull_response: PullResponse = read_json(response, "pull response").await?;
+            for change in pull_response.changes {
+                let path = decode_path(&change.encrypted_path)?;
+                if change.operation == "delete" {
+                    files.remove(&path);
+                } else {
+                    files.insert(path, change);
+                }
+            }
+
+            if !pull_response.has_more {
+                break;
+            }
+            cursor = Some(pull_response.next_cursor);
+        }
+
+        Ok(files)
+    }
+
+    /// Re-encrypt every file the server holds with `new_key`, in place: each
+    /// is downloaded with the current vault key and uploaded again to the
+    /// same server file, so its version stays and other devices don't pull
+    /// it again. Files listed in `rekeyed` at their current version are
+    /// skipped, as are ones that already open with `new_key`, so an
+    /// interrupted rotation picks up where it stopped. `on_rekeyed` gets
+    /// the server file ID and version of each file once its new content is
+    /// on the server. Passes repeat until one finds nothing left, which
+    /// catches files another device updated with the old key meanwhile.
+    /// Returns the number of uploads.
+    pub async fn rekey_remote_files(
+        &self,
+        new_key: &CryptoKey,
+        rekeyed: &HashMap<String, u32>,
+        mut on_rekeyed: impl FnMut(&str, u32, KeyRotationProgress),
+    ) -> SyncResult<u32> {
+        let mut rekeyed = rekeyed.clone();
+        let mut uploaded = 0u32;
+
+        loop {
+            let files = self.remote_files().await?;
+            let pending: Vec<(&String, &RemoteChange)> = files
+                .iter()
+                .filter(|(_, change)| rekeyed.get(&change.id) != Some(&(change.version as u32)))
+                .collect();
+            if pending.is_empty() {
+                return Ok(uploaded);
+            }
+
+            let files_total = files.len() as u32;
+            let already_done = files_total - pending.len() as u32;
+            for (index, (path, change)) in pending.into_iter().enumerate() {
+                let download_url = change.download_url.as_deref().ok_or_else(|| {
+                    SyncError::InvalidData(format!("No download URL for {}", path))
+                })?;
+                let body = self.download_body(&self.full_url(download_url), path).await?;
+                match crypto::open_content(self.vault_key.as_ref(), path, body.clone()) {
+                    Ok(content) => {
+                        let body = if crypto::keeps_plaintext(path, &content) {
+                            crypto::mark_plaintext(&content)
+                        } else {
+                            crypto::encrypt(new_key, &content)?
+                        };
+                        let upload_url = format!("/api/v1/vaults/{}/files/{}/upload", self.vault_id, change.id);
+                        self.upload_file(&self.full_url(&upload_url), &body).await?;
+                        uploaded += 1;
+                    }
+                    // Uploaded by the interrupted run, which stopped before
+                    // recording it
+                    Err(_) if crypto::open_content(Some(new_key), path, body).is_ok() => {}
+                    Err(e) => return Err(SyncError::Decryption(format!("{}: {}", path, e))),
+                }
+
+                rekeyed.insert(change.id.clone(), change.version as u32);
+                on_rekeyed(
+                    &change.id,
+                    change.version as u32,
+                    KeyRotationProgress {
+                        vault_path: self.vault_path.clone(),
+                        relative_path: path.clone(),
+                        files_done: already_done + index as u32 + 1,
+                        files_total,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Encode a path for the server. A file the server already holds keeps
+    /// the encoding it has there; others use the form this vault uses now.
+    fn encode_path(&self, path: &str) -> String {
+        let Some(sm) = &self.state_manager else {
+            return encode_path(path);
+        };
+        if let Some(server_copy) = sm.server_copy_by_id(&self.vault_id, path) {
+            // Synced before remote paths were recorded, when all were bare
+            return server_copy.remote_path.unwrap_or_else(|| encode_path(path));
+        }
+        if sm.uses_prefixed_paths(&self.vault_id) {
+            encode_path_prefixed(path)
+        } else {
+            encode_path(path)
+        }
+    }
+
+    /// Build a full URL (the server returns paths relative to its origin)
+    fn full_url(&self, url: &str) -> String {
+        if url.starts_with('/') {
+            format!("{}{}", self.server_url, url)
+        } else {
+            url.to_string()
+        }
+    }
+
+    /// Download a file from the given URL, decrypting it for encrypted
+    /// vaults. Content that doesn't decrypt may come from a key rotated on
+    /// another device, so the cached key is dropped and fetched again for
+    /// the next sync.
+    async fn download_file(&self, url: &str, relative_path: &str) -> SyncResult<Vec<u8>> {
+        let body = self.download_body(url, relative_path).await?;
+        crypto::open_content(self.vault_key.as_ref(), relative_path, body).map_err(|e| {
+            if let Some(ref state_manager) = self.state_manager {
+                state_manager.forget_vault_key(&self.vault_id);
+            }
+            SyncError::Decryption(format!("{}: {}", relative_path, e))
+        })
+    }
+
+    /// Download a file's body as stored on the server, reporting progress
+    /// for large ones
+    async fn download_body(&self, url: &str, relative_path: &str) -> SyncResult<Vec<u8>> {
+        self.wait_for_rate_limit().await?;
         let response = self
             .client
             .get(url)
             .header("Authorization", format!("Bearer {}", self.access_token))
             .send()
             .await
-            .map_err(|e| SyncError::Network(e.to_string()))?;
+            .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
 
+        self.check_rate_limit(&response)?;
         if !response.status().is_success() {
             let status = response.status();
             let text = response.text().await.unwrap_or_default();
@@ -588,31 +1424,53 @@ impl SyncEngine {
             )));
         }
 
-        response
-            .bytes()
-            .await
-            .map(|b| b.to_vec())
-            .map_err(|e| SyncError::Network(e.to_string()))
+        // Files of unknown size start reporting once they pass the threshold
+        let mut last_reported = 0u64;
+        read_body(response, |received, total| {
+            let Some(report) = &self.download_progress else { return };
+            if total.unwrap_or(received) < DOWNLOAD_PROGRESS_THRESHOLD {
+                return;
+            }
+            if received - last_reported < STREAM_CHUNK_SIZE as u64 && Some(received) != total {
+                return;
+            }
+            last_reported = received;
+            report(DownloadProgress {
+                vault_path: self.vault_path.clone(),
+                relative_path: relative_path.to_string(),
+                bytes_received: received,
+                total_bytes: total,
+                percent: total.filter(|t| *t > 0).map(|t| received as f64 * 100.0 / t as f64),
+            });
+        })
+        .await
     }
 
     /// Push local changes to server (incremental - only changed files)
-    async fn push_changes_incremental(&self, change_set: &ChangeSet, _scan: &ScanResult) -> SyncResult<(u32, u32)> {
+    async fn push_changes_incremental(&self, change_set: &ChangeSet, _scan: &ScanResult) -> SyncResult<PushOutcome> {
         let mut uploaded = 0u32;
         let mut deleted = 0u32;
+        let mut rejected_confirms = Vec::new();
 
         // Build list of changes to push - only changed and deleted files
         let mut changes = Vec::new();
         
+        // Send changes in a reproducible order: shallow creates first,
+        // deepest deletes first
+        let (changed_files, deleted_files) = push_order(change_set);
+
         // Add changed files
-        for info in &change_set.changed {
+        for info in changed_files {
             // Get base_version from state manager if available (use vault_id)
+            // A renamed file is new at its current path on the server
             let base_version = self.state_manager.as_ref().and_then(|sm| {
                 sm.get_file_state_by_id(&self.vault_id, &info.relative_path)
+                    .filter(|fs| fs.renamed_from.is_none())
                     .and_then(|fs| fs.remote_version)
             });
 
             changes.push(serde_json::json!({
-                "encrypted_path": encode_path(&info.relative_path),
+                "encrypted_path": self.encode_path(&info.relative_path),
                 "operation": if base_version.is_some() { "update" } else { "create" },
                 "content_hash": info.content_hash,
                 "size": info.size_bytes,
@@ -622,7 +1480,7 @@ impl SyncEngine {
         }
 
         // Add deleted files
-        for path in &change_set.deleted {
+        for path in deleted_files {
             // Use vault_id for state lookups
             let base_version = self.state_manager.as_ref().and_then(|sm| {
                 sm.get_file_state_by_id(&self.vault_id, path)
@@ -630,7 +1488,7 @@ impl SyncEngine {
             });
 
             changes.push(serde_json::json!({
-                "encrypted_path": encode_path(path),
+                "encrypted_path": self.encode_path(path),
                 "operation": "delete",
                 "content_hash": "",
                 "size": 0,
@@ -641,7 +1499,7 @@ impl SyncEngine {
 
         if changes.is_empty() {
             println!("[Sync] No changes to push");
-            return Ok((0, 0));
+            return Ok(PushOutcome::default());
         }
 
         println!("[Sync] Pushing {} changes to server", changes.len());
@@ -652,6 +1510,7 @@ impl SyncEngine {
             self.server_url, self.vault_id
         );
 
+        self.wait_for_rate_limit().await?;
         let response = self
             .client
             .post(&url)
@@ -659,8 +1518,9 @@ impl SyncEngine {
             .json(&serde_json::json!({ "changes": changes }))
             .send()
             .await
-            .map_err(|e| SyncError::Network(e.to_string()))?;
+            .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
 
+        self.check_rate_limit(&response)?;
         if !response.status().is_success() {
             let status = response.status();
             let text = response.text().await.unwrap_or_default();
@@ -670,10 +1530,18 @@ impl SyncEngine {
             )));
         }
 
-        let push_response: PushResponse = response
-            .json()
-            .await
-            .map_err(|e| SyncError::InvalidData(e.to_string()))?;
+        let push_response: PushResponse = read_json(response, "push response").await?;
+
+        // Each change is identified by its encoded path; anything the server
+        // didn't answer stays pending for the next sync
+        let sent: Vec<&str> = changes
+            .iter()
+            .filter_map(|change| change["encrypted_path"].as_str())
+            .collect();
+        let unanswered = unanswered_changes(&sent, &push_response.results);
+        for path in &unanswered {
+            eprintln!("[Sync] No push result for {}, will retry next sync", path);
+        }
 
         // Build a set of deleted paths for quick lookup
         let deleted_paths: std::collections::HashSet<&str> = change_set.deleted.iter().map(|s| s.as_str()).collect();
@@ -686,7 +1554,7 @@ impl SyncEngine {
                 if let Some(upload_url) = result.upload_url {
                     // This is a create/update operation that needs file upload
                     let vault_path = Path::new(&self.vault_path);
-                    let file_path = vault_path.join(&path);
+                    let file_path = crate::vault::long_path(resolve_local_path(vault_path, &path));
 
                     // Build full upload URL (server returns relative path)
                     let full_upload_url = if upload_url.starts_with('/') {
@@ -696,25 +1564,44 @@ impl SyncEngine {
                     };
 
                     // Read and upload file
-                    if let Ok(content) = fs::read(&file_path) {
-                        let content_hash = compute_hash(&content);
-                        match self.upload_file(&full_upload_url, &content).await {
-                            Ok(_) => {
-                                uploaded += 1;
-                                // Confirm upload
-                                if let Some(ref file_id) = result.file_id {
-                                    let _ = self.confirm_upload(file_id).await;
-                                }
-                                // Update local state to mark as synced (use vault_id)
-                                if let Some(ref state_manager) = self.state_manager {
-                                    let version = result.new_version.unwrap_or(1) as u32;
-                                    state_manager.mark_synced_by_id(&self.vault_id, &path, &content_hash, version);
-                                }
+                    match self.upload_local_file(&full_upload_url, &file_path, &path).await {
+                        Ok(None) => {}
+                        Ok(Some(content_hash)) => {
+                            uploaded += 1;
+                            // Update local state to mark as synced (use vault_id)
+                            if let Some(ref state_manager) = self.state_manager {
+                                let version = result.new_version.unwrap_or(1) as u32;
+                                // Scanned body, unless the note changed since
+                                let body_hash = change_set
+                                    .changed
+                                    .iter()
+                                    .find(|f| f.relative_path == path && f.content_hash == content_hash)
+                                    .and_then(|f| f.body_hash.clone());
+                                state_manager.mark_synced_with_body_by_id(&self.vault_id, &path, &content_hash, body_hash, version);
+                                state_manager.set_remote_path_by_id(&self.vault_id, &path, &result.encrypted_path);
                             }
-                            Err(e) => {
-                                eprintln!("Upload failed for {}: {}", path, e);
+                            // Confirm upload; the content is already on the server,
+                            // so a failed confirm is retried alone on the next sync
+                            // unless the server refused it for good
+                            if let Some(ref file_id) = result.file_id {
+                                match self.confirm_upload(file_id).await {
+                                    Ok(()) => {}
+                                    Err(e @ SyncError::Rejected(_)) => {
+                                        eprintln!("[Sync] Confirm rejected for {}: {}", path, e);
+                                        rejected_confirms.push(format!("Confirm failed for {}: {}", path, e));
+                                    }
+                                    Err(e) => {
+                                        eprintln!("[Sync] Confirm failed for {}: {}", path, e);
+                                        if let Some(ref state_manager) = self.state_manager {
+                                            state_manager.set_pending_confirm_by_id(&self.vault_id, &path, Some(file_id));
+                                        }
+                                    }
+                                }
                             }
                         }
+                        Err(e) => {
+                            eprintln!("Upload failed for {}: {}", path, e);
+                        }
                     }
                 } else if deleted_paths.contains(path.as_str()) {
                     // This is a delete operation that was accepted
@@ -730,7 +1617,14 @@ impl SyncEngine {
                         let version = result.new_version.unwrap_or(1) as u32;
                         // Get the content hash from the scan result for this file
                         if let Some(info) = change_set.changed.iter().find(|f| f.relative_path == path) {
-                            state_manager.mark_synced_by_id(&self.vault_id, &path, &info.content_hash, version);
+                            state_manager.mark_synced_with_body_by_id(
+                                &self.vault_id,
+                                &path,
+                                &info.content_hash,
+                                info.body_hash.clone(),
+                                version,
+                            );
+                            state_manager.set_remote_path_by_id(&self.vault_id, &path, &result.encrypted_path);
                             println!("[Sync] File already up-to-date on server: {}", path);
                         }
                     }
@@ -738,11 +1632,111 @@ impl SyncEngine {
             }
         }
 
-        Ok((uploaded, deleted))
+        Ok(PushOutcome {
+            uploaded,
+            deleted,
+            unanswered,
+            rejected_confirms,
+        })
+    }
+
+    /// Upload a local file, streaming it from disk when it is large.
+    /// Encrypted vaults encrypt the whole file in memory instead, since the
+    /// ciphertext can't be produced chunk by chunk; notes with
+    /// `encrypt: false` are sent as marked plaintext. Returns the hash of the
+    /// plaintext content, or `None` if the file couldn't be read.
+    async fn upload_local_file(&self, url: &str, file_path: &Path, relative_path: &str) -> SyncResult<Option<String>> {
+        let Ok(metadata) = fs::metadata(file_path) else {
+            return Ok(None);
+        };
+        if let Some(key) = &self.vault_key {
+            let Ok(content) = fs::read(file_path) else {
+                return Ok(None);
+            };
+            let body = if crypto::keeps_plaintext(relative_path, &content) {
+                crypto::mark_plaintext(&content)
+            } else {
+                crypto::encrypt(key, &content)?
+            };
+            self.upload_file(url, &body).await?;
+            return Ok(Some(compute_hash(&content)));
+        }
+        if metadata.len() >= STREAM_UPLOAD_THRESHOLD {
+            return self
+                .upload_file_streaming(url, file_path, relative_path, metadata.len())
+                .await
+                .map(Some);
+        }
+
+        let Ok(content) = fs::read(file_path) else {
+            return Ok(None);
+        };
+        self.upload_file(url, &content).await?;
+        Ok(Some(compute_hash(&content)))
+    }
+
+    /// Upload a file chunk by chunk without holding it in memory, reporting
+    /// progress after each chunk. Returns the hash of the bytes sent.
+    async fn upload_file_streaming(
+        &self,
+        url: &str,
+        file_path: &Path,
+        relative_path: &str,
+        total_bytes: u64,
+    ) -> SyncResult<String> {
+        let file = tokio::fs::File::open(file_path).await?;
+        let hasher = Arc::new(parking_lot::Mutex::new(blake3::Hasher::new()));
+
+        let chunk_hasher = Arc::clone(&hasher);
+        let progress = self.upload_progress.clone();
+        let vault_path = self.vault_path.clone();
+        let relative_path = relative_path.to_string();
+        let mut bytes_sent = 0u64;
+        let body = tokio_util::io::ReaderStream::with_capacity(file, STREAM_CHUNK_SIZE).map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                chunk_hasher.lock().update(bytes);
+                bytes_sent += bytes.len() as u64;
+                if let Some(report) = &progress {
+                    report(UploadProgress {
+                        vault_path: vault_path.clone(),
+                        relative_path: relative_path.clone(),
+                        bytes_sent,
+                        total_bytes,
+                    });
+                }
+            }
+            chunk
+        });
+
+        self.wait_for_rate_limit().await?;
+        let response = self
+            .client
+            .put(url)
+            .header("Authorization", format!("Bearer {}", self.access_token))
+            .header("Content-Type", "application/octet-stream")
+            .header(reqwest::header::CONTENT_LENGTH, total_bytes)
+            .body(reqwest::Body::wrap_stream(body))
+            .send()
+            .await
+            .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
+
+        self.check_rate_limit(&response)?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(SyncError::Network(format!(
+                "Upload failed: {} - {}",
+                status, text
+            )));
+        }
+
+        let hash = hasher.lock().finalize().to_hex().to_string();
+        Ok(hash)
     }
 
     /// Upload a file to the server
     async fn upload_file(&self, url: &str, content: &[u8]) -> SyncResult<()> {
+        self.wait_for_rate_limit().await?;
         let response = self
             .client
             .put(url)
@@ -751,8 +1745,9 @@ impl SyncEngine {
             .body(content.to_vec())
             .send()
             .await
-            .map_err(|e| SyncError::Network(e.to_string()))?;
+            .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
 
+        self.check_rate_limit(&response)?;
         if !response.status().is_success() {
             let status = response.status();
             let text = response.text().await.unwrap_or_default();
@@ -772,6 +1767,7 @@ impl SyncEngine {
             self.server_url, self.vault_id
         );
 
+        self.wait_for_rate_limit().await?;
         let response = self
             .client
             .post(&url)
@@ -779,36 +1775,187 @@ impl SyncEngine {
             .json(&serde_json::json!({ "file_ids": [file_id] }))
             .send()
             .await
-            .map_err(|e| SyncError::Network(e.to_string()))?;
+            .map_err(|e| SyncError::Network(proxy::describe_error(&e)))?;
 
-        if !response.status().is_success() {
+        self.check_rate_limit(&response)?;
+        let status = response.status();
+        if is_permanent_rejection(status) {
+            return Err(SyncError::Rejected(format!("confirm upload: {}", status)));
+        }
+        if !status.is_success() {
             return Err(SyncError::Server("Confirm upload failed".to_string()));
         }
 
         Ok(())
     }
+
+    /// Wait out the shared rate-limit hold before a request to the server
+    async fn wait_for_rate_limit(&self) -> SyncResult<()> {
+        match &self.rate_limit {
+            Some(hold) => hold.wait().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Fail a 429 answer with [`SyncError::RateLimited`], holding every
+    /// other request of the app until its `Retry-After` has passed
+    fn check_rate_limit(&self, response: &reqwest::Response) -> SyncResult<()> {
+        if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Ok(());
+        }
+        let retry_after = retry_after_secs(response.headers());
+        if let Some(hold) = &self.rate_limit {
+            hold.hold(retry_after);
+        }
+        Err(SyncError::RateLimited(retry_after))
+    }
+}
+
+/// Whether a response status means the request will never succeed as is:
+/// a client error, except timeouts and rate limits
+fn is_permanent_rejection(status: reqwest::StatusCode) -> bool {
+    status.is_client_error()
+        && status != reqwest::StatusCode::REQUEST_TIMEOUT
+        && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Number of directories above a relative path
+fn path_depth(relative_path: &str) -> usize {
+    relative_path.matches('/').count()
+}
+
+/// Order the remote changes to one decoded path. The server may hold the
+/// path under more than one encoding, and versions only count up within one
+/// encoded path, so they don't order changes across encodings. Deletes go
+/// first instead: encoded paths whose last change is a delete come before
+/// the others, so a rename round trip (delete, then create at the same
+/// path) always ends with the file present. Within one encoded path changes
+/// go by version, a delete before a create or update of the same version.
+/// Ties otherwise keep the server's order.
+fn order_path_changes<'a>(changes: &mut [&'a RemoteChange]) {
+    let mut last: HashMap<&'a str, &'a RemoteChange> = HashMap::new();
+    for &change in changes.iter() {
+        let key = (change.version, change.operation != "delete");
+        last.entry(change.encrypted_path.as_str())
+            .and_modify(|latest| {
+                if key >= (latest.version, latest.operation != "delete") {
+                    *latest = change;
+                }
+            })
+            .or_insert(change);
+    }
+    changes.sort_by_key(|change| {
+        let ends_deleted = last[change.encrypted_path.as_str()].operation == "delete";
+        (!ends_deleted, change.version, change.operation != "delete")
+    });
 }
 
-/// Encode a path for transmission (simple base64)
+/// Order a change set for pushing. Changed files go parents-first (by
+/// depth, then path) and deletes go deepest-first, so the order is the
+/// same on every run regardless of scan order.
+fn push_order(change_set: &ChangeSet) -> (Vec<&FileInfo>, Vec<&String>) {
+    let mut changed: Vec<&FileInfo> = change_set.changed.iter().collect();
+    changed.sort_by(|a, b| {
+        path_depth(&a.relative_path)
+            .cmp(&path_depth(&b.relative_path))
+            .then_with(|| a.relative_path.cmp(&b.relative_path))
+    });
+
+    let mut deleted: Vec<&String> = change_set.deleted.iter().collect();
+    deleted.sort_by(|a, b| path_depth(b).cmp(&path_depth(a)).then_with(|| a.cmp(b)));
+
+    (changed, deleted)
+}
+
+/// Server version of each changed file the server holds with the same
+/// content, keyed by relative path. `encoded` holds the encoded path of each
+/// changed file, in the same order.
+fn held_versions(changed: &[FileInfo], encoded: &[String], held: &[HeldFile]) -> HashMap<String, u32> {
+    let held: HashMap<&str, &HeldFile> = held.iter().map(|f| (f.encrypted_path.as_str(), f)).collect();
+    changed
+        .iter()
+        .zip(encoded)
+        .filter_map(|(info, encoded)| {
+            let file = held.get(encoded.as_str())?;
+            (file.content_hash == info.content_hash && file.version > 0)
+                .then_some((info.relative_path.clone(), file.version as u32))
+        })
+        .collect()
+}
+
+/// Decoded paths of sent changes (by encoded path) that have no entry in the
+/// push results
+fn unanswered_changes(sent: &[&str], results: &[PushResult]) -> Vec<String> {
+    let answered: HashSet<&str> = results.iter().map(|r| r.encrypted_path.as_str()).collect();
+    sent.iter()
+        .filter(|encrypted| !answered.contains(*encrypted))
+        .map(|encrypted| decode_path(encrypted).unwrap_or_else(|_| encrypted.to_string()))
+        .collect()
+}
+
+/// Scheme prefix of a base64-encoded path
+const BASE64_PATH_PREFIX: &str = "b64:";
+
+/// Scheme prefix of a path sent as is
+const PLAIN_PATH_PREFIX: &str = "plain:";
+
+/// Scheme prefix reserved for encrypted paths
+const ENCRYPTED_PATH_PREFIX: &str = "enc:";
+
+/// Whether an encoded path names its scheme
+fn has_path_scheme(encoded: &str) -> bool {
+    [BASE64_PATH_PREFIX, PLAIN_PATH_PREFIX, ENCRYPTED_PATH_PREFIX]
+        .iter()
+        .any(|prefix| encoded.starts_with(prefix))
+}
+
+/// Encode a path for transmission the legacy way (bare base64)
 fn encode_path(path: &str) -> String {
     BASE64.encode(path.as_bytes())
 }
 
-/// Decode a path from transmission
+/// Encode a path for transmission with its scheme prefix
+fn encode_path_prefixed(path: &str) -> String {
+    format!("{}{}", BASE64_PATH_PREFIX, BASE64.encode(path.as_bytes()))
+}
+
+/// Decode a path from transmission. Prefixed paths are decoded by their
+/// scheme only. Bare values come from clients that predate prefixes and
+/// are base64 unless they don't decode to text without control characters,
+/// in which case they were sent as plain paths.
 fn decode_path(encoded: &str) -> SyncResult<String> {
-    // Try base64 decode first
+    if let Some(rest) = encoded.strip_prefix(BASE64_PATH_PREFIX) {
+        let bytes = BASE64
+            .decode(rest)
+            .map_err(|e| SyncError::InvalidData(format!("Invalid base64 path {:?}: {}", encoded, e)))?;
+        let path = String::from_utf8(bytes)
+            .map_err(|_| SyncError::InvalidData(format!("Path {:?} is not UTF-8", encoded)))?;
+        return Ok(normalize_path(&path));
+    }
+    if let Some(rest) = encoded.strip_prefix(PLAIN_PATH_PREFIX) {
+        return Ok(normalize_path(rest));
+    }
+    if encoded.starts_with(ENCRYPTED_PATH_PREFIX) {
+        return Err(SyncError::InvalidData(format!(
+            "Path {:?} is encrypted, which this version can't read",
+            encoded
+        )));
+    }
+
     if let Ok(bytes) = BASE64.decode(encoded) {
         if let Ok(s) = String::from_utf8(bytes) {
-            return Ok(s);
+            if !s.is_empty() && !s.chars().any(char::is_control) {
+                return Ok(normalize_path(&s));
+            }
         }
     }
-    // If not base64, assume it's already a plain path
-    Ok(encoded.to_string())
+    Ok(normalize_path(encoded))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sync::fixture::VaultFixture;
 
     #[test]
     fn test_encode_decode_path() {
@@ -824,5 +1971,705 @@ mod tests {
         let decoded = decode_path(path).unwrap();
         assert_eq!(path, decoded);
     }
-}
 
+    #[test]
+    fn test_prefixed_paths_decode_unambiguously() {
+        // "TWFu" is valid base64 for "Man" and "AAAA" for three NUL bytes
+        assert_eq!(decode_path("plain:TWFu").unwrap(), "TWFu");
+        assert_eq!(decode_path(&encode_path_prefixed("TWFu")).unwrap(), "TWFu");
+        assert_eq!(decode_path(&encode_path_prefixed("notes/test.md")).unwrap(), "notes/test.md");
+        assert!(decode_path("b64:not base64!").is_err());
+        assert!(decode_path("enc:abc").is_err());
+
+        // Bare legacy values still decode as before, but never to control
+        // characters
+        assert_eq!(decode_path(&encode_path("TWFu")).unwrap(), "TWFu");
+        assert_eq!(decode_path("AAAA").unwrap(), "AAAA");
+        assert!(has_path_scheme("b64:") && !has_path_scheme(&encode_path("b64:")));
+    }
+
+    #[test]
+    fn test_switching_to_prefixed_paths_keeps_server_encoding() {
+        let fixture = VaultFixture::builder()
+            .synced_note("old.md", "synced bare")
+            .synced_note("pulled.md", "pulled prefixed")
+            .note("new.md", "never synced")
+            .build();
+        let sm = &fixture.state_manager;
+        sm.set_remote_path_by_id(&fixture.vault_id, "pulled.md", &encode_path_prefixed("pulled.md"));
+        sm.set_prefixed_paths(&fixture.vault_id);
+        let engine = fixture.engine();
+
+        assert_eq!(engine.encode_path("old.md"), encode_path("old.md"));
+        assert_eq!(engine.encode_path("pulled.md"), encode_path_prefixed("pulled.md"));
+        assert_eq!(engine.encode_path("new.md"), encode_path_prefixed("new.md"));
+
+        // A later sync of the file keeps what the server has
+        fixture.write("pulled.md", "edited");
+        fixture.mark_synced("pulled.md", 2);
+        assert_eq!(engine.encode_path("pulled.md"), encode_path_prefixed("pulled.md"));
+
+        // The old path of a renamed file is deleted under its server encoding
+        sm.rename_path_prefix(&fixture.vault_id, "old.md", "renamed.md");
+        assert_eq!(engine.encode_path("old.md"), encode_path("old.md"));
+        assert_eq!(engine.encode_path("renamed.md"), encode_path_prefixed("renamed.md"));
+    }
+
+    #[test]
+    fn test_only_client_errors_that_never_succeed_are_permanent() {
+        use reqwest::StatusCode;
+        assert!(is_permanent_rejection(StatusCode::BAD_REQUEST));
+        assert!(is_permanent_rejection(StatusCode::NOT_FOUND));
+        assert!(!is_permanent_rejection(StatusCode::REQUEST_TIMEOUT));
+        assert!(!is_permanent_rejection(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_permanent_rejection(StatusCode::BAD_GATEWAY));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_transfer_holds_later_transfers() {
+        use std::io::{Read, Write};
+
+        // Answers one request with a 429
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/files/file-1/download", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 4096]);
+            let _ = stream.write_all(b"HTTP/1.1 429 Too Many Requests\r\nRetry-After: 120\r\nContent-Length: 0\r\n\r\n");
+        });
+
+        let hold = Arc::new(RateLimitHold::new());
+        let mut engine = SyncEngine::new(
+            "http://127.0.0.1:9".to_string(),
+            String::new(),
+            "vault-1".to_string(),
+            String::new(),
+        );
+        engine.set_rate_limit(Arc::clone(&hold));
+
+        assert!(matches!(engine.download_file(&url, "a.md").await, Err(SyncError::RateLimited(120))));
+        assert!(hold.remaining().is_some());
+
+        // Refused without reaching the (unreachable) server
+        assert!(matches!(engine.upload_file(&url, b"a").await, Err(SyncError::RateLimited(_))));
+    }
+
+    #[test]
+    fn test_unanswered_push_changes_are_detected() {
+        let result = |path: &str| PushResult {
+            encrypted_path: encode_path(path),
+            status: "accepted".to_string(),
+            upload_url: None,
+            new_version: Some(1),
+            file_id: None,
+        };
+        let sent: Vec<String> = ["a.md", "b.md", "c.md"].iter().map(|p| encode_path(p)).collect();
+        let sent: Vec<&str> = sent.iter().map(String::as_str).collect();
+
+        assert!(unanswered_changes(&sent, &[result("c.md"), result("a.md"), result("b.md")]).is_empty());
+        assert_eq!(unanswered_changes(&sent, &[result("a.md")]), vec!["b.md", "c.md"]);
+        assert_eq!(unanswered_changes(&sent, &[]).len(), 3);
+    }
+
+    #[test]
+    fn test_only_files_held_with_same_content_are_skipped() {
+        let info = |path: &str, hash: &str| FileInfo {
+            relative_path: path.to_string(),
+            content_hash: hash.to_string(),
+            body_hash: None,
+            size_bytes: 0,
+            modified_at: 0,
+        };
+        let held = |path: &str, hash: &str, version: i32| HeldFile {
+            encrypted_path: encode_path(path),
+            content_hash: hash.to_string(),
+            version,
+        };
+        let changed = vec![info("same.md", "h1"), info("edited.md", "h2"), info("new.md", "h3")];
+        let encoded: Vec<String> = changed.iter().map(|f| encode_path(&f.relative_path)).collect();
+
+        let versions = held_versions(
+            &changed,
+            &encoded,
+            &[held("same.md", "h1", 4), held("edited.md", "old", 2), held("other.md", "h3", 1)],
+        );
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions.get("same.md"), Some(&4));
+    }
+
+    #[tokio::test]
+    async fn test_files_the_server_has_are_skipped() {
+        use std::io::{Read, Write};
+
+        let fixture = VaultFixture::builder().note("same.md", "uploaded").note("new.md", "not yet").build();
+        let info = |path: &str| {
+            let content = fs::read(fixture.path(path)).unwrap();
+            FileInfo {
+                relative_path: path.to_string(),
+                content_hash: compute_hash(&content),
+                body_hash: None,
+                size_bytes: content.len() as u64,
+                modified_at: 0,
+            }
+        };
+        let mut change_set = ChangeSet {
+            changed: vec![info("same.md"), info("new.md")],
+            deleted: Vec::new(),
+        };
+
+        // The body `hasFiles` in workers/sync-api answers with
+        let body = serde_json::json!({
+            "files": [{
+                "encrypted_path": encode_path("same.md"),
+                "content_hash": change_set.changed[0].content_hash,
+                "version": 4,
+            }],
+        })
+        .to_string();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 4096]);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = stream.write_all(response.as_bytes());
+        });
+
+        let engine = SyncEngine::with_state_manager(
+            server_url,
+            String::new(),
+            fixture.vault_id.clone(),
+            fixture.vault_path.clone(),
+            Arc::clone(&fixture.state_manager),
+        );
+        assert_eq!(engine.skip_files_held_by_server(&mut change_set).await, 1);
+        assert_eq!(change_set.changed.len(), 1);
+        assert_eq!(change_set.changed[0].relative_path, "new.md");
+        fixture.assert_synced("same.md", 4);
+    }
+
+    #[tokio::test]
+    async fn test_rekey_re_encrypts_only_files_not_done_yet() {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let old_key = CryptoKey::generate();
+        let new_key = CryptoKey::generate();
+        let change = |id: &str, path: &str, operation: &str, version: u32| {
+            serde_json::json!({
+                "id": id,
+                "encrypted_path": encode_path(path),
+                "operation": operation,
+                "content_hash": "",
+                "version": version,
+                "download_url": format!("/download/{}", id),
+            })
+        };
+        let pull = serde_json::json!({
+            "changes": [
+                change("a", "a.md", "create", 1),
+                // Recorded as done by an earlier run
+                change("b", "b.md", "update", 2),
+                // Uploaded by an earlier run that stopped before recording it
+                change("c", "c.md", "create", 1),
+                change("d", "gone.md", "delete", 3),
+            ],
+            "next_cursor": "1",
+            "has_more": false,
+        })
+        .to_string();
+        let mut stored = HashMap::new();
+        stored.insert("a".to_string(), crypto::encrypt(&old_key, b"alpha").unwrap());
+        stored.insert("b".to_string(), crypto::encrypt(&new_key, b"bravo").unwrap());
+        stored.insert("c".to_string(), crypto::encrypt(&new_key, b"charlie").unwrap());
+        let stored = Arc::new(parking_lot::Mutex::new(stored));
+        let uploads = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let server_url = format!("http://{}", listener.local_addr().unwrap());
+        let (server_stored, server_uploads) = (Arc::clone(&stored), Arc::clone(&uploads));
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                stream.read_exact(&mut body).unwrap();
+
+                let target = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                let response: Vec<u8> = if target.ends_with("/sync/pull") {
+                    pull.clone().into_bytes()
+                } else if let Some(id) = target.strip_prefix("/download/") {
+                    server_stored.lock()[id].clone()
+                } else {
+                    let id = target.trim_end_matches("/upload").rsplit('/').next().unwrap().to_string();
+                    server_uploads.lock().push(id.clone());
+                    server_stored.lock().insert(id, body);
+                    b"{}".to_vec()
+                };
+                let mut stream = stream.into_inner();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+                    response.len()
+                );
+                let _ = stream.write_all(&response);
+            }
+        });
+
+        let mut engine = SyncEngine::new(server_url, String::new(), "vault".to_string(), String::new());
+        engine.set_vault_key(Some(old_key));
+        let mut rekeyed = HashMap::new();
+        rekeyed.insert("b".to_string(), 2);
+        let mut recorded = Vec::new();
+        let uploaded = engine
+            .rekey_remote_files(&new_key, &rekeyed, |id, version, progress| {
+                recorded.push((id.to_string(), version, progress.files_done, progress.files_total));
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(uploaded, 1);
+        assert_eq!(*uploads.lock(), vec!["a".to_string()]);
+        assert_eq!(
+            recorded,
+            vec![("a".to_string(), 1, 2, 3), ("c".to_string(), 1, 3, 3)]
+        );
+        assert_eq!(crypto::decrypt(&new_key, &stored.lock()["a"]).unwrap(), b"alpha");
+    }
+
+    #[test]
+    fn test_push_order_is_stable() {
+        let info = |path: &str| FileInfo {
+            relative_path: path.to_string(),
+            content_hash: String::new(),
+            body_hash: None,
+            size_bytes: 0,
+            modified_at: 0,
+        };
+        let paths = ["b/c/deep.md", "z.md", "b/note.md", "a.md", "a-b/x.md"];
+        let deletes = ["x.md", "old/nested/deep.md", "old/a.md", "old/b.md"];
+
+        let expected_changed = vec!["a.md", "z.md", "a-b/x.md", "b/note.md", "b/c/deep.md"];
+        let expected_deleted = vec!["old/nested/deep.md", "old/a.md", "old/b.md", "x.md"];
+
+        // Same result whatever order the scan produced
+        for rotation in 0..paths.len() {
+            let mut changed: Vec<FileInfo> = paths.iter().map(|p| info(p)).collect();
+            changed.rotate_left(rotation);
+            let mut deleted: Vec<String> = deletes.iter().map(|p| p.to_string()).collect();
+            deleted.rotate_left(rotation % deletes.len());
+            let change_set = ChangeSet { changed, deleted };
+
+            let (changed, deleted) = push_order(&change_set);
+            let changed: Vec<&str> = changed.iter().map(|f| f.relative_path.as_str()).collect();
+            let deleted: Vec<&str> = deleted.iter().map(|p| p.as_str()).collect();
+            assert_eq!(changed, expected_changed);
+            assert_eq!(deleted, expected_deleted);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_changes_outside_vault_are_rejected() {
+        let root = std::env::temp_dir().join(format!("echopad-engine-{}", uuid::Uuid::new_v4()));
+        let vault = root.join("vault");
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(root.join("config.md"), "outside").unwrap();
+
+        let mut engine = SyncEngine::new(
+            "http://127.0.0.1:9".to_string(),
+            String::new(),
+            "vault-1".to_string(),
+            vault.to_string_lossy().to_string(),
+        );
+        engine.set_placeholder_mode(true);
+
+        for (path, operation) in [("../config.md", "delete"), ("../evil.md", "create"), ("run.sh", "create")] {
+            let change = RemoteChange {
+                id: "file-1".to_string(),
+                encrypted_path: encode_path(path),
+                operation: operation.to_string(),
+                content_hash: String::new(),
+                version: 1,
+                download_url: Some("/files/file-1/download".to_string()),
+            };
+            assert!(engine.apply_remote_change(&vault, &change).await.is_err());
+        }
+
+        assert!(root.join("config.md").exists());
+        assert!(!root.join("evil.md").exists());
+        assert!(!vault.join("run.sh").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_estimate_upload_counts_local_changes() {
+        let vault = VaultFixture::builder()
+            .synced_note("synced.md", "same")
+            .synced_note("edited.md", "old")
+            .synced_note("removed.md", "gone")
+            .build();
+        vault.write("edited.md", "new content");
+        vault.write("added.md", "added");
+        vault.remove("removed.md");
+
+        let estimate = vault.engine().estimate_upload().unwrap();
+        assert_eq!(
+            estimate,
+            UploadEstimate {
+                files_to_upload: 2,
+                bytes_to_upload: ("new content".len() + "added".len()) as u64,
+                files_to_delete: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deleted_notebook_is_not_resurrected() {
+        let vault = VaultFixture::builder()
+            .synced_note("Journal/1.md", "one")
+            .synced_note("Journal/2.md", "two")
+            .synced_note("keep.md", "keep")
+            .build();
+
+        let marked = vault.state_manager.delete_vault_dir(&vault.vault_path, "Journal").unwrap();
+        assert_eq!(marked, 2);
+        assert!(!vault.exists("Journal"));
+
+        let engine = vault.engine();
+
+        // The next sync pushes the deletes...
+        let mut deletes = engine.detect_local_deletes(&vault.scan());
+        deletes.sort();
+        assert_eq!(deletes, vec!["Journal/1.md", "Journal/2.md"]);
+
+        // ...and a pull that still carries the old files doesn't write them back
+        let change = RemoteChange {
+            id: "file-1".to_string(),
+            encrypted_path: encode_path("Journal/1.md"),
+            operation: "update".to_string(),
+            content_hash: compute_hash(b"one"),
+            version: 2,
+            download_url: Some("/files/file-1/download".to_string()),
+        };
+        engine.apply_remote_change(&vault.root, &change).await.unwrap();
+        assert!(!vault.exists("Journal"));
+        vault.assert_synced("keep.md", 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_notebook_is_not_deleted() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let vault = VaultFixture::builder()
+            .synced_note("Locked/1.md", "one")
+            .synced_note("Locked/Sub/2.md", "two")
+            .synced_note("Lockedness.md", "three")
+            .build();
+        vault.remove("Lockedness.md");
+        fs::set_permissions(vault.path("Locked"), fs::Permissions::from_mode(0o000)).unwrap();
+
+        let scan = vault.scan();
+        let readable_anyway = fs::read_dir(vault.path("Locked")).is_ok(); // e.g. running as root
+        let deletes = vault.engine().detect_local_deletes(&scan);
+
+        fs::set_permissions(vault.path("Locked"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        if !readable_anyway {
+            assert!(scan.is_skipped("Locked/Sub/2.md"));
+            assert_eq!(deletes, vec!["Lockedness.md"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_placeholders_are_never_pushed() {
+        let vault = VaultFixture::builder().synced_note("keep.md", "keep").build();
+        let mut engine = vault.engine();
+        engine.set_placeholder_mode(true);
+        for (i, path) in ["edited.md", "removed.md"].iter().enumerate() {
+            let change = RemoteChange {
+                id: format!("file-{}", i),
+                encrypted_path: encode_path(path),
+                operation: "create".to_string(),
+                content_hash: compute_hash(b"server content"),
+                version: 3,
+                download_url: Some(format!("/files/file-{}/download", i)),
+            };
+            engine.apply_remote_change(&vault.root, &change).await.unwrap();
+        }
+
+        vault.write("edited.md", "typed into the placeholder");
+        vault.remove("removed.md");
+        let scan = vault.scan();
+        assert!(engine.get_local_changes(&scan).changed.is_empty());
+        assert!(engine.detect_local_deletes(&scan).is_empty());
+        vault.assert_pending(0);
+
+        // A later pull keeps what was typed
+        let change = RemoteChange {
+            id: "file-0".to_string(),
+            encrypted_path: encode_path("edited.md"),
+            operation: "update".to_string(),
+            content_hash: compute_hash(b"newer server content"),
+            version: 4,
+            download_url: Some("/files/file-0/download".to_string()),
+        };
+        engine.apply_remote_change(&vault.root, &change).await.unwrap();
+        assert_eq!(fs::read_to_string(vault.path("edited.md")).unwrap(), "typed into the placeholder");
+    }
+
+    #[tokio::test]
+    async fn test_emoji_notebook_round_trips() {
+        let path = "📥 Inbox/Café ✨/🎉 party.md";
+        let vault = VaultFixture::builder().note(path, "party").build();
+
+        // Scanned as-is and encoded either way, the path comes back unchanged
+        let scan = vault.scan();
+        assert!(scan.files.contains_key(path));
+        assert_eq!(decode_path(&encode_path(path)).unwrap(), path);
+        assert_eq!(decode_path(&encode_path_prefixed(path)).unwrap(), path);
+        // A decomposed é from macOS is the same note
+        let nfd = "📥 Inbox/Cafe\u{301} ✨/🎉 party.md";
+        assert_eq!(decode_path(&encode_path(nfd)).unwrap(), path);
+
+        // Pulling into a notebook that doesn't exist yet creates it
+        let mut engine = vault.engine();
+        engine.set_placeholder_mode(true);
+        let pulled = "🗂\u{fe0f} Archive/👨\u{200d}👩\u{200d}👧 family.md";
+        let change = RemoteChange {
+            id: "file-1".to_string(),
+            encrypted_path: encode_path_prefixed(pulled),
+            operation: "create".to_string(),
+            content_hash: compute_hash(b"family"),
+            version: 1,
+            download_url: Some("/files/file-1/download".to_string()),
+        };
+        engine.apply_remote_change(&vault.root, &change).await.unwrap();
+        assert!(vault.exists(pulled));
+        assert!(vault.state_manager.get_file_state_by_id(&vault.vault_id, pulled).is_some());
+        assert!(vault.scan().files.contains_key(pulled));
+    }
+
+    #[tokio::test]
+    async fn test_matching_local_note_gets_its_body_hash() {
+        let content = "---\ntags: [a]\n---\nbody";
+        let fixture = VaultFixture::builder().note("note.md", content).build();
+        let mut engine = fixture.engine();
+        engine.set_body_hash_only(true);
+
+        let change = RemoteChange {
+            id: "file-1".to_string(),
+            encrypted_path: encode_path("note.md"),
+            operation: "update".to_string(),
+            content_hash: compute_hash(content.as_bytes()),
+            version: 2,
+            download_url: Some("/files/file-1/download".to_string()),
+        };
+        engine.apply_remote_change(&fixture.root, &change).await.unwrap();
+
+        let file_state = fixture.state_manager.get_file_state_by_id(&fixture.vault_id, "note.md").unwrap();
+        assert_eq!(file_state.body_hash, compute_body_hash("note.md", content.as_bytes()));
+        fixture.assert_synced("note.md", 2);
+    }
+
+    #[tokio::test]
+    async fn test_matching_local_file_is_not_rewritten() {
+        use super::super::state::MemoryStore;
+        use std::sync::Arc;
+
+        let vault = std::env::temp_dir().join(format!("echopad-engine-{}", uuid::Uuid::new_v4()));
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::create_dir_all(vault.join("attachments")).unwrap();
+        fs::write(vault.join("attachments/photo.png"), b"png bytes").unwrap();
+        let modified = fs::metadata(vault.join("attachments/photo.png")).unwrap().modified().unwrap();
+
+        let state_manager = Arc::new(SyncStateManager::with_store(Arc::new(MemoryStore::new())));
+        state_manager.enable_vault(&vault_path, "vault-1");
+
+        // The server is unreachable, so this only succeeds without a download
+        let engine = SyncEngine::with_state_manager(
+            "http://127.0.0.1:9".to_string(),
+            String::new(),
+            "vault-1".to_string(),
+            vault_path.clone(),
+            Arc::clone(&state_manager),
+        );
+        let change = RemoteChange {
+            id: "file-1".to_string(),
+            encrypted_path: encode_path("attachments/photo.png"),
+            operation: "update".to_string(),
+            content_hash: compute_hash(b"png bytes"),
+            version: 3,
+            download_url: Some("/files/file-1/download".to_string()),
+        };
+        engine.apply_remote_change(&vault, &change).await.unwrap();
+
+        let file_state = state_manager.get_file_state_by_id("vault-1", "attachments/photo.png").unwrap();
+        assert_eq!(file_state.local_hash, Some(compute_hash(b"png bytes")));
+        assert_eq!(file_state.remote_version, Some(3));
+        assert_eq!(fs::metadata(vault.join("attachments/photo.png")).unwrap().modified().unwrap(), modified);
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_pull_keeps_per_path_order() {
+        use super::super::state::MemoryStore;
+        use std::sync::Arc;
+
+        let vault = std::env::temp_dir().join(format!("echopad-engine-{}", uuid::Uuid::new_v4()));
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::create_dir_all(&vault).unwrap();
+
+        let state_manager = Arc::new(SyncStateManager::with_store(Arc::new(MemoryStore::new())));
+        state_manager.enable_vault(&vault_path, "vault-1");
+
+        let mut engine = SyncEngine::with_state_manager(
+            "http://127.0.0.1:9".to_string(),
+            String::new(),
+            "vault-1".to_string(),
+            vault_path.clone(),
+            Arc::clone(&state_manager),
+        );
+        // Placeholder pulls need no download, so the test stays offline
+        engine.set_placeholder_mode(true);
+        engine.set_pull_concurrency(4);
+
+        let change = |path: &str, operation: &str, version: i32| RemoteChange {
+            id: path.to_string(),
+            encrypted_path: encode_path(path),
+            operation: operation.to_string(),
+            content_hash: compute_hash(path.as_bytes()),
+            version,
+            download_url: Some(format!("/files/{}/download", path)),
+        };
+
+        let mut changes = Vec::new();
+        for i in 0..20 {
+            changes.push(change(&format!("note-{}.md", i), "create", 1));
+        }
+        changes.push(change("recreated.md", "create", 1));
+        changes.push(change("removed.md", "create", 1));
+        for i in 0..20 {
+            changes.push(change(&format!("note-{}.md", i), "update", 2));
+        }
+        changes.push(change("recreated.md", "delete", 2));
+        changes.push(change("removed.md", "delete", 2));
+        changes.push(change("recreated.md", "create", 3));
+
+        let applied = engine.apply_remote_changes(&vault, changes.iter().collect()).await;
+        assert_eq!(applied, changes.len() as u32);
+
+        for i in 0..20 {
+            let path = format!("note-{}.md", i);
+            assert!(vault.join(&path).exists());
+            let file_state = state_manager.get_file_state_by_id("vault-1", &path).unwrap();
+            assert_eq!(file_state.remote_version, Some(2));
+        }
+        assert!(vault.join("recreated.md").exists());
+        assert_eq!(
+            state_manager.get_file_state_by_id("vault-1", "recreated.md").unwrap().remote_version,
+            Some(3)
+        );
+        assert!(!vault.join("removed.md").exists());
+        assert!(state_manager.get_file_state_by_id("vault-1", "removed.md").is_none());
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_is_applied_before_create_of_same_path() {
+        use super::super::state::MemoryStore;
+        use std::sync::Arc;
+
+        let vault = std::env::temp_dir().join(format!("echopad-engine-{}", uuid::Uuid::new_v4()));
+        let vault_path = vault.to_string_lossy().to_string();
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("renamed.md"), "old").unwrap();
+        fs::write(vault.join("swapped.md"), "old").unwrap();
+
+        let state_manager = Arc::new(SyncStateManager::with_store(Arc::new(MemoryStore::new())));
+        state_manager.enable_vault(&vault_path, "vault-1");
+        for path in ["renamed.md", "swapped.md"] {
+            state_manager.mark_synced_by_id("vault-1", path, &compute_hash(b"old"), 1);
+        }
+
+        let mut engine = SyncEngine::with_state_manager(
+            "http://127.0.0.1:9".to_string(),
+            String::new(),
+            "vault-1".to_string(),
+            vault_path.clone(),
+            Arc::clone(&state_manager),
+        );
+        engine.set_placeholder_mode(true);
+
+        let change = |path: &str, operation: &str, version: i32| RemoteChange {
+            id: path.to_string(),
+            encrypted_path: encode_path(path),
+            operation: operation.to_string(),
+            content_hash: compute_hash(b"new"),
+            version,
+            download_url: Some(format!("/files/{}/download", path)),
+        };
+
+        // The server sent each create ahead of the delete it follows
+        let changes = [
+            change("renamed.md", "create", 3),
+            change("swapped.md", "create", 2),
+            change("renamed.md", "delete", 2),
+            change("swapped.md", "delete", 2),
+        ];
+        let applied = engine.apply_remote_changes(&vault, changes.iter().collect()).await;
+        assert_eq!(applied, changes.len() as u32);
+
+        for (path, version) in [("renamed.md", 3), ("swapped.md", 2)] {
+            assert!(vault.join(path).exists(), "{} should exist", path);
+            let file_state = state_manager.get_file_state_by_id("vault-1", path).unwrap();
+            assert_eq!(file_state.remote_version, Some(version));
+        }
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn test_order_path_changes_puts_deletes_of_other_encodings_first() {
+        let change = |encrypted_path: String, operation: &str, version: i32| RemoteChange {
+            id: format!("{}-{}", encrypted_path, version),
+            encrypted_path,
+            operation: operation.to_string(),
+            content_hash: String::new(),
+            version,
+            download_url: None,
+        };
+        // The bare copy has a much higher version than the prefixed one
+        let changes = [
+            change(encode_path_prefixed("a.md"), "create", 1),
+            change(encode_path("a.md"), "create", 6),
+            change(encode_path("a.md"), "delete", 7),
+            change(encode_path_prefixed("a.md"), "update", 2),
+        ];
+        let mut ordered: Vec<&RemoteChange> = changes.iter().collect();
+        order_path_changes(&mut ordered);
+
+        let order: Vec<(&str, i32)> = ordered.iter().map(|c| (c.operation.as_str(), c.version)).collect();
+        assert_eq!(order, [("create", 6), ("delete", 7), ("create", 1), ("update", 2)]);
+    }
+}
@@ -39,9 +39,17 @@ pub enum SyncError {
     #[error("Network error: {0}")]
     Network(String),
 
+    #[error("Server unreachable: {0}")]
+    Offline(String),
+
     #[error("Server error: {0}")]
     Server(String),
 
+    /// The server refused the request for good (a 4xx other than 408 or
+    /// 429); sending it again won't help
+    #[error("Rejected by the server: {0}")]
+    Rejected(String),
+
     #[error("Rate limited, retry after {0} seconds")]
     RateLimited(u64),
 
@@ -62,6 +70,50 @@ pub enum SyncError {
 
     #[error("Invalid data: {0}")]
     InvalidData(String),
+
+    #[error("File watcher error: {0}")]
+    Watch(String),
+
+    #[error("Too many watched files; on Linux raise the limit with `sudo sysctl fs.inotify.max_user_watches=524288`")]
+    WatchLimitReached,
+}
+
+/// `ENOSPC` from `inotify_add_watch`, i.e. `fs.inotify.max_user_watches` is used up
+#[cfg(target_os = "linux")]
+fn is_watch_limit(err: &std::io::Error) -> bool {
+    err.raw_os_error() == Some(28)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_watch_limit(_err: &std::io::Error) -> bool {
+    false
+}
+
+impl From<notify::Error> for SyncError {
+    fn from(err: notify::Error) -> Self {
+        let paths = err
+            .paths
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match err.kind {
+            notify::ErrorKind::MaxFilesWatch => SyncError::WatchLimitReached,
+            notify::ErrorKind::Io(io) if is_watch_limit(&io) => SyncError::WatchLimitReached,
+            // Keeps NotFound / PermissionDenied distinguishable for callers
+            notify::ErrorKind::Io(io) => SyncError::Io(io),
+            notify::ErrorKind::PathNotFound => SyncError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Path not found: {}", paths),
+            )),
+            notify::ErrorKind::WatchNotFound => SyncError::Watch("Watch not found".to_string()),
+            notify::ErrorKind::InvalidConfig(config) => {
+                SyncError::Watch(format!("Invalid watcher configuration: {:?}", config))
+            }
+            notify::ErrorKind::Generic(message) => SyncError::Watch(message),
+        }
+    }
 }
 
 impl From<SyncError> for String {
@@ -0,0 +1,172 @@
+//! Temporary vaults for tests
+//!
+//! [`VaultFixture::builder`] lays out notebooks, notes and attachments in a
+//! fresh folder under the temp dir and enables sync for it on a
+//! [`SyncStateManager`] backed by a [`MemoryStore`], so engine, scanner and
+//! state tests don't each repeat that setup. Files can be marked as already
+//! synced to start from a vault that has been through a sync. The folder is
+//! removed when the fixture is dropped, also when a test fails.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::engine::SyncEngine;
+use super::scanner::{compute_hash, scan_vault, ScanResult};
+use super::state::{MemoryStore, SyncStateManager};
+
+/// Vault ID fixtures are enabled under
+pub const FIXTURE_VAULT_ID: &str = "vault-1";
+
+/// Server URL that refuses connections, for engines that must stay offline
+pub const OFFLINE_SERVER_URL: &str = "http://127.0.0.1:9";
+
+/// Describes the vault to create; see [`VaultFixture::builder`]
+pub struct VaultFixtureBuilder {
+    notebooks: Vec<String>,
+    files: Vec<(String, Vec<u8>)>,
+    synced: Vec<String>,
+}
+
+impl VaultFixtureBuilder {
+    /// An empty notebook (folder), parents included
+    pub fn notebook(mut self, relative_path: &str) -> Self {
+        self.notebooks.push(relative_path.to_string());
+        self
+    }
+
+    /// A note, creating its notebook as needed
+    pub fn note(mut self, relative_path: &str, content: &str) -> Self {
+        self.files.push((relative_path.to_string(), content.as_bytes().to_vec()));
+        self
+    }
+
+    /// A binary file, e.g. `attachments/photo.png`
+    pub fn attachment(mut self, relative_path: &str, bytes: &[u8]) -> Self {
+        self.files.push((relative_path.to_string(), bytes.to_vec()));
+        self
+    }
+
+    /// A note that was synced at version 1 and hasn't changed since
+    pub fn synced_note(self, relative_path: &str, content: &str) -> Self {
+        let mut builder = self.note(relative_path, content);
+        builder.synced.push(relative_path.to_string());
+        builder
+    }
+
+    /// Create the folder and enable sync for it
+    pub fn build(self) -> VaultFixture {
+        let root = std::env::temp_dir().join(format!("echopad-fixture-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        let fixture = VaultFixture {
+            vault_path: root.to_string_lossy().to_string(),
+            root,
+            vault_id: FIXTURE_VAULT_ID.to_string(),
+            state_manager: Arc::new(SyncStateManager::with_store(Arc::new(MemoryStore::new()))),
+        };
+        fixture.state_manager.enable_vault(&fixture.vault_path, &fixture.vault_id);
+
+        for notebook in &self.notebooks {
+            fs::create_dir_all(fixture.path(notebook)).unwrap();
+        }
+        for (relative_path, bytes) in &self.files {
+            fixture.write(relative_path, bytes);
+        }
+        for relative_path in &self.synced {
+            fixture.mark_synced(relative_path, 1);
+        }
+        fixture
+    }
+}
+
+/// A vault folder with sync enabled on its own state manager
+pub struct VaultFixture {
+    /// The vault folder
+    pub root: PathBuf,
+    /// `root` as the string commands and the state manager take
+    pub vault_path: String,
+    pub vault_id: String,
+    pub state_manager: Arc<SyncStateManager>,
+}
+
+impl VaultFixture {
+    pub fn builder() -> VaultFixtureBuilder {
+        VaultFixtureBuilder {
+            notebooks: Vec::new(),
+            files: Vec::new(),
+            synced: Vec::new(),
+        }
+    }
+
+    /// Absolute path of a vault-relative path
+    pub fn path(&self, relative_path: &str) -> PathBuf {
+        self.root.join(relative_path)
+    }
+
+    /// Create or replace a file, creating its folders as needed
+    pub fn write(&self, relative_path: impl AsRef<Path>, contents: impl AsRef<[u8]>) {
+        let path = self.root.join(relative_path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn exists(&self, relative_path: &str) -> bool {
+        self.path(relative_path).exists()
+    }
+
+    /// Delete a file or a whole notebook
+    pub fn remove(&self, relative_path: &str) {
+        let path = self.path(relative_path);
+        if path.is_dir() {
+            fs::remove_dir_all(path).unwrap();
+        } else {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    /// Record the file's current content as synced at `version`
+    pub fn mark_synced(&self, relative_path: &str, version: u32) {
+        let hash = compute_hash(&fs::read(self.path(relative_path)).unwrap());
+        self.state_manager.mark_synced_by_id(&self.vault_id, relative_path, &hash, version);
+    }
+
+    pub fn scan(&self) -> ScanResult {
+        scan_vault(&self.root).unwrap()
+    }
+
+    /// An engine for this vault that shares the fixture's state manager and
+    /// can't reach a server
+    pub fn engine(&self) -> SyncEngine {
+        SyncEngine::with_state_manager(
+            OFFLINE_SERVER_URL.to_string(),
+            String::new(),
+            self.vault_id.clone(),
+            self.vault_path.clone(),
+            Arc::clone(&self.state_manager),
+        )
+    }
+
+    /// Assert the file on disk is recorded as synced at `version`
+    pub fn assert_synced(&self, relative_path: &str, version: u32) {
+        let state = self
+            .state_manager
+            .get_file_state_by_id(&self.vault_id, relative_path)
+            .unwrap_or_else(|| panic!("{} has no sync state", relative_path));
+        let hash = compute_hash(&fs::read(self.path(relative_path)).unwrap());
+        assert_eq!(state.local_hash.as_deref(), Some(hash.as_str()), "{} changed since its sync", relative_path);
+        assert_eq!(state.remote_version, Some(version), "{} version", relative_path);
+    }
+
+    /// Assert how many local changes the next sync would push
+    pub fn assert_pending(&self, count: u32) {
+        assert_eq!(self.state_manager.count_pending_changes(&self.vault_path), count, "pending changes");
+    }
+}
+
+impl Drop for VaultFixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
@@ -0,0 +1,105 @@
+//! Quick reachability check of the sync server
+//!
+//! Against a server that is down, every pull and push request waits out the
+//! full request timeout, so a sync could hang for tens of seconds before
+//! failing. A sync first asks `/health` with a short timeout and gives up
+//! right away when the server can't be reached. The answer is kept briefly
+//! so syncing several vaults in a row probes once.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use reqwest::Client;
+
+use super::client::normalize_server_url;
+use super::error::{SyncError, SyncResult};
+use super::proxy;
+
+/// How long the probe waits for any answer
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a probe result is reused
+pub const PROBE_CACHE_TTL: Duration = Duration::from_secs(10);
+
+struct ProbeResult {
+    server_url: String,
+    checked_at: Instant,
+    error: Option<String>,
+}
+
+/// Latest probe result, shared by every sync of the session
+#[derive(Default)]
+pub struct ServerProbe {
+    last: Mutex<Option<ProbeResult>>,
+}
+
+impl ServerProbe {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn cached(&self, server_url: &str) -> Option<Option<String>> {
+        self.last
+            .lock()
+            .as_ref()
+            .filter(|last| last.server_url == server_url && last.checked_at.elapsed() < PROBE_CACHE_TTL)
+            .map(|last| last.error.clone())
+    }
+
+    /// Fail with [`SyncError::Offline`] when the server can't be reached.
+    /// Any HTTP answer counts as reachable, so servers without a `/health`
+    /// route aren't mistaken for offline ones.
+    pub async fn check(&self, client: &Client, server_url: &str) -> SyncResult<()> {
+        let error = match self.cached(server_url) {
+            Some(error) => error,
+            None => {
+                let url = format!("{}/health", normalize_server_url(server_url));
+                let error = client
+                    .get(&url)
+                    .timeout(PROBE_TIMEOUT)
+                    .send()
+                    .await
+                    .err()
+                    .map(|e| proxy::describe_error(&e));
+                *self.last.lock() = Some(ProbeResult {
+                    server_url: server_url.to_string(),
+                    checked_at: Instant::now(),
+                    error: error.clone(),
+                });
+                error
+            }
+        };
+
+        match error {
+            Some(error) => Err(SyncError::Offline(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Forget the cached result, e.g. after the network came back
+    pub fn reset(&self) {
+        *self.last.lock() = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unreachable_server_fails_fast_and_is_cached() {
+        // Nothing listens on port 1
+        let server_url = "http://127.0.0.1:1";
+        let probe = ServerProbe::new();
+        let client = Client::new();
+
+        let started = Instant::now();
+        assert!(matches!(probe.check(&client, server_url).await, Err(SyncError::Offline(_))));
+        assert!(started.elapsed() < PROBE_TIMEOUT + Duration::from_secs(1));
+        assert!(matches!(probe.cached(server_url), Some(Some(_))));
+        assert_eq!(probe.cached("http://127.0.0.1:2"), None);
+
+        probe.reset();
+        assert_eq!(probe.cached(server_url), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Per-vault sync serialization
+//!
+//! Two engine runs on the same vault race on file state and uploads, so
+//! every sync of a vault runs under that vault's lock. A sync requested while
+//! another is in flight waits for it to finish and then runs, which also
+//! picks up anything changed during the first run. Different vaults still
+//! sync concurrently.
+
+#![allow(dead_code)]
+
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+
+/// Async locks keyed by vault_id
+#[derive(Debug, Default)]
+pub struct SyncLocks {
+    locks: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl SyncLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock_for(&self, vault_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        Arc::clone(self.locks.lock().entry(vault_id.to_string()).or_default())
+    }
+
+    /// Whether a sync of the vault is currently running
+    pub fn is_syncing(&self, vault_id: &str) -> bool {
+        self.locks
+            .lock()
+            .get(vault_id)
+            .is_some_and(|lock| lock.try_lock().is_err())
+    }
+
+    /// Whether a sync of any vault is currently running
+    pub fn any_syncing(&self) -> bool {
+        self.locks.lock().values().any(|lock| lock.try_lock().is_err())
+    }
+
+    /// Wait for running syncs of `vault_ids` to finish and keep new ones
+    /// from starting until the guards are dropped
+    pub async fn hold_all(&self, vault_ids: &[String]) -> Vec<tokio::sync::OwnedMutexGuard<()>> {
+        let mut vault_ids = vault_ids.to_vec();
+        vault_ids.sort();
+        vault_ids.dedup();
+        let mut guards = Vec::new();
+        for vault_id in &vault_ids {
+            guards.push(self.lock_for(vault_id).lock_owned().await);
+        }
+        guards
+    }
+
+    /// Run `task` once no other sync of the same vault is in progress
+    pub async fn run<F, T>(&self, vault_id: &str, task: F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let lock = self.lock_for(vault_id);
+        if lock.try_lock().is_err() {
+            println!("[Sync] Sync of {} already in progress, waiting", vault_id);
+        }
+        let _guard = lock.lock().await;
+        task.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    /// Simulated sync that records how many runs overlap
+    async fn fake_sync(active: &AtomicU32, max_active: &AtomicU32) {
+        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+        max_active.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_syncs_of_one_vault_are_serialized() {
+        let locks = SyncLocks::new();
+        let (active, max_active) = (AtomicU32::new(0), AtomicU32::new(0));
+
+        tokio::join!(
+            locks.run("vault-1", fake_sync(&active, &max_active)),
+            locks.run("vault-1", fake_sync(&active, &max_active)),
+        );
+
+        assert_eq!(max_active.load(Ordering::SeqCst), 1);
+        assert!(!locks.is_syncing("vault-1"));
+        assert!(!locks.any_syncing());
+    }
+
+    #[tokio::test]
+    async fn test_different_vaults_sync_concurrently() {
+        let locks = SyncLocks::new();
+        let (active, max_active) = (AtomicU32::new(0), AtomicU32::new(0));
+
+        tokio::join!(
+            locks.run("vault-1", fake_sync(&active, &max_active)),
+            locks.run("vault-2", fake_sync(&active, &max_active)),
+        );
+
+        assert_eq!(max_active.load(Ordering::SeqCst), 2);
+    }
+}
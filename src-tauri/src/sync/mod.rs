@@ -15,6 +15,14 @@ pub mod types;
 pub mod commands;
 pub mod scanner;
 pub mod engine;
+pub mod lock;
+pub mod scheduler;
+pub mod proxy;
+pub mod health;
+pub mod rate_limit;
+pub mod data_dir;
+#[cfg(test)]
+pub mod fixture;
 
 pub use commands::SyncState;
 
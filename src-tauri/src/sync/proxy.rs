@@ -0,0 +1,188 @@
+//! HTTP proxy settings for sync
+//!
+//! Without an override, reqwest clients already honor the `HTTP_PROXY`,
+//! `HTTPS_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables. An
+//! override is kept per server URL, so an account on a self-hosted server
+//! inside the corporate network can go direct while the managed backend goes
+//! through the proxy.
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::client::normalize_server_url;
+use super::error::{SyncError, SyncResult};
+
+/// How long to wait for a connection, directly or to the proxy
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Message for a `407 Proxy Authentication Required` answer
+pub const PROXY_AUTH_REQUIRED: &str =
+    "The proxy requires authentication; set a proxy username and password";
+
+/// Proxy override for one server
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProxySettings {
+    /// Proxy URL such as `http://proxy.corp:3128`, used for every request
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Never returned by `sync_get_proxy`
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl ProxySettings {
+    fn to_proxy(&self) -> SyncResult<reqwest::Proxy> {
+        let proxy = reqwest::Proxy::all(&self.url)
+            .map_err(|e| SyncError::InvalidData(format!("Invalid proxy URL {}: {}", self.url, e)))?;
+        Ok(match &self.username {
+            Some(username) => proxy.basic_auth(username, self.password.as_deref().unwrap_or("")),
+            None => proxy,
+        })
+    }
+}
+
+/// Proxy overrides keyed by server URL, persisted as JSON in the sync data
+/// directory
+pub struct ProxyConfig {
+    path: RwLock<PathBuf>,
+    overrides: RwLock<HashMap<String, ProxySettings>>,
+}
+
+fn server_key(server_url: &str) -> String {
+    normalize_server_url(server_url)
+}
+
+impl ProxyConfig {
+    pub fn load(path: PathBuf) -> Self {
+        let overrides = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path: RwLock::new(path),
+            overrides: RwLock::new(overrides),
+        }
+    }
+
+    /// File the overrides are saved to
+    pub fn path(&self) -> PathBuf {
+        self.path.read().clone()
+    }
+
+    /// Save overrides to `path` from now on
+    pub fn set_path(&self, path: PathBuf) {
+        *self.path.write() = path;
+    }
+
+    pub fn get(&self, server_url: &str) -> Option<ProxySettings> {
+        self.overrides.read().get(&server_key(server_url)).cloned()
+    }
+
+    /// Set or clear (`None`) the override for a server. A missing password
+    /// keeps the stored one when the username is unchanged.
+    pub fn set(&self, server_url: &str, settings: Option<ProxySettings>) -> SyncResult<()> {
+        let key = server_key(server_url);
+        let mut overrides = self.overrides.write();
+        match settings {
+            Some(mut settings) => {
+                settings.to_proxy()?;
+                if settings.password.is_none() {
+                    settings.password = overrides
+                        .get(&key)
+                        .filter(|old| old.username == settings.username)
+                        .and_then(|old| old.password.clone());
+                }
+                overrides.insert(key, settings);
+            }
+            None => {
+                overrides.remove(&key);
+            }
+        }
+
+        let json = serde_json::to_string_pretty(&*overrides)?;
+        fs::write(self.path(), json)?;
+        Ok(())
+    }
+
+    /// The override proxy for a server, if one is set
+    pub fn proxy_for(&self, server_url: Option<&str>) -> SyncResult<Option<reqwest::Proxy>> {
+        server_url
+            .and_then(|url| self.get(url))
+            .map(|settings| settings.to_proxy())
+            .transpose()
+    }
+
+    /// Client builder for talking to `server_url`, with its proxy applied
+    pub fn client_builder(&self, server_url: Option<&str>) -> SyncResult<reqwest::ClientBuilder> {
+        let builder = reqwest::Client::builder().connect_timeout(CONNECT_TIMEOUT);
+        Ok(match self.proxy_for(server_url)? {
+            Some(proxy) => builder.proxy(proxy),
+            None => builder,
+        })
+    }
+
+    /// HTTP client for talking to `server_url`
+    pub fn client(&self, server_url: Option<&str>) -> SyncResult<reqwest::Client> {
+        self.client_builder(server_url)?
+            .build()
+            .map_err(|e| SyncError::Network(format!("Failed to create HTTP client: {}", e)))
+    }
+}
+
+/// Describe a failed request, calling out a proxy that refused the
+/// connection instead of leaving a bare "error sending request"
+pub fn describe_error(e: &reqwest::Error) -> String {
+    let mut chain = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(err) = source {
+        chain.push_str(": ");
+        chain.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    let lower = chain.to_lowercase();
+    if e.is_connect() && (lower.contains("proxy") || lower.contains("tunnel")) {
+        format!(
+            "Couldn't connect through the proxy; check the proxy address and credentials ({})",
+            chain
+        )
+    } else {
+        chain
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_is_per_server_and_keeps_password() {
+        let path = std::env::temp_dir().join(format!("echopad-proxy-{}.json", uuid::Uuid::new_v4()));
+        let config = ProxyConfig::load(path.clone());
+        let settings = ProxySettings {
+            url: "http://proxy.corp:3128".to_string(),
+            username: Some("alice".to_string()),
+            password: Some("secret".to_string()),
+        };
+        config.set("https://sync.example.com/", Some(settings.clone())).unwrap();
+        config
+            .set("https://sync.example.com", Some(ProxySettings { password: None, ..settings.clone() }))
+            .unwrap();
+
+        let reloaded = ProxyConfig::load(path.clone());
+        assert_eq!(reloaded.get("https://sync.example.com"), Some(settings));
+        assert_eq!(reloaded.get("https://self-hosted.corp"), None);
+        assert!(config.set("https://sync.example.com", Some(ProxySettings {
+            url: "not a url".to_string(),
+            username: None,
+            password: None,
+        })).is_err());
+
+        let _ = fs::remove_file(path);
+    }
+}
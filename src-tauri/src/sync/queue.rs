@@ -42,21 +42,16 @@ impl From<ChangeOperation> for QueueOperation {
 }
 
 /// Priority levels for queue items
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum Priority {
     Low = 0,
+    #[default]
     Normal = 1,
     High = 2,
     Critical = 3,
 }
 
-impl Default for Priority {
-    fn default() -> Self {
-        Self::Normal
-    }
-}
-
 /// Sync queue for managing pending operations
 pub struct SyncQueue {
     /// Upload queue
@@ -261,8 +256,6 @@ impl SyncQueue {
 
     /// Retry failed items
     pub fn retry_failed(&self) {
-        let now = Self::now();
-
         {
             let mut queue = self.upload_queue.write();
             for item in queue.iter_mut() {
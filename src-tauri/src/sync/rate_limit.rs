@@ -0,0 +1,110 @@
+//! App-wide hold after the server rate-limits a request
+//!
+//! When one request is answered with `429 Too Many Requests`, every other
+//! request (other vaults' syncs, device lists, usage fetches, ...) would hit
+//! the limiter too. The `Retry-After` deadline is shared instead: requests
+//! wait for it before going out, or fail right away when it is too far off.
+
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use reqwest::header::HeaderMap;
+
+use super::error::{SyncError, SyncResult};
+
+/// Wait used when a 429 has no usable `Retry-After`
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+/// Longest a request waits for the hold to end; further off, it fails with
+/// [`SyncError::RateLimited`] instead of blocking its command
+pub const MAX_HOLD_WAIT: Duration = Duration::from_secs(30);
+
+/// Seconds to wait from a 429 response's `Retry-After` header
+pub fn retry_after_secs(headers: &HeaderMap) -> u64 {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(DEFAULT_RETRY_AFTER_SECS)
+}
+
+/// Deadline until which no request should reach the server
+#[derive(Default)]
+pub struct RateLimitHold {
+    until: Mutex<Option<Instant>>,
+}
+
+impl RateLimitHold {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold requests for `retry_after_secs`; an existing later deadline is
+    /// kept
+    pub fn hold(&self, retry_after_secs: u64) {
+        let until = Instant::now() + Duration::from_secs(retry_after_secs);
+        let mut current = self.until.lock();
+        if current.map_or(true, |current| current < until) {
+            println!("[Sync] Rate limited, holding requests for {}s", retry_after_secs);
+            *current = Some(until);
+        }
+    }
+
+    /// Time left on the hold, clearing it once it has passed
+    pub fn remaining(&self) -> Option<Duration> {
+        let mut until = self.until.lock();
+        let remaining = until.and_then(|until| until.checked_duration_since(Instant::now()));
+        if remaining.is_none() {
+            *until = None;
+        }
+        remaining.filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Wait out the hold before sending a request. Fails with
+    /// [`SyncError::RateLimited`] when more than [`MAX_HOLD_WAIT`] is left.
+    pub async fn wait(&self) -> SyncResult<()> {
+        let Some(remaining) = self.remaining() else {
+            return Ok(());
+        };
+        if remaining > MAX_HOLD_WAIT {
+            return Err(SyncError::RateLimited(remaining.as_secs_f64().ceil() as u64));
+        }
+        tokio::time::sleep(remaining).await;
+        Ok(())
+    }
+
+    /// Record the hold an error calls for, if it is a rate limit
+    pub fn observe<T>(&self, result: &SyncResult<T>) {
+        if let Err(SyncError::RateLimited(secs)) = result {
+            self.hold(*secs);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hold_is_shared_and_expires() {
+        let hold = RateLimitHold::new();
+        assert!(hold.remaining().is_none());
+        assert!(hold.wait().await.is_ok());
+
+        hold.hold(120);
+        assert!(matches!(hold.wait().await, Err(SyncError::RateLimited(secs)) if secs == 120));
+        // A shorter Retry-After doesn't cut the hold short
+        hold.hold(0);
+        assert!(hold.remaining().is_some_and(|left| left > MAX_HOLD_WAIT));
+
+        let short = RateLimitHold::new();
+        short.hold(0);
+        assert!(short.remaining().is_none());
+        assert!(short.until.lock().is_none());
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after_secs(&headers), DEFAULT_RETRY_AFTER_SECS);
+        headers.insert(reqwest::header::RETRY_AFTER, "7".parse().unwrap());
+        assert_eq!(retry_after_secs(&headers), 7);
+    }
+}
@@ -2,11 +2,13 @@
 
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use blake3::Hasher;
 use serde::{Deserialize, Serialize};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
 
 use super::error::{SyncError, SyncResult};
 
@@ -17,14 +19,61 @@ pub struct FileInfo {
     pub relative_path: String,
     /// BLAKE3 hash of file content
     pub content_hash: String,
+    /// Hash of a note's body without its frontmatter (`None` for other files)
+    #[serde(default)]
+    pub body_hash: Option<String>,
     /// File size in bytes
     pub size_bytes: u64,
     /// Last modification time (Unix timestamp ms)
     pub modified_at: u64,
 }
 
+/// A file or directory the scanner could not read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanError {
+    /// Relative path from vault root
+    pub relative_path: String,
+    /// Why it could not be read (permission denied, locked, ...)
+    pub error: String,
+}
+
+/// A file left out of sync because it exceeds the size limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedFile {
+    /// Relative path from vault root
+    pub relative_path: String,
+    /// File size in bytes
+    pub size_bytes: u64,
+}
+
+/// Reported for files and folders whose name isn't valid Unicode. Paths are
+/// sent to the server as UTF-8, so such a name can't round-trip to other
+/// devices (or even back to this one).
+pub const NON_UNICODE_NAME_ERROR: &str = "Name is not valid Unicode and can't be synced";
+
+/// Reported for symlinks whose target is scanned and uploaded as a file
+pub const SYMLINK_FOLLOWED_NOTE: &str = "symlink — content synced as a copy";
+
+/// Reported for symlinks left out because the vault doesn't follow them
+pub const SYMLINK_SKIPPED_NOTE: &str = "symlink — skipped";
+
+/// A symlinked file or directory found in the vault. The server has no
+/// notion of links, so a followed link syncs as a plain copy of its target:
+/// other devices get independent files, and edits there don't reach the
+/// link target here until they are pulled back through the link.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SymlinkEntry {
+    /// Relative path of the link from vault root
+    pub relative_path: String,
+    /// Where the link points, as stored in the link
+    pub target: String,
+    pub followed: bool,
+    /// [`SYMLINK_FOLLOWED_NOTE`] or [`SYMLINK_SKIPPED_NOTE`]
+    pub note: String,
+}
+
 /// Result of scanning a vault
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ScanResult {
     /// All files found in the vault
     pub files: HashMap<String, FileInfo>,
@@ -32,8 +81,59 @@ pub struct ScanResult {
     pub total_size: u64,
     /// Number of files scanned
     pub file_count: usize,
+    /// Entries that were skipped because they could not be read
+    pub errors: Vec<ScanError>,
+    /// Files that were skipped because they are larger than the size limit
+    pub skipped: Vec<SkippedFile>,
+    /// Files left out because their extension isn't allowed by the server
+    pub excluded: Vec<String>,
+    /// Symlinked files and directories, followed or not
+    pub symlinks: Vec<SymlinkEntry>,
+}
+
+impl ScanResult {
+    /// Whether a path may exist locally but was left out of the scan for
+    /// being too large, having an excluded extension, or sitting in or under
+    /// an entry that couldn't be read. Such files must not be treated as
+    /// deleted.
+    pub fn is_skipped(&self, relative_path: &str) -> bool {
+        self.skipped.iter().any(|f| f.relative_path == relative_path)
+            || self.excluded.iter().any(|p| p == relative_path)
+            || self.errors.iter().any(|e| is_within(relative_path, &e.relative_path))
+            || self
+                .symlinks
+                .iter()
+                .any(|link| !link.followed && is_within(relative_path, &link.relative_path))
+    }
+
+    /// Leave out files whose extension is not in `allowed` (case-insensitive)
+    pub fn retain_extensions(&mut self, allowed: &[String]) {
+        let excluded: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| {
+                let ext = Path::new(path)
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_lowercase())
+                    .unwrap_or_default();
+                !allowed.iter().any(|a| a.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+            })
+            .cloned()
+            .collect();
+
+        for path in excluded {
+            if let Some(info) = self.files.remove(&path) {
+                self.total_size -= info.size_bytes;
+                self.excluded.push(path);
+            }
+        }
+        self.file_count = self.files.len();
+    }
 }
 
+/// Default maximum size of a synced file (100 MB)
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 100 * 1024 * 1024;
+
 /// File extensions to sync (markdown and common attachments)
 const SYNC_EXTENSIONS: &[&str] = &[
     "md", "markdown", "txt", "png", "jpg", "jpeg", "gif", "webp", "svg", "pdf", "json", "yaml",
@@ -43,25 +143,209 @@ const SYNC_EXTENSIONS: &[&str] = &[
 /// Directories to skip
 const SKIP_DIRS: &[&str] = &[".git", ".obsidian", ".trash", "node_modules", ".sync"];
 
+/// Skipped directories that can't be opted back into sync: the vault trash
+/// and sync's own bookkeeping
+const ALWAYS_SKIPPED_DIRS: &[&str] = &[".trash", ".sync"];
+
+/// Check a directory name for a vault's include list (see
+/// [`scan_vault_including`]). It must be a single path component that the
+/// scanner would otherwise skip.
+pub fn validate_include_dir(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(format!("{:?} is not a directory name", name));
+    }
+    if ALWAYS_SKIPPED_DIRS.contains(&name) {
+        return Err(format!("{} can't be synced", name));
+    }
+    if !name.starts_with('.') && !SKIP_DIRS.contains(&name) {
+        return Err(format!("{} is already synced", name));
+    }
+    Ok(())
+}
+
+/// Whether `relative_path` is `entry` itself or lies under it
+fn is_within(relative_path: &str, entry: &str) -> bool {
+    relative_path
+        .strip_prefix(entry)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Whether a directory is left out of sync, given the vault's include list
+fn is_skipped_dir(name: &str, include_dirs: &[String]) -> bool {
+    (name.starts_with('.') || SKIP_DIRS.contains(&name))
+        && (ALWAYS_SKIPPED_DIRS.contains(&name) || !include_dirs.iter().any(|d| d == name))
+}
+
+/// Whether a vault-relative path (`/`-separated) is one the scanner would
+/// sync: plain components only (no absolute paths or `..`), no hidden or
+/// skipped directories, and a synced extension. Used to vet paths coming
+/// from the server before anything is written. `include_dirs` are the
+/// normally skipped directories the vault opted into sync.
+pub fn is_syncable_path(relative_path: &str, include_dirs: &[String]) -> bool {
+    use std::path::Component;
+
+    let path = Path::new(relative_path);
+    if relative_path.is_empty()
+        || relative_path.contains('\\')
+        || !path.components().all(|c| matches!(c, Component::Normal(_)))
+    {
+        return false;
+    }
+
+    let mut parts: Vec<&str> = relative_path.split('/').collect();
+    let file_name = parts.pop().unwrap_or_default();
+    if parts
+        .iter()
+        .any(|dir| dir.is_empty() || is_skipped_dir(dir, include_dirs))
+    {
+        return false;
+    }
+    if file_name.starts_with('.') && !file_name.ends_with(".md") {
+        return false;
+    }
+
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| SYNC_EXTENSIONS.contains(&ext.as_str()))
+}
+
 /// Scan a vault directory and return information about all syncable files
 pub fn scan_vault(vault_path: &Path) -> SyncResult<ScanResult> {
-    let mut files = HashMap::new();
-    let mut total_size = 0u64;
+    scan_vault_with_limit(vault_path, DEFAULT_MAX_FILE_SIZE)
+}
 
-    scan_directory(vault_path, vault_path, &mut files, &mut total_size)?;
+/// Scan a vault, skipping (and reporting) files larger than `max_file_size`
+/// bytes without reading them
+pub fn scan_vault_with_limit(vault_path: &Path, max_file_size: u64) -> SyncResult<ScanResult> {
+    scan_vault_including(vault_path, max_file_size, &[], true, false)
+}
 
-    Ok(ScanResult {
-        file_count: files.len(),
-        files,
-        total_size,
-    })
+/// Scan a vault with a size limit, also descending into the normally
+/// skipped directories named in `include_dirs` (e.g. `.obsidian`). Symlinks
+/// are followed, and their targets synced as copies, only with
+/// `follow_symlinks`; either way they are listed in
+/// [`ScanResult::symlinks`]. Notes get a [`FileInfo::body_hash`] only with
+/// `body_hashes`, as that costs a second hash of every note.
+pub fn scan_vault_including(
+    vault_path: &Path,
+    max_file_size: u64,
+    include_dirs: &[String],
+    follow_symlinks: bool,
+    body_hashes: bool,
+) -> SyncResult<ScanResult> {
+    let mut result = ScanResult::default();
+
+    let root = crate::vault::long_path(vault_path);
+    let options = WalkOptions {
+        max_file_size,
+        include_dirs,
+        follow_symlinks,
+        body_hashes,
+    };
+    scan_directory(&root, &root, &options, &mut result)?;
+
+    result.file_count = result.files.len();
+    Ok(result)
+}
+
+/// Normalize a vault-relative path to Unicode NFC. macOS hands out
+/// decomposed (NFD) file names while Linux and Windows keep names as
+/// written, so state and the server are keyed by the composed form to keep
+/// one logical name stable across platforms.
+pub fn normalize_path(relative_path: &str) -> String {
+    if is_nfc(relative_path) {
+        relative_path.to_string()
+    } else {
+        relative_path.nfc().collect()
+    }
+}
+
+/// Find the file on disk for a normalized vault-relative path, matching
+/// path components stored in another normalization form (e.g. an NFD name
+/// copied onto Linux). Falls back to the plain join when nothing matches,
+/// which is also where a new file should be created.
+pub fn resolve_local_path(vault_path: &Path, relative_path: &str) -> PathBuf {
+    let direct = vault_path.join(relative_path);
+    if direct.exists() {
+        return direct;
+    }
+
+    let mut current = vault_path.to_path_buf();
+    for component in relative_path.split('/') {
+        let candidate = current.join(component);
+        if candidate.exists() {
+            current = candidate;
+            continue;
+        }
+        let on_disk = fs::read_dir(crate::vault::long_path(&current)).ok().and_then(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name())
+                .find(|name| normalize_path(&name.to_string_lossy()) == component)
+        });
+        match on_disk {
+            Some(name) => current = current.join(name),
+            None => return direct,
+        }
+    }
+    current
+}
+
+/// Relative, `/`-separated, NFC form of a path for keys and reporting
+fn relative_display(root: &Path, path: &Path) -> String {
+    normalize_path(
+        &path.strip_prefix(root)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .replace('\\', "/"),
+    )
+}
+
+/// Settings shared by every level of a vault scan
+struct WalkOptions<'a> {
+    max_file_size: u64,
+    include_dirs: &'a [String],
+    follow_symlinks: bool,
+    body_hashes: bool,
+}
+
+/// Record a symlink the scan came across. Returns whether to scan through it.
+fn visit_symlink(root: &Path, current: &Path, path: &Path, follow: bool, result: &mut ScanResult) -> bool {
+    let relative_path = relative_display(root, path);
+    let target = fs::read_link(path)
+        .map(|target| target.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    // A directory link to one of its own ancestors would recurse forever
+    if follow && path.is_dir() {
+        let looping = match (fs::canonicalize(path), fs::canonicalize(current)) {
+            (Ok(target), Ok(current)) => current.starts_with(target),
+            _ => false,
+        };
+        if looping {
+            result.errors.push(ScanError {
+                relative_path,
+                error: format!("Symlink to {} loops back into the vault", target),
+            });
+            return false;
+        }
+    }
+
+    let note = if follow { SYMLINK_FOLLOWED_NOTE } else { SYMLINK_SKIPPED_NOTE };
+    result.symlinks.push(SymlinkEntry {
+        relative_path,
+        target,
+        followed: follow,
+        note: note.to_string(),
+    });
+    follow
 }
 
 fn scan_directory(
     root: &Path,
     current: &Path,
-    files: &mut HashMap<String, FileInfo>,
-    total_size: &mut u64,
+    options: &WalkOptions,
+    result: &mut ScanResult,
 ) -> SyncResult<()> {
     let entries = fs::read_dir(current).map_err(|e| {
         SyncError::Io(std::io::Error::new(
@@ -71,23 +355,56 @@ fn scan_directory(
     })?;
 
     for entry in entries {
-        let entry = entry.map_err(|e| SyncError::Io(e))?;
+        let entry = entry.map_err(SyncError::Io)?;
         let path = entry.path();
         let file_name = entry.file_name();
         let file_name_str = file_name.to_string_lossy();
-
-        // Skip hidden files and directories (except .md files)
-        if file_name_str.starts_with('.') && !file_name_str.ends_with(".md") {
+        let Ok(file_type) = entry.file_type() else {
             continue;
-        }
+        };
+        let is_symlink = file_type.is_symlink();
+        // Links are scanned as what they point to
+        let target_type = if is_symlink {
+            match fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(_) => {
+                    eprintln!("[Scanner] Skipping broken symlink {:?}", path);
+                    continue;
+                }
+            }
+        } else {
+            file_type
+        };
 
-        if path.is_dir() {
-            // Skip certain directories
-            if SKIP_DIRS.contains(&file_name_str.as_ref()) {
+        if target_type.is_dir() {
+            // Skip hidden and certain directories unless the vault opted in
+            if is_skipped_dir(&file_name_str, options.include_dirs) {
+                continue;
+            }
+            if skip_non_unicode_name(root, &path, result) {
                 continue;
             }
-            scan_directory(root, &path, files, total_size)?;
-        } else if path.is_file() {
+            if is_symlink && !visit_symlink(root, current, &path, options.follow_symlinks, result) {
+                continue;
+            }
+            // An unreadable subdirectory shouldn't abort the whole scan
+            match scan_directory(root, &path, options, result) {
+                Ok(()) => {}
+                Err(e) if vanished(&e) => eprintln!("[Scanner] {:?} was removed during the scan", path),
+                Err(e) => {
+                    eprintln!("[Scanner] Failed to read directory {:?}: {}", path, e);
+                    result.errors.push(ScanError {
+                        relative_path: relative_display(root, &path),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        } else if target_type.is_file() {
+            // Skip hidden files (except .md files)
+            if file_name_str.starts_with('.') && !file_name_str.ends_with(".md") {
+                continue;
+            }
+
             // Check if file extension should be synced
             if let Some(ext) = path.extension() {
                 let ext_str = ext.to_string_lossy().to_lowercase();
@@ -98,35 +415,118 @@ fn scan_directory(
                 // Skip files without extensions
                 continue;
             }
+            if skip_non_unicode_name(root, &path, result) {
+                continue;
+            }
 
-            // Get file info
-            match get_file_info(root, &path) {
-                Ok(file_info) => {
-                    *total_size += file_info.size_bytes;
-                    files.insert(file_info.relative_path.clone(), file_info);
-                }
-                Err(e) => {
-                    eprintln!("[Scanner] Failed to read file {:?}: {}", path, e);
+            if is_symlink && !visit_symlink(root, current, &path, options.follow_symlinks, result) {
+                continue;
+            }
+
+            // Skip oversized files before reading them (following links)
+            if let Ok(metadata) = fs::metadata(&path) {
+                if metadata.len() > options.max_file_size {
+                    eprintln!(
+                        "[Scanner] Skipping {:?}: {} bytes exceeds the {} byte limit",
+                        path,
+                        metadata.len(),
+                        options.max_file_size
+                    );
+                    result.skipped.push(SkippedFile {
+                        relative_path: relative_display(root, &path),
+                        size_bytes: metadata.len(),
+                    });
+                    continue;
                 }
             }
+
+            scan_file(root, &path, options.body_hashes, result);
+        } else {
+            // Named pipes, sockets and devices have no content to sync, and
+            // reading a pipe would block the scan
+            eprintln!("[Scanner] Skipping special file {:?}", path);
         }
     }
 
     Ok(())
 }
 
+/// Report and skip an entry whose name isn't valid Unicode. Its lossy form
+/// would be uploaded under a name that matches nothing on disk.
+fn skip_non_unicode_name(root: &Path, path: &Path, result: &mut ScanResult) -> bool {
+    if path.file_name().and_then(|name| name.to_str()).is_some() {
+        return false;
+    }
+    eprintln!("[Scanner] Skipping {:?}: {}", path, NON_UNICODE_NAME_ERROR);
+    result.errors.push(ScanError {
+        relative_path: relative_display(root, path),
+        error: NON_UNICODE_NAME_ERROR.to_string(),
+    });
+    true
+}
+
+/// Whether an error means the entry was deleted after it was listed. The
+/// vault can change while it's being scanned; such entries are simply not
+/// part of the result, unlike unreadable ones which are reported.
+fn vanished(error: &SyncError) -> bool {
+    matches!(error, SyncError::Io(e) if e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Hash one file into the result
+fn scan_file(root: &Path, path: &Path, body_hashes: bool, result: &mut ScanResult) {
+    match get_file_info(root, path, body_hashes) {
+        Ok(file_info) => {
+            result.total_size += file_info.size_bytes;
+            result.files.insert(file_info.relative_path.clone(), file_info);
+        }
+        Err(e) if vanished(&e) => {
+            eprintln!("[Scanner] {:?} was removed during the scan", path);
+        }
+        Err(e) => {
+            eprintln!("[Scanner] Failed to read file {:?}: {}", path, e);
+            result.errors.push(ScanError {
+                relative_path: relative_display(root, path),
+                error: e.to_string(),
+            });
+        }
+    }
+}
+
+/// Read a file that must be a regular file. On Unix it is opened
+/// non-blocking, so a named pipe swapped in after the directory was listed
+/// is refused instead of hanging the scan until something writes to it.
+fn read_regular_file(path: &Path) -> std::io::Result<(fs::Metadata, Vec<u8>)> {
+    let mut options = fs::OpenOptions::new();
+    options.read(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NONBLOCK);
+    }
+    let mut file = options.open(path)?;
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Not a regular file"));
+    }
+
+    let mut content = Vec::with_capacity(metadata.len() as usize);
+    file.read_to_end(&mut content)?;
+    Ok((metadata, content))
+}
+
 /// Get information about a single file
-fn get_file_info(root: &Path, path: &Path) -> SyncResult<FileInfo> {
-    let metadata = fs::metadata(path).map_err(SyncError::Io)?;
+fn get_file_info(root: &Path, path: &Path, body_hashes: bool) -> SyncResult<FileInfo> {
 
     let relative_path = path
         .strip_prefix(root)
         .map_err(|_| SyncError::InvalidData("Failed to get relative path".to_string()))?
         .to_string_lossy()
         .replace('\\', "/"); // Normalize path separators
+    let relative_path = normalize_path(&relative_path);
 
-    let content = fs::read(path).map_err(SyncError::Io)?;
+    let (metadata, content) = read_regular_file(path).map_err(SyncError::Io)?;
     let content_hash = compute_hash(&content);
+    let body_hash = if body_hashes { compute_body_hash(&relative_path, &content) } else { None };
 
     let modified_at = metadata
         .modified()
@@ -138,6 +538,7 @@ fn get_file_info(root: &Path, path: &Path) -> SyncResult<FileInfo> {
     Ok(FileInfo {
         relative_path,
         content_hash,
+        body_hash,
         size_bytes: metadata.len(),
         modified_at,
     })
@@ -150,6 +551,17 @@ pub fn compute_hash(content: &[u8]) -> String {
     hasher.finalize().to_hex().to_string()
 }
 
+/// Hash of a note's body, leaving out its frontmatter, so that edits to
+/// metadata alone (e.g. a bumped `updated_at`) can be told apart from edits
+/// to the text. `None` for files that aren't UTF-8 notes.
+pub fn compute_body_hash(relative_path: &str, content: &[u8]) -> Option<String> {
+    if !crate::vault::has_extension_in(Path::new(relative_path), crate::vault::NOTE_EXTENSIONS) {
+        return None;
+    }
+    let text = std::str::from_utf8(content).ok()?;
+    Some(compute_hash(crate::vault::strip_frontmatter(text).as_bytes()))
+}
+
 /// Compare local scan with previous state to find changes
 #[derive(Debug, Clone)]
 pub struct ChangeSet {
@@ -180,9 +592,9 @@ pub fn detect_changes(
         }
     }
 
-    // Find deleted files
+    // Find deleted files (oversized files still exist, they just aren't synced)
     for path in previous.keys() {
-        if !current.files.contains_key(path) {
+        if !current.files.contains_key(path) && !current.is_skipped(path) {
             deleted.push(path.clone());
         }
     }
@@ -193,6 +605,7 @@ pub fn detect_changes(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sync::fixture::VaultFixture;
 
     #[test]
     fn test_compute_hash() {
@@ -202,6 +615,17 @@ mod tests {
         assert_eq!(hash.len(), 64); // BLAKE3 produces 256-bit hash = 64 hex chars
     }
 
+    #[test]
+    fn test_body_hash_ignores_frontmatter() {
+        let before = b"---\nupdated_at: 1\n---\nHello";
+        let after = b"---\nupdated_at: 2\n---\nHello";
+        assert_ne!(compute_hash(before), compute_hash(after));
+        assert_eq!(compute_body_hash("a.md", before), compute_body_hash("a.md", after));
+        assert_ne!(compute_body_hash("a.md", before), compute_body_hash("a.md", b"---\nupdated_at: 1\n---\nBye"));
+        assert_eq!(compute_body_hash("a.txt", before), compute_body_hash("a.markdown", after));
+        assert_eq!(compute_body_hash("a.png", before), None);
+    }
+
     #[test]
     fn test_detect_changes() {
         let mut files = HashMap::new();
@@ -210,6 +634,7 @@ mod tests {
             FileInfo {
                 relative_path: "test.md".to_string(),
                 content_hash: "abc123".to_string(),
+                body_hash: None,
                 size_bytes: 100,
                 modified_at: 1000,
             },
@@ -219,6 +644,7 @@ mod tests {
             FileInfo {
                 relative_path: "new.md".to_string(),
                 content_hash: "def456".to_string(),
+                body_hash: None,
                 size_bytes: 50,
                 modified_at: 2000,
             },
@@ -228,6 +654,10 @@ mod tests {
             files,
             total_size: 150,
             file_count: 2,
+            errors: Vec::new(),
+            skipped: Vec::new(),
+            excluded: Vec::new(),
+            symlinks: Vec::new(),
         };
 
         let mut previous = HashMap::new();
@@ -239,4 +669,317 @@ mod tests {
         assert_eq!(changes.deleted.len(), 1); // "deleted.md" was removed
         assert_eq!(changes.deleted[0], "deleted.md");
     }
+
+    #[test]
+    fn test_oversized_file_is_skipped() {
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("small.md"), "tiny").unwrap();
+        fs::write(dir.join("big.md"), "x".repeat(64)).unwrap();
+
+        let result = scan_vault_with_limit(&dir, 16).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.files.contains_key("small.md"));
+        assert!(!result.files.contains_key("big.md"));
+        assert_eq!(result.skipped.len(), 1);
+        assert_eq!(result.skipped[0].size_bytes, 64);
+
+        // A previously synced file that grew past the limit is not a delete
+        let mut previous = HashMap::new();
+        previous.insert("big.md".to_string(), "old".to_string());
+        let changes = detect_changes(&result, &previous);
+        assert!(changes.deleted.is_empty());
+    }
+
+    #[test]
+    fn test_nfc_and_nfd_names_are_the_same_file() {
+        let nfc = "Caf\u{e9}.md";
+        let nfd = "Cafe\u{301}.md";
+        assert_ne!(nfc, nfd);
+        assert_eq!(normalize_path(nfd), nfc);
+        assert_eq!(normalize_path(nfc), nfc);
+
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(nfd), "bonjour").unwrap();
+
+        let result = scan_vault(&dir).unwrap();
+        let resolved = resolve_local_path(&dir, nfc);
+        let content = fs::read_to_string(&resolved).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.files.keys().collect::<Vec<_>>(), vec![nfc]);
+        assert_eq!(content, "bonjour");
+
+        // A previously synced composed name is unchanged, not deleted and re-added
+        let mut previous = HashMap::new();
+        previous.insert(nfc.to_string(), compute_hash(b"bonjour"));
+        let changes = detect_changes(&result, &previous);
+        assert!(changes.deleted.is_empty());
+        assert!(changes.changed.is_empty());
+    }
+
+    #[test]
+    fn test_included_dirs_are_synced() {
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join(".obsidian")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".obsidian/app.json"), "{}").unwrap();
+        fs::write(dir.join(".git/config.json"), "{}").unwrap();
+
+        let include = vec![".obsidian".to_string()];
+        let default = scan_vault_with_limit(&dir, DEFAULT_MAX_FILE_SIZE).unwrap();
+        let included = scan_vault_including(&dir, DEFAULT_MAX_FILE_SIZE, &include, true, false).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(default.files.is_empty());
+        assert!(included.files.contains_key(".obsidian/app.json"));
+        assert!(!included.files.contains_key(".git/config.json"));
+
+        assert!(is_syncable_path(".obsidian/app.json", &include));
+        assert!(!is_syncable_path(".obsidian/app.json", &[]));
+        assert!(!is_syncable_path(".trash/note.md", &[".trash".to_string()]));
+
+        assert!(validate_include_dir(".obsidian").is_ok());
+        assert!(validate_include_dir("node_modules").is_ok());
+        assert!(validate_include_dir(".trash").is_err());
+        assert!(validate_include_dir("Journal").is_err());
+        assert!(validate_include_dir(".obsidian/themes").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unreadable_file_is_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ok.md"), "fine").unwrap();
+        let locked = dir.join("locked.md");
+        fs::write(&locked, "secret").unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+
+        let result = scan_vault(&dir).unwrap();
+        let readable_anyway = fs::read(&locked).is_ok(); // e.g. running as root
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o644)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.files.contains_key("ok.md"));
+        if !readable_anyway {
+            assert_eq!(result.errors.len(), 1);
+            assert_eq!(result.errors[0].relative_path, "locked.md");
+        }
+    }
+
+    #[test]
+    fn test_files_removed_during_scan_are_left_out() {
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("folder")).unwrap();
+        for i in 0..200 {
+            fs::write(dir.join(format!("note-{}.md", i)), "note").unwrap();
+            fs::write(dir.join(format!("folder/note-{}.md", i)), "note").unwrap();
+        }
+
+        // A file listed but gone by the time it's read
+        let mut result = ScanResult::default();
+        scan_file(&dir, &dir.join("gone.md"), false, &mut result);
+        assert!(result.files.is_empty() && result.errors.is_empty());
+
+        // Deleting files and a whole folder while the scan runs
+        let deleting = {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                for i in 0..200 {
+                    let _ = fs::remove_file(dir.join(format!("note-{}.md", i)));
+                }
+                let _ = fs::remove_dir_all(dir.join("folder"));
+            })
+        };
+        let result = scan_vault(&dir);
+        deleting.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let result = result.unwrap();
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.file_count, result.files.len());
+    }
+
+    #[test]
+    fn test_emoji_names_keep_their_path() {
+        let vault = VaultFixture::builder()
+            .note("📥 Inbox/🎉 party.md", "party")
+            .note("👨\u{200d}👩\u{200d}👧 Family/❤\u{fe0f}.md", "family")
+            .build();
+
+        let result = vault.scan();
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        for path in ["📥 Inbox/🎉 party.md", "👨\u{200d}👩\u{200d}👧 Family/❤\u{fe0f}.md"] {
+            assert!(result.files.contains_key(path), "{} not scanned", path);
+            assert!(is_syncable_path(path, &[]));
+            assert_eq!(resolve_local_path(&vault.root, path), vault.path(path));
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_non_unicode_names_are_reported() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let vault = VaultFixture::builder().note("ok.md", "fine").build();
+        let name = std::ffi::OsStr::from_bytes(b"caf\xe9.md");
+        fs::write(vault.root.join(name), "latin-1 name").unwrap();
+
+        let result = vault.scan();
+        assert_eq!(result.files.keys().collect::<Vec<_>>(), ["ok.md"]);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].error, NON_UNICODE_NAME_ERROR);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_named_pipes_are_skipped() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let vault = VaultFixture::builder().note("note.md", "note").build();
+        let pipe = vault.path("pipe.md");
+        let c_path = std::ffi::CString::new(pipe.as_os_str().as_bytes()).unwrap();
+        // SAFETY: `c_path` is a valid NUL-terminated path
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0);
+
+        // Neither the scan nor a direct read waits for a writer
+        let result = vault.scan();
+        assert_eq!(result.files.keys().collect::<Vec<_>>(), ["note.md"]);
+        assert!(result.errors.is_empty());
+        assert!(read_regular_file(&pipe).is_err());
+    }
+
+    #[test]
+    fn test_note_extensions_are_synced() {
+        for ext in crate::vault::NOTE_EXTENSIONS {
+            assert!(SYNC_EXTENSIONS.contains(ext), "note extension {} is not synced", ext);
+        }
+    }
+
+    #[test]
+    fn test_retain_extensions_excludes_other_files() {
+        let vault = VaultFixture::builder()
+            .note("note.md", "note")
+            .attachment("image.PNG", b"png")
+            .attachment("doc.pdf", b"pdf")
+            .build();
+
+        let mut result = vault.scan();
+        result.retain_extensions(&["md".to_string(), ".png".to_string()]);
+
+        assert_eq!(result.file_count, 2);
+        assert_eq!(result.total_size, 7);
+        assert!(result.files.contains_key("image.PNG"));
+        assert_eq!(result.excluded, vec!["doc.pdf".to_string()]);
+        // Excluded files still exist locally and must not look deleted
+        assert!(result.is_skipped("doc.pdf"));
+    }
+
+    #[test]
+    fn test_empty_notes_are_synced() {
+        // Clearing a note has to reach other devices, so empty files are
+        // scanned (and uploaded) like any other note
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("empty.md"), "").unwrap();
+        fs::write(dir.join("blank.md"), "  \n\n").unwrap();
+
+        let result = scan_vault(&dir).unwrap();
+        assert_eq!(result.file_count, 2);
+        assert_eq!(result.files["empty.md"].size_bytes, 0);
+        assert_eq!(result.files["empty.md"].content_hash, compute_hash(b""));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_scan_finds_notes_beyond_max_path() {
+        let dir = std::env::temp_dir().join(format!("echopad-scan-{}", uuid::Uuid::new_v4()));
+        let segment = "a-notebook-with-a-rather-long-descriptive-name";
+        let nested: Vec<&str> = std::iter::repeat(segment).take(6).collect();
+        let relative = format!("{}/{}.md", nested.join("/"), "a".repeat(40));
+        let note = dir.join(&relative);
+        assert!(note.to_string_lossy().len() > 260);
+
+        fs::create_dir_all(crate::vault::long_path(note.parent().unwrap())).unwrap();
+        fs::write(crate::vault::long_path(&note), "deep").unwrap();
+
+        let result = scan_vault(&dir).unwrap();
+        assert!(result.errors.is_empty());
+        assert!(result.files.contains_key(&relative));
+
+        fs::remove_dir_all(crate::vault::long_path(&dir)).unwrap();
+    }
+
+    #[test]
+    fn test_is_syncable_path() {
+        assert!(is_syncable_path("note.md", &[]));
+        assert!(is_syncable_path("Journal/2024/photo.PNG", &[]));
+        assert!(is_syncable_path(".hidden.md", &[]));
+
+        assert!(!is_syncable_path("", &[]));
+        assert!(!is_syncable_path("/etc/passwd.txt", &[]));
+        assert!(!is_syncable_path("../outside.md", &[]));
+        assert!(!is_syncable_path("notes/../../outside.md", &[]));
+        assert!(!is_syncable_path("./note.md", &[]));
+        assert!(!is_syncable_path("notes//note.md", &[]));
+        assert!(!is_syncable_path("..\\outside.md", &[]));
+        assert!(!is_syncable_path("C:\\Windows\\win.ini", &[]));
+        assert!(!is_syncable_path(".git/config.json", &[]));
+        assert!(!is_syncable_path("node_modules/pkg/readme.md", &[]));
+        assert!(!is_syncable_path("run.exe", &[]));
+        assert!(!is_syncable_path("Makefile", &[]));
+    }
+
+    #[cfg(unix)]
+    fn vault_with_symlinks() -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("echopad-links-{}", uuid::Uuid::new_v4()));
+        let (vault, shared) = (base.join("vault"), base.join("shared"));
+        fs::create_dir_all(&vault).unwrap();
+        fs::create_dir_all(shared.join("folder")).unwrap();
+        fs::write(shared.join("note.md"), "shared note").unwrap();
+        fs::write(shared.join("folder/inner.md"), "inner").unwrap();
+        fs::write(vault.join("own.md"), "own").unwrap();
+        std::os::unix::fs::symlink(shared.join("note.md"), vault.join("linked.md")).unwrap();
+        std::os::unix::fs::symlink(shared.join("folder"), vault.join("folder")).unwrap();
+        std::os::unix::fs::symlink(&vault, vault.join("loop")).unwrap();
+        (base, vault)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_followed_symlinks_sync_as_copies() {
+        let (base, vault) = vault_with_symlinks();
+        let result = scan_vault_including(&vault, DEFAULT_MAX_FILE_SIZE, &[], true, false).unwrap();
+        fs::remove_dir_all(&base).unwrap();
+
+        assert!(result.files.contains_key("own.md"));
+        assert_eq!(result.files["linked.md"].content_hash, compute_hash(b"shared note"));
+        assert!(result.files.contains_key("folder/inner.md"));
+        let linked = result.symlinks.iter().find(|l| l.relative_path == "linked.md").unwrap();
+        assert!(linked.followed);
+        assert_eq!(linked.note, SYMLINK_FOLLOWED_NOTE);
+        assert!(result.errors.iter().any(|e| e.relative_path == "loop"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unfollowed_symlinks_are_skipped_not_deleted() {
+        let (base, vault) = vault_with_symlinks();
+        let result = scan_vault_including(&vault, DEFAULT_MAX_FILE_SIZE, &[], false, false).unwrap();
+        fs::remove_dir_all(&base).unwrap();
+
+        assert_eq!(result.files.keys().collect::<Vec<_>>(), vec!["own.md"]);
+        assert_eq!(result.symlinks.len(), 3);
+        assert!(result.symlinks.iter().all(|l| !l.followed && l.note == SYMLINK_SKIPPED_NOTE));
+        assert!(result.is_skipped("linked.md"));
+        assert!(result.is_skipped("folder/inner.md"));
+        assert!(!result.is_skipped("folderish.md"));
+    }
 }
@@ -0,0 +1,147 @@
+//! Per-vault sync scheduling
+//!
+//! Tracks when each vault is next due for a background sync. Each vault has
+//! its own interval (0 = manual only); repeated failures push the next run
+//! out with exponential backoff until a sync succeeds again.
+
+#![allow(dead_code)]
+
+use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How often the background loop checks for due vaults
+pub const SCHEDULER_TICK: Duration = Duration::from_secs(5);
+
+/// Upper bound for the backoff delay after repeated failures
+pub const MAX_BACKOFF_SECS: u64 = 60 * 60;
+
+/// Scheduling state for one vault
+#[derive(Debug, Clone, Default)]
+struct ScheduleEntry {
+    /// Interval the entry was scheduled with, to notice setting changes
+    interval_seconds: u64,
+    /// When the next sync should run (ms since epoch)
+    next_sync_at: u64,
+    /// Failed syncs since the last success
+    consecutive_failures: u32,
+}
+
+/// Next-run bookkeeping for every scheduled vault, keyed by vault path
+#[derive(Debug, Default)]
+pub struct SyncScheduler {
+    entries: RwLock<HashMap<String, ScheduleEntry>>,
+}
+
+impl SyncScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether the vault should sync now. Vaults seen for the first time or
+    /// whose interval changed are scheduled one interval from `now`.
+    pub fn is_due(&self, vault_path: &str, interval_seconds: u64, now: u64) -> bool {
+        if interval_seconds == 0 {
+            self.entries.write().remove(vault_path);
+            return false;
+        }
+
+        let mut entries = self.entries.write();
+        let entry = entries.entry(vault_path.to_string()).or_default();
+        if entry.interval_seconds != interval_seconds {
+            entry.interval_seconds = interval_seconds;
+            entry.next_sync_at = now + interval_seconds * 1000;
+            entry.consecutive_failures = 0;
+        }
+        now >= entry.next_sync_at
+    }
+
+    /// Schedule the next run one interval after a successful sync
+    pub fn record_success(&self, vault_path: &str, now: u64) {
+        if let Some(entry) = self.entries.write().get_mut(vault_path) {
+            entry.consecutive_failures = 0;
+            entry.next_sync_at = now + entry.interval_seconds * 1000;
+        }
+    }
+
+    /// Back off after a failed sync, doubling the delay for each consecutive
+    /// failure up to [`MAX_BACKOFF_SECS`] (but never below the interval)
+    pub fn record_failure(&self, vault_path: &str, now: u64) {
+        if let Some(entry) = self.entries.write().get_mut(vault_path) {
+            entry.consecutive_failures += 1;
+            let delay = backoff_seconds(entry.interval_seconds, entry.consecutive_failures);
+            entry.next_sync_at = now + delay * 1000;
+        }
+    }
+
+    /// When the vault is next scheduled to sync, if it is scheduled
+    pub fn next_sync_at(&self, vault_path: &str) -> Option<u64> {
+        self.entries.read().get(vault_path).map(|e| e.next_sync_at)
+    }
+
+    /// Forget a vault (e.g. after its interval was changed or it was disabled)
+    pub fn reset(&self, vault_path: &str) {
+        self.entries.write().remove(vault_path);
+    }
+}
+
+/// Delay in seconds after `failures` consecutive failures
+fn backoff_seconds(interval_seconds: u64, failures: u32) -> u64 {
+    let factor = 1u64 << failures.min(16);
+    interval_seconds
+        .saturating_mul(factor)
+        .min(MAX_BACKOFF_SECS)
+        .max(interval_seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_only_is_never_due() {
+        let scheduler = SyncScheduler::new();
+        assert!(!scheduler.is_due("/vault", 0, 0));
+        assert!(!scheduler.is_due("/vault", 0, u64::MAX / 2));
+        assert_eq!(scheduler.next_sync_at("/vault"), None);
+    }
+
+    #[test]
+    fn test_first_run_waits_one_interval() {
+        let scheduler = SyncScheduler::new();
+        assert!(!scheduler.is_due("/vault", 60, 1_000));
+        assert_eq!(scheduler.next_sync_at("/vault"), Some(61_000));
+        assert!(scheduler.is_due("/vault", 60, 61_000));
+    }
+
+    #[test]
+    fn test_failures_back_off_and_success_resets() {
+        let scheduler = SyncScheduler::new();
+        scheduler.is_due("/vault", 60, 0);
+
+        scheduler.record_failure("/vault", 0);
+        assert_eq!(scheduler.next_sync_at("/vault"), Some(120_000));
+        scheduler.record_failure("/vault", 0);
+        assert_eq!(scheduler.next_sync_at("/vault"), Some(240_000));
+
+        scheduler.record_success("/vault", 0);
+        assert_eq!(scheduler.next_sync_at("/vault"), Some(60_000));
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        assert_eq!(backoff_seconds(60, 20), MAX_BACKOFF_SECS);
+        // An interval longer than the cap is never shortened
+        assert_eq!(backoff_seconds(2 * MAX_BACKOFF_SECS, 3), 2 * MAX_BACKOFF_SECS);
+    }
+
+    #[test]
+    fn test_interval_change_reschedules() {
+        let scheduler = SyncScheduler::new();
+        scheduler.is_due("/vault", 60, 0);
+        scheduler.record_failure("/vault", 0);
+
+        assert!(!scheduler.is_due("/vault", 300, 10_000));
+        assert_eq!(scheduler.next_sync_at("/vault"), Some(310_000));
+    }
+}
@@ -1,4 +1,4 @@
-//! Sync state management with JSON file persistence
+//! Sync state management with pluggable persistence (JSON file by default)
 //! 
 //! State is keyed by `vault_id` (remote vault identifier) rather than local path,
 //! allowing the same remote vault to be connected from different local paths
@@ -8,15 +8,38 @@
 
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use super::crypto::CryptoKey;
+use super::engine::{clamp_pull_batch_size, DEFAULT_PULL_BATCH_SIZE, DEFAULT_PULL_CONCURRENCY};
 use super::error::{SyncError, SyncResult};
-use super::scanner::scan_vault;
-use super::types::{VaultSyncState, VaultSyncStatus};
+use super::scanner::{compute_hash, normalize_path, resolve_local_path, scan_vault_including, FileInfo, ScanError, ScanResult, SkippedFile, DEFAULT_MAX_FILE_SIZE};
+use super::types::{DuplicateVaultPath, MappingRepair, ServerSyncConfig, VaultSyncState, VaultSyncStatus};
+
+/// Reported for enabled vaults whose local folder is gone
+pub const MISSING_FOLDER_ERROR: &str = "Local folder not found — relocate the vault";
+
+/// Reported for vaults whose remote vault was deleted on the server
+pub const REMOTE_VAULT_MISSING_ERROR: &str =
+    "Remote vault no longer exists — connect the folder to a new remote vault or disconnect it";
+
+/// Reported while background syncs of a vault are held back by its
+/// circuit breaker
+pub const AUTO_SYNC_PAUSED_WARNING: &str = "Auto-sync paused due to repeated errors";
+
+/// Consecutive failed syncs after which background syncs stop
+pub const CIRCUIT_BREAKER_THRESHOLD: u32 = 5;
+
+/// How long background syncs stay stopped once the circuit opens
+pub const CIRCUIT_BREAKER_COOLDOWN_MS: u64 = 30 * 60 * 1000;
+
+/// How long a file state must have gone without a sync before its missing
+/// file makes it orphaned, so deletes the next sync will push aren't listed
+pub const ORPHANED_STATE_AGE_MS: u64 = 7 * 24 * 60 * 60 * 1000;
 
 /// Sync state for a vault (keyed by vault_id)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,6 +53,76 @@ pub struct VaultState {
     pub last_sync_at: Option<u64>,
     pub status: VaultSyncState,
     pub last_error: Option<String>,
+    /// Pull records remote files as empty placeholders instead of
+    /// downloading their content
+    #[serde(default)]
+    pub placeholder_mode: bool,
+    /// Files larger than this are never scanned or uploaded
+    /// (`None` uses [`DEFAULT_MAX_FILE_SIZE`])
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    /// Seconds between background syncs; 0 means manual only
+    #[serde(default)]
+    pub sync_interval_seconds: u64,
+    /// Remote changes applied at the same time during pull
+    /// (`None` uses [`DEFAULT_PULL_CONCURRENCY`])
+    #[serde(default)]
+    pub pull_concurrency: Option<usize>,
+    /// Remote changes requested per pull page
+    /// (`None` uses [`DEFAULT_PULL_BATCH_SIZE`])
+    #[serde(default)]
+    pub pull_batch_size: Option<u32>,
+    /// Conflict files older than this many days are cleaned up after each
+    /// sync (`None` keeps them until resolved)
+    #[serde(default)]
+    pub conflict_retention_days: Option<u32>,
+    /// Push deletes for notebooks moved to the trash right away instead of
+    /// when the trash is emptied
+    #[serde(default)]
+    pub push_trash_deletes_immediately: bool,
+    /// Normally skipped directories (e.g. `.obsidian`) this vault syncs anyway
+    #[serde(default)]
+    pub include_dirs: Vec<String>,
+    /// Whether symlinked files and folders are synced as copies of their
+    /// targets (`None` follows them, as vaults always did)
+    #[serde(default)]
+    pub follow_symlinks: Option<bool>,
+    /// Whether file content is end-to-end encrypted with the vault key.
+    /// Decided when the vault is created; older vaults are plaintext.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Paths are sent with a scheme prefix (`b64:`); vaults that predate
+    /// prefixes keep sending bare base64 so the server's paths stay the same
+    #[serde(default)]
+    pub prefixed_paths: bool,
+    /// Notes count as changed only when their body changes; edits to the
+    /// frontmatter alone are uploaded with the next body change, and are
+    /// lost if a body change from another device is pulled first
+    #[serde(default)]
+    pub hash_note_body_only: bool,
+    /// Syncs that failed in a row since the last successful one
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Background syncs are skipped until this time (ms since epoch) after
+    /// too many failures in a row
+    #[serde(default)]
+    pub circuit_open_until: Option<u64>,
+    /// Vault key rotation started and not finished yet
+    #[serde(default)]
+    pub key_rotation: Option<KeyRotation>,
+}
+
+/// Progress of a vault key rotation, kept so an interrupted rotation
+/// resumes with the same new key and skips the files already done
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// The new vault key, wrapped with the account key
+    pub encrypted_key: String,
+    pub key_nonce: String,
+    /// Server file IDs already re-encrypted with the new key, with the
+    /// version each had then
+    #[serde(default)]
+    pub rekeyed: HashMap<String, u32>,
 }
 
 impl VaultState {
@@ -42,17 +135,80 @@ impl VaultState {
             last_sync_at: None,
             status: VaultSyncState::Disabled,
             last_error: None,
+            placeholder_mode: false,
+            max_file_size: None,
+            sync_interval_seconds: 0,
+            pull_concurrency: None,
+            pull_batch_size: None,
+            conflict_retention_days: None,
+            push_trash_deletes_immediately: false,
+            include_dirs: Vec::new(),
+            follow_symlinks: None,
+            encrypted: false,
+            prefixed_paths: false,
+            hash_note_body_only: false,
+            consecutive_failures: 0,
+            circuit_open_until: None,
+            key_rotation: None,
         }
     }
 
+    /// Size limit for synced files in bytes
+    pub fn effective_max_file_size(&self) -> u64 {
+        self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE)
+    }
+
+    /// Number of remote changes pull applies concurrently
+    pub fn effective_pull_concurrency(&self) -> usize {
+        self.pull_concurrency.unwrap_or(DEFAULT_PULL_CONCURRENCY)
+    }
+
+    /// Number of remote changes requested per pull page, within the range
+    /// the server accepts
+    pub fn effective_pull_batch_size(&self) -> u32 {
+        clamp_pull_batch_size(self.pull_batch_size.unwrap_or(DEFAULT_PULL_BATCH_SIZE))
+    }
+
+    /// Whether symlinks are followed when scanning the vault
+    pub fn effective_follow_symlinks(&self) -> bool {
+        self.follow_symlinks.unwrap_or(true)
+    }
+
+    /// Whether the vault's local folder no longer exists (e.g. it was
+    /// deleted or moved while sync was enabled)
+    pub fn local_path_missing(&self) -> bool {
+        !Path::new(&self.vault_path).is_dir()
+    }
+
+    /// Whether the circuit breaker holds back background syncs at `now`
+    pub fn auto_sync_paused(&self, now: u64) -> bool {
+        self.circuit_open_until.is_some_and(|until| now < until)
+    }
+
     pub fn to_status(&self, pending_changes: u32) -> VaultSyncStatus {
+        let (status, last_error) = if self.enabled && self.local_path_missing() {
+            (VaultSyncState::Error, Some(MISSING_FOLDER_ERROR.to_string()))
+        } else {
+            (self.status.clone(), self.last_error.clone())
+        };
+
         VaultSyncStatus {
             vault_path: self.vault_path.clone(),
             vault_id: Some(self.vault_id.clone()),
             enabled: self.enabled,
-            status: self.status.clone(),
+            status,
             last_sync_at: self.last_sync_at,
             pending_changes,
+            last_error,
+            warnings: Vec::new(),
+            sync_interval_seconds: self.sync_interval_seconds,
+            next_sync_at: None,
+            encrypted: self.encrypted,
+            read_only: false,
+            consecutive_failures: self.consecutive_failures,
+            auto_sync_paused_until: None,
+            conflict_count: 0,
+            last_result: None,
         }
     }
 }
@@ -65,6 +221,47 @@ pub struct FileSyncState {
     pub remote_hash: Option<String>,
     pub remote_version: Option<u32>,
     pub last_synced_at: Option<u64>,
+    /// The local file is an empty placeholder; `remote_hash` describes the
+    /// real content, which is fetched on demand from `download_url`
+    #[serde(default)]
+    pub placeholder: bool,
+    #[serde(default)]
+    pub download_url: Option<String>,
+    /// The file was deleted locally (e.g. with its notebook) and the delete
+    /// still has to be pushed; pulls must not bring it back meanwhile
+    #[serde(default)]
+    pub pending_delete: bool,
+    /// Path the file was synced under before its notebook was renamed; the
+    /// next push deletes it there and creates it at the new path
+    #[serde(default)]
+    pub renamed_from: Option<String>,
+    /// Server file id of an upload whose content arrived but whose confirm
+    /// failed; the next sync retries only the confirm
+    #[serde(default)]
+    pub pending_confirm: Option<String>,
+    /// The file's notebook is in the vault trash. Its delete is held back
+    /// (and pulls skip it) until the trash is emptied or it is restored.
+    #[serde(default)]
+    pub trashed: bool,
+    /// Hash of the note's body without frontmatter when it was last synced
+    #[serde(default)]
+    pub body_hash: Option<String>,
+    /// Encoded path the server holds the file under, as last pulled. Pushes
+    /// reuse it, so a vault that switched path schemes keeps updating the
+    /// server's copy instead of creating a second one. For a renamed file it
+    /// is that of `renamed_from`.
+    #[serde(default)]
+    pub remote_path: Option<String>,
+}
+
+impl FileSyncState {
+    /// Whether a scanned file still matches what was last synced. With
+    /// `body_only`, a note whose body is unchanged counts as unchanged even
+    /// if its frontmatter differs.
+    pub fn matches(&self, info: &FileInfo, body_only: bool) -> bool {
+        self.local_hash.as_ref() == Some(&info.content_hash)
+            || (body_only && self.body_hash.is_some() && self.body_hash == info.body_hash)
+    }
 }
 
 /// Persisted state structure (saved to JSON)
@@ -73,23 +270,360 @@ pub struct FileSyncState {
 /// not by local file path. This allows the same vault to be connected from
 /// different local paths across devices or after moving the vault folder.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
-struct PersistedState {
+pub struct PersistedState {
     /// Version of the state format for migration purposes
     #[serde(default)]
-    version: u32,
+    pub version: u32,
     /// Vault states keyed by vault_id (remote ID)
-    vaults: HashMap<String, VaultState>,
+    pub vaults: HashMap<String, VaultState>,
     /// File states keyed by vault_id (remote ID), then by relative file path
-    file_states: HashMap<String, HashMap<String, FileSyncState>>,
+    pub file_states: HashMap<String, HashMap<String, FileSyncState>>,
     /// Mapping from local vault paths to vault_ids for quick lookup
     #[serde(default)]
-    path_to_vault_id: HashMap<String, String>,
+    pub path_to_vault_id: HashMap<String, String>,
+}
+
+/// Storage backend for persisted sync state
+pub trait StateStore: Send + Sync {
+    /// Load the stored state, or `None` if nothing has been saved yet
+    fn load(&self) -> SyncResult<Option<PersistedState>>;
+
+    /// Replace the stored state
+    fn save(&self, state: &PersistedState) -> SyncResult<()>;
+
+    /// Save the vaults and path index of `state` and the file states of
+    /// the vaults in `changed`. `state.file_states` only holds those vaults
+    /// (a changed vault missing from it has no file states left); the file
+    /// states of every other vault are as last saved.
+    fn save_changed(&self, state: &PersistedState, changed: &HashSet<String>) -> SyncResult<()>;
+
+    /// The stored state is in an older layout that `save_changed` can't
+    /// update, so the next save must be a full `save`
+    fn needs_full_save(&self) -> bool {
+        false
+    }
+
+    /// Delete everything the store holds, returning any files removed
+    fn clear(&self) -> SyncResult<Vec<PathBuf>>;
+
+    /// Human-readable location for log messages
+    fn describe(&self) -> String;
+
+    /// Files the store may keep on disk, for moving the data directory
+    fn files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Keep the store's files in `dir` from now on. Their current content
+    /// is copied there by the caller.
+    fn set_dir(&self, _dir: &Path) {}
+}
+
+/// File name of the default store inside the data directory
+pub const STATE_FILE_NAME: &str = "sync_state.json";
+
+/// Main file of [`JsonFileStore`]. File states are kept per vault instead;
+/// the empty `file_states` lets older versions still read it.
+#[derive(Serialize)]
+struct StateIndex<'a> {
+    version: u32,
+    vaults: &'a HashMap<String, VaultState>,
+    file_states: HashMap<String, HashMap<String, FileSyncState>>,
+    path_to_vault_id: &'a HashMap<String, String>,
+}
+
+/// File states of one vault, in a file of their own
+#[derive(Serialize, Deserialize)]
+struct VaultFileStates {
+    vault_id: String,
+    file_states: HashMap<String, FileSyncState>,
+}
+
+/// Default store: pretty-printed JSON files. `sync_state.json` holds the
+/// vaults and path index, and each vault's file states are in
+/// `sync_state.<vault_id>.json`, so saving a change to one file state only
+/// rewrites its own vault's file. State saved by older versions keeps every
+/// file state in `sync_state.json`; the first full save splits it up and
+/// leaves the original as `sync_state.json.bak`.
+pub struct JsonFileStore {
+    path: RwLock<PathBuf>,
+    /// The last load found file states in the main file
+    single_file: AtomicBool,
+}
+
+impl JsonFileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path: RwLock::new(path),
+            single_file: AtomicBool::new(false),
+        }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.path.read().clone()
+    }
+
+    fn backup_path(&self) -> PathBuf {
+        self.path().with_extension("json.bak")
+    }
+
+    fn file_prefix(&self) -> String {
+        let path = self.path();
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+        format!("{}.", stem.unwrap_or_else(|| "sync_state".to_string()))
+    }
+
+    /// File holding the file states of `vault_id`
+    fn vault_file(&self, vault_id: &str) -> PathBuf {
+        let safe_id: String = vault_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.path().with_file_name(format!("{}{}.json", self.file_prefix(), safe_id))
+    }
+
+    /// Per-vault files currently on disk
+    fn vault_files(&self) -> Vec<PathBuf> {
+        let path = self.path();
+        let Some(Ok(entries)) = path.parent().map(fs::read_dir) else {
+            return Vec::new();
+        };
+        let prefix = self.file_prefix();
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.strip_prefix(&prefix)
+                    .and_then(|rest| rest.strip_suffix(".json"))
+                    .is_some_and(|id| !id.is_empty())
+            })
+            .map(|entry| entry.path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn write_json<T: Serialize>(path: &Path, value: &T) -> SyncResult<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(SyncError::Io)?;
+        }
+        let content = serde_json::to_string_pretty(value).map_err(SyncError::Json)?;
+        fs::write(path, content).map_err(SyncError::Io)
+    }
+
+    fn write_vault(&self, vault_id: &str, file_states: Option<&HashMap<String, FileSyncState>>) -> SyncResult<()> {
+        let path = self.vault_file(vault_id);
+        match file_states {
+            Some(file_states) => Self::write_json(
+                &path,
+                &VaultFileStates {
+                    vault_id: vault_id.to_string(),
+                    file_states: file_states.clone(),
+                },
+            ),
+            None => match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SyncError::Io(e)),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    fn write_index(&self, state: &PersistedState) -> SyncResult<()> {
+        Self::write_json(
+            &self.path(),
+            &StateIndex {
+                version: state.version,
+                vaults: &state.vaults,
+                file_states: HashMap::new(),
+                path_to_vault_id: &state.path_to_vault_id,
+            },
+        )
+    }
+}
+
+impl StateStore for JsonFileStore {
+    fn load(&self) -> SyncResult<Option<PersistedState>> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path).map_err(SyncError::Io)?;
+        let mut persisted: PersistedState = serde_json::from_str(&content).map_err(SyncError::Json)?;
+        self.single_file.store(!persisted.file_states.is_empty(), Ordering::SeqCst);
+
+        for vault_path in self.vault_files() {
+            let content = fs::read_to_string(&vault_path).map_err(SyncError::Io)?;
+            let vault: VaultFileStates = serde_json::from_str(&content).map_err(SyncError::Json)?;
+            persisted.file_states.insert(vault.vault_id, vault.file_states);
+        }
+        Ok(Some(persisted))
+    }
+
+    fn save(&self, state: &PersistedState) -> SyncResult<()> {
+        if self.single_file.load(Ordering::SeqCst) {
+            fs::copy(self.path(), self.backup_path()).map_err(SyncError::Io)?;
+            println!(
+                "[SyncState] Splitting file states of {} vaults into per-vault files",
+                state.file_states.len()
+            );
+        }
+
+        let keep: HashSet<PathBuf> = state.file_states.keys().map(|id| self.vault_file(id)).collect();
+        for (vault_id, file_states) in &state.file_states {
+            self.write_vault(vault_id, Some(file_states))?;
+        }
+        // Written last, so a failure above leaves the old main file in place
+        self.write_index(state)?;
+        self.single_file.store(false, Ordering::SeqCst);
+
+        for stale in self.vault_files().into_iter().filter(|path| !keep.contains(path)) {
+            let _ = fs::remove_file(stale);
+        }
+        Ok(())
+    }
+
+    fn save_changed(&self, state: &PersistedState, changed: &HashSet<String>) -> SyncResult<()> {
+        for vault_id in changed {
+            self.write_vault(vault_id, state.file_states.get(vault_id))?;
+        }
+        self.write_index(state)
+    }
+
+    fn needs_full_save(&self) -> bool {
+        self.single_file.load(Ordering::SeqCst)
+    }
+
+    fn clear(&self) -> SyncResult<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        for path in self.files() {
+            if path.exists() {
+                fs::remove_file(&path).map_err(SyncError::Io)?;
+                removed.push(path);
+            }
+        }
+        self.single_file.store(false, Ordering::SeqCst);
+        Ok(removed)
+    }
+
+    fn describe(&self) -> String {
+        format!("{:?}", self.path())
+    }
+
+    fn files(&self) -> Vec<PathBuf> {
+        let mut files = vec![self.backup_path(), self.path()];
+        files.extend(self.vault_files());
+        files
+    }
+
+    fn set_dir(&self, dir: &Path) {
+        let mut path = self.path.write();
+        let file_name = path.file_name().map(|name| name.to_owned()).unwrap_or_else(|| STATE_FILE_NAME.into());
+        *path = dir.join(file_name);
+    }
+}
+
+/// In-memory store, used by tests and anywhere state must not touch disk
+#[derive(Default)]
+pub struct MemoryStore {
+    state: RwLock<Option<PersistedState>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from an existing state, as if it had been saved earlier
+    pub fn with_state(state: PersistedState) -> Self {
+        Self {
+            state: RwLock::new(Some(state)),
+        }
+    }
+
+    /// The last saved state
+    pub fn snapshot(&self) -> Option<PersistedState> {
+        self.state.read().clone()
+    }
+}
+
+impl StateStore for MemoryStore {
+    fn load(&self) -> SyncResult<Option<PersistedState>> {
+        Ok(self.state.read().clone())
+    }
+
+    fn save(&self, state: &PersistedState) -> SyncResult<()> {
+        *self.state.write() = Some(state.clone());
+        Ok(())
+    }
+
+    fn save_changed(&self, state: &PersistedState, changed: &HashSet<String>) -> SyncResult<()> {
+        let mut stored = self.state.write();
+        let mut file_states = stored.take().map(|stored| stored.file_states).unwrap_or_default();
+        for vault_id in changed {
+            match state.file_states.get(vault_id) {
+                Some(files) => file_states.insert(vault_id.clone(), files.clone()),
+                None => file_states.remove(vault_id),
+            };
+        }
+        *stored = Some(PersistedState {
+            version: state.version,
+            vaults: state.vaults.clone(),
+            file_states,
+            path_to_vault_id: state.path_to_vault_id.clone(),
+        });
+        Ok(())
+    }
+
+    fn clear(&self) -> SyncResult<Vec<PathBuf>> {
+        *self.state.write() = None;
+        Ok(Vec::new())
+    }
+
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+}
+
+/// Pending change count for a vault, with the files the scan left out
+#[derive(Debug, Default)]
+pub struct PendingChanges {
+    pub count: u32,
+    /// Files that could not be read
+    pub errors: Vec<ScanError>,
+    /// Files over the size limit
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Whether folder `inner` is strictly inside folder `outer`
+pub fn path_is_nested_in(inner: &str, outer: &str) -> bool {
+    let normalize = |p: &str| fs::canonicalize(p).unwrap_or_else(|_| PathBuf::from(p));
+    let (inner, outer) = (normalize(inner), normalize(outer));
+    inner != outer && inner.starts_with(&outer)
+}
+
+/// Whether one folder is nested inside the other (but not the same folder)
+pub fn paths_overlap(a: &str, b: &str) -> bool {
+    path_is_nested_in(a, b) || path_is_nested_in(b, a)
+}
+
+/// Whether a `/`-separated relative path is `dir` itself or inside it
+fn is_under_dir(relative_path: &str, dir: &str) -> bool {
+    relative_path == dir
+        || relative_path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
 }
 
 /// Current state format version
 const STATE_VERSION: u32 = 2;
 
-/// Sync state manager with JSON file persistence
+/// A vault as [`SyncStateManager::snapshot_vault`] found it
+pub struct VaultSnapshot {
+    vault: VaultState,
+    file_states: Option<HashMap<String, FileSyncState>>,
+    key: Option<CryptoKey>,
+}
+
+/// Sync state manager backed by a [`StateStore`]
 pub struct SyncStateManager {
     /// State for each vault (keyed by vault_id - the remote vault identifier)
     vaults: Arc<RwLock<HashMap<String, VaultState>>>,
@@ -99,10 +633,17 @@ pub struct SyncStateManager {
     path_to_vault_id: Arc<RwLock<HashMap<String, String>>>,
     /// Decrypted vault keys (in memory only, keyed by vault_id)
     vault_keys: Arc<RwLock<HashMap<String, CryptoKey>>>,
-    /// Path to the JSON state file
-    state_file: PathBuf,
+    /// Folders (relative paths, keyed by vault_id) that pulls must not write
+    /// into while they are being deleted
+    paused_dirs: Arc<RwLock<HashMap<String, Vec<String>>>>,
+    /// Defaults recommended by the server, below per-vault settings
+    server_config: Arc<RwLock<ServerSyncConfig>>,
+    /// Where state is persisted
+    store: Arc<dyn StateStore>,
     /// Dirty flag to track if we need to save
     dirty: Arc<RwLock<bool>>,
+    /// Vaults whose file states changed since the last save
+    dirty_vaults: Arc<RwLock<HashSet<String>>>,
 }
 
 impl SyncStateManager {
@@ -110,16 +651,24 @@ impl SyncStateManager {
     pub fn new(db_path: PathBuf) -> Self {
         // Use the parent directory and create a sync_state.json file
         let state_file = db_path.parent()
-            .map(|p| p.join("sync_state.json"))
-            .unwrap_or_else(|| PathBuf::from("sync_state.json"));
+            .map(|p| p.join(STATE_FILE_NAME))
+            .unwrap_or_else(|| PathBuf::from(STATE_FILE_NAME));
+
+        Self::with_store(Arc::new(JsonFileStore::new(state_file)))
+    }
 
+    /// Create a manager persisting through the given store and load its state
+    pub fn with_store(store: Arc<dyn StateStore>) -> Self {
         let manager = Self {
             vaults: Arc::new(RwLock::new(HashMap::new())),
             file_states: Arc::new(RwLock::new(HashMap::new())),
             path_to_vault_id: Arc::new(RwLock::new(HashMap::new())),
             vault_keys: Arc::new(RwLock::new(HashMap::new())),
-            state_file,
+            paused_dirs: Arc::new(RwLock::new(HashMap::new())),
+            server_config: Arc::new(RwLock::new(ServerSyncConfig::default())),
+            store,
             dirty: Arc::new(RwLock::new(false)),
+            dirty_vaults: Arc::new(RwLock::new(HashSet::new())),
         };
 
         // Load existing state
@@ -174,6 +723,132 @@ impl SyncStateManager {
         self.mark_dirty();
     }
 
+    /// Drop file states kept for vaults that no longer exist, e.g. left
+    /// behind by an interrupted removal. Returns the vault IDs dropped.
+    pub fn compact(&self) -> Vec<String> {
+        let mut dropped: Vec<String> = {
+            let vaults = self.vaults.read();
+            let mut file_states = self.file_states.write();
+            let dropped: Vec<String> = file_states.keys().filter(|id| !vaults.contains_key(*id)).cloned().collect();
+            for vault_id in &dropped {
+                file_states.remove(vault_id);
+            }
+            dropped
+        };
+        dropped.sort();
+        if !dropped.is_empty() {
+            println!("[SyncState] Dropped file states of {} removed vaults", dropped.len());
+            self.dirty_vaults.write().extend(dropped.iter().cloned());
+            self.mark_dirty();
+        }
+        dropped
+    }
+
+    /// Rebuild the path -> vault_id index from the vaults' own `vault_path`
+    /// fields. When several vaults claim one folder, the enabled one (then
+    /// the most recently synced) keeps it.
+    pub fn repair_mappings(&self) -> MappingRepair {
+        let mut report = MappingRepair::default();
+        let mut expected: HashMap<String, String> = HashMap::new();
+        {
+            let vaults = self.vaults.read();
+            let mut by_path: HashMap<&str, Vec<&VaultState>> = HashMap::new();
+            for state in vaults.values() {
+                by_path.entry(state.vault_path.as_str()).or_default().push(state);
+            }
+
+            for (vault_path, mut claimants) in by_path {
+                claimants.sort_by_key(|v| std::cmp::Reverse((v.enabled, v.last_sync_at)));
+                let kept = claimants[0].vault_id.clone();
+                if claimants.len() > 1 {
+                    let mut vault_ids: Vec<String> = claimants.iter().map(|v| v.vault_id.clone()).collect();
+                    vault_ids.sort();
+                    report.duplicates.push(DuplicateVaultPath {
+                        vault_path: vault_path.to_string(),
+                        vault_ids,
+                        kept_vault_id: kept.clone(),
+                    });
+                }
+                expected.insert(vault_path.to_string(), kept);
+            }
+        }
+
+        {
+            let mut mapping = self.path_to_vault_id.write();
+            for (vault_path, vault_id) in &expected {
+                if mapping.get(vault_path) != Some(vault_id) {
+                    report.fixed.push(vault_path.clone());
+                }
+            }
+            report.removed = mapping
+                .keys()
+                .filter(|path| !expected.contains_key(*path))
+                .cloned()
+                .collect();
+
+            if report.changed_anything() {
+                *mapping = expected;
+            }
+        }
+
+        report.fixed.sort();
+        report.removed.sort();
+        report.duplicates.sort_by(|a, b| a.vault_path.cmp(&b.vault_path));
+
+        if report.changed_anything() {
+            println!(
+                "[SyncState] Repaired path mappings: {} fixed, {} removed, {} duplicate paths",
+                report.fixed.len(),
+                report.removed.len(),
+                report.duplicates.len()
+            );
+            self.mark_dirty();
+        }
+        report
+    }
+
+    // ==========================================
+    // Server-recommended defaults
+    // ==========================================
+
+    /// Replace the server-recommended defaults
+    pub fn set_server_config(&self, config: ServerSyncConfig) {
+        *self.server_config.write() = config;
+    }
+
+    pub fn server_config(&self) -> ServerSyncConfig {
+        self.server_config.read().clone()
+    }
+
+    /// File size limit for a vault: its own setting, else the server's
+    /// recommendation, else [`DEFAULT_MAX_FILE_SIZE`]
+    pub fn max_file_size_for(&self, vault_state: &VaultState) -> u64 {
+        vault_state
+            .max_file_size
+            .or(self.server_config.read().max_file_size)
+            .unwrap_or(DEFAULT_MAX_FILE_SIZE)
+    }
+
+    /// Extensions the server restricts sync to, if any
+    pub fn allowed_extensions(&self) -> Option<Vec<String>> {
+        self.server_config.read().allowed_extensions.clone()
+    }
+
+    /// Scan a vault with its size limit and the server's extension policy
+    pub fn scan_vault(&self, vault_state: &VaultState) -> SyncResult<ScanResult> {
+        let mut scan = scan_vault_including(
+            Path::new(&vault_state.vault_path),
+            self.max_file_size_for(vault_state),
+            &vault_state.include_dirs,
+            vault_state.effective_follow_symlinks(),
+            vault_state.hash_note_body_only,
+        )?;
+        if let Some(allowed) = self.allowed_extensions() {
+            scan.retain_extensions(&allowed);
+        }
+        Ok(scan)
+    }
+
     // ==========================================
     // Vault state management
     // ==========================================
@@ -222,6 +897,329 @@ impl SyncStateManager {
         self.mark_dirty();
     }
 
+    /// Find an enabled vault whose folder contains, or is contained in,
+    /// `vault_path`. The vault at exactly `vault_path` is not an overlap.
+    pub fn find_overlapping_vault(&self, vault_path: &str) -> Option<VaultState> {
+        self.vaults
+            .read()
+            .values()
+            .filter(|v| v.enabled)
+            .find(|v| paths_overlap(&v.vault_path, vault_path))
+            .cloned()
+    }
+
+    /// Set the file size limit for a vault (by local path); `None` restores
+    /// the default. Returns false if the vault is unknown.
+    pub fn set_max_file_size(&self, vault_path: &str, max_file_size: Option<u64>) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.max_file_size = max_file_size,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Set how many remote changes a pull applies at once for a vault (by
+    /// local path); `None` restores the default. Returns false if the vault
+    /// is unknown.
+    pub fn set_pull_concurrency(&self, vault_path: &str, concurrency: Option<usize>) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.pull_concurrency = concurrency,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Set which normally skipped directories a vault syncs (by local
+    /// path). Names are expected to be checked with
+    /// [`validate_include_dir`](super::scanner::validate_include_dir).
+    /// Returns false if the vault is unknown.
+    ///
+    /// Files under a directory taken off the list stay on disk and on the
+    /// server, they just stop syncing. Their file states are dropped so the
+    /// next sync doesn't take them for local deletes.
+    pub fn set_include_dirs(&self, vault_path: &str, include_dirs: Vec<String>) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        let removed: Vec<String> = {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => {
+                    let previous = std::mem::replace(&mut state.include_dirs, include_dirs);
+                    previous.into_iter().filter(|dir| !state.include_dirs.contains(dir)).collect()
+                }
+                None => return false,
+            }
+        };
+        self.mark_dirty();
+
+        if !removed.is_empty() {
+            let excluded: Vec<String> = self
+                .get_all_file_states_by_id(&vault_id)
+                .into_iter()
+                .map(|fs| fs.relative_path)
+                .filter(|path| {
+                    let mut dirs: Vec<&str> = path.split('/').collect();
+                    dirs.pop();
+                    dirs.iter().any(|dir| removed.iter().any(|r| r == dir))
+                })
+                .collect();
+            let dropped = self.remove_file_states_by_id(&vault_id, &excluded);
+            if dropped > 0 {
+                println!("[SyncState] Stopped tracking {} files in {:?}", dropped, removed);
+            }
+        }
+        true
+    }
+
+    /// Set whether a vault (by local path) detects note changes by body
+    /// only. Returns false if the vault is unknown.
+    pub fn set_hash_note_body_only(&self, vault_path: &str, enabled: bool) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.hash_note_body_only = enabled,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Set whether a vault (by local path) follows symlinks; `None` restores
+    /// the default. Returns false if the vault is unknown.
+    pub fn set_follow_symlinks(&self, vault_path: &str, follow: Option<bool>) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.follow_symlinks = follow,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Record whether a vault (by remote ID) is end-to-end encrypted
+    pub fn set_vault_encrypted(&self, vault_id: &str, encrypted: bool) {
+        if let Some(state) = self.vaults.write().get_mut(vault_id) {
+            state.encrypted = encrypted;
+        }
+        self.mark_dirty();
+    }
+
+    /// Count a finished sync of a vault (by local path) towards its circuit
+    /// breaker. A success resets it; a failure that brings the streak to
+    /// [`CIRCUIT_BREAKER_THRESHOLD`] opens the circuit for
+    /// [`CIRCUIT_BREAKER_COOLDOWN_MS`]. Once the cooldown has passed, the
+    /// next failure opens it again right away. Returns true if the circuit
+    /// was just opened.
+    pub fn record_sync_outcome(&self, vault_path: &str, success: bool, now: u64) -> bool {
+        let Some(vault_id) = self.get_vault_id_for_path(vault_path) else {
+            return false;
+        };
+        let opened = {
+            let mut vaults = self.vaults.write();
+            let Some(state) = vaults.get_mut(&vault_id) else {
+                return false;
+            };
+            if success {
+                if state.consecutive_failures == 0 && state.circuit_open_until.is_none() {
+                    return false;
+                }
+                state.consecutive_failures = 0;
+                state.circuit_open_until = None;
+                false
+            } else {
+                state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+                if state.consecutive_failures >= CIRCUIT_BREAKER_THRESHOLD && !state.auto_sync_paused(now) {
+                    state.circuit_open_until = Some(now + CIRCUIT_BREAKER_COOLDOWN_MS);
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        self.mark_dirty();
+        opened
+    }
+
+    /// Let background syncs of a vault (by local path) run again, e.g.
+    /// because the user asked for a sync. The failure streak is kept, so
+    /// another failure reopens the circuit.
+    pub fn close_circuit(&self, vault_path: &str) {
+        let Some(vault_id) = self.get_vault_id_for_path(vault_path) else {
+            return;
+        };
+        if let Some(state) = self.vaults.write().get_mut(&vault_id) {
+            if state.circuit_open_until.take().is_none() {
+                return;
+            }
+        }
+        self.mark_dirty();
+    }
+
+    /// Switch a vault (by remote ID) to scheme-prefixed paths
+    pub fn set_prefixed_paths(&self, vault_id: &str) {
+        if let Some(state) = self.vaults.write().get_mut(vault_id) {
+            if state.prefixed_paths {
+                return;
+            }
+            state.prefixed_paths = true;
+        }
+        self.mark_dirty();
+    }
+
+    /// Whether a vault (by remote ID) sends scheme-prefixed paths
+    pub fn uses_prefixed_paths(&self, vault_id: &str) -> bool {
+        self.vaults.read().get(vault_id).is_some_and(|state| state.prefixed_paths)
+    }
+
+    /// Set how many remote changes a pull requests per page for a vault (by
+    /// local path); values are clamped and `None` restores the default.
+    /// Returns false if the vault is unknown.
+    pub fn set_pull_batch_size(&self, vault_path: &str, batch_size: Option<u32>) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.pull_batch_size = batch_size.map(clamp_pull_batch_size),
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Set after how many days conflict files of a vault (by local path) are
+    /// cleaned up automatically; `None` turns automatic cleanup off. Returns
+    /// false if the vault is unknown.
+    pub fn set_conflict_retention(&self, vault_path: &str, days: Option<u32>) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.conflict_retention_days = days,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Set the background sync interval for a vault (by local path); 0 means
+    /// manual only. Returns false if the vault is unknown.
+    pub fn set_sync_interval(&self, vault_path: &str, seconds: u64) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.sync_interval_seconds = seconds,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Enable or disable placeholder pulls for a vault (by local path).
+    /// Returns false if the vault is unknown.
+    pub fn set_placeholder_mode(&self, vault_path: &str, enabled: bool) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.placeholder_mode = enabled,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
+    /// Relative paths of a vault's files (by local path) that are still
+    /// placeholders, with their content left on the server
+    pub fn placeholder_paths(&self, vault_path: &str) -> HashSet<String> {
+        let Some(vault_id) = self.get_vault_id_for_path(vault_path) else {
+            return HashSet::new();
+        };
+        self.file_states
+            .read()
+            .get(&vault_id)
+            .map(|states| {
+                states
+                    .values()
+                    .filter(|fs| fs.placeholder)
+                    .map(|fs| fs.relative_path.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Whether a vault's file (by local path) is still a placeholder
+    pub fn is_placeholder(&self, vault_path: &str, relative_path: &str) -> bool {
+        self.get_vault_id_for_path(vault_path).is_some_and(|vault_id| {
+            self.get_file_state_by_id(&vault_id, relative_path)
+                .is_some_and(|fs| fs.placeholder)
+        })
+    }
+
+    /// Choose whether deletes of trashed notebooks are pushed right away or
+    /// when the trash is emptied, for a vault (by local path). Returns false
+    /// if the vault is unknown.
+    pub fn set_push_trash_deletes_immediately(&self, vault_path: &str, immediately: bool) -> bool {
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(&vault_id) {
+                Some(state) => state.push_trash_deletes_immediately = immediately,
+                None => return false,
+            }
+        }
+        self.mark_dirty();
+        true
+    }
+
     /// Disable sync for a vault (by local path)
     pub fn disable_vault(&self, vault_path: &str) {
         let vault_id = match self.get_vault_id_for_path(vault_path) {
@@ -263,6 +1261,25 @@ impl SyncStateManager {
         self.mark_dirty();
     }
 
+    /// Put a vault left `Syncing` back to `Idle`; other statuses (e.g. a
+    /// vault disabled mid-sync) are kept. Returns whether it was `Syncing`.
+    pub fn clear_syncing_status(&self, vault_id: &str) -> bool {
+        let cleared = {
+            let mut vaults = self.vaults.write();
+            match vaults.get_mut(vault_id) {
+                Some(state) if state.status == VaultSyncState::Syncing => {
+                    state.status = VaultSyncState::Idle;
+                    true
+                }
+                _ => false,
+            }
+        };
+        if cleared {
+            self.mark_dirty();
+        }
+        cleared
+    }
+
     /// Set vault error (by local path)
     pub fn set_vault_error(&self, vault_path: &str, error: Option<String>) {
         let vault_id = match self.get_vault_id_for_path(vault_path) {
@@ -364,10 +1381,10 @@ impl SyncStateManager {
             let mut file_states = self.file_states.write();
             file_states
                 .entry(vault_id.to_string())
-                .or_insert_with(HashMap::new)
+                .or_default()
                 .insert(state.relative_path.clone(), state);
         }
-        self.mark_dirty();
+        self.mark_vault_dirty(vault_id);
     }
 
     /// Set file sync state (by local path - convenience method)
@@ -385,7 +1402,7 @@ impl SyncStateManager {
                 files.remove(relative_path);
             }
         }
-        self.mark_dirty();
+        self.mark_vault_dirty(vault_id);
     }
 
     /// Remove file state (by local path - convenience method)
@@ -403,6 +1420,47 @@ impl SyncStateManager {
             .unwrap_or_default()
     }
 
+    /// Number of files tracked for a vault (by vault_id)
+    pub fn file_state_count_by_id(&self, vault_id: &str) -> usize {
+        self.file_states.read().get(vault_id).map_or(0, |files| files.len())
+    }
+
+    /// File states of a vault whose file is gone from `vault_path` and that
+    /// haven't been synced for [`ORPHANED_STATE_AGE_MS`], e.g. after the
+    /// file was deleted with an external tool. Pending deletes, renames,
+    /// confirms and trashed files are still in use and never orphaned.
+    pub fn orphaned_file_states_by_id(&self, vault_id: &str, vault_path: &Path, now: u64) -> Vec<FileSyncState> {
+        let mut orphaned: Vec<FileSyncState> = self
+            .get_all_file_states_by_id(vault_id)
+            .into_iter()
+            .filter(|fs| {
+                !fs.pending_delete
+                    && !fs.trashed
+                    && fs.renamed_from.is_none()
+                    && fs.pending_confirm.is_none()
+                    && fs.last_synced_at.map_or(true, |at| now.saturating_sub(at) >= ORPHANED_STATE_AGE_MS)
+                    && !crate::vault::long_path(resolve_local_path(vault_path, &fs.relative_path)).exists()
+            })
+            .collect();
+        orphaned.sort_by(|a, b| a.relative_path.cmp(&b.relative_path));
+        orphaned
+    }
+
+    /// Remove several file states of a vault, returning how many existed
+    pub fn remove_file_states_by_id(&self, vault_id: &str, relative_paths: &[String]) -> usize {
+        let removed = {
+            let mut file_states = self.file_states.write();
+            let Some(files) = file_states.get_mut(vault_id) else {
+                return 0;
+            };
+            relative_paths.iter().filter(|path| files.remove(path.as_str()).is_some()).count()
+        };
+        if removed > 0 {
+            self.mark_vault_dirty(vault_id);
+        }
+        removed
+    }
+
     /// Get all file states for a vault (by local path - convenience method)
     pub fn get_all_file_states(&self, vault_path: &str) -> Vec<FileSyncState> {
         match self.get_vault_id_for_path(vault_path) {
@@ -432,7 +1490,21 @@ impl SyncStateManager {
 
     /// Mark file as synced (by vault_id)
     pub fn mark_synced_by_id(&self, vault_id: &str, relative_path: &str, hash: &str, version: u32) {
+        self.mark_synced_with_body_by_id(vault_id, relative_path, hash, None, version);
+    }
+
+    /// Mark a file as synced (by vault_id), also recording the hash of the
+    /// note's body for body-only change detection
+    pub fn mark_synced_with_body_by_id(
+        &self,
+        vault_id: &str,
+        relative_path: &str,
+        hash: &str,
+        body_hash: Option<String>,
+        version: u32,
+    ) {
         let now = Self::now();
+        let remote_path = self.synced_remote_path_by_id(vault_id, relative_path);
 
         self.set_file_state_by_id(vault_id, FileSyncState {
             relative_path: relative_path.to_string(),
@@ -440,9 +1512,309 @@ impl SyncStateManager {
             remote_hash: Some(hash.to_string()),
             remote_version: Some(version),
             last_synced_at: Some(now),
+            placeholder: false,
+            download_url: None,
+            pending_delete: false,
+            renamed_from: None,
+            pending_confirm: None,
+            trashed: false,
+            body_hash,
+            remote_path,
+        });
+    }
+
+    /// Record a remote file that was pulled as an empty placeholder (by vault_id).
+    /// The local hash is that of an empty file, so the placeholder itself is
+    /// never seen as a local change.
+    pub fn mark_placeholder_by_id(
+        &self,
+        vault_id: &str,
+        relative_path: &str,
+        remote_hash: &str,
+        version: u32,
+        download_url: &str,
+    ) {
+        let now = Self::now();
+        let remote_path = self.synced_remote_path_by_id(vault_id, relative_path);
+
+        self.set_file_state_by_id(vault_id, FileSyncState {
+            relative_path: relative_path.to_string(),
+            local_hash: Some(compute_hash(&[])),
+            remote_hash: Some(remote_hash.to_string()),
+            remote_version: Some(version),
+            last_synced_at: Some(now),
+            placeholder: true,
+            download_url: Some(download_url.to_string()),
+            pending_delete: false,
+            renamed_from: None,
+            pending_confirm: None,
+            trashed: false,
+            body_hash: None,
+            remote_path,
+        });
+    }
+
+    /// Whether a pull must skip writing this file: it lies in a folder that
+    /// is being deleted, or its local delete hasn't been pushed yet
+    pub fn is_pull_blocked_by_id(&self, vault_id: &str, relative_path: &str) -> bool {
+        let in_paused_dir = self.paused_dirs.read()
+            .get(vault_id)
+            .is_some_and(|dirs| dirs.iter().any(|dir| is_under_dir(relative_path, dir)));
+
+        in_paused_dir || self.get_file_state_by_id(vault_id, relative_path)
+            .is_some_and(|state| state.pending_delete || state.trashed)
+    }
+
+    /// Delete a folder inside a vault without sync bringing it back. Pulls
+    /// into the folder are paused while it is removed, and every synced file
+    /// in it is marked as a pending delete so the next sync pushes the
+    /// deletes instead of re-downloading the files. Returns the number of
+    /// synced files marked.
+    pub fn delete_vault_dir(&self, vault_path: &str, relative_path: &str) -> SyncResult<u32> {
+        self.remove_vault_dir(
+            vault_path,
+            relative_path,
+            |state| state.pending_delete = true,
+            |full_path| fs::remove_dir_all(full_path),
+        )
+    }
+
+    /// Move a notebook folder (relative to the vault) to `destination` in
+    /// the trash. Its files' deletes are held back until
+    /// [`Self::release_trashed_dir`], unless the vault pushes trash deletes
+    /// immediately. Returns the number of synced files affected.
+    pub fn trash_vault_dir(&self, vault_path: &str, relative_path: &str, destination: &Path) -> SyncResult<u32> {
+        let immediately = self
+            .get_vault_state(vault_path)
+            .is_some_and(|vault| vault.push_trash_deletes_immediately);
+        self.remove_vault_dir(
+            vault_path,
+            relative_path,
+            |state| {
+                if immediately {
+                    state.pending_delete = true;
+                } else {
+                    state.trashed = true;
+                }
+            },
+            |full_path| fs::rename(full_path, destination),
+        )
+    }
+
+    /// A trashed notebook folder was put back: its files are ordinary
+    /// synced files again. Deletes already pushed are undone by the next
+    /// sync uploading the files as new.
+    pub fn restore_trashed_dir(&self, vault_path: &str, relative_path: &str) {
+        self.update_files_under(vault_path, relative_path, |state| {
+            state.trashed = false;
+            state.pending_delete = false;
         });
     }
 
+    /// The trash holding a notebook folder was emptied: push its deletes
+    /// on the next sync
+    pub fn release_trashed_dir(&self, vault_path: &str, relative_path: &str) {
+        self.update_files_under(vault_path, relative_path, |state| {
+            if state.trashed {
+                state.trashed = false;
+                state.pending_delete = true;
+            }
+        });
+    }
+
+    /// Apply `update` to the file states under a vault folder
+    fn update_files_under(&self, vault_path: &str, relative_path: &str, update: impl Fn(&mut FileSyncState)) {
+        let Some(vault_id) = self.get_vault_id_for_path(vault_path) else {
+            return;
+        };
+        let dir = relative_path.replace('\\', "/").trim_matches('/').to_string();
+        {
+            let mut file_states = self.file_states.write();
+            if let Some(files) = file_states.get_mut(&vault_id) {
+                for state in files.values_mut().filter(|s| is_under_dir(&s.relative_path, &dir)) {
+                    update(state);
+                }
+            }
+        }
+        self.mark_vault_dirty(&vault_id);
+    }
+
+    /// Mark the file states under a vault folder with `mark`, then take the
+    /// folder off disk with `remove` while pulls into it are paused
+    fn remove_vault_dir(
+        &self,
+        vault_path: &str,
+        relative_path: &str,
+        mark: impl Fn(&mut FileSyncState),
+        remove: impl FnOnce(&Path) -> std::io::Result<()>,
+    ) -> SyncResult<u32> {
+        let dir = relative_path.replace('\\', "/").trim_matches('/').to_string();
+        let full_path = Path::new(vault_path).join(&dir);
+
+        let vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => {
+                remove(&full_path).map_err(SyncError::Io)?;
+                return Ok(0);
+            }
+        };
+
+        self.paused_dirs.write().entry(vault_id.clone()).or_default().push(dir.clone());
+
+        let marked = {
+            let mut file_states = self.file_states.write();
+            let mut marked = 0;
+            if let Some(files) = file_states.get_mut(&vault_id) {
+                for state in files.values_mut().filter(|s| is_under_dir(&s.relative_path, &dir)) {
+                    mark(state);
+                    marked += 1;
+                }
+            }
+            marked
+        };
+        self.mark_vault_dirty(&vault_id);
+
+        let result = remove(&full_path).map_err(SyncError::Io);
+
+        if let Some(dirs) = self.paused_dirs.write().get_mut(&vault_id) {
+            if let Some(pos) = dirs.iter().position(|d| *d == dir) {
+                dirs.remove(pos);
+            }
+        }
+
+        result.map(|_| marked)
+    }
+
+    /// Move every file state under `old_prefix` to the same path under
+    /// `new_prefix`, e.g. after a notebook folder was renamed. Entries keep
+    /// their sync history and remember the path they were synced under.
+    /// Returns the number of entries moved.
+    pub fn rename_path_prefix(&self, vault_id: &str, old_prefix: &str, new_prefix: &str) -> u32 {
+        let old_prefix = old_prefix.replace('\\', "/").trim_matches('/').to_string();
+        let new_prefix = new_prefix.replace('\\', "/").trim_matches('/').to_string();
+        if old_prefix == new_prefix {
+            return 0;
+        }
+
+        let moved = {
+            let mut file_states = self.file_states.write();
+            let Some(files) = file_states.get_mut(vault_id) else {
+                return 0;
+            };
+
+            let old_paths: Vec<String> = files
+                .keys()
+                .filter(|path| is_under_dir(path, &old_prefix))
+                .cloned()
+                .collect();
+
+            for old_path in &old_paths {
+                if let Some(mut state) = files.remove(old_path) {
+                    let new_path = format!("{}{}", new_prefix, &old_path[old_prefix.len()..]);
+                    if state.renamed_from.is_none() {
+                        state.renamed_from = Some(old_path.clone());
+                    }
+                    // Moving a file back to where it was synced undoes the rename
+                    if state.renamed_from.as_deref() == Some(new_path.as_str()) {
+                        state.renamed_from = None;
+                    }
+                    state.relative_path = new_path.clone();
+                    files.insert(new_path, state);
+                }
+            }
+            old_paths.len() as u32
+        };
+
+        if moved > 0 {
+            self.mark_vault_dirty(vault_id);
+        }
+        moved
+    }
+
+    /// Renamed files whose rename hasn't been pushed yet, as
+    /// `(synced path, current path)` pairs
+    pub fn pending_renames_by_id(&self, vault_id: &str) -> Vec<(String, String)> {
+        self.file_states.read()
+            .get(vault_id)
+            .map(|files| {
+                files.values()
+                    .filter_map(|s| s.renamed_from.clone().map(|from| (from, s.relative_path.clone())))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Record that an uploaded file still has to be confirmed (by vault_id)
+    pub fn set_pending_confirm_by_id(&self, vault_id: &str, relative_path: &str, file_id: Option<&str>) {
+        {
+            let mut file_states = self.file_states.write();
+            let Some(state) = file_states
+                .get_mut(vault_id)
+                .and_then(|files| files.get_mut(relative_path))
+            else {
+                return;
+            };
+            state.pending_confirm = file_id.map(str::to_string);
+        }
+        self.mark_vault_dirty(vault_id);
+    }
+
+    /// State of the file the server holds at `relative_path` (by vault_id):
+    /// the file synced there, or the renamed file whose `renamed_from` it is
+    pub fn server_copy_by_id(&self, vault_id: &str, relative_path: &str) -> Option<FileSyncState> {
+        let file_states = self.file_states.read();
+        let files = file_states.get(vault_id)?;
+        match files.get(relative_path) {
+            Some(state) if state.renamed_from.is_none() => Some(state.clone()),
+            _ => files
+                .values()
+                .find(|state| state.renamed_from.as_deref() == Some(relative_path))
+                .cloned(),
+        }
+        .filter(|state| state.remote_version.is_some())
+    }
+
+    /// The recorded remote path of a file still synced at `relative_path`,
+    /// to carry over when its state is replaced
+    fn synced_remote_path_by_id(&self, vault_id: &str, relative_path: &str) -> Option<String> {
+        self.file_states
+            .read()
+            .get(vault_id)
+            .and_then(|files| files.get(relative_path))
+            .filter(|state| state.renamed_from.is_none())
+            .and_then(|state| state.remote_path.clone())
+    }
+
+    /// Record the encoded path the server holds a file under (by vault_id)
+    pub fn set_remote_path_by_id(&self, vault_id: &str, relative_path: &str, remote_path: &str) {
+        {
+            let mut file_states = self.file_states.write();
+            let Some(state) = file_states
+                .get_mut(vault_id)
+                .and_then(|files| files.get_mut(relative_path))
+            else {
+                return;
+            };
+            if state.remote_path.as_deref() == Some(remote_path) {
+                return;
+            }
+            state.remote_path = Some(remote_path.to_string());
+        }
+        self.mark_vault_dirty(vault_id);
+    }
+
+    /// Uploads awaiting confirmation as (relative path, file id) pairs
+    pub fn pending_confirms_by_id(&self, vault_id: &str) -> Vec<(String, String)> {
+        self.file_states.read()
+            .get(vault_id)
+            .map(|files| {
+                files.values()
+                    .filter_map(|s| s.pending_confirm.clone().map(|id| (s.relative_path.clone(), id)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// Mark file as synced (by local path - convenience method)
     pub fn mark_synced(&self, vault_path: &str, relative_path: &str, hash: &str, version: u32) {
         if let Some(vault_id) = self.get_vault_id_for_path(vault_path) {
@@ -464,6 +1836,39 @@ impl SyncStateManager {
         self.vault_keys.read().get(vault_id).cloned()
     }
 
+    /// Drop a vault's cached key, so it is fetched again when next needed
+    pub fn forget_vault_key(&self, vault_id: &str) {
+        self.vault_keys.write().remove(vault_id);
+    }
+
+    /// Record the new key of a vault key rotation that is starting
+    pub fn start_key_rotation(&self, vault_id: &str, encrypted_key: String, key_nonce: String) {
+        if let Some(state) = self.vaults.write().get_mut(vault_id) {
+            state.key_rotation = Some(KeyRotation {
+                encrypted_key,
+                key_nonce,
+                rekeyed: HashMap::new(),
+            });
+        }
+        self.mark_dirty();
+    }
+
+    /// Record a server file re-encrypted by the running key rotation
+    pub fn record_rekeyed_file(&self, vault_id: &str, file_id: &str, version: u32) {
+        if let Some(rotation) = self.vaults.write().get_mut(vault_id).and_then(|s| s.key_rotation.as_mut()) {
+            rotation.rekeyed.insert(file_id.to_string(), version);
+        }
+        self.mark_dirty();
+    }
+
+    /// Forget a vault's key rotation once the new key is on the server
+    pub fn finish_key_rotation(&self, vault_id: &str) {
+        if let Some(state) = self.vaults.write().get_mut(vault_id) {
+            state.key_rotation = None;
+        }
+        self.mark_dirty();
+    }
+
     /// Clear all vault keys
     pub fn clear_vault_keys(&self) {
         self.vault_keys.write().clear();
@@ -475,18 +1880,30 @@ impl SyncStateManager {
 
     /// Count pending changes for a vault by comparing current files with stored state
     pub fn count_pending_changes(&self, vault_path: &str) -> u32 {
-        // Check if vault is enabled and get vault_id
-        let vault_state = match self.get_vault_state(vault_path) {
-            Some(s) if s.enabled => s,
-            _ => return 0,
-        };
+        self.pending_changes(vault_path).count
+    }
+
+    /// Count pending changes and also report the files the scan left out
+    pub fn pending_changes(&self, vault_path: &str) -> PendingChanges {
+        match self.get_vault_state(vault_path) {
+            Some(vault_state) => self.pending_changes_for(&vault_state),
+            None => PendingChanges::default(),
+        }
+    }
+
+    /// Pending changes of a vault state that is already at hand, so a vault
+    /// sharing its path with another one is counted against its own file
+    /// states. Always none for disabled vaults.
+    pub fn pending_changes_for(&self, vault_state: &VaultState) -> PendingChanges {
+        if !vault_state.enabled {
+            return PendingChanges::default();
+        }
         let vault_id = &vault_state.vault_id;
 
         // Scan current files
-        let path = Path::new(vault_path);
-        let scan_result = match scan_vault(path) {
+        let scan_result = match self.scan_vault(vault_state) {
             Ok(r) => r,
-            Err(_) => return 0,
+            Err(_) => return PendingChanges::default(),
         };
 
         // Get stored file states (keyed by vault_id)
@@ -495,11 +1912,11 @@ impl SyncStateManager {
 
         let mut pending = 0u32;
 
-        // Count new and modified files
+        // Count new and modified files; placeholders aren't pushed
         for (rel_path, info) in &scan_result.files {
             let needs_sync = match stored_states {
                 Some(states) => match states.get(rel_path) {
-                    Some(stored) => stored.local_hash.as_ref() != Some(&info.content_hash),
+                    Some(stored) => !stored.placeholder && !stored.matches(info, vault_state.hash_note_body_only),
                     None => true, // New file
                 },
                 None => true, // No stored states at all
@@ -510,23 +1927,39 @@ impl SyncStateManager {
             }
         }
 
-        // Count deleted files (files in stored state but not on disk)
+        // Count deleted files (files in stored state but not on disk);
+        // trashed files aren't deleted until the trash is emptied
         if let Some(states) = stored_states {
-            for rel_path in states.keys() {
-                if !scan_result.files.contains_key(rel_path) {
+            for (rel_path, stored) in states {
+                if !stored.trashed
+                    && !stored.placeholder
+                    && !scan_result.files.contains_key(rel_path)
+                    && !scan_result.is_skipped(rel_path)
+                {
                     pending += 1;
                 }
             }
         }
 
-        pending
+        PendingChanges {
+            count: pending,
+            errors: scan_result.errors,
+            skipped: scan_result.skipped,
+        }
     }
 
     // ==========================================
-    // JSON File Persistence
+    // Persistence
     // ==========================================
 
-    /// Mark state as dirty (needs saving)
+    /// Mark the file states of a vault as changed and save
+    fn mark_vault_dirty(&self, vault_id: &str) {
+        self.dirty_vaults.write().insert(vault_id.to_string());
+        self.mark_dirty();
+    }
+
+    /// Mark state as dirty (needs saving). Changes to file states must go
+    /// through [`Self::mark_vault_dirty`] to be saved.
     fn mark_dirty(&self) {
         *self.dirty.write() = true;
         // Auto-save on changes
@@ -535,18 +1968,20 @@ impl SyncStateManager {
         }
     }
 
-    /// Load state from JSON file (synchronous)
-    fn load_sync(&self) -> SyncResult<()> {
-        if !self.state_file.exists() {
-            println!("[SyncState] No existing state file, starting fresh");
-            return Ok(());
-        }
-
-        let content = fs::read_to_string(&self.state_file)
-            .map_err(SyncError::Io)?;
+    /// Write the current state to the store now, e.g. before the app exits
+    pub fn flush(&self) -> SyncResult<()> {
+        self.save_sync()
+    }
 
-        let persisted: PersistedState = serde_json::from_str(&content)
-            .map_err(SyncError::Json)?;
+    /// Load state from the store (synchronous)
+    fn load_sync(&self) -> SyncResult<()> {
+        let persisted = match self.store.load()? {
+            Some(persisted) => persisted,
+            None => {
+                println!("[SyncState] No existing state, starting fresh");
+                return Ok(());
+            }
+        };
 
         // Check if migration is needed (version 0 or 1 = old format keyed by path)
         if persisted.version < STATE_VERSION {
@@ -559,14 +1994,43 @@ impl SyncStateManager {
             *self.path_to_vault_id.write() = persisted.path_to_vault_id;
         }
 
+        // Heal a path index that drifted from the vaults (e.g. after a
+        // partial migration or a hand-edited state file)
+        self.repair_mappings();
+
+        // No sync is running yet, so a `Syncing` status was left behind by
+        // a crash or forced quit mid-sync
+        let vault_ids: Vec<String> = self.vaults.read().keys().cloned().collect();
+        let stuck = vault_ids.iter().filter(|id| self.clear_syncing_status(id)).count();
+        if stuck > 0 {
+            println!("[SyncState] Reset {} vaults left syncing by an interrupted session", stuck);
+        }
+
+        // File states saved before paths were NFC-normalized may be keyed
+        // by decomposed names
+        for (vault_id, files) in self.file_states.write().iter_mut() {
+            if files.keys().all(|path| normalize_path(path) == *path) {
+                continue;
+            }
+            self.dirty_vaults.write().insert(vault_id.clone());
+            *files = std::mem::take(files)
+                .into_iter()
+                .map(|(path, mut file_state)| {
+                    let path = normalize_path(&path);
+                    file_state.relative_path = path.clone();
+                    (path, file_state)
+                })
+                .collect();
+        }
+
         let vault_count = self.vaults.read().len();
         let file_count: usize = self.file_states.read()
             .values()
             .map(|f| f.len())
             .sum();
 
-        println!("[SyncState] Loaded {} vaults, {} file states from {:?}", 
-            vault_count, file_count, self.state_file);
+        println!("[SyncState] Loaded {} vaults, {} file states from {}", 
+            vault_count, file_count, self.store.describe());
 
         Ok(())
     }
@@ -618,6 +2082,7 @@ impl SyncStateManager {
 
         // Store migrated state
         *self.vaults.write() = new_vaults;
+        self.dirty_vaults.write().extend(new_file_states.keys().cloned());
         *self.file_states.write() = new_file_states;
         *self.path_to_vault_id.write() = path_mapping;
 
@@ -628,53 +2093,111 @@ impl SyncStateManager {
         Ok(())
     }
 
-    /// Save state to JSON file (synchronous)
+    /// Drop path mappings that can no longer resolve to a usable vault:
+    /// entries pointing at a vault_id that is gone, and old paths of
+    /// disabled vaults that no longer exist on disk. A vault's current path
+    /// is always kept. Returns the number of entries removed.
+    fn prune_path_mappings(&self) -> usize {
+        let vaults = self.vaults.read();
+        let mut mapping = self.path_to_vault_id.write();
+        let before = mapping.len();
+        mapping.retain(|path, vault_id| match vaults.get(vault_id) {
+            None => false,
+            Some(state) => state.enabled || state.vault_path == *path || Path::new(path).exists(),
+        });
+        before - mapping.len()
+    }
+
+    /// Save state to the store (synchronous)
     fn save_sync(&self) -> SyncResult<()> {
+        let pruned = self.prune_path_mappings();
+        if pruned > 0 {
+            println!("[SyncState] Pruned {} stale path mappings", pruned);
+        }
+
+        // Only the file states of changed vaults are copied and written,
+        // unless the store has to be rewritten as a whole
+        let full = self.store.needs_full_save();
+        let changed = std::mem::take(&mut *self.dirty_vaults.write());
+        let file_states = {
+            let file_states = self.file_states.read();
+            if full {
+                file_states.clone()
+            } else {
+                changed
+                    .iter()
+                    .filter_map(|id| file_states.get(id).map(|files| (id.clone(), files.clone())))
+                    .collect()
+            }
+        };
         let persisted = PersistedState {
             version: STATE_VERSION,
             vaults: self.vaults.read().clone(),
-            file_states: self.file_states.read().clone(),
+            file_states,
             path_to_vault_id: self.path_to_vault_id.read().clone(),
         };
 
-        // Ensure parent directory exists
-        if let Some(parent) = self.state_file.parent() {
-            fs::create_dir_all(parent).map_err(SyncError::Io)?;
+        let result = if full {
+            self.store.save(&persisted)
+        } else {
+            self.store.save_changed(&persisted, &changed)
+        };
+        if let Err(e) = result {
+            // Try these vaults again with the next save
+            self.dirty_vaults.write().extend(changed);
+            return Err(e);
         }
 
-        let content = serde_json::to_string_pretty(&persisted)
-            .map_err(SyncError::Json)?;
-
-        fs::write(&self.state_file, content)
-            .map_err(SyncError::Io)?;
-
         *self.dirty.write() = false;
         Ok(())
     }
 
-    /// Load state from file (async wrapper)
+    /// Load state from the store (async wrapper)
     pub async fn load(&self) -> SyncResult<()> {
         self.load_sync()
     }
 
-    /// Save state to file (async wrapper)
+    /// Save state to the store (async wrapper)
     pub async fn save(&self) -> SyncResult<()> {
         self.save_sync()
     }
 
-    /// Clear all state (memory and file)
+    /// Files the store keeps on disk
+    pub fn data_files(&self) -> Vec<PathBuf> {
+        self.store.files()
+    }
+
+    /// Persist state in `dir` from now on
+    pub fn set_data_dir(&self, dir: &Path) {
+        self.store.set_dir(dir);
+    }
+
+    /// Clear all state (memory and store)
     pub fn clear(&self) {
         self.vaults.write().clear();
         self.file_states.write().clear();
+        self.dirty_vaults.write().clear();
         self.path_to_vault_id.write().clear();
         self.vault_keys.write().clear();
 
-        // Delete state file
-        if self.state_file.exists() {
-            let _ = fs::remove_file(&self.state_file);
+        if let Err(e) = self.store.clear() {
+            eprintln!("[SyncState] Failed to clear stored state: {}", e);
         }
     }
 
+    /// Clear all state and delete the state file along with its backup,
+    /// returning the files that were removed
+    pub fn purge(&self) -> SyncResult<Vec<PathBuf>> {
+        self.vaults.write().clear();
+        self.file_states.write().clear();
+        self.path_to_vault_id.write().clear();
+        self.vault_keys.write().clear();
+        *self.dirty.write() = false;
+        self.dirty_vaults.write().clear();
+
+        self.store.clear()
+    }
+
     /// Clear file states for a specific vault (by local path)
     pub fn clear_vault_file_states(&self, vault_path: &str) {
         if let Some(vault_id) = self.get_vault_id_for_path(vault_path) {
@@ -688,7 +2211,7 @@ impl SyncStateManager {
             let mut file_states = self.file_states.write();
             file_states.remove(vault_id);
         }
-        self.mark_dirty();
+        self.mark_vault_dirty(vault_id);
     }
 
     /// Remove a vault completely (by local path)
@@ -701,6 +2224,67 @@ impl SyncStateManager {
         self.mark_dirty();
     }
 
+    /// Copy of a vault's state, file states and key, to put back with
+    /// [`Self::restore_vault`] if a change to it has to be undone
+    pub fn snapshot_vault(&self, vault_path: &str) -> Option<VaultSnapshot> {
+        let vault_id = self.get_vault_id_for_path(vault_path)?;
+        Some(VaultSnapshot {
+            vault: self.vaults.read().get(&vault_id)?.clone(),
+            file_states: self.file_states.read().get(&vault_id).cloned(),
+            key: self.vault_keys.read().get(&vault_id).cloned(),
+        })
+    }
+
+    /// Put a vault back the way it was when `snapshot` was taken, replacing
+    /// whatever its folder is connected to now
+    pub fn restore_vault(&self, snapshot: VaultSnapshot) {
+        let VaultSnapshot { vault, file_states, key } = snapshot;
+        if let Some(current_id) = self.get_vault_id_for_path(&vault.vault_path) {
+            if current_id != vault.vault_id {
+                self.remove_vault_by_id(&current_id);
+            }
+        }
+        let (vault_id, vault_path) = (vault.vault_id.clone(), vault.vault_path.clone());
+        self.vaults.write().insert(vault_id.clone(), vault);
+        match file_states {
+            Some(file_states) => self.file_states.write().insert(vault_id.clone(), file_states),
+            None => self.file_states.write().remove(&vault_id),
+        };
+        if let Some(key) = key {
+            self.vault_keys.write().insert(vault_id.clone(), key);
+        }
+        self.register_path_mapping(&vault_path, &vault_id);
+        self.mark_vault_dirty(&vault_id);
+    }
+
+    /// Point a vault folder at a different remote vault (e.g. after moving
+    /// it to another server), keeping its local settings. The sync cursor
+    /// and file states are reset, so the next sync uploads every file.
+    /// Returns false if the vault is unknown.
+    pub fn move_vault_to_remote(&self, vault_path: &str, new_vault_id: &str) -> bool {
+        let old_vault_id = match self.get_vault_id_for_path(vault_path) {
+            Some(id) => id,
+            None => return false,
+        };
+        {
+            let mut vaults = self.vaults.write();
+            let Some(mut state) = vaults.remove(&old_vault_id) else {
+                return false;
+            };
+            state.vault_id = new_vault_id.to_string();
+            state.last_cursor = None;
+            state.last_sync_at = None;
+            state.last_error = None;
+            state.status = VaultSyncState::Idle;
+            vaults.insert(new_vault_id.to_string(), state);
+        }
+        self.file_states.write().remove(&old_vault_id);
+        self.vault_keys.write().remove(&old_vault_id);
+        self.register_path_mapping(vault_path, new_vault_id);
+        self.mark_vault_dirty(&old_vault_id);
+        true
+    }
+
     /// Remove a vault completely (by vault_id)
     pub fn remove_vault_by_id(&self, vault_id: &str) {
         // Get the path before removing so we can clean up the mapping
@@ -712,7 +2296,7 @@ impl SyncStateManager {
         if let Some(path) = vault_path {
             self.path_to_vault_id.write().remove(&path);
         }
-        self.mark_dirty();
+        self.mark_vault_dirty(vault_id);
     }
 
     /// Get current timestamp in milliseconds
@@ -731,8 +2315,11 @@ impl Clone for SyncStateManager {
             file_states: Arc::clone(&self.file_states),
             path_to_vault_id: Arc::clone(&self.path_to_vault_id),
             vault_keys: Arc::clone(&self.vault_keys),
-            state_file: self.state_file.clone(),
+            paused_dirs: Arc::clone(&self.paused_dirs),
+            server_config: Arc::clone(&self.server_config),
+            store: Arc::clone(&self.store),
             dirty: Arc::clone(&self.dirty),
+            dirty_vaults: Arc::clone(&self.dirty_vaults),
         }
     }
 }
@@ -745,3 +2332,409 @@ fn compute_simple_hash(s: &str) -> String {
     s.hash(&mut hasher);
     format!("{:016x}", hasher.finish())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::fixture::VaultFixture;
+
+    fn manager_with_memory_store() -> (SyncStateManager, Arc<MemoryStore>) {
+        let store = Arc::new(MemoryStore::new());
+        (SyncStateManager::with_store(store.clone()), store)
+    }
+
+    #[test]
+    fn test_changes_are_saved_to_store() {
+        let (manager, store) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+
+        let saved = store.snapshot().expect("state should be saved");
+        assert_eq!(saved.version, STATE_VERSION);
+        assert!(saved.vaults.contains_key("vault-1"));
+        assert_eq!(saved.path_to_vault_id.get("/vaults/notes").map(String::as_str), Some("vault-1"));
+    }
+
+    #[test]
+    fn test_path_churn_keeps_mapping_bounded() {
+        let (manager, store) = manager_with_memory_store();
+        for i in 0..50 {
+            manager.enable_vault(&format!("/vaults/moved-{}", i), "vault-1");
+            manager.disable_vault_by_id("vault-1");
+        }
+        manager.enable_vault("/vaults/other", "vault-2");
+        manager.remove_vault_by_id("vault-2");
+
+        let saved = store.snapshot().unwrap();
+        assert_eq!(saved.path_to_vault_id.len(), 1);
+        assert_eq!(saved.path_to_vault_id.get("/vaults/moved-49").map(String::as_str), Some("vault-1"));
+        assert_eq!(manager.get_vault_id_for_path("/vaults/moved-49").as_deref(), Some("vault-1"));
+    }
+
+    #[test]
+    fn test_state_is_loaded_from_store() {
+        let (first, store) = manager_with_memory_store();
+        first.enable_vault("/vaults/notes", "vault-1");
+
+        let second = SyncStateManager::with_store(Arc::new(MemoryStore::with_state(
+            store.snapshot().unwrap(),
+        )));
+        assert_eq!(second.get_vault_id_for_path("/vaults/notes").as_deref(), Some("vault-1"));
+    }
+
+    #[test]
+    fn test_purge_clears_store() {
+        let (manager, store) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+
+        let removed = manager.purge().unwrap();
+        assert!(removed.is_empty());
+        assert!(store.snapshot().is_none());
+        assert!(manager.get_vault_id_for_path("/vaults/notes").is_none());
+    }
+
+    #[test]
+    fn test_circuit_breaker_opens_after_repeated_failures() {
+        let (manager, _store) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+        let now = 1_000_000;
+
+        for _ in 1..CIRCUIT_BREAKER_THRESHOLD {
+            assert!(!manager.record_sync_outcome("/vaults/notes", false, now));
+        }
+        assert!(manager.record_sync_outcome("/vaults/notes", false, now));
+        let vault = manager.get_vault_state("/vaults/notes").unwrap();
+        assert!(vault.auto_sync_paused(now));
+        assert!(!vault.auto_sync_paused(now + CIRCUIT_BREAKER_COOLDOWN_MS));
+
+        // A manual sync closes it, but another failure reopens it at once
+        manager.close_circuit("/vaults/notes");
+        assert!(!manager.get_vault_state("/vaults/notes").unwrap().auto_sync_paused(now));
+        assert!(manager.record_sync_outcome("/vaults/notes", false, now));
+
+        assert!(!manager.record_sync_outcome("/vaults/notes", true, now));
+        let vault = manager.get_vault_state("/vaults/notes").unwrap();
+        assert_eq!(vault.consecutive_failures, 0);
+        assert!(!vault.auto_sync_paused(now));
+    }
+
+    #[test]
+    fn test_missing_vault_folder_reports_error() {
+        let mut vault = VaultState::new("vault-1".to_string(), "/definitely/missing/vault".to_string());
+        vault.enabled = true;
+        vault.status = VaultSyncState::Idle;
+
+        let status = vault.to_status(0);
+        assert_eq!(status.status, VaultSyncState::Error);
+        assert_eq!(status.last_error.as_deref(), Some(MISSING_FOLDER_ERROR));
+
+        vault.vault_path = std::env::temp_dir().to_string_lossy().to_string();
+        assert_eq!(vault.to_status(0).status, VaultSyncState::Idle);
+    }
+
+    #[test]
+    fn test_pull_batch_size_is_clamped() {
+        let (manager, _) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+        let batch_size = || manager.get_vault_state("/vaults/notes").unwrap().effective_pull_batch_size();
+
+        assert_eq!(batch_size(), DEFAULT_PULL_BATCH_SIZE);
+        assert!(manager.set_pull_batch_size("/vaults/notes", Some(250)));
+        assert_eq!(batch_size(), 250);
+        assert!(manager.set_pull_batch_size("/vaults/notes", Some(100_000)));
+        assert_eq!(batch_size(), 500);
+        assert!(manager.set_pull_batch_size("/vaults/notes", Some(0)));
+        assert_eq!(batch_size(), 1);
+        assert!(manager.set_pull_batch_size("/vaults/notes", None));
+        assert_eq!(batch_size(), DEFAULT_PULL_BATCH_SIZE);
+        assert!(!manager.set_pull_batch_size("/vaults/other", Some(50)));
+    }
+
+    #[test]
+    fn test_rename_path_prefix_moves_file_states() {
+        let (manager, _) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+        for path in ["Journal/1.md", "Journal/2024/2.md", "Journal2/3.md"] {
+            manager.mark_synced_by_id("vault-1", path, "hash", 4);
+        }
+
+        assert_eq!(manager.rename_path_prefix("vault-1", "Journal", "Diary"), 2);
+
+        let moved = manager.get_file_state_by_id("vault-1", "Diary/2024/2.md").unwrap();
+        assert_eq!(moved.remote_version, Some(4));
+        assert_eq!(moved.renamed_from.as_deref(), Some("Journal/2024/2.md"));
+        assert!(manager.get_file_state_by_id("vault-1", "Journal/1.md").is_none());
+        // A sibling sharing the name prefix is not part of the folder
+        assert!(manager.get_file_state_by_id("vault-1", "Journal2/3.md").is_some());
+
+        let mut renames = manager.pending_renames_by_id("vault-1");
+        renames.sort();
+        assert_eq!(renames, vec![
+            ("Journal/1.md".to_string(), "Diary/1.md".to_string()),
+            ("Journal/2024/2.md".to_string(), "Diary/2024/2.md".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_trashed_dir_deletes_wait_for_release() {
+        let vault = VaultFixture::builder()
+            .synced_note("Journal/1.md", "one")
+            .notebook(".trash")
+            .build();
+        let manager = &vault.state_manager;
+
+        let trashed = vault.path(".trash/1-Journal");
+        assert_eq!(manager.trash_vault_dir(&vault.vault_path, "Journal", &trashed).unwrap(), 1);
+        assert!(trashed.join("1.md").exists());
+        let state = manager.get_file_state_by_id("vault-1", "Journal/1.md").unwrap();
+        assert!(state.trashed && !state.pending_delete);
+        assert!(manager.is_pull_blocked_by_id("vault-1", "Journal/1.md"));
+        vault.assert_pending(0);
+
+        manager.release_trashed_dir(&vault.vault_path, "Journal");
+        let state = manager.get_file_state_by_id("vault-1", "Journal/1.md").unwrap();
+        assert!(!state.trashed && state.pending_delete);
+        vault.assert_pending(1);
+    }
+
+    #[test]
+    fn test_shrinking_include_dirs_forgets_their_files() {
+        let vault = VaultFixture::builder()
+            .synced_note(".obsidian/app.json", "{}")
+            .synced_note("node_modules/x/readme.md", "x")
+            .synced_note("note.md", "note")
+            .build();
+        let manager = &vault.state_manager;
+        let dirs = vec![".obsidian".to_string(), "node_modules".to_string()];
+        assert!(manager.set_include_dirs(&vault.vault_path, dirs));
+        vault.assert_pending(0);
+
+        assert!(manager.set_include_dirs(&vault.vault_path, vec!["node_modules".to_string()]));
+        assert!(manager.get_file_state_by_id("vault-1", ".obsidian/app.json").is_none());
+        vault.assert_synced("node_modules/x/readme.md", 1);
+        vault.assert_synced("note.md", 1);
+        vault.assert_pending(0);
+        assert!(vault.exists(".obsidian/app.json"));
+    }
+
+    #[test]
+    fn test_rename_back_clears_pending_rename() {
+        let (manager, _) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+        manager.mark_synced_by_id("vault-1", "Journal/1.md", "hash", 1);
+
+        manager.rename_path_prefix("vault-1", "Journal", "Diary");
+        manager.rename_path_prefix("vault-1", "Diary", "Log");
+        assert_eq!(
+            manager.get_file_state_by_id("vault-1", "Log/1.md").unwrap().renamed_from.as_deref(),
+            Some("Journal/1.md")
+        );
+
+        manager.rename_path_prefix("vault-1", "Log", "Journal");
+        assert!(manager.pending_renames_by_id("vault-1").is_empty());
+    }
+
+    #[test]
+    fn test_pending_confirm_is_kept_until_synced() {
+        let (manager, store) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+        manager.mark_synced_by_id("vault-1", "a.md", "hash", 2);
+        manager.set_pending_confirm_by_id("vault-1", "a.md", Some("file-1"));
+
+        assert_eq!(
+            manager.pending_confirms_by_id("vault-1"),
+            vec![("a.md".to_string(), "file-1".to_string())]
+        );
+        let saved = store.snapshot().unwrap();
+        assert_eq!(saved.file_states["vault-1"]["a.md"].pending_confirm.as_deref(), Some("file-1"));
+
+        manager.set_pending_confirm_by_id("vault-1", "a.md", None);
+        assert!(manager.pending_confirms_by_id("vault-1").is_empty());
+
+        manager.set_pending_confirm_by_id("vault-1", "a.md", Some("file-2"));
+        manager.mark_synced_by_id("vault-1", "a.md", "hash2", 3);
+        assert!(manager.pending_confirms_by_id("vault-1").is_empty());
+    }
+
+    fn synced_file(path: &str, synced_at: u64) -> FileSyncState {
+        FileSyncState {
+            relative_path: path.to_string(),
+            local_hash: Some("hash".into()),
+            remote_hash: Some("hash".into()),
+            remote_version: Some(1),
+            last_synced_at: Some(synced_at),
+            placeholder: false,
+            download_url: None,
+            pending_delete: false,
+            renamed_from: None,
+            pending_confirm: None,
+            trashed: false,
+            body_hash: None,
+            remote_path: None,
+        }
+    }
+
+    #[test]
+    fn test_json_store_splits_single_file_and_saves_changed_vaults_only() {
+        let dir = std::env::temp_dir().join(format!("echopad-state-split-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(STATE_FILE_NAME);
+
+        // State as saved by older versions, everything in one file
+        let vaults = ["vault-a", "vault-b"].map(|id| VaultState::new(id.to_string(), format!("/vaults/{}", id)));
+        let single = PersistedState {
+            version: STATE_VERSION,
+            vaults: vaults.into_iter().map(|v| (v.vault_id.clone(), v)).collect(),
+            file_states: ["vault-a", "vault-b"]
+                .into_iter()
+                .map(|id| (id.to_string(), [("a.md".to_string(), synced_file("a.md", 1))].into_iter().collect()))
+                .collect(),
+            path_to_vault_id: HashMap::new(),
+        };
+        fs::write(&path, serde_json::to_string(&single).unwrap()).unwrap();
+
+        let store = JsonFileStore::new(path.clone());
+        let manager = SyncStateManager::with_store(Arc::new(JsonFileStore::new(path.clone())));
+        manager.set_file_state_by_id("vault-a", synced_file("b.md", 2));
+        assert!(path.with_extension("json.bak").exists());
+        let index: PersistedState = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(index.file_states.is_empty());
+        assert_eq!(store.vault_files().len(), 2);
+
+        // Mark vault-b's file so a rewrite would be noticed
+        let vault_b = store.vault_file("vault-b");
+        let marked = fs::read_to_string(&vault_b).unwrap().replace("\"a.md\"", "\"marked.md\"");
+        fs::write(&vault_b, &marked).unwrap();
+        manager.set_file_state_by_id("vault-a", synced_file("c.md", 3));
+        assert_eq!(fs::read_to_string(&vault_b).unwrap(), marked);
+
+        let loaded = store.load().unwrap().unwrap();
+        assert!(!store.needs_full_save());
+        assert_eq!(loaded.file_states["vault-a"].len(), 3);
+        assert!(loaded.file_states["vault-b"].contains_key("marked.md"));
+
+        manager.remove_vault_by_id("vault-b");
+        assert!(!vault_b.exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_compact_drops_file_states_of_removed_vaults() {
+        let (manager, store) = manager_with_memory_store();
+        manager.enable_vault("/vaults/notes", "vault-1");
+        manager.set_file_state_by_id("vault-1", synced_file("a.md", 1));
+        manager.set_file_state_by_id("vault-gone", synced_file("b.md", 1));
+
+        assert_eq!(manager.compact(), ["vault-gone"]);
+        assert!(manager.compact().is_empty());
+        let saved = store.snapshot().unwrap();
+        assert_eq!(saved.file_states.keys().collect::<Vec<_>>(), ["vault-1"]);
+    }
+
+    #[test]
+    fn test_orphaned_file_states() {
+        let (manager, _store) = manager_with_memory_store();
+        let vault = std::env::temp_dir().join(format!("echopad-orphans-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+        fs::write(vault.join("kept.md"), "here").unwrap();
+
+        let now = ORPHANED_STATE_AGE_MS * 2;
+        let old = now - ORPHANED_STATE_AGE_MS;
+        manager.set_file_state_by_id("vault-1", synced_file("kept.md", old));
+        manager.set_file_state_by_id("vault-1", synced_file("gone.md", old));
+        manager.set_file_state_by_id("vault-1", synced_file("recent.md", now - 1000));
+        manager.set_file_state_by_id(
+            "vault-1",
+            FileSyncState { pending_delete: true, ..synced_file("deleting.md", old) },
+        );
+
+        let orphaned = manager.orphaned_file_states_by_id("vault-1", &vault, now);
+        let paths: Vec<&str> = orphaned.iter().map(|fs| fs.relative_path.as_str()).collect();
+        assert_eq!(paths, ["gone.md"]);
+
+        let paths: Vec<String> = orphaned.into_iter().map(|fs| fs.relative_path).collect();
+        assert_eq!(manager.remove_file_states_by_id("vault-1", &paths), 1);
+        assert!(manager.get_file_state_by_id("vault-1", "gone.md").is_none());
+        assert_eq!(manager.get_all_file_states_by_id("vault-1").len(), 3);
+
+        let _ = fs::remove_dir_all(vault);
+    }
+
+    #[test]
+    fn test_syncing_status_left_by_a_crash_is_reset_on_load() {
+        let mut stuck = VaultState::new("vault-a".to_string(), "/vaults/a".to_string());
+        stuck.enabled = true;
+        stuck.status = VaultSyncState::Syncing;
+        let disabled = VaultState::new("vault-b".to_string(), "/vaults/b".to_string());
+
+        let persisted = PersistedState {
+            version: STATE_VERSION,
+            vaults: [stuck, disabled].into_iter().map(|v| (v.vault_id.clone(), v)).collect(),
+            file_states: HashMap::new(),
+            path_to_vault_id: HashMap::new(),
+        };
+        let manager = SyncStateManager::with_store(Arc::new(MemoryStore::with_state(persisted)));
+
+        assert_eq!(manager.get_vault_state("/vaults/a").unwrap().status, VaultSyncState::Idle);
+        assert_eq!(manager.get_vault_state("/vaults/b").unwrap().status, VaultSyncState::Disabled);
+        assert!(!manager.clear_syncing_status("vault-b"));
+    }
+
+    #[test]
+    fn test_repair_mappings_rebuilds_index() {
+        let mut vault_a = VaultState::new("vault-a".to_string(), "/vaults/a".to_string());
+        vault_a.enabled = true;
+        let mut vault_b = VaultState::new("vault-b".to_string(), "/vaults/b".to_string());
+        vault_b.enabled = true;
+        // An old, disabled vault pointing at the same folder as vault-b
+        let stale = VaultState::new("vault-old".to_string(), "/vaults/b".to_string());
+
+        let persisted = PersistedState {
+            version: STATE_VERSION,
+            vaults: [vault_a, vault_b, stale]
+                .into_iter()
+                .map(|v| (v.vault_id.clone(), v))
+                .collect(),
+            file_states: HashMap::new(),
+            // vault-a missing, vault-b pointing at the wrong vault, one leftover path
+            path_to_vault_id: [
+                ("/vaults/b".to_string(), "vault-old".to_string()),
+                ("/vaults/gone".to_string(), "vault-gone".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let store = Arc::new(MemoryStore::with_state(persisted));
+        let manager = SyncStateManager::with_store(store.clone());
+
+        assert_eq!(manager.get_vault_id_for_path("/vaults/a").as_deref(), Some("vault-a"));
+        assert_eq!(manager.get_vault_id_for_path("/vaults/b").as_deref(), Some("vault-b"));
+        assert!(manager.get_vault_id_for_path("/vaults/gone").is_none());
+        assert_eq!(store.snapshot().unwrap().path_to_vault_id.len(), 2);
+
+        // Running it again finds the duplicate but nothing to fix
+        let report = manager.repair_mappings();
+        assert!(!report.changed_anything());
+        assert_eq!(report.duplicates, vec![DuplicateVaultPath {
+            vault_path: "/vaults/b".to_string(),
+            vault_ids: vec!["vault-b".to_string(), "vault-old".to_string()],
+            kept_vault_id: "vault-b".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_restore_vault_undoes_a_move_to_another_remote() {
+        let vault = VaultFixture::builder().synced_note("Notes/a.md", "a").build();
+        let manager = &vault.state_manager;
+        let snapshot = manager.snapshot_vault(&vault.vault_path).unwrap();
+
+        assert!(manager.move_vault_to_remote(&vault.vault_path, "vault-new"));
+        manager.mark_synced_by_id("vault-new", "Notes/a.md", "other", 1);
+        manager.restore_vault(snapshot);
+
+        assert_eq!(manager.get_vault_id_for_path(&vault.vault_path).as_deref(), Some("vault-1"));
+        assert!(manager.get_vault_state_by_id("vault-new").is_none());
+        assert!(manager.get_file_state_by_id("vault-new", "Notes/a.md").is_none());
+        vault.assert_synced("Notes/a.md", 1);
+    }
+}
@@ -25,21 +25,58 @@ pub struct UserInfo {
     pub storage_used_bytes: u64,
 }
 
+/// URLs of one vault's sync endpoints
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultEndpoints {
+    pub vault_id: String,
+    pub pull: String,
+    pub push: String,
+    pub confirm: String,
+    pub has: String,
+    pub status: String,
+    pub key: String,
+}
+
+/// URLs the client would request, from `sync_get_endpoints`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncEndpoints {
+    pub server_url: String,
+    pub health: String,
+    pub login: String,
+    pub refresh: String,
+    pub vaults: String,
+    pub devices: String,
+    pub config: String,
+    /// `None` when the vault isn't connected to sync
+    pub vault: Option<VaultEndpoints>,
+    /// Likely problems with the server URL, e.g. a trailing slash
+    pub warnings: Vec<String>,
+}
+
+/// Everything about the signed-in session, returned by `sync_whoami`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub user: UserInfo,
+    pub device_id: String,
+    pub server_url: String,
+    /// When the access token expires (Unix seconds)
+    pub token_expires_at: u64,
+    /// Seconds until the access token expires (0 once it has)
+    pub expires_in_seconds: u64,
+    /// The token is within the refresh window and will be renewed soon
+    pub refresh_due: bool,
+}
+
 /// Subscription tiers
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SubscriptionTier {
+    #[default]
     Free,
     Pro,
     Team,
 }
 
-impl Default for SubscriptionTier {
-    fn default() -> Self {
-        Self::Free
-    }
-}
-
 /// Sync status for a vault
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncStatus {
@@ -47,6 +84,12 @@ pub struct SyncStatus {
     pub user: Option<UserInfo>,
     pub vaults: Vec<VaultSyncStatus>,
     pub last_error: Option<String>,
+    /// Whether all syncing is paused by `sync_pause_all`
+    #[serde(default)]
+    pub paused: bool,
+    /// Unresolved conflicts across all enabled vaults
+    #[serde(default)]
+    pub total_conflicts: u32,
 }
 
 /// Individual vault sync status
@@ -58,21 +101,114 @@ pub struct VaultSyncStatus {
     pub status: VaultSyncState,
     pub last_sync_at: Option<u64>,
     pub pending_changes: u32,
+    /// Why the vault is in the `Error` state
+    #[serde(default)]
+    pub last_error: Option<String>,
+    /// Non-fatal problems, e.g. files that could not be read and won't sync
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Seconds between background syncs; 0 means manual only
+    #[serde(default)]
+    pub sync_interval_seconds: u64,
+    /// When the scheduler will next sync this vault (ms since epoch)
+    #[serde(default)]
+    pub next_sync_at: Option<u64>,
+    /// Whether file content is end-to-end encrypted
+    #[serde(default)]
+    pub encrypted: bool,
+    /// The vault folder can't be written; sync only uploads local changes
+    #[serde(default)]
+    pub read_only: bool,
+    /// Syncs that failed in a row since the last successful one
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    /// Background syncs are paused after repeated errors until this time
+    /// (ms since epoch); a manual sync resumes them
+    #[serde(default)]
+    pub auto_sync_paused_until: Option<u64>,
+    /// Unresolved conflict files in the vault
+    #[serde(default)]
+    pub conflict_count: u32,
+    /// Outcome of the vault's latest sync this session
+    #[serde(default)]
+    pub last_result: Option<LastSyncResult>,
 }
 
 /// Vault sync state
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum VaultSyncState {
+    #[default]
     Idle,
     Syncing,
     Error,
     Disabled,
 }
 
-impl Default for VaultSyncState {
-    fn default() -> Self {
-        Self::Idle
+/// Sync policy recommended by the server (`GET /api/v1/config`). Every
+/// field is optional and unknown fields are ignored, so older clients keep
+/// working when a server adds settings. Per-vault user settings win.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSyncConfig {
+    /// Default file size limit in bytes
+    pub max_file_size: Option<u64>,
+    /// File extensions (without the dot) the server accepts
+    pub allowed_extensions: Option<Vec<String>>,
+    /// Storage usage (percent of quota) at which clients should warn
+    pub quota_warning_percent: Option<u8>,
+}
+
+/// Several vaults in sync state claim the same local folder
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DuplicateVaultPath {
+    pub vault_path: String,
+    /// All vault IDs claiming the folder
+    pub vault_ids: Vec<String>,
+    /// The vault the folder is now mapped to
+    pub kept_vault_id: String,
+}
+
+/// Result of rebuilding the local path -> vault ID index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MappingRepair {
+    /// Paths whose mapping was missing or pointed at the wrong vault
+    pub fixed: Vec<String>,
+    /// Mapped paths that no vault uses any more
+    pub removed: Vec<String>,
+    pub duplicates: Vec<DuplicateVaultPath>,
+}
+
+/// How long key derivation takes on this device, from `sync_benchmark_kdf`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfBenchmark {
+    pub duration_ms: u64,
+    pub memory_kb: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Slower than `crypto::SLOW_KEY_DERIVATION`. The parameters are fixed,
+    /// since keys derived with others wouldn't match the account's, so this
+    /// only explains a slow login.
+    pub slow: bool,
+}
+
+/// Result of moving sync data to another folder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppDataRelocation {
+    pub old_dir: String,
+    pub new_dir: String,
+    /// Names of the files that were moved
+    pub moved_files: Vec<String>,
+    /// Old copies that could not be deleted afterwards, and other problems
+    /// that didn't stop the move
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl MappingRepair {
+    /// Whether the index had to be changed
+    pub fn changed_anything(&self) -> bool {
+        !self.fixed.is_empty() || !self.removed.is_empty()
     }
 }
 
@@ -88,6 +224,129 @@ pub struct SyncOperationResult {
     pub duration_ms: u64,
 }
 
+/// Payload of `sync-upload-progress`, emitted while a large file uploads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadProgress {
+    pub vault_path: String,
+    pub relative_path: String,
+    pub bytes_sent: u64,
+    pub total_bytes: u64,
+}
+
+/// Payload of `download-progress`, emitted while a large file downloads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadProgress {
+    pub vault_path: String,
+    pub relative_path: String,
+    pub bytes_received: u64,
+    /// From `Content-Length`; `None` when the server didn't send one and
+    /// progress is indeterminate
+    pub total_bytes: Option<u64>,
+    /// Percentage complete, when the total is known
+    pub percent: Option<f64>,
+}
+
+/// Payload of `sync-rotate-key-progress`, emitted as each file of a vault
+/// is re-encrypted with its new key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotationProgress {
+    pub vault_path: String,
+    pub relative_path: String,
+    /// Files on the new key so far, including those done by an earlier,
+    /// interrupted run
+    pub files_done: u32,
+    pub files_total: u32,
+}
+
+/// Result of rotating a vault's key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotationResult {
+    /// Files uploaded again under the new key by this run
+    pub files_reencrypted: u32,
+}
+
+/// Step of `sync_migrate_server`, sent in `sync-migrate-progress` events
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStage {
+    LoggingIn,
+    CreatingVault,
+    Uploading,
+    DeletingOld,
+    Done,
+}
+
+/// Payload of `sync-migrate-progress`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerMigrationProgress {
+    pub vault_path: String,
+    pub stage: MigrationStage,
+}
+
+/// Result of moving a vault to another server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerMigration {
+    /// The vault's id on the new server
+    pub vault_id: String,
+    pub server_url: String,
+    /// The sync that uploaded the vault
+    pub result: SyncOperationResult,
+    pub old_vault_deleted: bool,
+    pub warnings: Vec<String>,
+}
+
+/// What the next sync would push, worked out locally without contacting
+/// the server
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct UploadEstimate {
+    /// New or modified files that would be uploaded
+    pub files_to_upload: u32,
+    /// Combined size of those files
+    pub bytes_to_upload: u64,
+    /// Files that would be deleted on the server
+    pub files_to_delete: u32,
+}
+
+/// Local and server storage of one synced vault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VaultStorage {
+    pub vault_path: String,
+    pub vault_id: String,
+    /// Size of the syncable files on disk
+    pub local_bytes: u64,
+    pub local_files: u32,
+    /// Size stored on the server; `None` when it couldn't be fetched
+    pub remote_bytes: Option<u64>,
+    pub remote_files: Option<u32>,
+    /// New or modified files not uploaded yet
+    pub unsynced_bytes: u64,
+    pub unsynced_files: u32,
+    /// Why the vault couldn't be scanned or its server size fetched
+    pub error: Option<String>,
+}
+
+/// Account storage against quota, with a per-vault breakdown
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageBreakdown {
+    /// `None` when the server couldn't be reached
+    pub storage_used_bytes: Option<u64>,
+    pub storage_quota_bytes: Option<u64>,
+    /// Vaults on the server, including ones not synced on this device
+    pub remote_vault_count: Option<u32>,
+    pub local_bytes: u64,
+    /// Local data not on the server yet, across all vaults
+    pub unsynced_bytes: u64,
+    pub vaults: Vec<VaultStorage>,
+}
+
+/// Most recent sync of a vault, kept so a UI can show it later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastSyncResult {
+    pub result: SyncOperationResult,
+    /// When the sync finished (ms since epoch)
+    pub finished_at: u64,
+}
+
 /// Conflict file information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConflictInfo {
@@ -98,6 +357,15 @@ pub struct ConflictInfo {
     pub created_at: u64,
 }
 
+/// Outcome of resolving one conflict in `sync_resolve_all_conflicts`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictResolutionResult {
+    pub original_path: String,
+    pub conflict_path: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
 /// Device information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
@@ -110,19 +378,14 @@ pub struct DeviceInfo {
 }
 
 /// Device type
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum DeviceType {
+    #[default]
     Desktop,
     Mobile,
 }
 
-impl Default for DeviceType {
-    fn default() -> Self {
-        Self::Desktop
-    }
-}
-
 /// Local change record
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LocalChange {
@@ -214,8 +477,19 @@ pub struct VaultInfo {
     pub created_at: u64,
     pub file_count: u32,
     pub total_size_bytes: u64,
+    /// Whether the vault's files are end-to-end encrypted with a vault key.
+    /// Servers that predate the flag only hold plaintext vaults.
+    #[serde(default)]
+    pub encrypted: bool,
+    /// How clients encode paths for this vault: [`PREFIXED_PATH_SCHEME`],
+    /// or `None` for bare base64 (vaults created before path prefixes)
+    #[serde(default)]
+    pub path_scheme: Option<String>,
 }
 
+/// `path_scheme` of vaults whose paths carry a scheme prefix (`b64:`)
+pub const PREFIXED_PATH_SCHEME: &str = "prefixed";
+
 /// Encrypted vault key from server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EncryptedVaultKey {
@@ -224,7 +498,7 @@ pub struct EncryptedVaultKey {
     pub key_nonce: String,
 }
 
-/// Vault sync manifest stored in .lazuli-sync.json
+/// Vault sync manifest stored in .echopad-sync.json
 /// This file is stored in the vault root to persist the vault-to-remote mapping
 /// across app reinstalls and to enable automatic reconnection after auth loss.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -237,23 +511,75 @@ pub struct VaultSyncManifest {
     pub user_id: String,
     /// Timestamp when the vault was first connected
     pub connected_at: u64,
+    /// Whether the remote vault is end-to-end encrypted, so a reconnect on
+    /// a fresh install knows to unlock its key
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Hash of the fields above, to tell a damaged manifest from a valid
+    /// one. Manifests written by older versions have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
 }
 
 impl VaultSyncManifest {
     /// Create a new manifest
-    pub fn new(remote_vault_id: String, server_url: String, user_id: String) -> Self {
+    pub fn new(remote_vault_id: String, server_url: String, user_id: String, encrypted: bool) -> Self {
         Self {
             remote_vault_id,
             server_url,
             user_id,
+            encrypted,
             connected_at: std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .unwrap()
                 .as_millis() as u64,
+            checksum: None,
         }
     }
+
+    /// Hash of every field except the checksum itself
+    pub fn compute_checksum(&self) -> String {
+        json_checksum(serde_json::to_value(self).unwrap_or_default())
+    }
+
+    /// The manifest with its checksum filled in, as it is written to disk
+    pub fn with_checksum(&self) -> Self {
+        Self {
+            checksum: Some(self.compute_checksum()),
+            ..self.clone()
+        }
+    }
+
+    /// Whether the checksum stored in a manifest file matches its content
+    /// (`None` if the manifest has no checksum). Checked on the raw JSON, so
+    /// fields added by a newer version count and still verify.
+    pub fn checksum_matches(json: &str) -> serde_json::Result<Option<bool>> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let checksum = value.get("checksum").and_then(|c| c.as_str()).map(str::to_string);
+        Ok(checksum.map(|checksum| checksum == json_checksum(value)))
+    }
+}
+
+/// Hash of a manifest's JSON without its `checksum` field
+fn json_checksum(mut value: serde_json::Value) -> String {
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("checksum");
+    }
+    super::scanner::compute_hash(value.to_string().as_bytes())
 }
 
 /// The filename for the sync manifest
-pub const SYNC_MANIFEST_FILENAME: &str = ".lazuli-sync.json";
+pub const SYNC_MANIFEST_FILENAME: &str = ".echopad-sync.json";
+
+/// Manifest filename used by earlier versions; renamed on first read
+pub const LEGACY_SYNC_MANIFEST_FILENAME: &str = ".lazuli-sync.json";
+
+/// What the UI gets about a folder's sync manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestInfo {
+    /// A manifest file is present in the folder
+    pub manifest_exists: bool,
+    /// Parsed manifest; `None` if missing or unreadable
+    pub manifest: Option<VaultSyncManifest>,
+}
 
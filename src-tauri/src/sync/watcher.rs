@@ -1,13 +1,20 @@
 //! File system watcher for detecting local changes
+//!
+//! OS watch handles can go stale without reporting an error (the folder was
+//! deleted and recreated, a network share reconnected, ...). A watchdog pass
+//! checks every watched vault and re-establishes dead watches.
 
 #![allow(dead_code)]
 
 use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use parking_lot::{Mutex, RwLock};
 use std::collections::HashSet;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::Arc;
+use std::time::Duration;
 
 use super::error::{SyncError, SyncResult};
 use super::types::ChangeOperation;
@@ -20,7 +27,20 @@ pub struct FileChange {
     pub operation: ChangeOperation,
 }
 
+/// How often the watchdog checks watched vaults. A vault with no events for
+/// this long gets a probe file; if the probe isn't seen by the next check
+/// the watch is restarted.
+pub const WATCHDOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Hidden file created and removed in the vault root to check that events
+/// still arrive (hidden files never reach the change channel)
+const PROBE_FILENAME: &str = ".echopad-watch-probe";
+
 /// File system watcher for vault directories
+///
+/// Cloning is cheap and shares the same watches, which lets the watchdog
+/// thread work on them.
+#[derive(Clone)]
 pub struct VaultWatcher {
     /// Active watchers for each vault
     watchers: Arc<RwLock<Vec<WatcherHandle>>>,
@@ -35,6 +55,33 @@ pub struct VaultWatcher {
 struct WatcherHandle {
     vault_path: PathBuf,
     _watcher: RecommendedWatcher,
+    /// When the last raw event arrived (ms since epoch)
+    last_event_at: Arc<AtomicU64>,
+    /// When a probe was written and not yet seen (0 = none outstanding)
+    probe_sent_at: u64,
+    /// Identity of the watched folder, to notice it being recreated
+    dir_id: Option<(u64, u64)>,
+    /// The folder was missing at the last check
+    missing: bool,
+}
+
+/// Device and inode of a directory, where the platform exposes them
+#[cfg(unix)]
+fn dir_identity(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|m| (m.dev(), m.ino()))
+}
+
+#[cfg(not(unix))]
+fn dir_identity(_path: &Path) -> Option<(u64, u64)> {
+    None
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
 }
 
 impl VaultWatcher {
@@ -51,39 +98,111 @@ impl VaultWatcher {
 
     /// Start watching a vault directory
     pub fn watch(&self, vault_path: &Path) -> SyncResult<()> {
+        let handle = self.create_handle(vault_path)?;
+        self.watchers.write().push(handle);
+        Ok(())
+    }
+
+    /// Set up an OS watch on a vault directory
+    fn create_handle(&self, vault_path: &Path) -> SyncResult<WatcherHandle> {
         let vault_path_buf = vault_path.to_path_buf();
         let tx = self.tx.clone();
         let ignored = Arc::clone(&self.ignored_paths);
+        let last_event_at = Arc::new(AtomicU64::new(now_ms()));
+        let last_event = Arc::clone(&last_event_at);
 
         let mut watcher = notify::recommended_watcher(move |res: Result<Event, notify::Error>| {
             match res {
                 Ok(event) => {
+                    last_event.store(now_ms(), Ordering::SeqCst);
                     if let Some(change) = Self::process_event(&vault_path_buf, event, &ignored) {
                         let _ = tx.send(change);
                     }
                 }
                 Err(e) => {
-                    eprintln!("Watch error: {:?}", e);
+                    eprintln!("Watch error: {}", SyncError::from(e));
                 }
             }
-        }).map_err(|e| SyncError::Io(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Failed to create watcher: {}", e),
-        )))?;
-
-        watcher
-            .watch(vault_path, RecursiveMode::Recursive)
-            .map_err(|e| SyncError::Io(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to watch path: {}", e),
-            )))?;
-
-        self.watchers.write().push(WatcherHandle {
+        })?;
+
+        watcher.watch(vault_path, RecursiveMode::Recursive)?;
+
+        Ok(WatcherHandle {
             vault_path: vault_path.to_path_buf(),
             _watcher: watcher,
-        });
+            last_event_at,
+            probe_sent_at: 0,
+            dir_id: dir_identity(vault_path),
+            missing: false,
+        })
+    }
 
-        Ok(())
+    /// Run one watchdog pass over every watched vault, re-establishing
+    /// watches that have gone stale. Returns the vaults that were restarted.
+    pub fn check_health(&self) -> Vec<PathBuf> {
+        let mut restarted = Vec::new();
+        let mut watchers = self.watchers.write();
+
+        for handle in watchers.iter_mut() {
+            let path = handle.vault_path.clone();
+            if !path.is_dir() {
+                // Nothing to watch until the folder comes back
+                handle.missing = true;
+                continue;
+            }
+
+            let recreated = handle.dir_id.is_some() && dir_identity(&path) != handle.dir_id;
+            let stale = if handle.missing || recreated {
+                true
+            } else if handle.probe_sent_at != 0 {
+                let seen = handle.last_event_at.load(Ordering::SeqCst) >= handle.probe_sent_at;
+                handle.probe_sent_at = 0;
+                !seen
+            } else {
+                let idle = now_ms().saturating_sub(handle.last_event_at.load(Ordering::SeqCst));
+                if idle >= WATCHDOG_INTERVAL.as_millis() as u64 {
+                    handle.probe_sent_at = now_ms();
+                    let probe = path.join(PROBE_FILENAME);
+                    if fs::write(&probe, b"").is_ok() {
+                        let _ = fs::remove_file(&probe);
+                    } else {
+                        // Read-only folder: can't probe, assume healthy
+                        handle.probe_sent_at = 0;
+                    }
+                }
+                false
+            };
+
+            if !stale {
+                continue;
+            }
+
+            match self.create_handle(&path) {
+                Ok(new_handle) => {
+                    println!("[Watcher] Restarted stale watch on {:?}", path);
+                    *handle = new_handle;
+                    restarted.push(path);
+                }
+                Err(e) => eprintln!("[Watcher] Failed to restart watch on {:?}: {}", path, e),
+            }
+        }
+
+        restarted
+    }
+
+    /// Run `check_health` every [`WATCHDOG_INTERVAL`] on a background
+    /// thread, calling `on_restart` for each vault whose watch was restarted
+    pub fn start_watchdog<F>(&self, on_restart: F)
+    where
+        F: Fn(&Path) + Send + 'static,
+    {
+        let watcher = self.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(WATCHDOG_INTERVAL);
+            for path in watcher.check_health() {
+                on_restart(&path);
+            }
+        });
     }
 
     /// Stop watching a vault directory
@@ -122,7 +241,7 @@ impl VaultWatcher {
 
         // Only sync .md files and attachments
         let is_attachment = relative_str.starts_with("attachments/");
-        let is_markdown = path.extension().map_or(false, |ext| ext == "md");
+        let is_markdown = path.extension().is_some_and(|ext| ext == "md");
         
         if !is_markdown && !is_attachment {
             return None;
@@ -215,7 +334,6 @@ impl ChangeDebouncer {
                     pending.retain(|c| {
                         !(c.vault_path == change.vault_path && c.relative_path == change.relative_path)
                     });
-                    return;
                 }
                 _ => {
                     existing.operation = change.operation;
@@ -285,5 +403,37 @@ mod tests {
         let changes = debouncer.take();
         assert_eq!(changes.len(), 0);
     }
-}
 
+    #[test]
+    fn test_watchdog_restarts_watch_on_recreated_folder() {
+        let vault = std::env::temp_dir().join(format!("echopad-watch-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&vault).unwrap();
+
+        let watcher = VaultWatcher::new();
+        watcher.watch(&vault).unwrap();
+        assert!(watcher.check_health().is_empty());
+
+        fs::remove_dir_all(&vault).unwrap();
+        assert!(watcher.check_health().is_empty());
+
+        fs::create_dir_all(&vault).unwrap();
+        assert_eq!(watcher.check_health(), vec![vault.clone()]);
+        assert!(watcher.check_health().is_empty());
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn test_watch_errors_keep_their_cause() {
+        let missing = std::env::temp_dir().join(format!("echopad-missing-{}", uuid::Uuid::new_v4()));
+        match VaultWatcher::new().watch(&missing) {
+            Err(SyncError::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            other => panic!("expected a not-found error, got {:?}", other),
+        }
+
+        let limit = notify::Error::new(notify::ErrorKind::MaxFilesWatch);
+        assert!(matches!(SyncError::from(limit), SyncError::WatchLimitReached));
+        let denied = notify::Error::io(std::io::Error::from(std::io::ErrorKind::PermissionDenied));
+        assert!(matches!(SyncError::from(denied), SyncError::Io(e) if e.kind() == std::io::ErrorKind::PermissionDenied));
+    }
+}
@@ -0,0 +1,191 @@
+//! Vault trash for deleted notebooks
+//!
+//! Deleting a notebook moves its folder to `.trash/<id>/` with a small
+//! `.trash/<id>.json` record of where it came from, so an accidental delete
+//! can be undone. The scanner and note walks skip `.trash`, so trashed notes
+//! disappear from the app and from sync change detection. Their deletes
+//! reach the server when the trash is emptied, or right away if the vault
+//! is set to push trash deletes immediately.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::sync;
+use crate::vault;
+use crate::Notebook;
+
+/// Trash folder in the vault root
+pub const TRASH_DIR: &str = ".trash";
+
+/// A notebook in the trash
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrashEntry {
+    /// Name of the folder inside `.trash`
+    pub id: String,
+    /// Notebook path relative to the vault before it was deleted
+    pub original_path: String,
+    /// When it was moved to the trash (ms since epoch)
+    pub deleted_at: u64,
+    pub note_count: u32,
+}
+
+fn trash_root(vault_path: &str) -> PathBuf {
+    Path::new(vault_path).join(TRASH_DIR)
+}
+
+fn record_path(vault_path: &str, id: &str) -> PathBuf {
+    trash_root(vault_path).join(format!("{}.json", id))
+}
+
+fn read_entry(vault_path: &str, id: &str) -> Result<TrashEntry, String> {
+    let json = fs::read_to_string(record_path(vault_path, id))
+        .map_err(|_| format!("Trash entry not found: {}", id))?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Move a notebook to the trash instead of deleting it
+pub fn move_to_trash(
+    sync_state: &sync::SyncState,
+    vault_path: &str,
+    relative_path: &str,
+) -> Result<TrashEntry, String> {
+    let relative_path = relative_path.replace('\\', "/").trim_matches('/').to_string();
+    let source = vault::resolve_in_vault(Path::new(vault_path), &relative_path)?;
+    if relative_path.is_empty() || !source.is_dir() {
+        return Err("Notebook does not exist".to_string());
+    }
+
+    let deleted_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_millis() as u64;
+    let name = relative_path.rsplit('/').next().unwrap_or(&relative_path);
+
+    let root = trash_root(vault_path);
+    fs::create_dir_all(vault::long_path(&root)).map_err(|e| e.to_string())?;
+    let mut id = format!("{}-{}", deleted_at, name);
+    let mut suffix = 1;
+    while root.join(&id).exists() || record_path(vault_path, &id).exists() {
+        suffix += 1;
+        id = format!("{}-{}-{}", deleted_at, name, suffix);
+    }
+
+    let note_count = vault::collect_note_paths(&source)?.len() as u32;
+    let entry = TrashEntry {
+        id: id.clone(),
+        original_path: relative_path.clone(),
+        deleted_at,
+        note_count,
+    };
+    let json = serde_json::to_string_pretty(&entry).map_err(|e| e.to_string())?;
+    fs::write(record_path(vault_path, &id), json).map_err(|e| e.to_string())?;
+
+    if let Err(e) = sync_state.state_manager.trash_vault_dir(
+        vault_path,
+        &relative_path,
+        &vault::long_path(root.join(&id)),
+    ) {
+        let _ = fs::remove_file(record_path(vault_path, &id));
+        return Err(e.to_string());
+    }
+    Ok(entry)
+}
+
+/// Every trash record, including ones whose folder was removed by hand
+fn records(vault_path: &str) -> Result<Vec<TrashEntry>, String> {
+    let root = trash_root(vault_path);
+    if !root.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries = Vec::new();
+    for item in fs::read_dir(&root).map_err(|e| e.to_string())? {
+        let path = item.map_err(|e| e.to_string())?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            let id = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            if let Ok(entry) = read_entry(vault_path, &id) {
+                entries.push(entry);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Notebooks in the trash, most recently deleted first
+pub fn list(vault_path: &str) -> Result<Vec<TrashEntry>, String> {
+    let root = trash_root(vault_path);
+    let mut entries: Vec<TrashEntry> = records(vault_path)?
+        .into_iter()
+        .filter(|entry| root.join(&entry.id).is_dir())
+        .collect();
+    entries.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+    Ok(entries)
+}
+
+/// Move a trashed notebook back to where it was deleted from
+pub fn restore(sync_state: &sync::SyncState, vault_path: &str, id: &str) -> Result<Notebook, String> {
+    if !vault::is_plain_filename(id) {
+        return Err(format!("Invalid trash entry: {}", id));
+    }
+    let entry = read_entry(vault_path, id)?;
+    let trashed = vault::long_path(trash_root(vault_path).join(id));
+    let destination = vault::resolve_in_vault(Path::new(vault_path), &entry.original_path)?;
+    if destination.exists() {
+        return Err(format!("A notebook already exists at {}", entry.original_path));
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    fs::rename(&trashed, &destination).map_err(|e| e.to_string())?;
+    sync_state.state_manager.restore_trashed_dir(vault_path, &entry.original_path);
+    let _ = fs::remove_file(record_path(vault_path, id));
+
+    Ok(Notebook {
+        name: entry.original_path.rsplit('/').next().unwrap_or_default().to_string(),
+        path: vault::display_path(&destination),
+        relative_path: entry.original_path,
+        children: None,
+    })
+}
+
+/// Permanently delete everything in the trash. Returns the number of
+/// notebooks removed.
+pub fn empty(sync_state: &sync::SyncState, vault_path: &str) -> Result<u32, String> {
+    let mut removed = 0;
+    for entry in records(vault_path)? {
+        let folder = vault::long_path(trash_root(vault_path).join(&entry.id));
+        if folder.is_dir() {
+            fs::remove_dir_all(&folder).map_err(|e| e.to_string())?;
+            removed += 1;
+        }
+        sync_state.state_manager.release_trashed_dir(vault_path, &entry.original_path);
+        let _ = fs::remove_file(record_path(vault_path, &entry.id));
+    }
+    Ok(removed)
+}
+
+/// List notebooks in the vault trash
+#[tauri::command]
+pub fn list_trash(vault_path: String) -> Result<Vec<TrashEntry>, String> {
+    list(&vault_path)
+}
+
+/// Put a trashed notebook back at its original path
+#[tauri::command]
+pub fn restore_notebook(
+    sync_state: tauri::State<'_, sync::SyncState>,
+    vault_path: String,
+    id: String,
+) -> Result<Notebook, String> {
+    restore(&sync_state, &vault_path, &id)
+}
+
+/// Permanently delete the trashed notebooks; their deletes are pushed on
+/// the next sync
+#[tauri::command]
+pub fn empty_trash(sync_state: tauri::State<'_, sync::SyncState>, vault_path: String) -> Result<u32, String> {
+    empty(&sync_state, &vault_path)
+}
@@ -0,0 +1,203 @@
+//! Checks run on a vault before the app starts working in it
+//!
+//! Picking the wrong folder, losing write access or a damaged sync manifest
+//! otherwise surface later as confusing errors from whichever command hits
+//! them first. `validate_vault` runs the checks up front and reports each as
+//! passed, worth a warning, or failed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::sync::types::{VaultSyncManifest, LEGACY_SYNC_MANIFEST_FILENAME, SYNC_MANIFEST_FILENAME};
+use crate::{attachments, vault};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Outcome of one check
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultCheck {
+    /// `path`, `read`, `write`, `notes`, `manifest` or `attachments`
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+/// Result of `validate_vault`
+#[derive(Debug, Clone, Serialize)]
+pub struct VaultValidation {
+    pub vault_path: String,
+    /// No check failed
+    pub ok: bool,
+    pub checks: Vec<VaultCheck>,
+}
+
+fn check(name: &'static str, status: CheckStatus, message: impl Into<String>) -> VaultCheck {
+    VaultCheck {
+        name,
+        status,
+        message: message.into(),
+    }
+}
+
+fn check_path(vault_path: &Path) -> VaultCheck {
+    if !vault_path.exists() {
+        check("path", CheckStatus::Fail, "Folder does not exist")
+    } else if !vault_path.is_dir() {
+        check("path", CheckStatus::Fail, "Path is a file, not a folder")
+    } else {
+        check("path", CheckStatus::Pass, "Folder exists")
+    }
+}
+
+/// Notebooks and notes directly in the vault; nothing is walked recursively,
+/// so picking a huge folder by mistake stays fast
+fn check_notes(vault_path: &Path) -> VaultCheck {
+    let Ok(entries) = fs::read_dir(vault_path) else {
+        return check("notes", CheckStatus::Fail, "Folder can't be listed");
+    };
+    let found = entries.flatten().any(|entry| {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        (path.is_dir() && !vault::is_skipped_dir(&name)) || vault::has_extension_in(&path, vault::NOTE_EXTENSIONS)
+    });
+    if found {
+        check("notes", CheckStatus::Pass, "Notebooks found")
+    } else {
+        check("notes", CheckStatus::Warn, "No notebooks or notes found; is this the right folder?")
+    }
+}
+
+fn check_manifest(vault_path: &Path) -> VaultCheck {
+    let Some(manifest_path) = [SYNC_MANIFEST_FILENAME, LEGACY_SYNC_MANIFEST_FILENAME]
+        .iter()
+        .map(|name| vault_path.join(name))
+        .find(|path| path.exists())
+    else {
+        return check("manifest", CheckStatus::Pass, "Not connected to sync");
+    };
+
+    let checksum = match fs::read_to_string(&manifest_path) {
+        Ok(json) => serde_json::from_str::<VaultSyncManifest>(&json)
+            .and_then(|_| VaultSyncManifest::checksum_matches(&json))
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match checksum {
+        Err(e) => check("manifest", CheckStatus::Fail, format!("Sync manifest is unreadable: {}", e)),
+        Ok(Some(false)) => check("manifest", CheckStatus::Fail, "Sync manifest checksum does not match its content"),
+        Ok(Some(true)) => check("manifest", CheckStatus::Pass, "Sync manifest is valid"),
+        Ok(None) => check("manifest", CheckStatus::Pass, "Sync manifest is readable (no checksum to verify)"),
+    }
+}
+
+fn check_attachments(vault_path: &Path) -> VaultCheck {
+    let dir = vault_path.join("attachments");
+    if !dir.exists() {
+        return check("attachments", CheckStatus::Pass, "No attachments folder");
+    }
+    if !dir.is_dir() {
+        return check("attachments", CheckStatus::Fail, "attachments is a file, not a folder");
+    }
+    match attachments::index_is_consistent(vault_path) {
+        Ok(true) => check("attachments", CheckStatus::Pass, "Attachments folder is readable"),
+        Ok(false) => check(
+            "attachments",
+            CheckStatus::Warn,
+            "Attachment index is out of date; run rebuild_attachment_index",
+        ),
+        Err(e) => check("attachments", CheckStatus::Fail, format!("Attachments folder is unreadable: {}", e)),
+    }
+}
+
+/// Run every check on a vault. Later checks are skipped when the folder is
+/// missing or can't be listed.
+pub fn validate(vault_path: &Path) -> VaultValidation {
+    let mut checks = vec![check_path(vault_path)];
+
+    if checks[0].status == CheckStatus::Pass {
+        checks.push(match fs::read_dir(vault_path) {
+            Ok(_) => check("read", CheckStatus::Pass, "Folder is readable"),
+            Err(e) => check("read", CheckStatus::Fail, format!("Folder can't be read: {}", e)),
+        });
+    }
+
+    if checks.iter().all(|c| c.status == CheckStatus::Pass) {
+        checks.push(if vault::is_read_only(vault_path) {
+            check("write", CheckStatus::Warn, "Vault is read-only; notes can be viewed but not changed")
+        } else {
+            check("write", CheckStatus::Pass, "Folder is writable")
+        });
+        checks.push(check_notes(vault_path));
+        checks.push(check_manifest(vault_path));
+        checks.push(check_attachments(vault_path));
+    }
+
+    VaultValidation {
+        vault_path: vault_path.to_string_lossy().to_string(),
+        ok: checks.iter().all(|c| c.status != CheckStatus::Fail),
+        checks,
+    }
+}
+
+/// Check a vault before working in it, e.g. when it is selected
+#[tauri::command]
+pub fn validate_vault(vault_path: String) -> VaultValidation {
+    validate(&PathBuf::from(vault_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_of(validation: &VaultValidation, name: &str) -> Option<CheckStatus> {
+        validation.checks.iter().find(|c| c.name == name).map(|c| c.status)
+    }
+
+    #[test]
+    fn test_validate_reports_each_check() {
+        let vault = std::env::temp_dir().join(format!("echopad-validate-{}", uuid::Uuid::new_v4()));
+        let missing = validate(&vault);
+        assert!(!missing.ok);
+        assert_eq!(missing.checks.len(), 1);
+
+        fs::create_dir_all(&vault).unwrap();
+        let empty = validate(&vault);
+        assert!(empty.ok);
+        assert_eq!(status_of(&empty, "notes"), Some(CheckStatus::Warn));
+        assert_eq!(status_of(&empty, "manifest"), Some(CheckStatus::Pass));
+
+        fs::create_dir_all(vault.join("Notes")).unwrap();
+        let manifest = VaultSyncManifest::new("vault-1".into(), "https://sync".into(), "user-1".into(), false);
+        fs::write(
+            vault.join(SYNC_MANIFEST_FILENAME),
+            serde_json::to_string(&manifest.with_checksum()).unwrap(),
+        )
+        .unwrap();
+        let valid = validate(&vault);
+        assert!(valid.ok);
+        assert_eq!(status_of(&valid, "notes"), Some(CheckStatus::Pass));
+
+        let mut tampered = manifest.with_checksum();
+        tampered.user_id = "someone-else".into();
+        fs::write(vault.join(SYNC_MANIFEST_FILENAME), serde_json::to_string(&tampered).unwrap()).unwrap();
+        assert_eq!(status_of(&validate(&vault), "manifest"), Some(CheckStatus::Fail));
+
+        // Written by a newer version with a field this one doesn't know
+        let mut newer = serde_json::to_value(&manifest).unwrap();
+        newer["region"] = "eu".into();
+        let checksum = crate::sync::scanner::compute_hash(newer.to_string().as_bytes());
+        newer["checksum"] = checksum.into();
+        fs::write(vault.join(SYNC_MANIFEST_FILENAME), newer.to_string()).unwrap();
+        assert_eq!(status_of(&validate(&vault), "manifest"), Some(CheckStatus::Pass));
+
+        let _ = fs::remove_dir_all(vault);
+    }
+}
@@ -0,0 +1,788 @@
+//! Vault filesystem helpers shared by the note commands
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use icu_collator::{Collator, CollatorOptions};
+use parking_lot::Mutex;
+use serde::Deserialize;
+
+/// Directory names (besides hidden ones) that never contain notes
+const NON_NOTE_DIRS: &[&str] = &["attachments"];
+
+/// Extensions of files listed as notes. The scanner syncs all of these, so
+/// every listed note also syncs.
+pub const NOTE_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+
+/// Whether the file's extension (case-insensitive) is one of `extensions`
+pub fn has_extension_in(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| extensions.contains(&ext.as_str()))
+}
+
+/// Comparator for sorting notebook and note names the way readers of
+/// `locale` expect (a BCP 47 tag such as `de` or `pt-BR`; the system
+/// locale when `None`), so accented and non-Latin names land in the right
+/// place. Falls back to a case-insensitive comparison when no collator can
+/// be built.
+pub fn name_comparator(locale: Option<&str>) -> impl Fn(&str, &str) -> Ordering {
+    let collator = locale
+        .map(str::to_string)
+        .or_else(sys_locale::get_locale)
+        .and_then(|tag| {
+            // OS locales may look like `en_US.UTF-8`
+            let tag = tag.split('.').next().unwrap_or_default().replace('_', "-");
+            tag.parse::<icu_locid::Locale>().ok()
+        })
+        .and_then(|locale| Collator::try_new(&(&locale).into(), CollatorOptions::new()).ok());
+
+    move |a: &str, b: &str| match &collator {
+        Some(collator) => collator.compare(a, b).then_with(|| a.cmp(b)),
+        None => a.to_lowercase().cmp(&b.to_lowercase()),
+    }
+}
+
+/// Whether a directory should be skipped when walking a vault for notes
+pub fn is_skipped_dir(name: &str) -> bool {
+    name.starts_with('.') || NON_NOTE_DIRS.contains(&name)
+}
+
+/// Recursively collect every note (any of [`NOTE_EXTENSIONS`]) in the
+/// vault, skipping hidden directories (`.boards`, `.trash`, ...) and the
+/// attachments folder.
+pub fn collect_note_paths(vault_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut notes = Vec::new();
+    collect_note_paths_recursive(vault_path, &mut notes)?;
+    Ok(notes)
+}
+
+/// Notebooks (relative paths, using `/`) holding nothing but hidden files
+/// and other such notebooks. Only the outermost one of a nested empty tree
+/// is listed. Any other file, note or not, keeps its notebook, and the
+/// attachments folder and hidden folders are never listed.
+pub fn empty_notebooks(vault_path: &Path) -> Result<Vec<String>, String> {
+    let mut empty = Vec::new();
+    collect_empty_notebooks(vault_path, "", &mut empty)?;
+    empty.sort();
+    Ok(empty)
+}
+
+/// Whether `dir` is empty in the sense of [`empty_notebooks`], adding the
+/// outermost empty notebooks below it to `empty`
+fn collect_empty_notebooks(dir: &Path, relative: &str, empty: &mut Vec<String>) -> Result<bool, String> {
+    let mut is_empty = true;
+    let mut empty_children = Vec::new();
+
+    for entry in fs::read_dir(long_path(dir)).map_err(|e| e.to_string())? {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if !path.is_dir() || is_skipped_dir(&name) {
+            is_empty = false;
+            continue;
+        }
+
+        let child = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+        if collect_empty_notebooks(&path, &child, &mut empty_children)? {
+            empty_children.push(child);
+        } else {
+            is_empty = false;
+        }
+    }
+
+    if !is_empty || relative.is_empty() {
+        // Children that are empty themselves stay listed
+        empty.extend(empty_children);
+    }
+    Ok(is_empty)
+}
+
+fn collect_note_paths_recursive(dir: &Path, notes: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| e.to_string())?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        if path.is_dir() {
+            if !is_skipped_dir(&name) {
+                collect_note_paths_recursive(&path, notes)?;
+            }
+        } else if path.is_file() && has_extension_in(&path, NOTE_EXTENSIONS) {
+            notes.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Error returned by commands that would change a read-only vault
+pub const READ_ONLY_ERROR: &str = "Vault is read-only";
+
+/// File briefly created in the vault root to test whether it is writable
+const WRITE_PROBE_FILE: &str = ".echopad-write-probe";
+
+/// How long [`ensure_writable`] trusts the last probe of a vault, so every
+/// save doesn't create and delete the probe file (and wake file watchers)
+const WRITABLE_CHECK_TTL: Duration = Duration::from_secs(30);
+
+/// Latest probe result per vault: when it ran and whether it was read-only
+fn read_only_checks() -> &'static Mutex<HashMap<PathBuf, (Instant, bool)>> {
+    static CHECKS: OnceLock<Mutex<HashMap<PathBuf, (Instant, bool)>>> = OnceLock::new();
+    CHECKS.get_or_init(Default::default)
+}
+
+/// Whether a failed write means the filesystem or folder is read-only
+fn is_read_only_error(e: &io::Error) -> bool {
+    #[cfg(unix)]
+    const READ_ONLY_FS: i32 = 30; // EROFS
+    #[cfg(windows)]
+    const READ_ONLY_FS: i32 = 19; // ERROR_WRITE_PROTECT
+    #[cfg(not(any(unix, windows)))]
+    const READ_ONLY_FS: i32 = -1;
+
+    e.kind() == io::ErrorKind::PermissionDenied || e.raw_os_error() == Some(READ_ONLY_FS)
+}
+
+/// Whether the vault is on read-only media or a folder the app can't write
+/// to. Tested by writing and removing a small file, since a read-only mount
+/// still reports writable permissions. A missing vault isn't read-only.
+pub fn is_read_only(vault_path: &Path) -> bool {
+    if !vault_path.is_dir() {
+        return false;
+    }
+    let probe = long_path(vault_path.join(WRITE_PROBE_FILE));
+    let read_only = match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            false
+        }
+        Err(e) => is_read_only_error(&e),
+    };
+    read_only_checks().lock().insert(vault_path.to_path_buf(), (Instant::now(), read_only));
+    read_only
+}
+
+/// Fail with [`READ_ONLY_ERROR`] when the vault can't be written. A probe
+/// from the last [`WRITABLE_CHECK_TTL`] is reused instead of probing again.
+pub fn ensure_writable(vault_path: &Path) -> Result<(), String> {
+    let recent = read_only_checks()
+        .lock()
+        .get(vault_path)
+        .filter(|(checked_at, _)| checked_at.elapsed() < WRITABLE_CHECK_TTL)
+        .map(|(_, read_only)| *read_only);
+    if recent.unwrap_or_else(|| is_read_only(vault_path)) {
+        return Err(READ_ONLY_ERROR.to_string());
+    }
+    Ok(())
+}
+
+/// Recursively collect every file in the vault's attachments folder,
+/// leaving out dotfiles such as the attachment index
+pub fn collect_attachment_paths(vault_path: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    let attachments = vault_path.join("attachments");
+    if attachments.is_dir() {
+        collect_files_recursive(&attachments, &mut files)?;
+    }
+    files.retain(|path| !path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.')));
+    Ok(files)
+}
+
+fn collect_files_recursive(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    for entry in fs::read_dir(dir).map_err(|e| e.to_string())? {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            collect_files_recursive(&path, files)?;
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Form of `path` to hand to filesystem calls. On Windows, absolute paths
+/// get the `\\?\` extended-length prefix so deeply nested notes past the
+/// 260 character MAX_PATH limit can still be read and written. Windows
+/// doesn't normalize prefixed paths, so `/`, `.` and `..` are resolved here
+/// first. Elsewhere the path is returned unchanged.
+pub fn long_path(path: impl AsRef<Path>) -> PathBuf {
+    #[cfg(windows)]
+    {
+        windows_long_path(path.as_ref())
+    }
+    #[cfg(not(windows))]
+    {
+        path.as_ref().to_path_buf()
+    }
+}
+
+#[cfg(windows)]
+fn windows_long_path(path: &Path) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let mut components = path.components();
+    let mut long = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => OsString::from(format!(r"\\?\{}:", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut unc = OsString::from(r"\\?\UNC\");
+                unc.push(server);
+                unc.push(r"\");
+                unc.push(share);
+                unc
+            }
+            // Already verbatim, or a device path
+            _ => return path.to_path_buf(),
+        },
+        // Relative paths can't take the prefix
+        _ => return path.to_path_buf(),
+    };
+
+    let mut parts = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+
+    if parts.is_empty() {
+        long.push(r"\");
+    }
+    for part in parts {
+        long.push(r"\");
+        long.push(part);
+    }
+    PathBuf::from(long)
+}
+
+/// Path for showing to the user or returning to the frontend, without the
+/// extended-length prefix added by [`long_path`]
+pub fn display_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if let Some(rest) = path.strip_prefix(r"\\?\UNC\") {
+        format!(r"\\{}", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\?\") {
+        rest.to_string()
+    } else {
+        path.into_owned()
+    }
+}
+
+/// Join a vault-relative path onto the vault, rejecting absolute paths and
+/// `..` components that would escape it
+pub fn resolve_in_vault(vault_path: &Path, relative: &str) -> Result<PathBuf, String> {
+    use std::path::Component;
+
+    let relative = Path::new(relative);
+    if relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(format!("Path {:?} is outside the vault", relative));
+    }
+    Ok(long_path(vault_path.join(relative)))
+}
+
+/// Whether `filename` is a bare file name (no directories)
+pub fn is_plain_filename(filename: &str) -> bool {
+    let path = Path::new(filename);
+    !filename.is_empty() && path.file_name().is_some_and(|name| name == path.as_os_str())
+}
+
+/// Split a note path into its notebook path (relative to the vault, using `/`)
+/// and filename. Notes at the vault root have an empty notebook path.
+pub fn split_note_path(vault_path: &Path, note_path: &Path) -> Option<(String, String)> {
+    let relative = note_path.strip_prefix(vault_path).ok()?;
+    let filename = relative.file_name()?.to_string_lossy().to_string();
+    let notebook_path = relative
+        .parent()
+        .map(|p| p.to_string_lossy().to_string().replace('\\', "/"))
+        .unwrap_or_default();
+    Some((notebook_path, filename))
+}
+
+/// Parse the `created_at` timestamp encoded in a note's filename stem,
+/// ignoring the `-<n>` suffix of [`create_note_file`]
+pub fn created_at_from_filename(path: &Path) -> u64 {
+    let stem = path.file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let timestamp = match stem.split_once('-') {
+        Some((timestamp, counter)) if counter.parse::<u32>().is_ok() => timestamp,
+        _ => &stem,
+    };
+    timestamp.parse::<u64>().unwrap_or(0)
+}
+
+/// Create a new note named after `timestamp` in `dir` without ever replacing
+/// an existing file. If `<timestamp>.md` is taken (two notes created in the
+/// same millisecond), `<timestamp>-1.md`, `<timestamp>-2.md`, ... are tried
+/// in turn. Returns the filename used.
+pub fn create_note_file(dir: &Path, timestamp: u64, contents: &[u8]) -> io::Result<String> {
+    let mut counter = 0u32;
+    loop {
+        let filename = match counter {
+            0 => format!("{}.md", timestamp),
+            n => format!("{}-{}.md", timestamp, n),
+        };
+        match fs::OpenOptions::new().write(true).create_new(true).open(dir.join(&filename)) {
+            Ok(mut file) => {
+                file.write_all(contents)?;
+                return Ok(filename);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => counter += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Filesystem modification time in milliseconds (0 if unavailable)
+pub fn modified_at_ms(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Bytes available to this user on the filesystem holding `path`, if that
+/// can be found out
+pub fn available_space(path: &Path) -> Option<u64> {
+    fs4::available_space(path).ok()
+}
+
+/// Skip a leading `---` frontmatter block, returning the remaining body
+pub fn strip_frontmatter(content: &str) -> &str {
+    let trimmed = content.trim_start_matches('\u{feff}');
+    if !trimmed.starts_with("---") {
+        return trimmed;
+    }
+
+    let after_open = match trimmed.find('\n') {
+        Some(idx) => &trimmed[idx + 1..],
+        None => return trimmed,
+    };
+
+    let mut offset = 0;
+    for line in after_open.split_inclusive('\n') {
+        offset += line.len();
+        if line.trim() == "---" {
+            return &after_open[offset..];
+        }
+    }
+
+    trimmed
+}
+
+/// Whether the note starts with a `---` frontmatter block
+pub fn has_frontmatter(content: &str) -> bool {
+    let trimmed = content.trim_start_matches('\u{feff}');
+    strip_frontmatter(content).len() != trimmed.len()
+}
+
+/// Whether a note has no text besides whitespace and its frontmatter
+pub fn is_blank_note(content: &str) -> bool {
+    strip_frontmatter(content).trim().is_empty()
+}
+
+/// Prepend a frontmatter block recording `created_at` (ms since epoch),
+/// keeping a leading byte order mark in front
+pub fn with_created_at_frontmatter(content: &str, created_at: u64) -> String {
+    let bom = if content.starts_with('\u{feff}') { "\u{feff}" } else { "" };
+    format!(
+        "{}---\ncreated_at: {}\n---\n{}",
+        bom,
+        created_at,
+        content.trim_start_matches('\u{feff}')
+    )
+}
+
+/// Set a top-level `key: value` line in the note's frontmatter, replacing
+/// the existing one or adding it. A note without frontmatter gets a new
+/// block, after a leading byte order mark.
+pub fn with_frontmatter_value(content: &str, key: &str, value: &str) -> String {
+    let trimmed = content.trim_start_matches('\u{feff}');
+    let bom = &content[..content.len() - trimmed.len()];
+    let line = format!("{}: {}", key, value);
+    if !has_frontmatter(content) {
+        return format!("{}---\n{}\n---\n{}", bom, line, trimmed);
+    }
+
+    let body = strip_frontmatter(content);
+    let block = &trimmed[..trimmed.len() - body.len()];
+    let newline = if block.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<&str> = block.lines().collect();
+    match lines
+        .iter()
+        .skip(1)
+        .position(|l| l.split_once(':').is_some_and(|(name, _)| name.trim_end() == key))
+    {
+        Some(index) => lines[index + 1] = &line,
+        None => lines.insert(lines.len() - 1, &line),
+    }
+    format!("{}{}{}{}", bom, lines.join(newline), newline, body)
+}
+
+/// First name not taken in `dir` among `<timestamp>.<extension>`,
+/// `<timestamp>-1.<extension>`, ..., following [`create_note_file`]
+pub fn free_note_filename(dir: &Path, timestamp: u64, extension: &str) -> String {
+    let mut counter = 0u32;
+    loop {
+        let filename = match counter {
+            0 => format!("{}.{}", timestamp, extension),
+            n => format!("{}-{}.{}", timestamp, n, extension),
+        };
+        if !long_path(dir.join(&filename)).exists() {
+            return filename;
+        }
+        counter += 1;
+    }
+}
+
+/// Value of a top-level `key:` line in the note's frontmatter, unquoted
+pub fn frontmatter_value<'a>(content: &'a str, key: &str) -> Option<&'a str> {
+    if !has_frontmatter(content) {
+        return None;
+    }
+    let trimmed = content.trim_start_matches('\u{feff}');
+    let block = &trimmed[..trimmed.len() - strip_frontmatter(content).len()];
+    block
+        .lines()
+        .skip(1)
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim_end() == key)
+        .map(|(_, value)| {
+            let value = value.trim();
+            value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+                .unwrap_or(value)
+        })
+}
+
+/// Whether a note opts out of sync encryption with `encrypt: false` in its
+/// frontmatter, so it is uploaded readable even to an encrypted vault
+pub fn opts_out_of_encryption(content: &str) -> bool {
+    frontmatter_value(content, "encrypt").is_some_and(|value| value.eq_ignore_ascii_case("false"))
+}
+
+/// Title of a note: a frontmatter `title:` if set, otherwise its first
+/// non-empty body line with heading markers removed
+pub fn extract_note_title(content: &str) -> Option<String> {
+    frontmatter_value(content, "title")
+        .map(str::trim)
+        .filter(|title| !title.is_empty())
+        .into_iter()
+        .chain(
+            strip_frontmatter(content)
+                .lines()
+                .map(|line| line.trim().trim_start_matches('#').trim()),
+        )
+        .find(|line| !line.is_empty())
+        .map(|line| line.chars().take(120).collect())
+}
+
+/// Plain-text preview of a note: the body without frontmatter or heading
+/// markers, whitespace collapsed, truncated to `max_chars` characters
+pub fn note_preview(content: &str, max_chars: usize) -> String {
+    let body = strip_frontmatter(content)
+        .lines()
+        .map(|line| line.trim_start().trim_start_matches('#'))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    body.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(max_chars)
+        .collect()
+}
+
+/// Where `split_note` cuts a note
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitMode {
+    /// At `---` lines (the delimiter lines are dropped)
+    HorizontalRule,
+    /// Before each `# ` heading (the heading starts the new note)
+    Heading,
+}
+
+/// Split a note body into segments at `mode`'s delimiters, ignoring ones
+/// inside fenced code blocks. Segments are trimmed of surrounding blank
+/// lines and empty ones are dropped.
+pub fn split_segments(body: &str, mode: SplitMode) -> Vec<String> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut in_fence = false;
+
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            in_fence = !in_fence;
+        }
+
+        if !in_fence {
+            match mode {
+                SplitMode::HorizontalRule if trimmed == "---" => {
+                    segments.push(std::mem::take(&mut current));
+                    continue;
+                }
+                SplitMode::Heading if line.starts_with("# ") => {
+                    segments.push(std::mem::take(&mut current));
+                }
+                _ => {}
+            }
+        }
+        current.push_str(line);
+    }
+    segments.push(current);
+
+    segments
+        .into_iter()
+        .map(|segment| segment.trim_matches(|c| c == '\n' || c == '\r').trim_end().to_string())
+        .filter(|segment| !segment.trim().is_empty())
+        .map(|segment| segment + "\n")
+        .collect()
+}
+
+/// Characters that aren't allowed in a notebook name on some platform
+const INVALID_NAME_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Check a `/`-separated notebook path relative to the vault. Every part
+/// must be a name the notebook list would show and every platform accepts,
+/// and the path can't leave the vault.
+pub fn validate_notebook_path(notebook_path: &str) -> Result<(), String> {
+    let parts: Vec<&str> = notebook_path.split('/').collect();
+    if notebook_path.trim().is_empty() {
+        return Err("Notebook path is empty".to_string());
+    }
+    for name in parts {
+        if name.trim().is_empty() || name == ".." {
+            return Err(format!("Invalid notebook path: {}", notebook_path));
+        }
+        if is_skipped_dir(name) {
+            return Err(format!("Notebook name is reserved: {}", name));
+        }
+        if name.chars().any(|c| c.is_control() || INVALID_NAME_CHARS.contains(&c)) || name.ends_with([' ', '.']) {
+            return Err(format!("Invalid notebook name: {}", name));
+        }
+    }
+    Ok(())
+}
+
+/// The `YYYY/MM` folder a note created at `timestamp` (ms) is filed under
+/// with date-based filing. `utc_offset_minutes` shifts the timestamp to the
+/// user's local time so notes written just before midnight on the last day
+/// of a month land in that month.
+pub fn dated_folder(timestamp: u64, utc_offset_minutes: i32) -> String {
+    let seconds = (timestamp / 1000) as i64 + i64::from(utc_offset_minutes) * 60;
+    // Days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let days = seconds.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}/{:02}", year, month)
+}
+
+/// Whether a notebook path already ends in a `YYYY/MM` folder of date-based
+/// filing, so notes created in it aren't filed another level down
+pub fn is_dated_folder(notebook_path: &str) -> bool {
+    let notebook_path = notebook_path.replace('\\', "/");
+    let mut parts = notebook_path.trim_matches('/').rsplit('/');
+    let (Some(month), Some(year)) = (parts.next(), parts.next()) else {
+        return false;
+    };
+    let digits = |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    digits(year, 4) && digits(month, 2) && matches!(month.parse::<u8>(), Ok(1..=12))
+}
+
+/// Number of words in the note body (frontmatter excluded)
+pub fn word_count(content: &str) -> u32 {
+    strip_frontmatter(content).split_whitespace().count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_frontmatter_value() {
+        assert_eq!(with_frontmatter_value("# Title\n", "created_at", "5"), "---\ncreated_at: 5\n---\n# Title\n");
+        assert_eq!(
+            with_frontmatter_value("\u{feff}---\ntitle: x\ncreated_at: 1\n---\nbody", "created_at", "5"),
+            "\u{feff}---\ntitle: x\ncreated_at: 5\n---\nbody"
+        );
+        assert_eq!(
+            with_frontmatter_value("---\r\ntitle: x\r\n---\r\nbody", "created_at", "5"),
+            "---\r\ntitle: x\r\ncreated_at: 5\r\n---\r\nbody"
+        );
+        let updated = with_frontmatter_value("---\ntitle: x\n---", "created_at", "5");
+        assert_eq!(frontmatter_value(&updated, "created_at"), Some("5"));
+    }
+
+    #[test]
+    fn test_available_space() {
+        let temp = std::env::temp_dir();
+        assert!(available_space(&temp).is_some());
+        assert_eq!(available_space(&temp.join(uuid::Uuid::new_v4().to_string())), None);
+    }
+
+    #[test]
+    fn test_split_segments_horizontal_rule() {
+        let body = "One\n\n---\n\nTwo\n```\n---\n```\n---\n---\nThree";
+        assert_eq!(
+            split_segments(body, SplitMode::HorizontalRule),
+            vec!["One\n", "Two\n```\n---\n```\n", "Three\n"]
+        );
+        assert_eq!(split_segments("Just one\n", SplitMode::HorizontalRule), vec!["Just one\n"]);
+    }
+
+    #[test]
+    fn test_split_segments_heading() {
+        let body = "Intro\n# First\ntext\n## Sub\n~~~\n# not a heading\n~~~\n# Second\n";
+        assert_eq!(
+            split_segments(body, SplitMode::Heading),
+            vec!["Intro\n", "# First\ntext\n## Sub\n~~~\n# not a heading\n~~~\n", "# Second\n"]
+        );
+        assert_eq!(split_segments("# Only\nbody", SplitMode::Heading), vec!["# Only\nbody\n"]);
+    }
+
+    #[test]
+    fn test_dated_folder() {
+        // 2023-11-14T22:13:20Z
+        assert_eq!(dated_folder(1_700_000_000_000, 0), "2023/11");
+        assert_eq!(dated_folder(0, 0), "1970/01");
+        // 2024-02-29T23:30:00Z is already March an hour east of UTC
+        assert_eq!(dated_folder(1_709_249_400_000, 0), "2024/02");
+        assert_eq!(dated_folder(1_709_249_400_000, 60), "2024/03");
+        // 2024-02-01T00:30:00Z is still January an hour west of UTC
+        assert_eq!(dated_folder(1_706_747_400_000, -60), "2024/01");
+    }
+
+    #[test]
+    fn test_is_dated_folder() {
+        assert!(is_dated_folder("2024/03"));
+        assert!(is_dated_folder("Journal/2024/12/"));
+        assert!(is_dated_folder("Journal\\2024\\01"));
+        assert!(!is_dated_folder("Journal"));
+        assert!(!is_dated_folder("2024"));
+        assert!(!is_dated_folder("Journal/2024/13"));
+        assert!(!is_dated_folder("Journal/24/03"));
+    }
+
+    #[test]
+    fn test_validate_notebook_path() {
+        assert!(validate_notebook_path("Inbox").is_ok());
+        assert!(validate_notebook_path("Work/Meetings 2024").is_ok());
+        for invalid in ["", "Work//Notes", "../outside", ".hidden", "attachments", "a:b", "Trailing.", "Work/ "] {
+            assert!(validate_notebook_path(invalid).is_err(), "{} should be rejected", invalid);
+        }
+    }
+
+    #[test]
+    fn test_empty_notebooks_lists_outermost_empty_folders() {
+        let vault = std::env::temp_dir().join(format!("echopad-empty-{}", uuid::Uuid::new_v4()));
+        for dir in ["Empty/Nested/Deeper", "Kept/Gone", "Kept/Hidden", "Pdf", "attachments", ".boards"] {
+            fs::create_dir_all(vault.join(dir)).unwrap();
+        }
+        fs::write(vault.join("Kept/note.md"), "note").unwrap();
+        fs::write(vault.join("Kept/Hidden/.DS_Store"), "").unwrap();
+        fs::write(vault.join("Pdf/paper.pdf"), "").unwrap();
+
+        assert_eq!(
+            empty_notebooks(&vault).unwrap(),
+            vec!["Empty".to_string(), "Kept/Gone".to_string(), "Kept/Hidden".to_string()]
+        );
+
+        fs::remove_dir_all(&vault).unwrap();
+    }
+
+    #[test]
+    fn test_encrypt_false_opts_out_of_encryption() {
+        assert!(opts_out_of_encryption("---\nencrypt: false\n---\nList"));
+        assert!(opts_out_of_encryption("---\ntitle: List\nencrypt: \"False\"\n---\n"));
+        assert!(!opts_out_of_encryption("---\nencrypt: true\n---\nList"));
+        assert!(!opts_out_of_encryption("encrypt: false\nList"));
+    }
+
+    #[test]
+    fn test_title_prefers_frontmatter_then_first_line() {
+        assert_eq!(
+            extract_note_title("---\ncreated_at: 1\ntitle: \"Plans\"\n---\n# Heading\n").as_deref(),
+            Some("Plans")
+        );
+        assert_eq!(extract_note_title("---\ncreated_at: 1\n---\n\n## Heading\nbody").as_deref(), Some("Heading"));
+        assert_eq!(extract_note_title("\n  first line\nsecond").as_deref(), Some("first line"));
+        assert_eq!(extract_note_title("---\ntitle:\n---\n").as_deref(), None);
+    }
+
+    #[test]
+    fn test_notes_created_in_the_same_millisecond_are_kept() {
+        let dir = std::env::temp_dir().join(format!("echopad-notes-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let filenames: Vec<String> = (0..50)
+            .map(|i| create_note_file(&dir, 1700000000000, format!("note {}", i).as_bytes()).unwrap())
+            .collect();
+
+        assert_eq!(filenames[0], "1700000000000.md");
+        assert_eq!(filenames[1], "1700000000000-1.md");
+        for (i, filename) in filenames.iter().enumerate() {
+            let path = dir.join(filename);
+            assert_eq!(fs::read_to_string(&path).unwrap(), format!("note {}", i));
+            assert_eq!(created_at_from_filename(&path), 1700000000000);
+        }
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 50);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_writable_vault_is_not_read_only_and_probe_is_removed() {
+        let dir = std::env::temp_dir().join(format!("echopad-probe-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        assert!(!is_read_only(&dir));
+        assert!(ensure_writable(&dir).is_ok());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        assert!(!is_read_only(&dir.join("missing")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_ensure_writable_reuses_a_recent_probe() {
+        let dir = std::env::temp_dir().join(format!("echopad-probe-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+
+        read_only_checks().lock().insert(dir.clone(), (Instant::now(), true));
+        assert_eq!(ensure_writable(&dir), Err(READ_ONLY_ERROR.to_string()));
+
+        // Probing again replaces the remembered result
+        assert!(!is_read_only(&dir));
+        assert!(ensure_writable(&dir).is_ok());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,6 +3,7 @@ import { Note, NoteMetadata } from '../types'
 import {
   listNotes,
   readNote,
+  materializeNote,
   createNote as apiCreateNote,
   updateNote as apiUpdateNote,
   deleteNote as apiDeleteNote,
@@ -15,6 +16,25 @@ import {
 
 const PAGE_SIZE = 100
 
+// Placeholders are downloaded when their notebook is opened. One that can't
+// be (e.g. offline) stays a placeholder and can't be edited until it is.
+async function readListedNote(
+  vaultPath: string,
+  notebookPath: string,
+  meta: NoteMetadata
+): Promise<Note> {
+  if (meta.placeholder) {
+    try {
+      await materializeNote(vaultPath, notebookPath, meta.filename)
+    } catch (err) {
+      console.warn(`Failed to download ${meta.filename}:`, err)
+      const note = await readNote(vaultPath, notebookPath, meta.filename)
+      return { ...note, placeholder: true }
+    }
+  }
+  return readNote(vaultPath, notebookPath, meta.filename)
+}
+
 interface NotesState {
   notes: Note[]
   recentNotes: Note[]
@@ -83,7 +103,7 @@ export const useNotesStore = create<NotesState>((set, get) => ({
 
       const loadedNotes: Note[] = []
       for (const meta of initialMetadata) {
-        const note = await readNote(vaultPath, notebookPath, meta.filename)
+        const note = await readListedNote(vaultPath, notebookPath, meta)
         const isFavorite = await isNoteFavorite(meta.filename, notebookPath)
         loadedNotes.push({ ...note, isFavorite })
       }
@@ -116,7 +136,7 @@ export const useNotesStore = create<NotesState>((set, get) => ({
 
       const olderNotes: Note[] = []
       for (const meta of nextMetadata) {
-        const note = await readNote(vaultPath, notebookPath, meta.filename)
+        const note = await readListedNote(vaultPath, notebookPath, meta)
         const isFavorite = await isNoteFavorite(meta.filename, notebookPath)
         olderNotes.push({ ...note, isFavorite })
       }
@@ -166,7 +186,10 @@ export const useNotesStore = create<NotesState>((set, get) => ({
 
   createNote: async (vaultPath, notebookPath, content) => {
     const newNote = await apiCreateNote(vaultPath, notebookPath, content)
-    set((state) => ({ notes: [...state.notes, newNote] }))
+    // With date-based filing the note lands in a dated subfolder instead
+    if (newNote.notebookName === notebookPath) {
+      set((state) => ({ notes: [...state.notes, newNote] }))
+    }
     return newNote
   },
 
@@ -12,6 +12,9 @@ import type {
   ConflictResolution,
   VaultInfo,
   VaultConnectionInfo,
+  LocalVaultInfo,
+  RefreshedVaultInfo,
+  VaultReconnectResult,
 } from '../types/sync'
 
 const STORAGE_KEY = 'echopad-sync-auth'
@@ -57,9 +60,12 @@ interface SyncState {
   restoreSession: () => Promise<void>
   updateLastSyncTime: (vaultPath: string) => void
   listRemoteVaults: () => Promise<VaultInfo[]>
-  connectVault: (vaultPath: string, remoteVaultId: string) => Promise<void>
+  listLocalVaults: () => Promise<LocalVaultInfo[]>
+  refreshVaultInfo: (vaultId: string) => Promise<RefreshedVaultInfo | null>
+  connectVault: (vaultPath: string, remoteVaultId: string, metadataOnly?: boolean) => Promise<void>
   detectVaultConnection: (vaultPath: string) => Promise<VaultConnectionInfo | null>
   autoReconnectVault: (vaultPath: string) => Promise<boolean>
+  reconnectAllVaults: () => Promise<VaultReconnectResult[]>
 }
 
 // Helper to save auth to localStorage
@@ -376,10 +382,30 @@ export const useSyncStore = create<SyncState>((set, get) => ({
     }
   },
 
-  connectVault: async (vaultPath: string, remoteVaultId: string) => {
+  listLocalVaults: async () => {
+    try {
+      return await invoke<LocalVaultInfo[]>('sync_list_local_vaults')
+    } catch (error) {
+      console.error('Failed to list local vaults:', error)
+      return []
+    }
+  },
+
+  refreshVaultInfo: async (vaultId: string) => {
+    try {
+      return await invoke<RefreshedVaultInfo>('sync_refresh_vault_info', { vaultId })
+    } catch (error) {
+      console.error('Failed to refresh vault info:', error)
+      // A vault found missing now carries an error in the status
+      await get().refreshStatus()
+      return null
+    }
+  },
+
+  connectVault: async (vaultPath: string, remoteVaultId: string, metadataOnly?: boolean) => {
     set({ isLoading: true, error: null })
     try {
-      await invoke('sync_connect_vault', { vaultPath, remoteVaultId })
+      await invoke('sync_connect_vault', { vaultPath, remoteVaultId, metadataOnly })
       await get().refreshStatus()
       set({ isLoading: false })
       
@@ -424,4 +450,17 @@ export const useSyncStore = create<SyncState>((set, get) => ({
       return false
     }
   },
+
+  reconnectAllVaults: async () => {
+    try {
+      const results = await invoke<VaultReconnectResult[]>('sync_reconnect_all')
+      if (results.some((result) => result.status === 'reconnected')) {
+        await get().refreshStatus()
+      }
+      return results
+    } catch (error) {
+      console.error('Failed to reconnect vaults:', error)
+      return []
+    }
+  },
 }))
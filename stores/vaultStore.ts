@@ -1,4 +1,5 @@
 import { create } from 'zustand'
+import { toast } from 'sonner'
 import { AppSettings } from '../types'
 import {
   getVaultPath,
@@ -7,7 +8,8 @@ import {
   getVaultAccentColor,
   addKnownVault,
   getSetting,
-  saveSetting
+  saveSetting,
+  validateVault
 } from '../api'
 
 interface VaultState {
@@ -23,6 +25,27 @@ interface VaultState {
   closeVaultSetup: () => void
 }
 
+/**
+ * Run `validate_vault` on a folder about to be used as the vault. Failed
+ * checks are shown and keep the folder from being selected; warnings are
+ * shown but don't.
+ */
+async function checkVault(path: string): Promise<boolean> {
+  const validation = await validateVault(path)
+  const failed = validation.checks.filter((c) => c.status === 'fail')
+  const warnings = validation.checks.filter((c) => c.status === 'warn')
+  if (failed.length > 0) {
+    toast.error("Can't use this folder as a vault", {
+      description: failed.map((c) => c.message).join('\n')
+    })
+  } else if (warnings.length > 0) {
+    toast.warning('Vault opened with warnings', {
+      description: warnings.map((c) => c.message).join('\n')
+    })
+  }
+  return validation.ok
+}
+
 const defaultSettings: AppSettings = {
   appName: 'Lazuli',
   accentColor: '#818cf8'
@@ -68,6 +91,7 @@ export const useVaultStore = create<VaultState>((set, get) => ({
   },
 
   selectVault: async (path: string) => {
+    if (!(await checkVault(path))) return
     await setVaultPath(path)
     await addKnownVault(path)
     set({
@@ -77,6 +101,7 @@ export const useVaultStore = create<VaultState>((set, get) => ({
   },
 
   switchVault: async (path: string) => {
+    if (!(await checkVault(path))) return
     await setVaultPath(path)
     set({ vaultPath: path })
     window.location.reload()
@@ -7,11 +7,16 @@ export interface Note {
   urls: string[];
   notebookName: string;
   isFavorite?: boolean;
+  /** Still a sync placeholder: the content is on the server, not on disk */
+  placeholder?: boolean;
 }
 
 export interface NoteMetadata {
   filename: string;
   createdAt: number;
+  preview?: string;
+  title?: string;
+  placeholder?: boolean;
 }
 
 export interface Notebook {
@@ -37,6 +42,20 @@ export interface AppSettings {
   accentColor: string;
 }
 
+/** One check of `validate_vault` */
+export interface VaultCheck {
+  name: 'path' | 'read' | 'write' | 'notes' | 'manifest' | 'attachments';
+  status: 'pass' | 'warn' | 'fail';
+  message: string;
+}
+
+export interface VaultValidation {
+  vault_path: string;
+  /** No check failed */
+  ok: boolean;
+  checks: VaultCheck[];
+}
+
 export interface OgMetadata {
   url: string;
   title: string | null;
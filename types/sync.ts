@@ -76,6 +76,11 @@ export interface VaultInfo {
   total_size_bytes: number;
 }
 
+export interface RefreshedVaultInfo extends VaultInfo {
+  /** When a file in the vault last changed on the server (ms since epoch) */
+  last_modified: number | null;
+}
+
 /**
  * Information about a vault's sync connection detected from the manifest file.
  * Used to auto-reconnect vaults after session restoration.
@@ -97,3 +102,31 @@ export interface VaultConnectionInfo {
   has_existing_state: boolean;
 }
 
+/**
+ * A vault this device keeps sync state for, as listed by `sync_list_local_vaults`.
+ */
+export interface LocalVaultInfo {
+  vault_id: string;
+  vault_path: string;
+  enabled: boolean;
+  status: VaultSyncState;
+  last_sync_at: number | null;
+  last_error: string | null;
+  /** Local changes not yet synced (always 0 for disabled vaults) */
+  pending_changes: number;
+  /** Files with sync state on this device */
+  tracked_files: number;
+  folder_missing: boolean;
+  encrypted: boolean;
+}
+
+/**
+ * Outcome of reconnecting one vault with `sync_reconnect_all`.
+ */
+export interface VaultReconnectResult {
+  vault_path: string;
+  status: 'reconnected' | 'skipped' | 'failed';
+  /** Why the vault was skipped or failed */
+  reason: string | null;
+}
+
@@ -6,9 +6,10 @@
 
 import type { Env, JWTPayload } from './types'
 import { verifyToken } from './utils/jwt'
+import { ensureSchema } from './utils/migrate'
 
 // Auth routes
-import { getSalt, register, login, refresh, logout } from './routes/auth'
+import { getSalt, register, login, refresh, logout, verifyPassword } from './routes/auth'
 // Device routes
 import { listDevices, revokeDevice } from './routes/devices'
 // Vault routes
@@ -25,6 +26,7 @@ import {
   pull,
   push,
   confirmUpload,
+  hasFiles,
   getSyncStatus,
   checkPendingChanges
 } from './routes/sync'
@@ -112,6 +114,8 @@ export default {
     try {
       let response: Response
 
+      await ensureSchema(env.DB)
+
       // ==========================================
       // Public routes (no auth required)
       // ==========================================
@@ -181,6 +185,12 @@ export default {
         return addCorsHeaders(response, origin)
       }
 
+      // POST /api/v1/auth/verify
+      if (method === 'POST' && path === '/api/v1/auth/verify') {
+        response = await verifyPassword(request, env, userId, deviceId)
+        return addCorsHeaders(response, origin)
+      }
+
       // ==========================================
       // Device routes
       // ==========================================
@@ -272,6 +282,12 @@ export default {
           return addCorsHeaders(response, origin)
         }
 
+        // POST /api/v1/vaults/:id/sync/has
+        if (method === 'POST' && subPath === '/sync/has') {
+          response = await hasFiles(request, env, userId, vaultId)
+          return addCorsHeaders(response, origin)
+        }
+
         // GET /api/v1/vaults/:id/sync/status
         if (method === 'GET' && subPath === '/sync/status') {
           response = await getSyncStatus(request, env, userId, vaultId)
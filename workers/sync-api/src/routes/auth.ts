@@ -9,6 +9,7 @@ import type {
   Session,
   RegisterRequest,
   LoginRequest,
+  VerifyPasswordRequest,
   AuthResponse,
   TokenRefreshRequest,
   TokenRefreshResponse,
@@ -557,6 +558,85 @@ export async function refresh(request: Request, env: Env): Promise<Response> {
   })
 }
 
+/**
+ * Check the signed-in user's password without creating a session
+ */
+export async function verifyPassword(
+  request: Request,
+  env: Env,
+  userId: string,
+  deviceId: string
+): Promise<Response> {
+  const retryAfter = await checkRateLimit(env.DB, 'verify_password', userId)
+  if (retryAfter > 0) {
+    return new Response(
+      JSON.stringify({ error: 'Too many requests', code: 'RATE_LIMITED' }),
+      {
+        status: 429,
+        headers: {
+          'Content-Type': 'application/json',
+          'Retry-After': String(retryAfter)
+        }
+      }
+    )
+  }
+
+  let body: VerifyPasswordRequest
+  try {
+    body = await request.json()
+  } catch {
+    return new Response(
+      JSON.stringify({ error: 'Invalid JSON', code: 'INVALID_JSON' }),
+      {
+        status: 400,
+        headers: { 'Content-Type': 'application/json' }
+      }
+    )
+  }
+
+  if (!body.password) {
+    return new Response(
+      JSON.stringify({
+        error: 'Missing required fields',
+        code: 'MISSING_FIELDS'
+      }),
+      {
+        status: 400,
+        headers: { 'Content-Type': 'application/json' }
+      }
+    )
+  }
+
+  const user = await env.DB.prepare('SELECT password_hash FROM users WHERE id = ?')
+    .bind(userId)
+    .first<Pick<User, 'password_hash'>>()
+  if (!user) {
+    return new Response(
+      JSON.stringify({ error: 'User not found', code: 'NOT_FOUND' }),
+      {
+        status: 404,
+        headers: { 'Content-Type': 'application/json' }
+      }
+    )
+  }
+
+  const passwordHash = await sha256Base64(body.password)
+  const valid = constantTimeCompare(passwordHash, user.password_hash)
+  if (!valid) {
+    await logAudit(env.DB, 'password_verify_failed', {
+      userId,
+      deviceId,
+      ipAddress: getClientIP(request),
+      userAgent: getUserAgent(request)
+    })
+  }
+
+  return new Response(JSON.stringify({ valid }), {
+    status: 200,
+    headers: { 'Content-Type': 'application/json' }
+  })
+}
+
 /**
  * Logout (revoke refresh token)
  */
@@ -71,6 +71,8 @@ export async function uploadFile(
     customMetadata: {
       vault_id: vaultId,
       file_id: fileId,
+      // Content this upload belongs to, checked by sync/has
+      content_hash: file.content_hash,
       uploaded_at: Date.now().toString(),
     },
   });
@@ -15,6 +15,9 @@ import type {
   PushResult,
   PushChange,
   ConfirmUploadRequest,
+  HasFilesRequest,
+  HasFilesResponse,
+  HeldFile,
   VaultSyncStatusResponse,
   ChangeOperation,
 } from '../types';
@@ -23,6 +26,7 @@ import { checkRateLimit } from '../utils/rate-limit';
 import { logAudit, getClientIP, getUserAgent } from '../utils/audit';
 
 const PRESIGNED_URL_EXPIRY = 3600; // 1 hour
+const MAX_HAS_FILES = 500;
 
 /**
  * Generate cursor from timestamp and ID
@@ -566,6 +570,88 @@ export async function confirmUpload(
   });
 }
 
+/**
+ * Report which of the given files the server already holds with the given
+ * content hash, so a retried sync can skip re-uploading them. A file only
+ * counts once its content was uploaded for that hash, not just pushed.
+ */
+export async function hasFiles(
+  request: Request,
+  env: Env,
+  userId: string,
+  vaultId: string
+): Promise<Response> {
+  // Rate limit
+  const retryAfter = await checkRateLimit(env.DB, 'sync_has', `${userId}:${vaultId}`);
+  if (retryAfter > 0) {
+    return new Response(JSON.stringify({ error: 'Too many requests', code: 'RATE_LIMITED' }), {
+      status: 429,
+      headers: {
+        'Content-Type': 'application/json',
+        'Retry-After': String(retryAfter),
+      },
+    });
+  }
+
+  // Verify vault ownership
+  const vault = await env.DB
+    .prepare('SELECT id FROM vaults WHERE id = ? AND user_id = ? AND deleted_at IS NULL')
+    .bind(vaultId, userId)
+    .first<Vault>();
+
+  if (!vault) {
+    return new Response(JSON.stringify({ error: 'Vault not found', code: 'VAULT_NOT_FOUND' }), {
+      status: 404,
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  let body: HasFilesRequest;
+  try {
+    body = await request.json();
+  } catch {
+    return new Response(JSON.stringify({ error: 'Invalid JSON', code: 'INVALID_JSON' }), {
+      status: 400,
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  const { files } = body;
+
+  if (!files || !Array.isArray(files) || files.length > MAX_HAS_FILES) {
+    return new Response(JSON.stringify({ error: 'Invalid files', code: 'INVALID_FILES' }), {
+      status: 400,
+      headers: { 'Content-Type': 'application/json' },
+    });
+  }
+
+  const held: HeldFile[] = [];
+
+  for (const { encrypted_path, content_hash } of files) {
+    const file = await env.DB
+      .prepare('SELECT * FROM vault_files WHERE vault_id = ? AND encrypted_path = ? AND deleted_at IS NULL')
+      .bind(vaultId, encrypted_path)
+      .first<VaultFile>();
+
+    if (!file || file.content_hash !== content_hash) {
+      continue;
+    }
+
+    // Pushed but not (yet) uploaded leaves older content, or none, in R2
+    const object = await env.STORAGE.head(file.storage_key);
+    if (object?.customMetadata?.content_hash === content_hash) {
+      held.push({ encrypted_path, content_hash, version: file.version });
+    }
+  }
+
+  const response: HasFilesResponse = { files: held };
+
+  return new Response(JSON.stringify(response), {
+    status: 200,
+    headers: { 'Content-Type': 'application/json' },
+  });
+}
+
 /**
  * Check for pending remote changes without downloading them
  * This is a lightweight endpoint for polling upstream changes
@@ -24,7 +24,7 @@ export async function listVaults(
 ): Promise<Response> {
   const vaults = await env.DB
     .prepare(
-      `SELECT v.id, v.name, v.created_at,
+      `SELECT v.id, v.name, v.path_scheme, v.created_at,
               COUNT(vf.id) as file_count,
               COALESCE(SUM(vf.size_bytes), 0) as total_size_bytes
        FROM vaults v
@@ -39,6 +39,7 @@ export async function listVaults(
   const vaultInfos: VaultInfo[] = (vaults.results ?? []).map(v => ({
     id: v.id,
     name: v.name,
+    path_scheme: v.path_scheme,
     created_at: v.created_at,
     file_count: v.file_count,
     total_size_bytes: v.total_size_bytes,
@@ -69,7 +70,7 @@ export async function createVault(
     });
   }
 
-  const { name, encrypted_key, key_nonce } = body;
+  const { name, encrypted_key, key_nonce, path_scheme } = body;
 
   if (!name) {
     return new Response(JSON.stringify({ error: 'Vault name is required', code: 'MISSING_NAME' }), {
@@ -84,10 +85,10 @@ export async function createVault(
   // Create vault
   await env.DB
     .prepare(
-      `INSERT INTO vaults (id, user_id, name, created_at, updated_at)
-       VALUES (?, ?, ?, ?, ?)`
+      `INSERT INTO vaults (id, user_id, name, path_scheme, created_at, updated_at)
+       VALUES (?, ?, ?, ?, ?, ?)`
     )
-    .bind(vaultId, userId, name, now, now)
+    .bind(vaultId, userId, name, path_scheme ?? null, now, now)
     .run();
 
   // Store encrypted vault key if provided (for E2E encryption)
@@ -114,6 +115,7 @@ export async function createVault(
   const vaultInfo: VaultInfo = {
     id: vaultId,
     name,
+    path_scheme: path_scheme ?? null,
     created_at: now,
     file_count: 0,
     total_size_bytes: 0,
@@ -136,7 +138,7 @@ export async function getVault(
 ): Promise<Response> {
   const vault = await env.DB
     .prepare(
-      `SELECT v.id, v.name, v.created_at,
+      `SELECT v.id, v.name, v.path_scheme, v.created_at,
               COUNT(vf.id) as file_count,
               COALESCE(SUM(vf.size_bytes), 0) as total_size_bytes
        FROM vaults v
@@ -157,6 +159,7 @@ export async function getVault(
   const vaultInfo: VaultInfo = {
     id: vault.id,
     name: vault.name,
+    path_scheme: vault.path_scheme,
     created_at: vault.created_at,
     file_count: vault.file_count,
     total_size_bytes: vault.total_size_bytes,
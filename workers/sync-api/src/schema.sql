@@ -87,6 +87,7 @@ CREATE TABLE IF NOT EXISTS vaults (
   id TEXT PRIMARY KEY,                          -- UUID
   user_id TEXT NOT NULL,
   name TEXT NOT NULL,                           -- Encrypted vault name
+  path_scheme TEXT,                             -- 'prefixed' or NULL for bare base64 paths (see utils/migrate.ts)
   created_at INTEGER NOT NULL,
   updated_at INTEGER NOT NULL,
   deleted_at INTEGER,                           -- Soft delete
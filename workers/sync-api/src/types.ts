@@ -87,6 +87,10 @@ export interface LoginRequest {
   password: string;
 }
 
+export interface VerifyPasswordRequest {
+  password: string;
+}
+
 export interface AuthResponse {
   access_token: string;
   refresh_token: string;
@@ -117,6 +121,7 @@ export interface Vault {
   id: string;
   user_id: string;
   name: string;
+  path_scheme: string | null;
   created_at: number;
   updated_at: number;
   deleted_at: number | null;
@@ -125,6 +130,7 @@ export interface Vault {
 export interface VaultInfo {
   id: string;
   name: string;
+  path_scheme: string | null;
   created_at: number;
   file_count: number;
   total_size_bytes: number;
@@ -144,6 +150,7 @@ export interface CreateVaultRequest {
   name: string;
   encrypted_key: string;
   key_nonce: string;
+  path_scheme?: string;
 }
 
 export interface PutVaultKeyRequest {
@@ -243,6 +250,25 @@ export interface ConfirmUploadRequest {
   file_ids: string[];
 }
 
+export interface HasFilesRequest {
+  files: HeldFileQuery[];
+}
+
+export interface HeldFileQuery {
+  encrypted_path: string;
+  content_hash: string;
+}
+
+export interface HasFilesResponse {
+  files: HeldFile[];
+}
+
+export interface HeldFile {
+  encrypted_path: string;
+  content_hash: string;
+  version: number;
+}
+
 export interface VaultSyncStatusResponse {
   file_count: number;
   total_size_bytes: number;
@@ -301,6 +327,7 @@ export type AuditAction =
   | 'register'
   | 'login'
   | 'login_failed'
+  | 'password_verify_failed'
   | 'logout'
   | 'token_refresh'
   | 'password_change'
@@ -0,0 +1,41 @@
+/**
+ * Schema migrations for databases created from an older schema.sql
+ *
+ * `CREATE TABLE IF NOT EXISTS` never changes an existing table, so columns
+ * added to schema.sql later are added here as well. Each statement is
+ * idempotent: a column that already exists is left alone.
+ */
+
+const MIGRATIONS = [
+  // Added with per-vault path schemes; NULL means bare base64 paths
+  'ALTER TABLE vaults ADD COLUMN path_scheme TEXT',
+];
+
+let migrated: Promise<void> | null = null;
+
+async function runMigrations(db: D1Database): Promise<void> {
+  for (const sql of MIGRATIONS) {
+    await db
+      .prepare(sql)
+      .run()
+      .catch((error: unknown) => {
+        if (!String(error).includes('duplicate column name')) {
+          throw error;
+        }
+      });
+  }
+}
+
+/**
+ * Bring the database schema up to date, once per worker instance
+ */
+export function ensureSchema(db: D1Database): Promise<void> {
+  if (!migrated) {
+    // Retried by the next request if it fails
+    migrated = runMigrations(db).catch((error) => {
+      migrated = null;
+      throw error;
+    });
+  }
+  return migrated;
+}
@@ -11,10 +11,12 @@ interface RateLimitConfig {
 
 const RATE_LIMITS: Record<string, RateLimitConfig> = {
   login: { maxRequests: 5, windowMs: 60 * 1000 }, // 5 per minute
+  verify_password: { maxRequests: 5, windowMs: 60 * 1000 }, // 5 per minute
   register: { maxRequests: 3, windowMs: 60 * 60 * 1000 }, // 3 per hour
   password_reset: { maxRequests: 3, windowMs: 60 * 60 * 1000 }, // 3 per hour
   sync_pull: { maxRequests: 60, windowMs: 60 * 1000 }, // 60 per minute
   sync_push: { maxRequests: 30, windowMs: 60 * 1000 }, // 30 per minute
+  sync_has: { maxRequests: 30, windowMs: 60 * 1000 }, // 30 per minute
   file_upload: { maxRequests: 100, windowMs: 60 * 1000 }, // 100 per minute
 };
 